@@ -593,6 +593,8 @@ let octez_risc_v_pvm =
         [S "source_tree"; S "../machine_state/src"];
         [S "glob_files"; S "../kernel_loader/*"];
         [S "source_tree"; S "../kernel_loader/src"];
+        [S "glob_files"; S "../../kernel_sdk/constants/*"];
+        [S "source_tree"; S "../../kernel_sdk/constants/src"];
         extra_dep;
       ];
       [S "enabled_if"; enable_if];
@@ -6,10 +6,18 @@
 //!
 //! The result of writing to the debug log is *implementation specific* - it may, for
 //! example, be written to a log file, or to `stdout` etc.
+//!
+//! Besides raw messages, written with [debug_msg] & [debug_str], kernels can write
+//! structured records with levels, targets & fields - see [log].
 #![no_std]
 #![deny(missing_docs)]
 #![deny(rustdoc::broken_intra_doc_links)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod log;
+
 /// Write a formatted message to host debug log. Formats follow [`core::fmt`].
 ///
 /// You can use `debug_msg!` with any variable such that implements [`Runtime`].
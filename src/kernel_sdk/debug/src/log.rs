@@ -0,0 +1,969 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Structured logging.
+//!
+//! A log record has a [Level], a *target* - the module path of the call site
+//! unless given explicitly - a message, and key/value fields:
+//!
+//! ```no_run
+//! extern crate alloc;
+//! use tezos_smart_rollup_debug::{info, warn};
+//! use tezos_smart_rollup_host::runtime::Runtime;
+//!
+//! fn deposit(host: &impl Runtime, amount: u64, receiver: &str) {
+//!     info!(host, amount = amount, receiver = receiver; "Deposit accepted");
+//!     warn!(host, target: "ledger", "Balance of {} is low", receiver);
+//! }
+//! ```
+//!
+//! # Compile-time filtering
+//!
+//! Records more verbose than [STATIC_MAX_LEVEL] are removed at compile time,
+//! including the evaluation of their message & fields. The maximum level is
+//! chosen with the `max-level-off`, `max-level-error`, `max-level-warn`,
+//! `max-level-info` & `max-level-debug` features; when several are enabled
+//! the most restrictive one applies. By default, all records are kept.
+//!
+//! # Output cap
+//!
+//! The output of a kernel run can be bounded with [set_max_bytes_per_run].
+//! The first record exceeding the cap is replaced by a notice that the cap was
+//! reached, and the following ones are dropped until the next run starts - see
+//! [start_run]. Kernels defined with `kernel_entry!` start a run on every call
+//! to `kernel_run`.
+//!
+//! # Encoding
+//!
+//! Records are written to the debug log in a compact binary encoding, which
+//! the host can decode with [decode] and filter with [LogFilter]. Since the
+//! debug log of [Runtime] is text, the encoding only uses ASCII bytes -
+//! besides the UTF-8 contents of strings:
+//!
+//! - the record separator `0x1e`, followed by the level as an ASCII digit,
+//! - the target, the message and the number of fields,
+//! - each field as its key, a tag & its value,
+//! - a newline, so that logs stay readable when not decoded.
+//!
+//! Strings & bytes are prefixed by their length. Numbers are encoded 5 bits
+//! per byte, least significant first: all bytes but the last are `0x40` plus
+//! their bits, and the last one is a base 32 digit (`0`-`9`, `a`-`v`), so that
+//! no number is encoded as a control character. Integers are zigzag-encoded,
+//! and bytes are written in hexadecimal.
+//!
+//! [Runtime]: tezos_smart_rollup_host::runtime::Runtime
+
+use core::fmt::{self, Display};
+use core::str::FromStr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+#[cfg(feature = "alloc")]
+use alloc::{string::String, string::ToString, vec::Vec};
+
+/// Verbosity of a log record, from the least to the most verbose.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum Level {
+    /// The kernel failed to process an input.
+    Error = 1,
+    /// Something unexpected happened, but processing continues.
+    Warn,
+    /// Progress of the kernel.
+    Info,
+    /// Information useful when debugging the kernel.
+    Debug,
+    /// Very detailed information.
+    Trace,
+}
+
+impl Level {
+    const ALL: [Level; 5] = [
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ];
+
+    /// Lower case name of the level.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    fn from_u8(level: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|l| *l as u8 == level)
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Error parsing a [Level] or a [LogFilter].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseLevelError;
+
+impl Display for ParseLevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected one of off, error, warn, info, debug or trace")
+    }
+}
+
+impl FromStr for Level {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|level| level.as_str().eq_ignore_ascii_case(s))
+            .ok_or(ParseLevelError)
+    }
+}
+
+/// The most verbose level kept at compile time, or `None` if logging is
+/// disabled. See the [module documentation](self).
+pub const STATIC_MAX_LEVEL: Option<Level> = if cfg!(feature = "max-level-off") {
+    None
+} else if cfg!(feature = "max-level-error") {
+    Some(Level::Error)
+} else if cfg!(feature = "max-level-warn") {
+    Some(Level::Warn)
+} else if cfg!(feature = "max-level-info") {
+    Some(Level::Info)
+} else if cfg!(feature = "max-level-debug") {
+    Some(Level::Debug)
+} else {
+    Some(Level::Trace)
+};
+
+/// Whether records of `level` are kept at compile time.
+pub const fn enabled(level: Level) -> bool {
+    match STATIC_MAX_LEVEL {
+        Some(max) => level as u8 <= max as u8,
+        None => false,
+    }
+}
+
+/// Value of a field of a log record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Value<'a> {
+    /// A natural number.
+    Nat(u64),
+    /// An integer.
+    Int(i64),
+    /// A boolean.
+    Bool(bool),
+    /// A string.
+    Str(&'a str),
+    /// Bytes, displayed in hexadecimal.
+    Bytes(&'a [u8]),
+}
+
+macro_rules! value_from {
+    ($variant: ident, $target: ty, $($ty: ty),*) => {
+        $(
+            impl<'a> From<$ty> for Value<'a> {
+                fn from(value: $ty) -> Self {
+                    Value::$variant(value as $target)
+                }
+            }
+        )*
+    };
+}
+
+value_from!(Nat, u64, u8, u16, u32, u64, usize);
+value_from!(Int, i64, i8, i16, i32, i64, isize);
+
+impl<'a> From<bool> for Value<'a> {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl<'a> From<&'a str> for Value<'a> {
+    fn from(value: &'a str) -> Self {
+        Value::Str(value)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Value<'a> {
+    fn from(value: &'a [u8]) -> Self {
+        Value::Bytes(value)
+    }
+}
+
+impl<'a, const N: usize> From<&'a [u8; N]> for Value<'a> {
+    fn from(value: &'a [u8; N]) -> Self {
+        Value::Bytes(value)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> From<&'a String> for Value<'a> {
+    fn from(value: &'a String) -> Self {
+        Value::Str(value)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> From<&'a Vec<u8>> for Value<'a> {
+    fn from(value: &'a Vec<u8>) -> Self {
+        Value::Bytes(value)
+    }
+}
+
+/// Bound on the size of the records written during a kernel run.
+///
+/// The kernel's records are charged to a global cap, set with
+/// [set_max_bytes_per_run]. Hosts running several kernels, such as tests,
+/// keep their own cap instead.
+#[derive(Debug)]
+pub struct OutputCap {
+    max_bytes: AtomicUsize,
+    written: AtomicUsize,
+    capped: AtomicBool,
+}
+
+impl OutputCap {
+    /// Cap without bound.
+    pub const fn new() -> Self {
+        Self {
+            max_bytes: AtomicUsize::new(usize::MAX),
+            written: AtomicUsize::new(0),
+            capped: AtomicBool::new(false),
+        }
+    }
+
+    /// Bound the size of the records written during a run, `usize::MAX`
+    /// meaning no bound.
+    pub fn set_max_bytes(&self, max_bytes: usize) {
+        self.max_bytes.store(max_bytes, Ordering::Relaxed);
+    }
+
+    /// Start a new run, resetting the bytes written.
+    pub fn start_run(&self) {
+        self.written.store(0, Ordering::Relaxed);
+        self.capped.store(false, Ordering::Relaxed);
+    }
+
+    /// Charge `record` to the run, returning what should be written to the
+    /// debug log.
+    #[cfg(feature = "alloc")]
+    pub fn charge(&self, record: String) -> Option<String> {
+        let max = self.max_bytes.load(Ordering::Relaxed);
+        let written = self.written.load(Ordering::Relaxed);
+
+        if written.saturating_add(record.len()) <= max {
+            self.written
+                .store(written + record.len(), Ordering::Relaxed);
+            Some(record)
+        } else if !self.capped.swap(true, Ordering::Relaxed) {
+            let max = Value::from(max);
+            Some(encode(
+                Level::Warn,
+                CAP_TARGET,
+                "Output cap reached, dropping records until the next run",
+                &[("max_bytes", max)],
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for OutputCap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static OUTPUT_CAP: OutputCap = OutputCap::new();
+
+/// Bound the size of the records written during a kernel run, `usize::MAX`
+/// (the default) meaning no bound.
+pub fn set_max_bytes_per_run(max_bytes: usize) {
+    OUTPUT_CAP.set_max_bytes(max_bytes);
+}
+
+/// Start a new kernel run, resetting the output cap.
+pub fn start_run() {
+    OUTPUT_CAP.start_run();
+}
+
+const RECORD_SEPARATOR: u8 = 0x1e;
+#[cfg(feature = "alloc")]
+const RECORD_END: u8 = b'\n';
+#[cfg(feature = "alloc")]
+const CONTINUATION: u8 = 0x40;
+#[cfg(feature = "alloc")]
+const DIGITS: &[u8; 32] = b"0123456789abcdefghijklmnopqrstuv";
+
+#[cfg(feature = "alloc")]
+const NAT_TAG: u8 = b'n';
+#[cfg(feature = "alloc")]
+const INT_TAG: u8 = b'i';
+#[cfg(feature = "alloc")]
+const BOOL_TAG: u8 = b'b';
+#[cfg(feature = "alloc")]
+const STR_TAG: u8 = b's';
+#[cfg(feature = "alloc")]
+const BYTES_TAG: u8 = b'x';
+
+/// Target of the notice replacing records over the output cap.
+pub const CAP_TARGET: &str = "log";
+
+#[cfg(feature = "alloc")]
+fn encode_number(mut n: u64, output: &mut Vec<u8>) {
+    while n >= 32 {
+        output.push(CONTINUATION | (n as u8 & 0x1f));
+        n >>= 5;
+    }
+    output.push(DIGITS[n as usize]);
+}
+
+#[cfg(feature = "alloc")]
+fn encode_str(s: &str, output: &mut Vec<u8>) {
+    encode_number(s.len() as u64, output);
+    output.extend_from_slice(s.as_bytes());
+}
+
+/// Encode a log record.
+#[cfg(feature = "alloc")]
+pub fn encode(
+    level: Level,
+    target: &str,
+    message: &str,
+    fields: &[(&str, Value)],
+) -> String {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    let mut output = Vec::with_capacity(8 + target.len() + message.len());
+    output.push(RECORD_SEPARATOR);
+    output.push(b'0' + level as u8);
+    encode_str(target, &mut output);
+    encode_str(message, &mut output);
+    encode_number(fields.len() as u64, &mut output);
+
+    for (key, value) in fields {
+        encode_str(key, &mut output);
+        match value {
+            Value::Nat(n) => {
+                output.push(NAT_TAG);
+                encode_number(*n, &mut output);
+            }
+            Value::Int(i) => {
+                output.push(INT_TAG);
+                encode_number(((i << 1) ^ (i >> 63)) as u64, &mut output);
+            }
+            Value::Bool(b) => {
+                output.push(BOOL_TAG);
+                output.push(if *b { b'1' } else { b'0' });
+            }
+            Value::Str(s) => {
+                output.push(STR_TAG);
+                encode_str(s, &mut output);
+            }
+            Value::Bytes(bytes) => {
+                output.push(BYTES_TAG);
+                encode_number(bytes.len() as u64, &mut output);
+                for byte in bytes.iter() {
+                    output.push(HEX[(byte >> 4) as usize]);
+                    output.push(HEX[(byte & 0xf) as usize]);
+                }
+            }
+        }
+    }
+    output.push(RECORD_END);
+
+    String::from_utf8(output).expect("Records are made of ASCII bytes & strings")
+}
+
+/// Charge `record` to the output cap of the run, returning what should be
+/// written to the debug log.
+#[doc(hidden)]
+#[cfg(feature = "alloc")]
+pub fn within_cap(record: String) -> Option<String> {
+    OUTPUT_CAP.charge(record)
+}
+
+/// Value of a field of a decoded log record.
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldValue {
+    /// A natural number.
+    Nat(u64),
+    /// An integer.
+    Int(i64),
+    /// A boolean.
+    Bool(bool),
+    /// A string.
+    Str(String),
+    /// Bytes.
+    Bytes(Vec<u8>),
+}
+
+#[cfg(feature = "alloc")]
+impl Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Nat(n) => write!(f, "{n}"),
+            FieldValue::Int(i) => write!(f, "{i}"),
+            FieldValue::Bool(b) => write!(f, "{b}"),
+            FieldValue::Str(s) => write!(f, "{s:?}"),
+            FieldValue::Bytes(bytes) => {
+                write!(f, "0x")?;
+                bytes.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+            }
+        }
+    }
+}
+
+/// A decoded log record.
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// Level of the record.
+    pub level: Level,
+    /// Target of the record, the module path of the call site by default.
+    pub target: String,
+    /// Formatted message.
+    pub message: String,
+    /// Key/value fields, in the order they were given.
+    pub fields: Vec<(String, FieldValue)>,
+}
+
+#[cfg(feature = "alloc")]
+impl Record {
+    /// The value of field `key`, if any.
+    pub fn field(&self, key: &str) -> Option<&FieldValue> {
+        self.fields
+            .iter()
+            .find_map(|(k, value)| (k == key).then_some(value))
+    }
+}
+
+/// Records are displayed as `LEVEL target: message key=value ...`.
+#[cfg(feature = "alloc")]
+impl Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = self.level.as_str().to_ascii_uppercase();
+        write!(f, "{level:<5} {}: {}", self.target, self.message)?;
+        for (key, value) in self.fields.iter() {
+            write!(f, " {key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Error decoding a log record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input is the start of a record.
+    Incomplete,
+    /// The input does not start with a record.
+    Invalid,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Incomplete => write!(f, "incomplete log record"),
+            DecodeError::Invalid => write!(f, "invalid log record"),
+        }
+    }
+}
+
+/// Whether `output` starts a log record, rather than being plain text.
+pub fn is_record(output: &[u8]) -> bool {
+    output.first() == Some(&RECORD_SEPARATOR)
+}
+
+#[cfg(feature = "alloc")]
+struct Decoder<'a> {
+    input: &'a [u8],
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> Decoder<'a> {
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let (byte, rest) = self.input.split_first().ok_or(DecodeError::Incomplete)?;
+        self.input = rest;
+        Ok(*byte)
+    }
+
+    fn take(&mut self, len: u64) -> Result<&'a [u8], DecodeError> {
+        let len = usize::try_from(len).map_err(|_| DecodeError::Invalid)?;
+        if self.input.len() < len {
+            return Err(DecodeError::Incomplete);
+        }
+        let (bytes, rest) = self.input.split_at(len);
+        self.input = rest;
+        Ok(bytes)
+    }
+
+    fn number(&mut self) -> Result<u64, DecodeError> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(5) {
+            let byte = self.byte()?;
+            let (bits, last) = match byte {
+                CONTINUATION..=0x5f => (byte & 0x1f, false),
+                _ => match DIGITS.iter().position(|&digit| digit == byte) {
+                    Some(digit) => (digit as u8, true),
+                    None => return Err(DecodeError::Invalid),
+                },
+            };
+            n |= (bits as u64)
+                .checked_shl(shift)
+                .filter(|shifted| shifted >> shift == bits as u64)
+                .ok_or(DecodeError::Invalid)?;
+            if last {
+                return Ok(n);
+            }
+        }
+        Err(DecodeError::Invalid)
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.number()?;
+        let bytes = self.take(len)?;
+        core::str::from_utf8(bytes)
+            .map(ToString::to_string)
+            .map_err(|_| DecodeError::Invalid)
+    }
+
+    fn value(&mut self) -> Result<FieldValue, DecodeError> {
+        match self.byte()? {
+            NAT_TAG => self.number().map(FieldValue::Nat),
+            INT_TAG => {
+                let n = self.number()?;
+                Ok(FieldValue::Int(((n >> 1) as i64) ^ -((n & 1) as i64)))
+            }
+            BOOL_TAG => match self.byte()? {
+                b'0' => Ok(FieldValue::Bool(false)),
+                b'1' => Ok(FieldValue::Bool(true)),
+                _ => Err(DecodeError::Invalid),
+            },
+            STR_TAG => self.string().map(FieldValue::Str),
+            BYTES_TAG => {
+                let len = self.number()?;
+                let hex = self.take(len.checked_mul(2).ok_or(DecodeError::Invalid)?)?;
+                let digit = |c: u8| (c as char).to_digit(16).ok_or(DecodeError::Invalid);
+                hex.chunks(2)
+                    .map(|pair| Ok((digit(pair[0])? << 4 | digit(pair[1])?) as u8))
+                    .collect::<Result<_, _>>()
+                    .map(FieldValue::Bytes)
+            }
+            _ => Err(DecodeError::Invalid),
+        }
+    }
+
+    fn record(&mut self) -> Result<Record, DecodeError> {
+        if self.byte()? != RECORD_SEPARATOR {
+            return Err(DecodeError::Invalid);
+        }
+        let level = self.byte()?;
+        let level =
+            Level::from_u8(level.wrapping_sub(b'0')).ok_or(DecodeError::Invalid)?;
+        let target = self.string()?;
+        let message = self.string()?;
+
+        let count = self.number()?;
+        let mut fields = Vec::new();
+        for _ in 0..count {
+            let key = self.string()?;
+            fields.push((key, self.value()?));
+        }
+
+        if self.byte()? != RECORD_END {
+            return Err(DecodeError::Invalid);
+        }
+
+        Ok(Record {
+            level,
+            target,
+            message,
+            fields,
+        })
+    }
+}
+
+/// Decode the log record at the start of `input`, returning it with the rest
+/// of the input.
+#[cfg(feature = "alloc")]
+pub fn decode(input: &[u8]) -> Result<(Record, &[u8]), DecodeError> {
+    let mut decoder = Decoder { input };
+    let record = decoder.record()?;
+    Ok((record, decoder.input))
+}
+
+/// Host-side filter of log records by level & target.
+///
+/// Filters are parsed from a comma separated list of directives: a level
+/// (or `off`) applying to all targets, or `target=level` applying to the
+/// targets starting with `target`. The longest matching target wins, eg
+/// `warn,my_kernel::inbox=trace`.
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogFilter {
+    default: Option<Level>,
+    targets: Vec<(String, Option<Level>)>,
+}
+
+#[cfg(feature = "alloc")]
+impl LogFilter {
+    /// Filter keeping records up to `level`, for all targets.
+    pub fn new(level: Option<Level>) -> Self {
+        Self {
+            default: level,
+            targets: Vec::new(),
+        }
+    }
+
+    /// Keep records of targets starting with `target` up to `level`.
+    pub fn with_target(mut self, target: &str, level: Option<Level>) -> Self {
+        self.targets.retain(|(t, _)| t != target);
+        self.targets.push((target.to_string(), level));
+        self
+    }
+
+    /// Whether records of `level` & `target` are kept.
+    pub fn enabled(&self, level: Level, target: &str) -> bool {
+        let max = self
+            .targets
+            .iter()
+            .filter(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |(_, max)| *max);
+        max.map_or(false, |max| level <= max)
+    }
+
+    /// Whether `record` is kept.
+    pub fn matches(&self, record: &Record) -> bool {
+        self.enabled(record.level, &record.target)
+    }
+}
+
+/// Keep all records.
+#[cfg(feature = "alloc")]
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self::new(Some(Level::Trace))
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl FromStr for LogFilter {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let level = |s: &str| match s.trim() {
+            s if s.eq_ignore_ascii_case("off") => Ok(None),
+            s => Level::from_str(s).map(Some),
+        };
+
+        let mut filter = Self::new(Some(Level::Trace));
+        for directive in s.split(',').filter(|d| !d.trim().is_empty()) {
+            filter = match directive.split_once('=') {
+                Some((target, max)) => filter.with_target(target.trim(), level(max)?),
+                None => Self {
+                    default: level(directive)?,
+                    ..filter
+                },
+            };
+        }
+        Ok(filter)
+    }
+}
+
+/// Log a record with the given level, message and fields.
+///
+/// The target defaults to the module path of the call site, and is
+/// overridden with `target: "..."`. Fields are given as `key = value`
+/// before the message, separated from it by `;`, with values converting into
+/// [`Value`]. See the [module documentation](crate::log).
+///
+/// ```no_run
+/// extern crate alloc;
+/// use tezos_smart_rollup_debug::log;
+/// use tezos_smart_rollup_debug::log::Level;
+/// use tezos_smart_rollup_host::runtime::Runtime;
+///
+/// fn log_runtime(host: &impl Runtime) {
+///     log!(host, Level::Info, "Processing level {}", 5);
+///     log!(host, Level::Debug, target: "inbox", id = 3, external = true; "Input");
+/// }
+/// ```
+///
+/// [`Value`]: crate::log::Value
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! log {
+    ($host: expr, $level: expr, target: $target: expr, $($key: ident = $value: expr),+ ; $($arg: tt)+) => {
+        $crate::__log!($host, $level, $target, [$(($key, $value)),+], $($arg)+)
+    };
+    ($host: expr, $level: expr, target: $target: expr, $($arg: tt)+) => {
+        $crate::__log!($host, $level, $target, [], $($arg)+)
+    };
+    ($host: expr, $level: expr, $($key: ident = $value: expr),+ ; $($arg: tt)+) => {
+        $crate::__log!($host, $level, module_path!(), [$(($key, $value)),+], $($arg)+)
+    };
+    ($host: expr, $level: expr, $($arg: tt)+) => {
+        $crate::__log!($host, $level, module_path!(), [], $($arg)+)
+    };
+}
+
+#[doc(hidden)]
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! __log {
+    ($host: expr, $level: expr, $target: expr, [$(($key: ident, $value: expr)),*], $($arg: tt)+) => {{
+        let level: $crate::log::Level = $level;
+        if $crate::log::enabled(level) {
+            extern crate alloc;
+            let record = $crate::log::encode(
+                level,
+                $target,
+                &alloc::format!($($arg)+),
+                &[$((stringify!($key), $crate::log::Value::from($value))),*],
+            );
+            if let Some(record) = $crate::log::within_cap(record) {
+                $crate::debug_str!($host, &record);
+            }
+        }
+    }};
+}
+
+/// Log a record at the [`Error`](crate::log::Level::Error) level, see [`log`].
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! error {
+    ($host: expr, $($arg: tt)+) => {
+        $crate::log!($host, $crate::log::Level::Error, $($arg)+)
+    };
+}
+
+/// Log a record at the [`Warn`](crate::log::Level::Warn) level, see [`log`].
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! warn {
+    ($host: expr, $($arg: tt)+) => {
+        $crate::log!($host, $crate::log::Level::Warn, $($arg)+)
+    };
+}
+
+/// Log a record at the [`Info`](crate::log::Level::Info) level, see [`log`].
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! info {
+    ($host: expr, $($arg: tt)+) => {
+        $crate::log!($host, $crate::log::Level::Info, $($arg)+)
+    };
+}
+
+/// Log a record at the [`Debug`](crate::log::Level::Debug) level, see [`log`].
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! debug {
+    ($host: expr, $($arg: tt)+) => {
+        $crate::log!($host, $crate::log::Level::Debug, $($arg)+)
+    };
+}
+
+/// Log a record at the [`Trace`](crate::log::Level::Trace) level, see [`log`].
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! trace {
+    ($host: expr, $($arg: tt)+) => {
+        $crate::log!($host, $crate::log::Level::Trace, $($arg)+)
+    };
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn round_trip(fields: &[(&str, Value)]) -> Record {
+        let encoded = encode(Level::Info, "target", "message", fields);
+        let (record, rest) = decode(encoded.as_bytes()).expect("Record should decode");
+        assert!(rest.is_empty());
+        record
+    }
+
+    #[test]
+    fn extreme_values_round_trip() {
+        let record = round_trip(&[
+            ("min", Value::Int(i64::MIN)),
+            ("max", Value::Int(i64::MAX)),
+            ("minus", Value::Int(-1)),
+            ("nat", Value::Nat(u64::MAX)),
+            ("zero", Value::Nat(0)),
+        ]);
+
+        assert_eq!(Some(&FieldValue::Int(i64::MIN)), record.field("min"));
+        assert_eq!(Some(&FieldValue::Int(i64::MAX)), record.field("max"));
+        assert_eq!(Some(&FieldValue::Int(-1)), record.field("minus"));
+        assert_eq!(Some(&FieldValue::Nat(u64::MAX)), record.field("nat"));
+        assert_eq!(Some(&FieldValue::Nat(0)), record.field("zero"));
+    }
+
+    #[test]
+    fn empty_values_round_trip() {
+        let encoded = encode(
+            Level::Trace,
+            "",
+            "",
+            &[("", Value::Str("")), ("bytes", Value::Bytes(&[]))],
+        );
+        let (record, _) = decode(encoded.as_bytes()).unwrap();
+
+        assert_eq!(
+            Record {
+                level: Level::Trace,
+                target: String::new(),
+                message: String::new(),
+                fields: vec![
+                    (String::new(), FieldValue::Str(String::new())),
+                    ("bytes".to_string(), FieldValue::Bytes(vec![])),
+                ],
+            },
+            record
+        );
+    }
+
+    #[test]
+    fn encoding_is_printable() {
+        let message = "m".repeat(1000);
+        let encoded = encode(
+            Level::Error,
+            "kernel",
+            &message,
+            &[("n", Value::Nat(u64::MAX)), ("i", Value::Int(i64::MIN))],
+        );
+        let bytes = encoded.as_bytes();
+
+        assert_eq!(RECORD_SEPARATOR, bytes[0]);
+        assert_eq!(Some(&RECORD_END), bytes.last());
+        assert!(bytes[1..bytes.len() - 1]
+            .iter()
+            .all(|byte| byte.is_ascii_graphic()));
+    }
+
+    #[test]
+    fn decode_truncated_and_invalid() {
+        let encoded = encode(
+            Level::Warn,
+            "target",
+            "message",
+            &[
+                ("bytes", Value::Bytes(&[1, 2, 3])),
+                ("ok", Value::Bool(true)),
+            ],
+        );
+        let bytes = encoded.as_bytes();
+
+        for len in 0..bytes.len() {
+            assert_eq!(Err(DecodeError::Incomplete), decode(&bytes[..len]));
+        }
+
+        let followed = [bytes, b"plain"].concat();
+        let (_, rest) = decode(&followed).unwrap();
+        assert_eq!(b"plain", rest);
+
+        let invalid = |position: usize, byte: u8| {
+            let mut bytes = bytes.to_vec();
+            bytes[position] = byte;
+            decode(&bytes).map(|_| ())
+        };
+        // Separator, level, length and record end
+        assert_eq!(Err(DecodeError::Invalid), invalid(0, b'x'));
+        assert_eq!(Err(DecodeError::Invalid), invalid(1, b'9'));
+        assert_eq!(Err(DecodeError::Invalid), invalid(2, b'\n'));
+        assert_eq!(Err(DecodeError::Invalid), invalid(bytes.len() - 1, b'x'));
+        // Hex digits of the bytes, and the boolean
+        let hex = encoded.find("010203").unwrap();
+        assert_eq!(Err(DecodeError::Invalid), invalid(hex, b'g'));
+        assert_eq!(Err(DecodeError::Invalid), invalid(bytes.len() - 2, b'2'));
+
+        // Numbers over 64 bits
+        let mut overflow = vec![RECORD_SEPARATOR, b'1'];
+        overflow.extend_from_slice(&[CONTINUATION | 0x1f; 12]);
+        overflow.push(b'v');
+        assert_eq!(Err(DecodeError::Invalid), decode(&overflow).map(|_| ()));
+    }
+
+    #[test]
+    fn filter_parsing() {
+        let filter: LogFilter =
+            " warn , kernel = trace,kernel::inbox=OFF,".parse().unwrap();
+        assert_eq!(
+            LogFilter::new(Some(Level::Warn))
+                .with_target("kernel", Some(Level::Trace))
+                .with_target("kernel::inbox", None),
+            filter
+        );
+
+        // Later directives override earlier ones
+        let filter: LogFilter = "error,kernel=info,info,kernel=debug".parse().unwrap();
+        assert_eq!(
+            LogFilter::new(Some(Level::Info)).with_target("kernel", Some(Level::Debug)),
+            filter
+        );
+
+        assert_eq!(Ok(LogFilter::default()), "".parse());
+        assert_eq!(Err(ParseLevelError), "verbose".parse::<LogFilter>());
+        assert_eq!(Err(ParseLevelError), "kernel=".parse::<LogFilter>());
+    }
+
+    #[test]
+    fn filter_longest_prefix() {
+        let filter: LogFilter =
+            "info,kernel=warn,kernel::inbox=trace,kernel::inbox::dal=off"
+                .parse()
+                .unwrap();
+
+        assert!(filter.enabled(Level::Info, "other"));
+        assert!(!filter.enabled(Level::Debug, "other"));
+        assert!(!filter.enabled(Level::Info, "kernel"));
+        assert!(!filter.enabled(Level::Info, "kernel::outbox"));
+        assert!(filter.enabled(Level::Trace, "kernel::inbox"));
+        assert!(filter.enabled(Level::Trace, "kernel::inbox::parse"));
+        assert!(!filter.enabled(Level::Error, "kernel::inbox::dal"));
+
+        let filter = LogFilter::new(None).with_target("kernel", Some(Level::Error));
+        assert!(!filter.enabled(Level::Error, "other"));
+        assert!(filter.enabled(Level::Error, "kernel"));
+    }
+
+    #[test]
+    fn output_cap() {
+        let cap = OutputCap::new();
+        let record = encode(Level::Info, "target", "message", &[]);
+        cap.set_max_bytes(2 * record.len());
+
+        assert_eq!(Some(&record), cap.charge(record.clone()).as_ref());
+        assert_eq!(Some(&record), cap.charge(record.clone()).as_ref());
+
+        let notice = cap.charge(record.clone()).unwrap();
+        let (notice, _) = decode(notice.as_bytes()).unwrap();
+        assert_eq!(CAP_TARGET, notice.target);
+        assert_eq!(
+            Some(&FieldValue::Nat(2 * record.len() as u64)),
+            notice.field("max_bytes")
+        );
+        assert_eq!(None, cap.charge(record.clone()));
+
+        cap.start_run();
+        assert_eq!(Some(record.clone()), cap.charge(record));
+    }
+}
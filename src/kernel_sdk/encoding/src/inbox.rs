@@ -15,17 +15,25 @@ use crate::michelson::Michelson;
 use crate::public_key_hash::PublicKeyHash;
 use crate::smart_rollup::SmartRollupAddress;
 use crate::timestamp::Timestamp;
+use crypto::blake2b::{digest_256, Blake2bError};
 use crypto::hash::{BlockHash, ContractKt1Hash};
-use nom::bytes::complete::tag;
-use nom::combinator::{map, rest};
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take};
+use nom::combinator::{eof, map, rest};
+use nom::multi::count;
+use nom::number::complete::{be_u16, be_u32};
 use nom::sequence::pair;
 use nom::sequence::preceded;
+use nom::sequence::terminated;
+use nom::sequence::tuple;
 use nom::Finish;
 use std::fmt::Display;
 use tezos_data_encoding::enc;
-use tezos_data_encoding::enc::BinWriter;
+use tezos_data_encoding::enc::{BinError, BinWriter};
 use tezos_data_encoding::encoding::HasEncoding;
 use tezos_data_encoding::nom::NomReader;
+use tezos_smart_rollup_core::MAX_INPUT_MESSAGE_SIZE;
+use thiserror::Error;
 
 #[derive(Debug, PartialEq, Eq, NomReader, HasEncoding, BinWriter)]
 enum InboxMessageRepr<Expr: Michelson> {
@@ -228,15 +236,260 @@ impl<T: AsRef<[u8]>> BinWriter for ExternalMessageFrame<T> {
     }
 }
 
+impl ExternalMessageFrame<Vec<u8>> {
+    /// Split `payload` into the frames of a [ChunkedMessage] upload to the
+    /// rollup at `address`: a [ChunkedMessage::New] announcing the hashes of
+    /// the chunks, followed by the [ChunkedMessage::Chunk]s.
+    ///
+    /// Each frame fits in a single external inbox message. An empty payload is
+    /// sent as a single empty chunk.
+    pub fn chunked(
+        address: &SmartRollupAddress,
+        payload: &[u8],
+    ) -> Result<Vec<Self>, ChunkedMessageError> {
+        let num_chunks = payload.len().div_ceil(MAX_CHUNK_SIZE).max(1);
+        if num_chunks > MAX_CHUNKS {
+            return Err(ChunkedMessageError::PayloadTooLarge(payload.len()));
+        }
+
+        let data: Vec<&[u8]> = (0..num_chunks)
+            .map(|index| {
+                let start = index * MAX_CHUNK_SIZE;
+                let end = payload.len().min(start + MAX_CHUNK_SIZE);
+                &payload[start..end]
+            })
+            .collect();
+        let chunk_hashes = data
+            .iter()
+            .map(|data| ChunkedMessage::<&[u8]>::chunk_hash(data))
+            .collect::<Result<Vec<_>, _>>()?;
+        let hash = ChunkedMessage::<&[u8]>::upload_hash(&chunk_hashes)?;
+
+        let chunks = data
+            .into_iter()
+            .zip(0..)
+            .map(|(data, index)| ChunkedMessage::Chunk { hash, index, data });
+
+        core::iter::once(ChunkedMessage::New {
+            hash,
+            size: payload.len() as u32,
+            chunk_hashes,
+        })
+        .chain(chunks)
+        .map(|message| {
+            let mut contents = Vec::new();
+            message.bin_write(&mut contents)?;
+            Ok(Self::Targetted {
+                address: address.clone(),
+                contents,
+            })
+        })
+        .collect()
+    }
+}
+
+/// Size of the hashes committing to a [ChunkedMessage] upload and its chunks.
+pub const CHUNKED_HASH_SIZE: usize = 32;
+
+/// Maximum number of chunks of a [ChunkedMessage] upload, so that their
+/// hashes fit in the [ChunkedMessage::New] announcing it.
+pub const MAX_CHUNKS: usize = (MAX_INPUT_MESSAGE_SIZE
+    - 1 // External message tag
+    - 1 // Targetted frame tag
+    - 20 // Rollup address
+    - 1 // New tag
+    - CHUNKED_HASH_SIZE
+    - 2 // Number of chunks
+    - 4) // Payload size
+    / CHUNKED_HASH_SIZE;
+
+/// Maximum size of the data of a [ChunkedMessage::Chunk], so that it fits in
+/// an external inbox message when framed by [ExternalMessageFrame::Targetted].
+pub const MAX_CHUNK_SIZE: usize = MAX_INPUT_MESSAGE_SIZE
+    - 1 // External message tag
+    - 1 // Targetted frame tag
+    - 20 // Rollup address
+    - 1 // Chunk tag
+    - CHUNKED_HASH_SIZE
+    - 2; // Chunk index
+
+/// Errors occurring when splitting a payload into chunks.
+#[derive(Debug, Error)]
+pub enum ChunkedMessageError {
+    /// The payload needs more than [MAX_CHUNKS] chunks.
+    #[error("Payload of {0} bytes is too large to be chunked")]
+    PayloadTooLarge(usize),
+    /// Hashing the payload failed.
+    #[error("Failed to hash the payload: {0}")]
+    Hashing(#[from] Blake2bError),
+    /// Encoding a chunk failed.
+    #[error("Failed to encode a chunk: {0}")]
+    Encoding(#[from] BinError),
+}
+
+/// Protocol for uploading payloads too large for a single external message.
+///
+/// A payload is announced by a [ChunkedMessage::New], committing to the
+/// blake2b hash of each of its chunks, and sent as [ChunkedMessage::Chunk]s,
+/// which may arrive in any order over several levels. The upload is identified
+/// by the hash of the chunk hashes, so that each chunk can be checked as soon
+/// as it arrives. See [ExternalMessageFrame::chunked] for splitting payloads;
+/// kernels reassemble them with the `ChunkReassembler` of the SDK.
+#[derive(Debug, Eq)]
+pub enum ChunkedMessage<T: AsRef<[u8]>> {
+    /// Start of the upload of a payload of `size` bytes, in as many chunks
+    /// as `chunk_hashes`.
+    New {
+        /// Hash of the upload - see [ChunkedMessage::upload_hash].
+        hash: [u8; CHUNKED_HASH_SIZE],
+        /// Size of the payload, in bytes.
+        size: u32,
+        /// Blake2b hash of each chunk of the payload.
+        chunk_hashes: Vec<[u8; CHUNKED_HASH_SIZE]>,
+    },
+    /// Chunk `index` of the upload identified by `hash`.
+    Chunk {
+        /// Hash of the upload - see [ChunkedMessage::upload_hash].
+        hash: [u8; CHUNKED_HASH_SIZE],
+        /// Index of the chunk in the payload.
+        index: u16,
+        /// The contents of the chunk.
+        data: T,
+    },
+}
+
+impl<T: AsRef<[u8]>> ChunkedMessage<T> {
+    const NEW_TAG: u8 = 0;
+    const CHUNK_TAG: u8 = 1;
+
+    /// The hash committing to the `data` of a chunk.
+    pub fn chunk_hash(data: &[u8]) -> Result<[u8; CHUNKED_HASH_SIZE], Blake2bError> {
+        let hash = digest_256(data)?;
+        hash.try_into().map_err(|_| Blake2bError::InvalidLength)
+    }
+
+    /// The hash identifying an upload: the hash of its chunk hashes, in order.
+    pub fn upload_hash(
+        chunk_hashes: &[[u8; CHUNKED_HASH_SIZE]],
+    ) -> Result<[u8; CHUNKED_HASH_SIZE], Blake2bError> {
+        Self::chunk_hash(&chunk_hashes.concat())
+    }
+}
+
+impl<'a> ChunkedMessage<&'a [u8]> {
+    /// Replacement for `nom_read` for [ChunkedMessage], borrowing the data of
+    /// chunks from the input - see [ExternalMessageFrame::parse].
+    pub fn parse(input: &'a [u8]) -> Result<Self, tezos_data_encoding::nom::NomError> {
+        let hash = |input| {
+            map(take(CHUNKED_HASH_SIZE), |hash: &[u8]| {
+                hash.try_into().expect("hash has the expected size")
+            })(input)
+        };
+
+        // The number of chunks comes before the size, but counts the hashes
+        // after it.
+        let new = |input| {
+            let (input, (upload_hash, num_chunks, size)) =
+                tuple((hash, be_u16, be_u32))(input)?;
+            let (input, chunk_hashes) = count(hash, num_chunks as usize)(input)?;
+            Ok((input, (upload_hash, size, chunk_hashes)))
+        };
+
+        let (_remaining, message) = alt((
+            map(
+                preceded(tag([Self::NEW_TAG]), terminated(new, eof)),
+                |(hash, size, chunk_hashes)| Self::New {
+                    hash,
+                    size,
+                    chunk_hashes,
+                },
+            ),
+            map(
+                preceded(tag([Self::CHUNK_TAG]), tuple((hash, be_u16, rest))),
+                |(hash, index, data)| Self::Chunk { hash, index, data },
+            ),
+        ))(input)
+        .finish()?;
+
+        Ok(message)
+    }
+}
+
+impl<T: AsRef<[u8]>, U: AsRef<[u8]>> core::cmp::PartialEq<ChunkedMessage<U>>
+    for ChunkedMessage<T>
+{
+    fn eq(&self, other: &ChunkedMessage<U>) -> bool {
+        match (self, other) {
+            (
+                Self::New {
+                    hash: h1,
+                    size: s1,
+                    chunk_hashes: c1,
+                },
+                ChunkedMessage::New {
+                    hash: h2,
+                    size: s2,
+                    chunk_hashes: c2,
+                },
+            ) => h1 == h2 && s1 == s2 && c1 == c2,
+            (
+                Self::Chunk {
+                    hash: h1,
+                    index: i1,
+                    data: d1,
+                },
+                ChunkedMessage::Chunk {
+                    hash: h2,
+                    index: i2,
+                    data: d2,
+                },
+            ) => h1 == h2 && i1 == i2 && d1.as_ref() == d2.as_ref(),
+            _ => false,
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> BinWriter for ChunkedMessage<T> {
+    fn bin_write(&self, output: &mut Vec<u8>) -> enc::BinResult {
+        match self {
+            Self::New {
+                hash,
+                size,
+                chunk_hashes,
+            } => {
+                let num_chunks = u16::try_from(chunk_hashes.len())
+                    .map_err(|_| BinError::custom("too many chunks".into()))?;
+                enc::put_byte(&Self::NEW_TAG, output);
+                enc::put_bytes(hash, output);
+                enc::put_bytes(&num_chunks.to_be_bytes(), output);
+                enc::put_bytes(&size.to_be_bytes(), output);
+                for chunk_hash in chunk_hashes {
+                    enc::put_bytes(chunk_hash, output);
+                }
+            }
+            Self::Chunk { hash, index, data } => {
+                enc::put_byte(&Self::CHUNK_TAG, output);
+                enc::put_bytes(hash, output);
+                enc::put_bytes(&index.to_be_bytes(), output);
+                enc::put_bytes(data.as_ref(), output);
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::ExternalMessageFrame;
     use super::InboxMessage;
     use super::InternalInboxMessage;
+    use super::{ChunkedMessage, CHUNKED_HASH_SIZE, MAX_CHUNKS, MAX_CHUNK_SIZE};
     use crate::michelson::Michelson;
     use crate::michelson::MichelsonUnit;
     use crate::smart_rollup::SmartRollupAddress;
     use tezos_data_encoding::enc::BinWriter;
+    use tezos_smart_rollup_core::MAX_INPUT_MESSAGE_SIZE;
 
     #[test]
     fn test_encode_decode_sol() {
@@ -337,4 +590,96 @@ mod test {
 
         assert_eq!(framed, parsed);
     }
+
+    #[test]
+    fn test_external_framing_chunked() {
+        let address =
+            SmartRollupAddress::from_b58check("sr163Lv22CdE8QagCwf48PWDTquk6isQwv57")
+                .unwrap();
+        let payload: Vec<u8> = (0..2 * MAX_CHUNK_SIZE + 10).map(|i| i as u8).collect();
+
+        let frames = ExternalMessageFrame::chunked(&address, &payload).unwrap();
+        assert_eq!(4, frames.len());
+
+        let mut chunks = Vec::new();
+        for frame in frames.iter() {
+            // Each frame fits in an external inbox message
+            let mut external = Vec::new();
+            frame.bin_write(&mut external).unwrap();
+            let mut message = Vec::new();
+            InboxMessage::<MichelsonUnit>::External(&external)
+                .serialize(&mut message)
+                .unwrap();
+            assert!(message.len() <= MAX_INPUT_MESSAGE_SIZE);
+
+            let ExternalMessageFrame::Targetted { contents, .. } = frame;
+            chunks.push(ChunkedMessage::parse(contents).unwrap());
+        }
+
+        let chunk_hashes: Vec<_> = payload
+            .chunks(MAX_CHUNK_SIZE)
+            .map(|data| ChunkedMessage::<&[u8]>::chunk_hash(data).unwrap())
+            .collect();
+        let hash = ChunkedMessage::<&[u8]>::upload_hash(&chunk_hashes).unwrap();
+        assert_eq!(
+            ChunkedMessage::<&[u8]>::New {
+                hash,
+                size: payload.len() as u32,
+                chunk_hashes: chunk_hashes.clone(),
+            },
+            chunks[0]
+        );
+        let data: Vec<u8> = chunks[1..]
+            .iter()
+            .enumerate()
+            .flat_map(|(i, chunk)| match chunk {
+                ChunkedMessage::Chunk {
+                    hash: h,
+                    index,
+                    data,
+                } => {
+                    assert_eq!((&hash, i as u16), (h, *index));
+                    assert_eq!(
+                        chunk_hashes[i],
+                        ChunkedMessage::<&[u8]>::chunk_hash(data).unwrap()
+                    );
+                    data.to_vec()
+                }
+                _ => panic!("expected a chunk"),
+            })
+            .collect();
+        assert_eq!(payload, data);
+
+        // Empty payloads are sent as a single empty chunk
+        let frames = ExternalMessageFrame::chunked(&address, &[]).unwrap();
+        assert_eq!(2, frames.len());
+
+        // The largest payload announces hashes filling a whole message
+        let payload = vec![0; MAX_CHUNKS * MAX_CHUNK_SIZE];
+        let frames = ExternalMessageFrame::chunked(&address, &payload).unwrap();
+        let mut external = Vec::new();
+        frames[0].bin_write(&mut external).unwrap();
+        assert!(external.len() < MAX_INPUT_MESSAGE_SIZE);
+        assert!(
+            ExternalMessageFrame::chunked(&address, &[payload, vec![0]].concat())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_chunked_new_trailing_bytes() {
+        let new = ChunkedMessage::<&[u8]>::New {
+            hash: [1; CHUNKED_HASH_SIZE],
+            size: 10,
+            chunk_hashes: vec![[2; CHUNKED_HASH_SIZE], [3; CHUNKED_HASH_SIZE]],
+        };
+        let mut bytes = Vec::new();
+        new.bin_write(&mut bytes).unwrap();
+        assert_eq!(new, ChunkedMessage::parse(&bytes).unwrap());
+
+        // Missing hashes, or bytes after them, are rejected
+        assert!(ChunkedMessage::parse(&bytes[..bytes.len() - 1]).is_err());
+        bytes.push(0);
+        assert!(ChunkedMessage::parse(&bytes).is_err());
+    }
 }
@@ -7,26 +7,36 @@
 //! Definitions & tezos-encodings for *michelson* data.
 use micheline::annots::Annotations;
 use nom::branch::alt;
-use nom::combinator::map;
+use nom::combinator::{map, recognize};
 use prim::*;
 use std::fmt::Debug;
-use tezos_data_encoding::enc::{self, BinResult, BinWriter};
+use tezos_data_encoding::enc::{self, BinError, BinResult, BinWriter};
 use tezos_data_encoding::encoding::{Encoding, HasEncoding};
 use tezos_data_encoding::nom::{self as nom_read, NomReader, NomResult};
 use tezos_data_encoding::types::Zarith;
 
+#[doc(hidden)]
+pub mod derive;
 mod micheline;
 #[cfg(feature = "alloc")]
+pub mod text;
+#[cfg(feature = "alloc")]
 pub mod ticket;
 
+#[cfg(feature = "derive")]
+pub use tezos_smart_rollup_michelson_derive::Michelson;
+
+use self::micheline::annots::Annotation;
 use self::micheline::Node;
 use super::contract::Contract;
+use super::timestamp::Timestamp;
 use micheline::{
     bin_write_micheline_bytes, bin_write_micheline_int, bin_write_micheline_string,
     bin_write_prim_1_arg_no_annots, bin_write_prim_2_args_no_annots,
     bin_write_prim_no_args_no_annots, nom_read_micheline_bytes, nom_read_micheline_int,
     nom_read_micheline_string, MichelinePrim1ArgNoAnnots, MichelinePrim2ArgsNoAnnots,
-    MichelinePrimNoArgsNoAnnots,
+    MichelinePrimNoArgsNoAnnots, MICHELINE_PRIM_1_ARG_NO_ANNOTS_TAG,
+    MICHELINE_PRIM_2_ARGS_NO_ANNOTS_TAG, MICHELINE_PRIM_NO_ARGS_NO_ANNOTS_TAG,
 };
 use v1_primitives as prim;
 
@@ -74,9 +84,18 @@ pub mod v1_primitives {
     /// bytes type tag
     pub const BYTES_TYPE_TAG: u8 = 105;
 
+    /// timestamp type tag
+    pub const TIMESTAMP_TYPE_TAG: u8 = 107;
+
     /// unit type tag
     pub const UNIT_TYPE_TAG: u8 = 108;
 
+    /// address type tag
+    pub const ADDRESS_TYPE_TAG: u8 = 110;
+
+    /// ticket type tag
+    pub const TICKET_TYPE_TAG: u8 = 135;
+
     /// ticket encoding case tag.
     pub const TICKET_TAG: u8 = 157;
 }
@@ -95,6 +114,7 @@ impl Michelson for MichelsonInt {}
 impl Michelson for MichelsonNat {}
 impl Michelson for MichelsonString {}
 impl Michelson for MichelsonBytes {}
+impl Michelson for MichelsonTimestamp {}
 impl<Arg0, Arg1> Michelson for MichelsonPair<Arg0, Arg1>
 where
     Arg0: Michelson,
@@ -108,6 +128,96 @@ where
 {
 }
 impl<Arg> Michelson for MichelsonOption<Arg> where Arg: Michelson {}
+impl Michelson for MichelsonExpr {}
+
+/// Michelson types with a known Micheline type expression.
+///
+/// Gives, for instance, the type of the parameter of an L1 contract
+/// matching a Rust type.
+pub trait MichelsonType: Michelson {
+    /// The type expression, eg `pair address nat` for
+    /// `MichelsonPair<MichelsonContract, MichelsonNat>`.
+    fn type_expr() -> MichelsonExpr;
+}
+
+impl MichelsonType for MichelsonUnit {
+    fn type_expr() -> MichelsonExpr {
+        MichelsonExpr::prim_type(UNIT_TYPE_TAG)
+    }
+}
+
+impl MichelsonType for MichelsonContract {
+    fn type_expr() -> MichelsonExpr {
+        MichelsonExpr::prim_type(ADDRESS_TYPE_TAG)
+    }
+}
+
+impl MichelsonType for MichelsonInt {
+    fn type_expr() -> MichelsonExpr {
+        MichelsonExpr::prim_type(INT_TYPE_TAG)
+    }
+}
+
+impl MichelsonType for MichelsonNat {
+    fn type_expr() -> MichelsonExpr {
+        MichelsonExpr::prim_type(NAT_TYPE_TAG)
+    }
+}
+
+impl MichelsonType for MichelsonString {
+    fn type_expr() -> MichelsonExpr {
+        MichelsonExpr::prim_type(STRING_TYPE_TAG)
+    }
+}
+
+impl MichelsonType for MichelsonBytes {
+    fn type_expr() -> MichelsonExpr {
+        MichelsonExpr::prim_type(BYTES_TYPE_TAG)
+    }
+}
+
+impl MichelsonType for MichelsonTimestamp {
+    fn type_expr() -> MichelsonExpr {
+        MichelsonExpr::prim_type(TIMESTAMP_TYPE_TAG)
+    }
+}
+
+impl<Arg0, Arg1> MichelsonType for MichelsonPair<Arg0, Arg1>
+where
+    Arg0: MichelsonType,
+    Arg1: MichelsonType,
+{
+    fn type_expr() -> MichelsonExpr {
+        MichelsonExpr::prim_2_args_type(
+            PAIR_TYPE_TAG,
+            &Arg0::type_expr(),
+            &Arg1::type_expr(),
+        )
+    }
+}
+
+impl<Arg0, Arg1> MichelsonType for MichelsonOr<Arg0, Arg1>
+where
+    Arg0: MichelsonType,
+    Arg1: MichelsonType,
+{
+    fn type_expr() -> MichelsonExpr {
+        MichelsonExpr::prim_2_args_type(
+            OR_TYPE_TAG,
+            &Arg0::type_expr(),
+            &Arg1::type_expr(),
+        )
+    }
+}
+
+impl<Arg> MichelsonType for MichelsonOption<Arg>
+where
+    Arg: MichelsonType,
+{
+    fn type_expr() -> MichelsonExpr {
+        MichelsonExpr::prim_1_arg_type(OPTION_TYPE_TAG, &Arg::type_expr())
+    }
+}
 
 /// Michelson *unit* encoding.
 #[derive(Debug, PartialEq, Eq)]
@@ -156,6 +266,89 @@ pub struct MichelsonBytes(pub Vec<u8>);
 #[derive(Debug, PartialEq, Eq)]
 pub struct MichelsonInt(pub Zarith);
 
+/// Michelson Timestamp encoding, as the number of seconds since the epoch.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MichelsonTimestamp(pub Timestamp);
+
+/// Any Michelson expression, kept in its binary encoding.
+///
+/// Useful when the type of an expression is not known in advance: it can be
+/// decoded into a specific type later on, with [MichelsonExpr::decode].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MichelsonExpr(Vec<u8>);
+
+impl MichelsonExpr {
+    /// Encode a Michelson value as an expression.
+    pub fn encode(value: &impl Michelson) -> Result<Self, BinError> {
+        let mut bytes = Vec::new();
+        value.bin_write(&mut bytes)?;
+        Ok(Self(bytes))
+    }
+
+    /// Decode the expression as a value of type `T`. Returns `None` if the
+    /// expression is not of that type.
+    pub fn decode<T: Michelson>(&self) -> Option<T> {
+        match T::nom_read(&self.0) {
+            Ok(([], value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Binary encoding of the expression.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Add the field annotation `%name` to the expression. Returns `None` if
+    /// `name` is not a valid annotation, or the expression is not a primitive
+    /// application (such as a type).
+    pub fn with_field_annotation(&self, name: &str) -> Option<Self> {
+        let annot = Annotation::field(name)?;
+        let node = match Node::nom_read(&self.0) {
+            Ok((
+                [],
+                Node::Prim {
+                    prim_tag,
+                    args,
+                    mut annots,
+                },
+            )) => {
+                annots.0.push(annot);
+                Node::Prim {
+                    prim_tag,
+                    args,
+                    annots,
+                }
+            }
+            _ => return None,
+        };
+
+        let mut bytes = Vec::new();
+        node.bin_write(&mut bytes).ok()?;
+        Some(Self(bytes))
+    }
+
+    /// Type with no arguments nor annotations.
+    pub(crate) fn prim_type(prim_tag: u8) -> Self {
+        Self(vec![MICHELINE_PRIM_NO_ARGS_NO_ANNOTS_TAG, prim_tag])
+    }
+
+    /// Type with one argument & no annotations.
+    pub(crate) fn prim_1_arg_type(prim_tag: u8, arg: &Self) -> Self {
+        let mut bytes = vec![MICHELINE_PRIM_1_ARG_NO_ANNOTS_TAG, prim_tag];
+        bytes.extend_from_slice(&arg.0);
+        Self(bytes)
+    }
+
+    /// Type with two arguments & no annotations.
+    pub(crate) fn prim_2_args_type(prim_tag: u8, arg1: &Self, arg2: &Self) -> Self {
+        let mut bytes = vec![MICHELINE_PRIM_2_ARGS_NO_ANNOTS_TAG, prim_tag];
+        bytes.extend_from_slice(&arg1.0);
+        bytes.extend_from_slice(&arg2.0);
+        Self(bytes)
+    }
+}
+
 /// Michelson Nat encoding.
 #[derive(Debug, PartialEq, Eq)]
 pub struct MichelsonNat(Zarith);
@@ -208,6 +401,12 @@ impl From<u32> for MichelsonNat {
     }
 }
 
+impl From<Timestamp> for MichelsonTimestamp {
+    fn from(value: Timestamp) -> MichelsonTimestamp {
+        MichelsonTimestamp(value)
+    }
+}
+
 // --------
 // ENCODING
 // --------
@@ -264,6 +463,12 @@ impl HasEncoding for MichelsonBytes {
     }
 }
 
+impl HasEncoding for MichelsonExpr {
+    fn encoding() -> Encoding {
+        Encoding::Custom
+    }
+}
+
 impl HasEncoding for MichelsonInt {
     fn encoding() -> Encoding {
         Encoding::Custom
@@ -275,6 +480,12 @@ impl HasEncoding for MichelsonNat {
     }
 }
 
+impl HasEncoding for MichelsonTimestamp {
+    fn encoding() -> Encoding {
+        Encoding::Custom
+    }
+}
+
 // --------
 // DECODING
 // --------
@@ -658,6 +869,14 @@ impl NomReader for MichelsonBytes {
     }
 }
 
+impl NomReader for MichelsonExpr {
+    fn nom_read(input: &[u8]) -> NomResult<Self> {
+        map(recognize(Node::nom_read), |bytes: &[u8]| {
+            MichelsonExpr(bytes.to_vec())
+        })(input)
+    }
+}
+
 impl NomReader for MichelsonInt {
     fn nom_read(input: &[u8]) -> NomResult<Self> {
         map(nom_read_micheline_int, MichelsonInt)(input)
@@ -681,6 +900,22 @@ impl NomReader for MichelsonNat {
     }
 }
 
+impl NomReader for MichelsonTimestamp {
+    fn nom_read(input: &[u8]) -> NomResult<Self> {
+        use nom::error::{ErrorKind, ParseError};
+        use tezos_data_encoding::nom::error::*;
+
+        let (rest, i) = nom_read_micheline_int(input)?;
+        match i64::try_from(&i.0) {
+            Ok(seconds) => Ok((rest, MichelsonTimestamp(seconds.into()))),
+            Err(_) => Err(nom::Err::Error(DecodeError::from_error_kind(
+                input,
+                ErrorKind::MapRes,
+            ))),
+        }
+    }
+}
+
 // --------
 // ENCODING
 // --------
@@ -800,6 +1035,13 @@ impl BinWriter for MichelsonBytes {
     }
 }
 
+impl BinWriter for MichelsonExpr {
+    fn bin_write(&self, output: &mut Vec<u8>) -> BinResult {
+        output.extend_from_slice(&self.0);
+        Ok(())
+    }
+}
+
 impl BinWriter for MichelsonInt {
     fn bin_write(&self, output: &mut Vec<u8>) -> BinResult {
         bin_write_micheline_int(&self.0, output)
@@ -810,3 +1052,9 @@ impl BinWriter for MichelsonNat {
         bin_write_micheline_int(&self.0, output)
     }
 }
+
+impl BinWriter for MichelsonTimestamp {
+    fn bin_write(&self, output: &mut Vec<u8>) -> BinResult {
+        bin_write_micheline_int(&Zarith(self.0.i64().into()), output)
+    }
+}
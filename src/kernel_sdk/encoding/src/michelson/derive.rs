@@ -0,0 +1,95 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Support for the code generated by `#[derive(Michelson)]`.
+//!
+//! Derived types are right-combs of pairs & ors, which are encoded without
+//! going through [MichelsonPair] & [MichelsonOr]: the prefix of each `Pair`,
+//! `Left` & `Right` is written (or read) before the arguments.
+//!
+//! [MichelsonPair]: super::MichelsonPair
+//! [MichelsonOr]: super::MichelsonOr
+
+use super::micheline::{
+    MICHELINE_PRIM_1_ARG_NO_ANNOTS_TAG, MICHELINE_PRIM_2_ARGS_NO_ANNOTS_TAG,
+};
+use super::prim::{LEFT_TAG, OR_TYPE_TAG, PAIR_TAG, PAIR_TYPE_TAG, RIGHT_TAG};
+use super::MichelsonUnit;
+use nom::bytes::complete::tag;
+use nom::combinator::value;
+use tezos_data_encoding::enc;
+
+pub use super::{Michelson, MichelsonExpr, MichelsonType};
+pub use tezos_data_encoding::enc::{BinResult, BinWriter};
+pub use tezos_data_encoding::encoding::{Encoding, HasEncoding};
+pub use tezos_data_encoding::nom::{NomReader, NomResult};
+
+const PAIR_PREFIX: [u8; 2] = [MICHELINE_PRIM_2_ARGS_NO_ANNOTS_TAG, PAIR_TAG];
+const LEFT_PREFIX: [u8; 2] = [MICHELINE_PRIM_1_ARG_NO_ANNOTS_TAG, LEFT_TAG];
+const RIGHT_PREFIX: [u8; 2] = [MICHELINE_PRIM_1_ARG_NO_ANNOTS_TAG, RIGHT_TAG];
+
+/// Write the prefix of `Pair`, to be followed by its two arguments.
+pub fn bin_write_pair_prefix(output: &mut Vec<u8>) -> BinResult {
+    enc::put_bytes(&PAIR_PREFIX, output);
+    Ok(())
+}
+
+/// Write the prefix of `Left`, to be followed by its argument.
+pub fn bin_write_left_prefix(output: &mut Vec<u8>) -> BinResult {
+    enc::put_bytes(&LEFT_PREFIX, output);
+    Ok(())
+}
+
+/// Write the prefix of `Right`, to be followed by its argument.
+pub fn bin_write_right_prefix(output: &mut Vec<u8>) -> BinResult {
+    enc::put_bytes(&RIGHT_PREFIX, output);
+    Ok(())
+}
+
+/// Write `Unit`, the value of variants & structs without fields.
+pub fn bin_write_unit(output: &mut Vec<u8>) -> BinResult {
+    MichelsonUnit.bin_write(output)
+}
+
+/// Read the prefix of `Pair`.
+pub fn nom_read_pair_prefix(input: &[u8]) -> NomResult<()> {
+    value((), tag(&PAIR_PREFIX[..]))(input)
+}
+
+/// Read the prefix of `Left`.
+pub fn nom_read_left_prefix(input: &[u8]) -> NomResult<()> {
+    value((), tag(&LEFT_PREFIX[..]))(input)
+}
+
+/// Read the prefix of `Right`.
+pub fn nom_read_right_prefix(input: &[u8]) -> NomResult<()> {
+    value((), tag(&RIGHT_PREFIX[..]))(input)
+}
+
+/// Read `Unit`.
+pub fn nom_read_unit(input: &[u8]) -> NomResult<()> {
+    value((), MichelsonUnit::nom_read)(input)
+}
+
+/// `pair left right`
+pub fn pair_type(left: &MichelsonExpr, right: &MichelsonExpr) -> MichelsonExpr {
+    MichelsonExpr::prim_2_args_type(PAIR_TYPE_TAG, left, right)
+}
+
+/// `or left right`
+pub fn or_type(left: &MichelsonExpr, right: &MichelsonExpr) -> MichelsonExpr {
+    MichelsonExpr::prim_2_args_type(OR_TYPE_TAG, left, right)
+}
+
+/// `unit`
+pub fn unit_type() -> MichelsonExpr {
+    MichelsonUnit::type_expr()
+}
+
+/// `ty` with the field annotation `%name`. The derive macro only generates
+/// valid annotations.
+pub fn field_annotated(ty: MichelsonExpr, name: &str) -> MichelsonExpr {
+    ty.with_field_annotation(name)
+        .expect("Derived types only have valid field annotations")
+}
@@ -98,8 +98,7 @@ pub(crate) mod annots {
             use nom::error::ParseError;
 
             let (remaining, anns) = nom_read::string(input)?;
-            let re =
-                Regex::new(r"^[@:$&%!?][a-zA-Z0-9_.%@]*$").expect("Expected valid regex");
+            let re = annotation_regex();
 
             anns.split(' ')
                 .filter(|&x| !x.is_empty()) // needed for the empty string
@@ -123,6 +122,25 @@ pub(crate) mod annots {
             self.0.is_empty()
         }
     }
+
+    impl Annotation {
+        /// The annotation `annot`, including its leading `%`, `@` or `:`, if
+        /// it is a valid annotation.
+        pub(crate) fn new(annot: &str) -> Option<Self> {
+            annotation_regex()
+                .is_match(annot)
+                .then(|| Annotation(annot.into()))
+        }
+
+        /// Field annotation `%name`, if it is a valid annotation.
+        pub(crate) fn field(name: &str) -> Option<Self> {
+            Self::new(&format!("%{name}"))
+        }
+    }
+
+    fn annotation_regex() -> Regex {
+        Regex::new(r"^[@:$&%!?][a-zA-Z0-9_.%@]*$").expect("Expected valid regex")
+    }
 }
 
 use annots::Annotations;
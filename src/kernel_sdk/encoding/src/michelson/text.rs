@@ -0,0 +1,886 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Michelson concrete syntax, as used by `octez-client`.
+//!
+//! [MichelsonText] parses Michelson values from, and prints them to, their
+//! concrete syntax, eg `Pair "KT1..." (Some 12)` for a
+//! `MichelsonPair<MichelsonContract, MichelsonOption<MichelsonNat>>`.
+//!
+//! Parsing accepts:
+//! - right combs written flat, eg `Pair 1 2 3` for `Pair 1 (Pair 2 3)`,
+//! - addresses & timestamps both in their readable form (`"tz1..."`,
+//!   `"2024-01-01T00:00:00Z"`) and in their optimized form (bytes, int),
+//! - tickets both as `Ticket "KT1..." nat 1 10` and as
+//!   `Pair "KT1..." 1 10`.
+//!
+//! Printing always gives the readable form, with nested combs in parentheses.
+//!
+//! [MichelsonExpr] is parsed and printed as is, without type: any expression
+//! made of the primitives above round-trips. Printing fails on an expression
+//! using a primitive with no concrete syntax here, rather than giving text
+//! that cannot be parsed back.
+
+use super::micheline::annots::{Annotation, Annotations};
+use super::micheline::Node;
+use super::ticket::Ticket;
+use super::*;
+use crate::contract::Contract;
+use num_bigint::BigInt;
+use std::str::FromStr;
+use thiserror::Error;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Names of the primitives with a concrete syntax.
+const PRIMITIVES: [(&str, u8); 18] = [
+    ("Left", LEFT_TAG),
+    ("None", NONE_TAG),
+    ("Pair", PAIR_TAG),
+    ("Right", RIGHT_TAG),
+    ("Some", SOME_TAG),
+    ("Unit", UNIT_TAG),
+    ("Ticket", TICKET_TAG),
+    ("address", ADDRESS_TYPE_TAG),
+    ("bytes", BYTES_TYPE_TAG),
+    ("int", INT_TYPE_TAG),
+    ("nat", NAT_TYPE_TAG),
+    ("option", OPTION_TYPE_TAG),
+    ("or", OR_TYPE_TAG),
+    ("pair", PAIR_TYPE_TAG),
+    ("string", STRING_TYPE_TAG),
+    ("ticket", TICKET_TYPE_TAG),
+    ("timestamp", TIMESTAMP_TYPE_TAG),
+    ("unit", UNIT_TYPE_TAG),
+];
+
+/// Errors occurring when parsing or printing Michelson values.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MichelsonTextError {
+    /// The text is not a Micheline expression, at the given byte offset.
+    #[error("invalid Micheline at offset {0}: {1}")]
+    Syntax(usize, &'static str),
+    /// The primitive is not supported.
+    #[error("unknown primitive {0}")]
+    UnknownPrimitive(String),
+    /// The expression is not a value of the expected type.
+    #[error("expression does not have the expected type")]
+    IllTyped,
+    /// The primitive of the given tag has no concrete syntax.
+    #[error("no concrete syntax for primitive {0}")]
+    UnprintablePrimitive(u8),
+}
+
+/// Michelson values with a concrete syntax.
+pub trait MichelsonText: Michelson {
+    /// Parse a value from its concrete syntax.
+    fn from_text(text: &str) -> Result<Self, MichelsonTextError> {
+        let node = Parser::parse(text)?;
+        Self::from_text_node(node).ok_or(MichelsonTextError::IllTyped)
+    }
+
+    /// Print the value in concrete syntax.
+    fn to_text(&self) -> Result<String, MichelsonTextError> {
+        let mut text = String::new();
+        print(&self.to_text_node(), false, &mut text)?;
+        Ok(text)
+    }
+
+    /// Value of a parsed expression, if it has the right type.
+    #[doc(hidden)]
+    fn from_text_node(node: Node) -> Option<Self>;
+
+    /// Expression to print.
+    #[doc(hidden)]
+    fn to_text_node(&self) -> Node;
+}
+
+impl MichelsonText for MichelsonUnit {
+    fn from_text_node(node: Node) -> Option<Self> {
+        prim_args::<0>(node, UNIT_TAG).map(|[]| MichelsonUnit)
+    }
+
+    fn to_text_node(&self) -> Node {
+        prim(UNIT_TAG, vec![])
+    }
+}
+
+impl MichelsonText for MichelsonInt {
+    fn from_text_node(node: Node) -> Option<Self> {
+        match node {
+            Node::Int(i) => Some(MichelsonInt(i)),
+            _ => None,
+        }
+    }
+
+    fn to_text_node(&self) -> Node {
+        Node::Int(self.0.clone())
+    }
+}
+
+impl MichelsonText for MichelsonNat {
+    fn from_text_node(node: Node) -> Option<Self> {
+        match node {
+            Node::Int(i) => MichelsonNat::new(i),
+            _ => None,
+        }
+    }
+
+    fn to_text_node(&self) -> Node {
+        Node::Int(self.0.clone())
+    }
+}
+
+impl MichelsonText for MichelsonString {
+    fn from_text_node(node: Node) -> Option<Self> {
+        match node {
+            Node::String(s) => Some(MichelsonString(s)),
+            _ => None,
+        }
+    }
+
+    fn to_text_node(&self) -> Node {
+        Node::String(self.0.clone())
+    }
+}
+
+impl MichelsonText for MichelsonBytes {
+    fn from_text_node(node: Node) -> Option<Self> {
+        match node {
+            Node::Bytes(b) => Some(MichelsonBytes(b)),
+            _ => None,
+        }
+    }
+
+    fn to_text_node(&self) -> Node {
+        Node::Bytes(self.0.clone())
+    }
+}
+
+impl MichelsonText for MichelsonContract {
+    fn from_text_node(node: Node) -> Option<Self> {
+        let contract = match node {
+            Node::String(s) => Contract::from_b58check(&s).ok()?,
+            Node::Bytes(b) => match Contract::nom_read(&b) {
+                Ok(([], contract)) => contract,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(MichelsonContract(contract))
+    }
+
+    fn to_text_node(&self) -> Node {
+        Node::String(self.0.to_b58check())
+    }
+}
+
+impl MichelsonText for MichelsonTimestamp {
+    fn from_text_node(node: Node) -> Option<Self> {
+        let seconds = match node {
+            Node::Int(i) => i64::try_from(&i.0).ok()?,
+            Node::String(s) => OffsetDateTime::parse(&s, &Rfc3339).ok()?.unix_timestamp(),
+            _ => return None,
+        };
+        Some(MichelsonTimestamp(seconds.into()))
+    }
+
+    fn to_text_node(&self) -> Node {
+        let seconds = self.0.i64();
+        match OffsetDateTime::from_unix_timestamp(seconds)
+            .ok()
+            .and_then(|t| t.format(&Rfc3339).ok())
+        {
+            Some(rfc_3339) => Node::String(rfc_3339),
+            None => Node::Int(Zarith(seconds.into())),
+        }
+    }
+}
+
+impl<Arg0, Arg1> MichelsonText for MichelsonPair<Arg0, Arg1>
+where
+    Arg0: MichelsonText,
+    Arg1: MichelsonText,
+{
+    fn from_text_node(node: Node) -> Option<Self> {
+        let [arg0, arg1] = prim_args(node, PAIR_TAG)?;
+        Some(MichelsonPair(
+            Arg0::from_text_node(arg0)?,
+            Arg1::from_text_node(arg1)?,
+        ))
+    }
+
+    fn to_text_node(&self) -> Node {
+        prim(PAIR_TAG, vec![self.0.to_text_node(), self.1.to_text_node()])
+    }
+}
+
+impl<Arg0, Arg1> MichelsonText for MichelsonOr<Arg0, Arg1>
+where
+    Arg0: MichelsonText,
+    Arg1: MichelsonText,
+{
+    fn from_text_node(node: Node) -> Option<Self> {
+        match node {
+            Node::Prim { prim_tag, .. } if prim_tag == LEFT_TAG => {
+                let [arg] = prim_args(node, LEFT_TAG)?;
+                Arg0::from_text_node(arg).map(MichelsonOr::Left)
+            }
+            node => {
+                let [arg] = prim_args(node, RIGHT_TAG)?;
+                Arg1::from_text_node(arg).map(MichelsonOr::Right)
+            }
+        }
+    }
+
+    fn to_text_node(&self) -> Node {
+        match self {
+            MichelsonOr::Left(arg) => prim(LEFT_TAG, vec![arg.to_text_node()]),
+            MichelsonOr::Right(arg) => prim(RIGHT_TAG, vec![arg.to_text_node()]),
+        }
+    }
+}
+
+impl<Arg> MichelsonText for MichelsonOption<Arg>
+where
+    Arg: MichelsonText,
+{
+    fn from_text_node(node: Node) -> Option<Self> {
+        match node {
+            Node::Prim { prim_tag, .. } if prim_tag == NONE_TAG => {
+                prim_args::<0>(node, NONE_TAG).map(|[]| MichelsonOption(None))
+            }
+            node => {
+                let [arg] = prim_args(node, SOME_TAG)?;
+                Arg::from_text_node(arg).map(|arg| MichelsonOption(Some(arg)))
+            }
+        }
+    }
+
+    fn to_text_node(&self) -> Node {
+        match &self.0 {
+            None => prim(NONE_TAG, vec![]),
+            Some(arg) => prim(SOME_TAG, vec![arg.to_text_node()]),
+        }
+    }
+}
+
+impl<Expr> MichelsonText for Ticket<Expr>
+where
+    Expr: MichelsonTicketContent + MichelsonText,
+{
+    fn from_text_node(node: Node) -> Option<Self> {
+        let (creator, contents, amount) = match node {
+            Node::Prim { prim_tag, .. } if prim_tag == TICKET_TAG => {
+                let [creator, ty, contents, amount] = prim_args(node, TICKET_TAG)?;
+                if !Expr::typecheck_node(&ty) {
+                    return None;
+                }
+                (creator, contents, amount)
+            }
+            node => {
+                let [creator, rest] = prim_args(node, PAIR_TAG)?;
+                let [contents, amount] = prim_args(rest, PAIR_TAG)?;
+                (creator, contents, amount)
+            }
+        };
+
+        let MichelsonContract(creator) = MichelsonContract::from_text_node(creator)?;
+        let contents = Expr::from_text_node(contents)?;
+        let MichelsonInt(Zarith(amount)) = MichelsonInt::from_text_node(amount)?;
+        Ticket::new(creator, contents, amount).ok()
+    }
+
+    fn to_text_node(&self) -> Node {
+        prim(
+            TICKET_TAG,
+            vec![
+                self.creator().to_text_node(),
+                Expr::node_of_type(),
+                self.contents().to_text_node(),
+                Node::Int(Zarith(self.amount().clone())),
+            ],
+        )
+    }
+}
+
+impl MichelsonText for MichelsonExpr {
+    fn from_text_node(node: Node) -> Option<Self> {
+        let mut bytes = Vec::new();
+        node.bin_write(&mut bytes).ok()?;
+        Some(MichelsonExpr(bytes))
+    }
+
+    fn to_text_node(&self) -> Node {
+        match Node::nom_read(&self.0) {
+            Ok(([], node)) => node,
+            _ => unreachable!("MichelsonExpr always holds a Micheline expression"),
+        }
+    }
+}
+
+fn prim(prim_tag: u8, args: Vec<Node>) -> Node {
+    Node::Prim {
+        prim_tag,
+        args,
+        annots: Annotations::default(),
+    }
+}
+
+/// Arguments of `node`, if it is the primitive `tag` applied to `N`
+/// arguments, without annotations.
+fn prim_args<const N: usize>(node: Node, tag: u8) -> Option<[Node; N]> {
+    match node {
+        Node::Prim {
+            prim_tag,
+            args,
+            annots,
+        } if prim_tag == tag && annots.is_empty() => args.try_into().ok(),
+        _ => None,
+    }
+}
+
+// -------
+// PARSING
+// -------
+
+/// Maximum nesting of parsed expressions, in parentheses, sequences or the
+/// right combs of pairs, so that parsing does not overflow the stack.
+const MAX_DEPTH: usize = 256;
+
+struct Parser<'a> {
+    text: &'a [u8],
+    offset: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(text: &'a str) -> Result<Node, MichelsonTextError> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            offset: 0,
+            depth: 0,
+        };
+        let node = parser.expr()?;
+        match parser.peek() {
+            None => Ok(node),
+            Some(_) => Err(parser.error("expected end of input")),
+        }
+    }
+
+    fn error(&self, reason: &'static str) -> MichelsonTextError {
+        MichelsonTextError::Syntax(self.offset, reason)
+    }
+
+    /// Next character, after whitespace.
+    fn peek(&mut self) -> Option<u8> {
+        while self
+            .text
+            .get(self.offset)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.offset += 1;
+        }
+        self.text.get(self.offset).copied()
+    }
+
+    /// Enter a nested expression, which is left by decreasing the depth.
+    fn nest(&mut self) -> Result<(), MichelsonTextError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("expression nested too deeply"));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn expect(&mut self, c: u8, reason: &'static str) -> Result<(), MichelsonTextError> {
+        if self.peek() != Some(c) {
+            return Err(self.error(reason));
+        }
+        self.offset += 1;
+        Ok(())
+    }
+
+    /// Expression, in which primitives are applied to the following
+    /// arguments.
+    fn expr(&mut self) -> Result<Node, MichelsonTextError> {
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() => {
+                let (prim_tag, annots) = self.prim()?;
+                let is_pair = prim_tag == PAIR_TAG || prim_tag == PAIR_TYPE_TAG;
+                let depth = self.depth;
+                let mut args = Vec::new();
+                while !matches!(self.peek(), None | Some(b')' | b'}' | b';')) {
+                    // Arguments after the second one are nested in right combs.
+                    if is_pair && args.len() >= 2 {
+                        self.nest()?;
+                    }
+                    args.push(self.atom()?);
+                }
+                self.depth = depth;
+                Ok(prim_node(prim_tag, args, annots))
+            }
+            _ => self.atom(),
+        }
+    }
+
+    /// Expression without arguments, unless in parentheses.
+    fn atom(&mut self) -> Result<Node, MichelsonTextError> {
+        match self.peek() {
+            Some(b'(') => {
+                self.nest()?;
+                self.offset += 1;
+                let node = self.expr()?;
+                self.expect(b')', "expected `)`")?;
+                self.depth -= 1;
+                Ok(node)
+            }
+            Some(b'{') => self.seq(),
+            Some(b'"') => self.string(),
+            Some(b'0') if self.text.get(self.offset + 1) == Some(&b'x') => self.bytes(),
+            Some(c) if c == b'-' || c.is_ascii_digit() => self.int(),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let (prim_tag, annots) = self.prim()?;
+                Ok(prim_node(prim_tag, vec![], annots))
+            }
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn token(&mut self, is_token_char: impl Fn(u8) -> bool) -> &'a [u8] {
+        let start = self.offset;
+        while self
+            .text
+            .get(self.offset)
+            .is_some_and(|&c| is_token_char(c))
+        {
+            self.offset += 1;
+        }
+        &self.text[start..self.offset]
+    }
+
+    fn prim(&mut self) -> Result<(u8, Annotations), MichelsonTextError> {
+        let name = self.token(|c| c.is_ascii_alphanumeric() || c == b'_');
+        let name = String::from_utf8_lossy(name);
+        let prim_tag = PRIMITIVES
+            .iter()
+            .find_map(|(prim_name, tag)| (*prim_name == name).then_some(*tag))
+            .ok_or_else(|| MichelsonTextError::UnknownPrimitive(name.into_owned()))?;
+
+        let mut annots = Vec::new();
+        while let Some(b'%' | b'@' | b':') = self.peek() {
+            let annot = self.token(|c| {
+                !c.is_ascii_whitespace() && !matches!(c, b'(' | b')' | b'{' | b'}' | b';')
+            });
+            let annot = Annotation::new(&String::from_utf8_lossy(annot))
+                .ok_or_else(|| self.error("invalid annotation"))?;
+            annots.push(annot);
+        }
+
+        Ok((prim_tag, Annotations(annots)))
+    }
+
+    fn seq(&mut self) -> Result<Node, MichelsonTextError> {
+        self.nest()?;
+        self.expect(b'{', "expected `{`")?;
+        let mut nodes = Vec::new();
+        loop {
+            if self.peek() == Some(b'}') {
+                self.offset += 1;
+                self.depth -= 1;
+                return Ok(Node::Seq(nodes));
+            }
+            nodes.push(self.expr()?);
+            match self.peek() {
+                Some(b';') => self.offset += 1,
+                Some(b'}') => (),
+                _ => return Err(self.error("expected `;` or `}`")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<Node, MichelsonTextError> {
+        self.expect(b'"', "expected `\"`")?;
+        let mut bytes = Vec::new();
+        loop {
+            let c = *self
+                .text
+                .get(self.offset)
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.offset += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.text.get(self.offset) {
+                        Some(b'n') => b'\n',
+                        Some(b'r') => b'\r',
+                        Some(b't') => b'\t',
+                        Some(b'\\') => b'\\',
+                        Some(b'"') => b'"',
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    self.offset += 1;
+                    bytes.push(escaped);
+                }
+                b'\n' => return Err(self.error("unterminated string")),
+                c => bytes.push(c),
+            }
+        }
+        // Only ASCII characters were replaced, the string is still UTF-8.
+        String::from_utf8(bytes)
+            .map(Node::String)
+            .map_err(|_| self.error("invalid string"))
+    }
+
+    fn bytes(&mut self) -> Result<Node, MichelsonTextError> {
+        self.offset += 2;
+        let digits = self.token(|c| c.is_ascii_hexdigit());
+        hex::decode(digits)
+            .map(Node::Bytes)
+            .map_err(|_| self.error("odd number of hex digits"))
+    }
+
+    fn int(&mut self) -> Result<Node, MichelsonTextError> {
+        let start = self.offset;
+        if self.text[start] == b'-' {
+            self.offset += 1;
+        }
+        if self.token(|c| c.is_ascii_digit()).is_empty() {
+            return Err(self.error("expected digits"));
+        }
+        let digits = String::from_utf8_lossy(&self.text[start..self.offset]);
+        BigInt::from_str(&digits)
+            .map(|i| Node::Int(Zarith(i)))
+            .map_err(|_| self.error("invalid int"))
+    }
+}
+
+/// Application of a primitive, where pairs of more than two arguments are
+/// right combs: `Pair a b c` is `Pair a (Pair b c)`.
+fn prim_node(prim_tag: u8, mut args: Vec<Node>, annots: Annotations) -> Node {
+    if (prim_tag == PAIR_TAG || prim_tag == PAIR_TYPE_TAG) && args.len() > 2 {
+        let rest = args.split_off(1);
+        args.push(prim_node(prim_tag, rest, Annotations::default()));
+    }
+    Node::Prim {
+        prim_tag,
+        args,
+        annots,
+    }
+}
+
+// --------
+// PRINTING
+// --------
+
+fn print(node: &Node, nested: bool, out: &mut String) -> Result<(), MichelsonTextError> {
+    match node {
+        Node::Int(i) => out.push_str(&i.0.to_string()),
+        Node::String(s) => print_string(s, out),
+        Node::Bytes(b) => {
+            out.push_str("0x");
+            out.push_str(&hex::encode(b));
+        }
+        Node::Seq(nodes) => {
+            out.push('{');
+            for (i, node) in nodes.iter().enumerate() {
+                out.push_str(if i == 0 { " " } else { " ; " });
+                print(node, false, out)?;
+            }
+            out.push_str(if nodes.is_empty() { "}" } else { " }" });
+        }
+        Node::Prim {
+            prim_tag,
+            args,
+            annots,
+        } => {
+            let parens = nested && !(args.is_empty() && annots.is_empty());
+            if parens {
+                out.push('(');
+            }
+            let name = PRIMITIVES
+                .iter()
+                .find_map(|(name, tag)| (tag == prim_tag).then_some(*name))
+                .ok_or(MichelsonTextError::UnprintablePrimitive(*prim_tag))?;
+            out.push_str(name);
+            if !annots.is_empty() {
+                out.push(' ');
+                out.push_str(&annots.to_string());
+            }
+            for arg in args {
+                out.push(' ');
+                print(arg, true, out)?;
+            }
+            if parens {
+                out.push(')');
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::michelson::ticket::{StringTicket, UnitTicket};
+
+    const KT1: &str = "KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesc";
+
+    fn contract() -> MichelsonContract {
+        MichelsonContract(Contract::from_b58check(KT1).unwrap())
+    }
+
+    /// Check that `text` parses as `value`, which prints as `printed`.
+    fn check<T: MichelsonText>(text: &str, value: T, printed: &str) {
+        assert_eq!(Ok(&value), T::from_text(text).as_ref());
+        assert_eq!(Ok(printed.to_owned()), value.to_text());
+        assert_eq!(Ok(value), T::from_text(printed));
+    }
+
+    #[test]
+    fn primitive_values() {
+        check("Unit", MichelsonUnit, "Unit");
+        check(" -12 ", MichelsonInt::from(-12), "-12");
+        check("12", MichelsonNat::from(12), "12");
+        check(
+            r#""a \"quoted\"\n\\ string""#,
+            MichelsonString("a \"quoted\"\n\\ string".into()),
+            r#""a \"quoted\"\n\\ string""#,
+        );
+        check("0x00Ff", MichelsonBytes(vec![0, 255]), "0x00ff");
+        check(&format!("\"{KT1}\""), contract(), &format!("\"{KT1}\""));
+        check(
+            "1704067200",
+            MichelsonTimestamp(1704067200.into()),
+            "\"2024-01-01T00:00:00Z\"",
+        );
+        check(
+            "\"2024-01-01T01:00:00+01:00\"",
+            MichelsonTimestamp(1704067200.into()),
+            "\"2024-01-01T00:00:00Z\"",
+        );
+
+        assert_eq!(
+            Err(MichelsonTextError::IllTyped),
+            MichelsonNat::from_text("-1")
+        );
+        assert_eq!(
+            Err(MichelsonTextError::IllTyped),
+            MichelsonContract::from_text("\"KT1\"")
+        );
+    }
+
+    #[test]
+    fn optimized_address() {
+        let mut bytes = Vec::new();
+        contract().0.bin_write(&mut bytes).unwrap();
+        let text = format!("0x{}", hex::encode(bytes));
+        assert_eq!(Ok(contract()), MichelsonContract::from_text(&text));
+    }
+
+    #[test]
+    fn composite_values() {
+        type Transfer = MichelsonPair<MichelsonContract, MichelsonNat>;
+        check::<Transfer>(
+            &format!("Pair \"{KT1}\" 12"),
+            MichelsonPair(contract(), 12.into()),
+            &format!("Pair \"{KT1}\" 12"),
+        );
+
+        type Comb =
+            MichelsonPair<MichelsonInt, MichelsonPair<MichelsonInt, MichelsonInt>>;
+        check::<Comb>(
+            "Pair 1 2 3",
+            MichelsonPair(1.into(), MichelsonPair(2.into(), 3.into())),
+            "Pair 1 (Pair 2 3)",
+        );
+
+        type Parameter = MichelsonOr<
+            MichelsonOption<MichelsonString>,
+            MichelsonOr<MichelsonUnit, MichelsonBytes>,
+        >;
+        check(
+            "Left (Some \"a\")",
+            Parameter::Left(MichelsonOption(Some(MichelsonString("a".into())))),
+            "Left (Some \"a\")",
+        );
+        check(
+            "Right ( Left Unit )",
+            Parameter::Right(MichelsonOr::Left(MichelsonUnit)),
+            "Right (Left Unit)",
+        );
+        check(
+            "(Left None)",
+            Parameter::Left(MichelsonOption(None)),
+            "Left None",
+        );
+
+        assert_eq!(
+            Err(MichelsonTextError::IllTyped),
+            Parameter::from_text("Right (Right \"a\")")
+        );
+    }
+
+    #[test]
+    fn tickets() {
+        let ticket =
+            StringTicket::new(contract().0, MichelsonString("a".into()), 10).unwrap();
+        let printed = format!("Ticket \"{KT1}\" string \"a\" 10");
+
+        assert_eq!(Ok(printed.clone()), ticket.to_text());
+        assert_eq!(Ok(&ticket), StringTicket::from_text(&printed).as_ref());
+        assert_eq!(
+            Ok(&ticket),
+            StringTicket::from_text(&format!("Pair \"{KT1}\" \"a\" 10")).as_ref()
+        );
+
+        // The type of the contents is checked
+        assert_eq!(
+            Err(MichelsonTextError::IllTyped),
+            StringTicket::from_text(&format!("Ticket \"{KT1}\" bytes \"a\" 10"))
+        );
+        // Tickets have a positive amount
+        assert_eq!(
+            Err(MichelsonTextError::IllTyped),
+            UnitTicket::from_text(&format!("Ticket \"{KT1}\" unit Unit 0"))
+        );
+    }
+
+    #[test]
+    fn parsed_value_is_encoded() {
+        type Transfer = MichelsonPair<MichelsonContract, MichelsonNat>;
+        let parsed = Transfer::from_text(&format!("Pair \"{KT1}\" 12")).unwrap();
+
+        let mut expected = Vec::new();
+        MichelsonPair(contract(), MichelsonNat::from(12))
+            .bin_write(&mut expected)
+            .unwrap();
+        let mut encoded = Vec::new();
+        parsed.bin_write(&mut encoded).unwrap();
+        assert_eq!(expected, encoded);
+
+        // Timestamps are encoded as ints
+        let parsed = MichelsonTimestamp::from_text("\"2024-01-01T00:00:00Z\"").unwrap();
+        let encoded = MichelsonExpr::encode(&parsed).unwrap();
+        assert_eq!(Some(MichelsonInt::from(1704067200)), encoded.decode());
+        assert_eq!(Some(parsed), encoded.decode());
+    }
+
+    #[test]
+    fn untyped_expressions() {
+        let typed = MichelsonPair(
+            MichelsonInt::from(1),
+            MichelsonPair(MichelsonString("a".into()), MichelsonBytes(vec![0])),
+        );
+        check(
+            "Pair 1 \"a\" 0x00",
+            MichelsonExpr::encode(&typed).unwrap(),
+            "Pair 1 (Pair \"a\" 0x00)",
+        );
+
+        // Annotations, sequences & types have no typed counterpart
+        let text = "{ Left %a Unit ; pair :t (option int) nat ; {} }";
+        let expr = MichelsonExpr::from_text(text).unwrap();
+        assert_eq!(Ok(text.to_owned()), expr.to_text());
+
+        let ill_typed = MichelsonExpr::from_text("Some 1 2").unwrap();
+        assert_eq!(Ok("Some 1 2".to_owned()), ill_typed.to_text());
+        assert_eq!(None, ill_typed.decode::<MichelsonOption<MichelsonInt>>());
+    }
+
+    #[test]
+    fn unprintable_primitive() {
+        // `DUP` has no concrete syntax here: printing it fails rather than
+        // giving text that cannot be parsed back.
+        let dup = MichelsonExpr::prim_type(33);
+        assert_eq!(
+            Err(MichelsonTextError::UnprintablePrimitive(33)),
+            dup.to_text()
+        );
+
+        let expr = MichelsonExpr::prim_1_arg_type(SOME_TAG, &dup);
+        assert_eq!(
+            Err(MichelsonTextError::UnprintablePrimitive(33)),
+            expr.to_text()
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(
+            Err(MichelsonTextError::UnknownPrimitive("Pear".into())),
+            MichelsonUnit::from_text("Pear 1 2")
+        );
+        assert_eq!(
+            Err(MichelsonTextError::Syntax(7, "expected `)`")),
+            MichelsonInt::from_text("(Some 1")
+        );
+        assert_eq!(
+            Err(MichelsonTextError::Syntax(5, "unterminated string")),
+            MichelsonString::from_text("\"abcd")
+        );
+        assert_eq!(
+            Err(MichelsonTextError::Syntax(5, "odd number of hex digits")),
+            MichelsonBytes::from_text("0x123")
+        );
+        assert_eq!(
+            Err(MichelsonTextError::Syntax(2, "expected end of input")),
+            MichelsonInt::from_text("1 2")
+        );
+    }
+
+    #[test]
+    fn nesting_is_bounded() {
+        let nested = |depth: usize| "(".repeat(depth) + "1" + &")".repeat(depth);
+        assert_eq!(
+            Ok(MichelsonInt::from(1)),
+            MichelsonInt::from_text(&nested(256))
+        );
+        assert_eq!(
+            Err(MichelsonTextError::Syntax(
+                256,
+                "expression nested too deeply"
+            )),
+            MichelsonInt::from_text(&nested(100_000))
+        );
+
+        let seqs = "{".repeat(100_000) + &"}".repeat(100_000);
+        assert_eq!(
+            Err(MichelsonTextError::Syntax(
+                256,
+                "expression nested too deeply"
+            )),
+            MichelsonUnit::from_text(&seqs)
+        );
+
+        let pairs = |len: usize| "Pair".to_owned() + &" 1".repeat(len);
+        assert_eq!(
+            Err(MichelsonTextError::IllTyped),
+            MichelsonUnit::from_text(&pairs(200))
+        );
+        assert!(matches!(
+            MichelsonUnit::from_text(&pairs(100_000)),
+            Err(MichelsonTextError::Syntax(
+                _,
+                "expression nested too deeply"
+            ))
+        ));
+    }
+}
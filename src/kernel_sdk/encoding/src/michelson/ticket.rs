@@ -8,13 +8,14 @@
 
 use super::{
     micheline::{annots::Annotations, Node},
-    MichelsonTicketContent, TICKET_TAG,
+    MichelsonTicketContent, TICKET_TAG, TICKET_TYPE_TAG,
 };
 use crate::{
     contract::Contract,
     michelson::{
-        Michelson, MichelsonBytes, MichelsonContract, MichelsonInt, MichelsonNat,
-        MichelsonOption, MichelsonPair, MichelsonString, MichelsonUnit,
+        Michelson, MichelsonBytes, MichelsonContract, MichelsonExpr, MichelsonInt,
+        MichelsonNat, MichelsonOption, MichelsonPair, MichelsonString, MichelsonType,
+        MichelsonUnit,
     },
 };
 use core::{
@@ -255,6 +256,15 @@ pub struct Ticket<Expr: MichelsonTicketContent>(pub(crate) LegacyTicketRepr<Expr
 
 impl<Expr> Michelson for Ticket<Expr> where Expr: MichelsonTicketContent {}
 
+impl<Expr> MichelsonType for Ticket<Expr>
+where
+    Expr: MichelsonTicketContent + MichelsonType,
+{
+    fn type_expr() -> MichelsonExpr {
+        MichelsonExpr::prim_1_arg_type(TICKET_TYPE_TAG, &Expr::type_expr())
+    }
+}
+
 impl<Expr> NomReader for Ticket<Expr>
 where
     Expr: MichelsonTicketContent,
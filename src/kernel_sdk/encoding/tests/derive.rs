@@ -0,0 +1,177 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Michelson encodings derived with `#[derive(Michelson)]`.
+#![cfg(feature = "derive")]
+
+use tezos_smart_rollup_encoding::contract::Contract;
+use tezos_smart_rollup_encoding::michelson::derive::{
+    field_annotated, or_type, pair_type,
+};
+use tezos_smart_rollup_encoding::michelson::{
+    Michelson, MichelsonBytes, MichelsonContract, MichelsonExpr, MichelsonNat,
+    MichelsonOr, MichelsonPair, MichelsonString, MichelsonType, MichelsonUnit,
+};
+
+#[derive(Debug, PartialEq, Eq, Michelson)]
+struct Transfer {
+    to: MichelsonContract,
+    amount: MichelsonNat,
+    #[michelson(annot = "data")]
+    payload: MichelsonBytes,
+}
+
+#[derive(Debug, PartialEq, Eq, Michelson)]
+struct Destination(MichelsonContract, MichelsonNat);
+
+#[derive(Debug, PartialEq, Eq, Michelson)]
+enum Parameter {
+    Default,
+    Transfer(Transfer),
+    SetName {
+        name: MichelsonString,
+    },
+    #[michelson(annot = "pair")]
+    Tuple(MichelsonNat, MichelsonString),
+}
+
+#[derive(Debug, PartialEq, Eq, Michelson)]
+struct Wrapper<T> {
+    inner: T,
+    count: MichelsonNat,
+}
+
+fn contract() -> MichelsonContract {
+    MichelsonContract(
+        Contract::from_b58check("KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesc").unwrap(),
+    )
+}
+
+/// Check that `value` is encoded as `expected`, and decoded back.
+fn assert_encoding<T: Michelson>(value: T, expected: impl Michelson) {
+    let expected = MichelsonExpr::encode(&expected).unwrap();
+
+    let mut bytes = Vec::new();
+    value.bin_write(&mut bytes).unwrap();
+    assert_eq!(expected.as_bytes(), bytes);
+
+    let (remaining, decoded) = T::nom_read(&bytes).unwrap();
+    assert!(remaining.is_empty());
+    assert_eq!(value, decoded);
+}
+
+#[test]
+fn struct_is_right_comb_of_pairs() {
+    assert_encoding(
+        Transfer {
+            to: contract(),
+            amount: 5.into(),
+            payload: vec![1, 2].into(),
+        },
+        MichelsonPair(
+            contract(),
+            MichelsonPair(MichelsonNat::from(5), MichelsonBytes(vec![1, 2])),
+        ),
+    );
+    assert_encoding(
+        Destination(contract(), 1.into()),
+        MichelsonPair(contract(), MichelsonNat::from(1)),
+    );
+}
+
+#[test]
+fn enum_is_right_comb_of_ors() {
+    type Encoding = MichelsonOr<
+        MichelsonUnit,
+        MichelsonOr<
+            MichelsonPair<MichelsonContract, MichelsonPair<MichelsonNat, MichelsonBytes>>,
+            MichelsonOr<MichelsonString, MichelsonPair<MichelsonNat, MichelsonString>>,
+        >,
+    >;
+
+    assert_encoding(Parameter::Default, Encoding::Left(MichelsonUnit));
+    assert_encoding(
+        Parameter::Transfer(Transfer {
+            to: contract(),
+            amount: 1.into(),
+            payload: vec![].into(),
+        }),
+        Encoding::Right(MichelsonOr::Left(MichelsonPair(
+            contract(),
+            MichelsonPair(MichelsonNat::from(1), MichelsonBytes(vec![])),
+        ))),
+    );
+    assert_encoding(
+        Parameter::SetName {
+            name: "kernel".to_string().into(),
+        },
+        Encoding::Right(MichelsonOr::Right(MichelsonOr::Left(MichelsonString(
+            "kernel".to_string(),
+        )))),
+    );
+    assert_encoding(
+        Parameter::Tuple(2.into(), "two".to_string().into()),
+        Encoding::Right(MichelsonOr::Right(MichelsonOr::Right(MichelsonPair(
+            MichelsonNat::from(2),
+            MichelsonString("two".to_string()),
+        )))),
+    );
+}
+
+#[test]
+fn generic_struct() {
+    assert_encoding(
+        Wrapper {
+            inner: MichelsonUnit,
+            count: 3.into(),
+        },
+        MichelsonPair(MichelsonUnit, MichelsonNat::from(3)),
+    );
+    assert_eq!(
+        pair_type(
+            &field_annotated(MichelsonString::type_expr(), "inner"),
+            &field_annotated(MichelsonNat::type_expr(), "count"),
+        ),
+        Wrapper::<MichelsonString>::type_expr()
+    );
+}
+
+#[test]
+fn type_expressions() {
+    // pair (address %to) (pair (nat %amount) (bytes %data))
+    assert_eq!(
+        concat!(
+            "0765",
+            "046e0000000325746f",
+            "0765",
+            "04620000000725616d6f756e74",
+            "0469000000052564617461"
+        ),
+        hex::encode(Transfer::type_expr().as_bytes())
+    );
+
+    // Fields of tuple structs are not annotated
+    assert_eq!(
+        MichelsonPair::<MichelsonContract, MichelsonNat>::type_expr(),
+        Destination::type_expr()
+    );
+
+    // or (unit %default)
+    //    (or (pair %transfer ...)
+    //        (or (string %set_name) (pair %pair nat string)))
+    let expected = or_type(
+        &field_annotated(MichelsonUnit::type_expr(), "default"),
+        &or_type(
+            &field_annotated(Transfer::type_expr(), "transfer"),
+            &or_type(
+                &field_annotated(MichelsonString::type_expr(), "set_name"),
+                &field_annotated(
+                    MichelsonPair::<MichelsonNat, MichelsonString>::type_expr(),
+                    "pair",
+                ),
+            ),
+        ),
+    );
+    assert_eq!(expected, Parameter::type_expr());
+}
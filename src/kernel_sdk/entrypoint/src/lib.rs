@@ -53,6 +53,7 @@ macro_rules! kernel_entry {
         #[no_mangle]
         pub extern "C" fn kernel_run() {
             $crate::set_panic_hook();
+            $crate::start_log_run();
             use $crate::RollupHost;
             let mut host = unsafe { RollupHost::new() };
             $kernel_run(&mut host)
@@ -65,6 +66,7 @@ macro_rules! kernel_entry {
             let mut host = unsafe { RollupHost::new() };
             loop {
                 // TODO #6727: Capture and recover panics.
+                $crate::start_log_run();
                 $kernel_run(&mut host);
             }
         }
@@ -77,6 +79,9 @@ pub use tezos_smart_rollup_core::rollup_host::RollupHost;
 
 pub(crate) mod host;
 
+#[doc(hidden)]
+pub use tezos_smart_rollup_debug::log::start_run as start_log_run;
+
 #[doc(hidden)]
 #[cfg(feature = "experimental-host-in-memory-store")]
 pub use host::RollupHostWithInMemoryStorage as RollupHost;
@@ -52,3 +52,20 @@ impl TryFrom<[u8; DAL_PARAMETERS_SIZE]> for RollupDalParameters {
         })
     }
 }
+
+impl From<RollupDalParameters> for [u8; DAL_PARAMETERS_SIZE] {
+    fn from(value: RollupDalParameters) -> [u8; DAL_PARAMETERS_SIZE] {
+        let fields = [
+            value.number_of_slots,
+            value.attestation_lag,
+            value.slot_size,
+            value.page_size,
+        ];
+
+        let mut data = [0; DAL_PARAMETERS_SIZE];
+        for (chunk, field) in data.chunks_exact_mut(8).zip(fields) {
+            chunk.copy_from_slice(&(field as i64).to_be_bytes());
+        }
+        data
+    }
+}
@@ -0,0 +1,380 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Derive macro for the Michelson encoding of Rust types.
+//!
+//! `#[derive(Michelson)]` implements `HasEncoding`, `NomReader`, `BinWriter`,
+//! `Michelson` & `MichelsonType` for:
+//!
+//! - structs, as a right-comb of pairs of their fields: `struct S { a: A, b:
+//!   B, c: C }` has type `pair (A %a) (pair (B %b) (C %c))`,
+//! - enums, as a right-comb of ors of their variants: `enum E { X(A), Y(B),
+//!   Z(C) }` has type `or (A %x) (or (B %y) (C %z))`.
+//!
+//! Structs & variants with no fields are `unit`, and with a single field are
+//! that field. The fields & variants must themselves implement `Michelson`
+//! (and `MichelsonType` for the type expression), and the derived type
+//! `Debug`, `PartialEq` & `Eq`.
+//!
+//! Named fields are annotated with their name, and variants with their name
+//! in snake case. `#[michelson(annot = "name")]` overrides the annotation of
+//! a field or variant. Fields of tuple structs & variants are not annotated
+//! by default, and neither are single-field payloads, whose annotation is the
+//! one of their variant.
+//!
+//! The generated code refers to `::tezos_smart_rollup_encoding`; kernels
+//! using the encodings re-exported by another crate give its path with
+//! `#[michelson(crate = "tezos_smart_rollup")]`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Fields,
+    GenericParam, Generics, Ident, LitStr, Path, Type,
+};
+
+/// Derive the Michelson encoding of a struct or enum.
+#[proc_macro_derive(Michelson, attributes(michelson))]
+pub fn derive_michelson(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// A field of a struct or variant.
+struct Field {
+    ty: Type,
+    annot: Option<String>,
+}
+
+/// Fields of a struct or variant, with the bindings used to destructure them.
+struct Payload {
+    fields: Vec<Field>,
+    bindings: Vec<Ident>,
+    shape: TokenStream2,
+}
+
+/// Attributes given with `#[michelson(...)]`.
+#[derive(Default)]
+struct Attrs {
+    annot: Option<String>,
+    krate: Option<Path>,
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let attrs = parse_attrs(&input.attrs)?;
+    if let Some(annot) = attrs.annot {
+        return Err(Error::new_spanned(
+            &input.ident,
+            format!("unexpected annotation `{annot}` on a type"),
+        ));
+    }
+
+    let krate = attrs
+        .krate
+        .unwrap_or_else(|| parse_quote!(::tezos_smart_rollup_encoding));
+    let m = quote!(#krate::michelson::derive);
+
+    let (write, read, type_expr) = match &input.data {
+        Data::Struct(data) => {
+            let payload = payload(&data.fields)?;
+            let pattern = payload.pattern(quote!(Self));
+            let write = payload.write(&m);
+            let read = payload.read(&m);
+            let value = payload.pattern(quote!(Self));
+            let type_expr = payload.type_expr(&m, None);
+            (
+                quote! {
+                    let #pattern = self;
+                    #write
+                    Ok(())
+                },
+                quote! {
+                    #read
+                    Ok((input, #value))
+                },
+                type_expr,
+            )
+        }
+        Data::Enum(data) => {
+            if data.variants.is_empty() {
+                return Err(Error::new_spanned(
+                    name,
+                    "cannot derive Michelson for an enum without variants",
+                ));
+            }
+
+            let mut variants = Vec::new();
+            for variant in &data.variants {
+                let annot = parse_attrs(&variant.attrs)?
+                    .annot
+                    .unwrap_or_else(|| snake_case(&variant.ident.to_string()));
+                let ident = &variant.ident;
+                variants.push((quote!(Self::#ident), payload(&variant.fields)?, annot));
+            }
+            expand_enum(&m, &variants)
+        }
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                name,
+                "cannot derive Michelson for a union",
+            ))
+        }
+    };
+
+    let generics = add_bound(&input.generics, quote!(#m::Michelson));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let type_generics = add_bound(&input.generics, quote!(#m::MichelsonType));
+    let (type_impl_generics, _, type_where_clause) = type_generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #m::HasEncoding for #name #ty_generics #where_clause {
+            fn encoding() -> #m::Encoding {
+                #m::Encoding::Custom
+            }
+        }
+
+        impl #impl_generics #m::NomReader for #name #ty_generics #where_clause {
+            fn nom_read(input: &[u8]) -> #m::NomResult<Self> {
+                #read
+            }
+        }
+
+        impl #impl_generics #m::BinWriter for #name #ty_generics #where_clause {
+            fn bin_write(&self, output: &mut Vec<u8>) -> #m::BinResult {
+                #write
+            }
+        }
+
+        impl #impl_generics #m::Michelson for #name #ty_generics #where_clause {}
+
+        impl #type_impl_generics #m::MichelsonType for #name #ty_generics
+            #type_where_clause
+        {
+            fn type_expr() -> #m::MichelsonExpr {
+                #type_expr
+            }
+        }
+    })
+}
+
+/// Variant `i` of `n` is `Right` `i` times, then `Left` unless it is the last
+/// one.
+fn expand_enum(
+    m: &TokenStream2,
+    variants: &[(TokenStream2, Payload, String)],
+) -> (TokenStream2, TokenStream2, TokenStream2) {
+    let last = variants.len() - 1;
+
+    let arms = variants.iter().enumerate().map(|(i, (path, payload, _))| {
+        let pattern = payload.pattern(path.clone());
+        let rights = (0..i).map(|_| quote!(#m::bin_write_right_prefix(output)?;));
+        let left = (i != last).then(|| quote!(#m::bin_write_left_prefix(output)?;));
+        let write = payload.write(m);
+        quote! {
+            #pattern => {
+                #(#rights)*
+                #left
+                #write
+            }
+        }
+    });
+    let write = quote! {
+        match self {
+            #(#arms)*
+        }
+        Ok(())
+    };
+
+    let cases = variants.iter().enumerate().map(|(i, (path, payload, _))| {
+        let read = payload.read(m);
+        let value = payload.pattern(path.clone());
+        if i == last {
+            quote! {
+                #read
+                Ok((input, #value))
+            }
+        } else {
+            quote! {
+                if let Ok((input, ())) = #m::nom_read_left_prefix(input) {
+                    #read
+                    return Ok((input, #value));
+                }
+                let (input, ()) = #m::nom_read_right_prefix(input)?;
+            }
+        }
+    });
+    let read = quote!(#(#cases)*);
+
+    let type_expr = variants
+        .iter()
+        .rev()
+        .map(|(_, payload, annot)| payload.type_expr(m, Some(annot)))
+        .reduce(|right, left| quote!(#m::or_type(&#left, &#right)))
+        .expect("enums have at least one variant");
+
+    (write, read, type_expr)
+}
+
+impl Payload {
+    /// Pattern destructuring the fields into the bindings, or expression
+    /// building the value from them.
+    fn pattern(&self, path: TokenStream2) -> TokenStream2 {
+        let shape = &self.shape;
+        quote!(#path #shape)
+    }
+
+    fn write(&self, m: &TokenStream2) -> TokenStream2 {
+        let Some((last, init)) = self.bindings.split_last() else {
+            return quote!(#m::bin_write_unit(output)?;);
+        };
+        quote! {
+            #(
+                #m::bin_write_pair_prefix(output)?;
+                #m::BinWriter::bin_write(#init, output)?;
+            )*
+            #m::BinWriter::bin_write(#last, output)?;
+        }
+    }
+
+    fn read(&self, m: &TokenStream2) -> TokenStream2 {
+        let Some((last, init)) = self.bindings.split_last() else {
+            return quote!(let (input, ()) = #m::nom_read_unit(input)?;);
+        };
+        let types = self.fields.iter().map(|field| &field.ty);
+        let init_types = types.clone().take(init.len());
+        let last_type = types.last();
+        quote! {
+            #(
+                let (input, ()) = #m::nom_read_pair_prefix(input)?;
+                let (input, #init) = <#init_types as #m::NomReader>::nom_read(input)?;
+            )*
+            let (input, #last) = <#last_type as #m::NomReader>::nom_read(input)?;
+        }
+    }
+
+    fn type_expr(&self, m: &TokenStream2, annot: Option<&String>) -> TokenStream2 {
+        let ty = match self.fields.as_slice() {
+            [] => quote!(#m::unit_type()),
+            [field] => {
+                let ty = &field.ty;
+                quote!(<#ty as #m::MichelsonType>::type_expr())
+            }
+            fields => fields
+                .iter()
+                .rev()
+                .map(|field| {
+                    let ty = &field.ty;
+                    let ty = quote!(<#ty as #m::MichelsonType>::type_expr());
+                    annotated(m, ty, field.annot.as_ref())
+                })
+                .reduce(|right, left| quote!(#m::pair_type(&#left, &#right)))
+                .expect("fields is not empty"),
+        };
+        annotated(m, ty, annot)
+    }
+}
+
+fn annotated(m: &TokenStream2, ty: TokenStream2, annot: Option<&String>) -> TokenStream2 {
+    match annot {
+        Some(annot) => quote!(#m::field_annotated(#ty, #annot)),
+        None => ty,
+    }
+}
+
+fn payload(fields: &Fields) -> Result<Payload, Error> {
+    let mut payload = Payload {
+        fields: Vec::new(),
+        bindings: Vec::new(),
+        shape: TokenStream2::new(),
+    };
+
+    for (i, field) in fields.iter().enumerate() {
+        let default_annot = field
+            .ident
+            .as_ref()
+            .map(|ident| ident.to_string().trim_start_matches("r#").to_string());
+        payload.fields.push(Field {
+            ty: field.ty.clone(),
+            annot: parse_attrs(&field.attrs)?.annot.or(default_annot),
+        });
+        payload.bindings.push(format_ident!("__field{}", i));
+    }
+
+    let bindings = &payload.bindings;
+    payload.shape = match fields {
+        Fields::Named(fields) => {
+            let names = fields.named.iter().map(|field| &field.ident);
+            quote!({ #(#names: #bindings),* })
+        }
+        Fields::Unnamed(_) => quote!((#(#bindings),*)),
+        Fields::Unit => TokenStream2::new(),
+    };
+
+    Ok(payload)
+}
+
+fn parse_attrs(attrs: &[Attribute]) -> Result<Attrs, Error> {
+    let mut result = Attrs::default();
+
+    for attr in attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("michelson"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("annot") {
+                let annot: LitStr = meta.value()?.parse()?;
+                if !is_valid_annot(&annot.value()) {
+                    return Err(
+                        meta.error(format!("invalid annotation `%{}`", annot.value()))
+                    );
+                }
+                result.annot = Some(annot.value());
+                Ok(())
+            } else if meta.path.is_ident("crate") {
+                let krate: LitStr = meta.value()?.parse()?;
+                result.krate = Some(krate.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `annot` or `crate`"))
+            }
+        })?;
+    }
+
+    Ok(result)
+}
+
+/// Whether `%name` is a valid field annotation.
+fn is_valid_annot(name: &str) -> bool {
+    name.chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '%' | '@'))
+}
+
+fn snake_case(name: &str) -> String {
+    let mut result = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                result.push('_');
+            }
+            result.push(c.to_ascii_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+fn add_bound(generics: &Generics, bound: TokenStream2) -> Generics {
+    let mut generics = generics.clone();
+    for param in &mut generics.params {
+        if let GenericParam::Type(param) = param {
+            param.bounds.push(parse_quote!(#bound));
+        }
+    }
+    generics
+}
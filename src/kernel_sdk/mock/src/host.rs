@@ -9,6 +9,7 @@
 //! _not_ compiling to **wasm**.
 
 use crate::state::{HostState, NextInput};
+use crate::ticks::HostFunction;
 use crate::MockHost;
 use core::{
     cell::RefCell,
@@ -25,6 +26,9 @@ impl From<HostState> for MockHost {
         Self {
             info: super::info_for_level(state.curr_level as i32),
             state: RefCell::new(state),
+            ticks: Default::default(),
+            logs: Default::default(),
+            l1: None,
         }
     }
 }
@@ -55,21 +59,23 @@ unsafe impl SmartRollupCore for MockHost {
             let slice = from_raw_parts_mut(dst, payload.len());
             slice.copy_from_slice(payload.as_slice());
 
+            self.charge(HostFunction::ReadInput, payload.len());
             payload.len().try_into().unwrap()
         } else {
+            self.charge(HostFunction::ReadInput, 0);
             0_i32
         }
     }
 
     unsafe fn write_debug(&self, src: *const u8, num_bytes: usize) {
-        let debug_out = from_raw_parts(src, num_bytes).to_vec();
+        self.charge(HostFunction::WriteDebug, num_bytes);
+        let debug_out = from_raw_parts(src, num_bytes);
 
-        let debug = String::from_utf8(debug_out).expect("unexpected non-utf8 debug log");
-
-        eprint!("{}", &debug);
+        self.logs.borrow_mut().write_debug(debug_out);
     }
 
     unsafe fn write_output(&self, src: *const u8, num_bytes: usize) -> i32 {
+        self.charge(HostFunction::WriteOutput, num_bytes);
         let output = from_raw_parts(src, num_bytes).to_vec();
 
         self.state
@@ -80,6 +86,7 @@ unsafe impl SmartRollupCore for MockHost {
     }
 
     unsafe fn store_has(&self, path: *const u8, len: usize) -> i32 {
+        self.charge(HostFunction::StoreHas, 0);
         self.state.borrow().store.store_has(path, len)
     }
 
@@ -91,10 +98,14 @@ unsafe impl SmartRollupCore for MockHost {
         dst: *mut u8,
         max_bytes: usize,
     ) -> i32 {
-        self.state
+        let result = self
+            .state
             .borrow()
             .store
-            .store_read(path, len, offset, dst, max_bytes)
+            .store_read(path, len, offset, dst, max_bytes);
+
+        self.charge(HostFunction::StoreRead, result.max(0) as usize);
+        result
     }
 
     unsafe fn store_write(
@@ -105,6 +116,7 @@ unsafe impl SmartRollupCore for MockHost {
         src: *const u8,
         num_bytes: usize,
     ) -> i32 {
+        self.charge(HostFunction::StoreWrite, num_bytes);
         self.state
             .borrow_mut()
             .store
@@ -112,14 +124,17 @@ unsafe impl SmartRollupCore for MockHost {
     }
 
     unsafe fn store_delete(&self, path: *const u8, len: usize) -> i32 {
+        self.charge(HostFunction::StoreDelete, 0);
         self.state.borrow_mut().store.store_delete(path, len)
     }
 
     unsafe fn store_delete_value(&self, path: *const u8, len: usize) -> i32 {
+        self.charge(HostFunction::StoreDeleteValue, 0);
         self.state.borrow_mut().store.store_delete_value(path, len)
     }
 
     unsafe fn store_list_size(&self, path: *const u8, len: usize) -> i64 {
+        self.charge(HostFunction::StoreListSize, 0);
         self.state.borrow().store.store_list_size(path, len)
     }
 
@@ -130,6 +145,7 @@ unsafe impl SmartRollupCore for MockHost {
         to_path: *const u8,
         to_path_len: usize,
     ) -> i32 {
+        self.charge(HostFunction::StoreMove, 0);
         self.state.borrow_mut().store.store_move(
             from_path,
             from_path_len,
@@ -145,6 +161,15 @@ unsafe impl SmartRollupCore for MockHost {
         to_path: *const u8,
         to_path_len: usize,
     ) -> i32 {
+        // Copies are charged for the size of the copied subtree, which is
+        // only walked when bytes are charged for.
+        let size = if self.ticks.borrow().costs.per_byte > 0 {
+            let from = from_raw_parts(from_path, from_path_len);
+            self.state.borrow().store.subtree_size(from)
+        } else {
+            0
+        };
+        self.charge(HostFunction::StoreCopy, size);
         self.state.borrow_mut().store.store_copy(
             from_path,
             from_path_len,
@@ -176,10 +201,12 @@ unsafe impl SmartRollupCore for MockHost {
         let slice = from_raw_parts_mut(destination_addr, bytes.len());
         slice.copy_from_slice(bytes.as_slice());
 
+        self.charge(HostFunction::RevealPreimage, bytes.len());
         bytes.len().try_into().unwrap()
     }
 
     unsafe fn store_value_size(&self, path: *const u8, path_len: usize) -> i32 {
+        self.charge(HostFunction::StoreValueSize, 0);
         self.state.borrow().store.store_value_size(path, path_len)
     }
 
@@ -189,19 +216,35 @@ unsafe impl SmartRollupCore for MockHost {
             self.state.borrow().get_metadata().clone().into();
         let slice = from_raw_parts_mut(destination_addr, metadata.len());
         slice.copy_from_slice(metadata.as_slice());
+
+        self.charge(HostFunction::RevealMetadata, metadata.len());
         metadata.len().try_into().unwrap()
     }
 
     #[cfg(feature = "proto-alpha")]
     unsafe fn reveal(
         &self,
-        _payload_addr: *const u8,
-        _payload_len: usize,
-        _destination_addr: *mut u8,
-        _max_bytes: usize,
+        payload_addr: *const u8,
+        payload_len: usize,
+        destination_addr: *mut u8,
+        max_bytes: usize,
     ) -> i32 {
-        // TODO: https://gitlab.com/tezos/tezos/-/issues/6171
-        unimplemented!("The `reveal` host function is not yet mocked.")
+        let payload = from_raw_parts(payload_addr, payload_len);
+
+        let result = self.state.borrow().handle_reveal(payload, max_bytes);
+        match result {
+            Ok(bytes) => {
+                let slice = from_raw_parts_mut(destination_addr, bytes.len());
+                slice.copy_from_slice(bytes.as_slice());
+
+                self.charge(HostFunction::Reveal, bytes.len());
+                bytes.len().try_into().unwrap()
+            }
+            Err(error) => {
+                self.charge(HostFunction::Reveal, 0);
+                error.code()
+            }
+        }
     }
 }
 
@@ -211,6 +254,7 @@ mod tests {
     use super::MockHost;
 
     use crate::state::HostState;
+    use crypto::hash::{ContractKt1Hash, HashTrait};
     use tezos_smart_rollup_core::{MAX_FILE_CHUNK_SIZE, MAX_INPUT_MESSAGE_SIZE};
     use tezos_smart_rollup_host::input::Message;
     use tezos_smart_rollup_host::{
@@ -397,4 +441,416 @@ mod tests {
         assert_ne!(new_value_in_store, initial_value_in_store);
         assert_eq!(new_value_in_store, smaller_value);
     }
+
+    #[test]
+    #[cfg(feature = "proto-alpha")]
+    fn test_reveal_dal_parameters() {
+        use tezos_smart_rollup_host::dal_parameters::RollupDalParameters;
+
+        let mut mock_host = MockHost::default();
+        assert_eq!(
+            mock_host.dal_parameters(),
+            mock_host.reveal_dal_parameters()
+        );
+
+        let parameters = RollupDalParameters {
+            number_of_slots: 4,
+            attestation_lag: 2,
+            slot_size: 64,
+            page_size: 16,
+        };
+        mock_host.set_dal_parameters(parameters.clone());
+
+        assert_eq!(parameters, mock_host.reveal_dal_parameters());
+    }
+
+    #[test]
+    #[cfg(feature = "proto-alpha")]
+    fn test_reveal_dal_page() {
+        use tezos_smart_rollup_host::dal_parameters::RollupDalParameters;
+
+        // Arrange
+        let mut mock_host = MockHost::default();
+        mock_host.set_dal_parameters(RollupDalParameters {
+            number_of_slots: 4,
+            attestation_lag: 2,
+            slot_size: 64,
+            page_size: 16,
+        });
+
+        let published_level = mock_host.level();
+        let slot: Vec<u8> = (0..40).collect();
+        mock_host.publish_dal_slot(published_level, 1, slot.clone());
+
+        let mut buffer = [0; 16];
+        let reveal = |host: &MockHost, slot_index, page_index, buffer: &mut [u8]| {
+            host.reveal_dal_page(published_level as i32, slot_index, page_index, buffer)
+        };
+
+        // Act & Assert: the slot is not attested yet
+        mock_host.run_level(|_| {});
+        assert_eq!(Ok(0), reveal(&mock_host, 1, 0, &mut buffer));
+
+        mock_host.run_level(|_| {});
+        assert_eq!(Ok(16), reveal(&mock_host, 1, 0, &mut buffer));
+        assert_eq!(&slot[..16], buffer);
+
+        // The last page is padded with zeros
+        assert_eq!(Ok(16), reveal(&mock_host, 1, 2, &mut buffer));
+        assert_eq!(&slot[32..], &buffer[..8]);
+        assert_eq!([0; 8], buffer[8..]);
+
+        // Pages are trimmed to the size of the buffer
+        assert_eq!(Ok(4), reveal(&mock_host, 1, 1, &mut buffer[..4]));
+        assert_eq!(&slot[16..20], &buffer[..4]);
+
+        // Slots that were not published are empty
+        assert_eq!(Ok(0), reveal(&mock_host, 0, 0, &mut buffer));
+
+        // Indices out of bounds are rejected
+        assert_eq!(
+            Err(RuntimeError::HostErr(
+                tezos_smart_rollup_host::Error::GenericInvalidAccess
+            )),
+            reveal(&mock_host, 1, 4, &mut buffer)
+        );
+        assert_eq!(
+            Err(RuntimeError::HostErr(
+                tezos_smart_rollup_host::Error::GenericInvalidAccess
+            )),
+            reveal(&mock_host, 4, 0, &mut buffer)
+        );
+    }
+
+    #[test]
+    fn run_level_tick_report() {
+        use crate::{HostFunction, TickCosts};
+        use std::collections::BTreeMap;
+
+        const PATH: RefPath = RefPath::assert_from(b"/counter");
+
+        fn kernel_run(host: &mut MockHost) {
+            let counter = host.store_read(&PATH, 0, 1).map_or(0, |bytes| bytes[0]);
+            host.store_write(&PATH, &[counter + 1], 0).unwrap();
+            if counter == 0 {
+                host.mark_for_reboot().unwrap();
+            }
+        }
+
+        let mut mock_host = MockHost::default();
+        mock_host.set_tick_costs(TickCosts {
+            per_call: BTreeMap::from([(HostFunction::StoreWrite, 1000)]),
+            per_byte: 1,
+            per_storage_op: 10,
+        });
+
+        let level = mock_host.run_level(kernel_run);
+
+        let report = mock_host.tick_report();
+        assert_eq!(2, report.len());
+        // The first run finds no counter, and writes the counter & reboot flag
+        assert_eq!((level, 0, 2032, false), {
+            let run = &report[0];
+            (run.level, run.run, run.ticks, run.aborted)
+        });
+        assert_eq!(None, report[0].per_function.get(&HostFunction::StoreRead));
+        // The second run reads and writes the counter
+        assert_eq!(1032, report[1].ticks);
+        assert_eq!(
+            Some(&11),
+            report[1].per_function.get(&HostFunction::StoreRead)
+        );
+    }
+
+    #[test]
+    fn run_level_tick_budget_exceeded() {
+        use crate::{HostFunction, TickCosts};
+        use std::collections::BTreeMap;
+
+        const PATH: RefPath = RefPath::assert_from(b"/value");
+
+        fn kernel_run(host: &mut MockHost) {
+            host.store_write(&PATH, &[1; 16], 0).unwrap();
+            host.write_output(&[2; 16]).unwrap();
+        }
+
+        let mut mock_host = MockHost::default();
+        mock_host.set_tick_costs(TickCosts {
+            per_call: BTreeMap::from([(HostFunction::WriteOutput, 100)]),
+            per_byte: 1,
+            per_storage_op: 0,
+        });
+        mock_host.set_tick_budget(100);
+
+        let level = mock_host.run_level(kernel_run);
+
+        // Changes of the aborted run are discarded
+        let report = mock_host.tick_report();
+        assert_eq!(1, report.len());
+        assert!(report[0].aborted);
+        assert_eq!(132, report[0].ticks);
+        assert_eq!(Ok(None), mock_host.store_has(&PATH));
+        assert!(mock_host.outbox_at(level).is_empty());
+
+        // Without a budget, the kernel runs to completion
+        mock_host.set_tick_budget(u64::MAX);
+        let level = mock_host.run_level(kernel_run);
+        assert!(!mock_host.tick_report()[1].aborted);
+        assert_eq!(1, mock_host.outbox_at(level).len());
+    }
+
+    #[test]
+    fn durable_snapshot_diff_and_restore() {
+        const KEPT: RefPath = RefPath::assert_from(b"/kept");
+        const CHANGED: RefPath = RefPath::assert_from(b"/changed/value");
+
+        let mut mock = MockHost::default();
+        mock.store_write(&KEPT, &[1], 0).unwrap();
+        mock.store_write(&CHANGED, &[2], 0).unwrap();
+        let before = mock.durable_snapshot();
+
+        mock.store_write(&CHANGED, &[3], 0).unwrap();
+        mock.store_delete(&KEPT).unwrap();
+
+        let diff = before.diff(&mock.durable_snapshot());
+        assert_eq!("~ /changed/value 02 -> 03\n- /kept 01\n", diff.to_string());
+
+        mock.restore_durable_snapshot(&before);
+        assert_eq!(Ok(vec![1]), mock.store_read_all(&KEPT));
+        assert!(before.diff(&mock.durable_snapshot()).is_empty());
+    }
+
+    #[test]
+    fn simulated_l1_executes_cemented_outbox() {
+        use crate::{L1Error, SimulatedL1};
+        use tezos_data_encoding::enc::BinWriter;
+        use tezos_smart_rollup_encoding::contract::Contract;
+        use tezos_smart_rollup_encoding::inbox::{InboxMessage, InternalInboxMessage};
+        use tezos_smart_rollup_encoding::michelson::MichelsonString;
+        use tezos_smart_rollup_encoding::outbox::{
+            OutboxMessage, OutboxMessageTransaction,
+        };
+
+        const BRIDGE: &str = "KT1AaiUqbT3NmQts2w7ofY4vJviVchztiW4y";
+        const UNKNOWN: &str = "KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesc";
+        const WITHDRAWN: RefPath = RefPath::assert_from(b"/withdrawn");
+        const DEPOSIT: RefPath = RefPath::assert_from(b"/deposit");
+
+        fn withdrawal(
+            contract: &str,
+            entrypoint: &str,
+        ) -> OutboxMessageTransaction<MichelsonString> {
+            OutboxMessageTransaction {
+                parameters: MichelsonString("tez".to_string()),
+                destination: Contract::from_b58check(contract).unwrap(),
+                entrypoint: entrypoint.to_string().try_into().unwrap(),
+            }
+        }
+
+        fn kernel_run(host: &mut MockHost) {
+            while let Some(input) = host.read_input().unwrap() {
+                if let Ok((
+                    _,
+                    InboxMessage::Internal(InternalInboxMessage::Transfer(transfer)),
+                )) = InboxMessage::<MichelsonString>::parse(input.as_ref())
+                {
+                    // Deposits are appended, to catch reverted transfers
+                    let mut deposits = host.store_read_all(&DEPOSIT).unwrap_or_default();
+                    deposits.extend_from_slice(transfer.payload.0.as_bytes());
+                    host.store_write_all(&DEPOSIT, &deposits).unwrap();
+                }
+            }
+
+            if host.store_has(&WITHDRAWN).unwrap().is_some() {
+                return;
+            }
+            host.store_write_all(&WITHDRAWN, &[]).unwrap();
+
+            let messages: [OutboxMessage<MichelsonString>; 3] = [
+                withdrawal(BRIDGE, "withdraw").into(),
+                // The transfer of the first transaction is dropped, as the
+                // second one fails
+                vec![
+                    withdrawal(BRIDGE, "withdraw"),
+                    withdrawal(UNKNOWN, "default"),
+                ]
+                .into(),
+                withdrawal(BRIDGE, "fail").into(),
+            ];
+            for message in messages {
+                let mut output = Vec::new();
+                message.bin_write(&mut output).unwrap();
+                host.write_output(&output).unwrap();
+            }
+        }
+
+        // Contract calls are counted, which the L1 cannot revert
+        let calls = std::rc::Rc::new(std::cell::Cell::new(0));
+        let contract_calls = calls.clone();
+
+        let mut l1 = SimulatedL1::new(2);
+        l1.register_contract(
+            ContractKt1Hash::from_b58check(BRIDGE).unwrap(),
+            move |context, entrypoint, parameters| {
+                contract_calls.set(contract_calls.get() + 1);
+                let MichelsonString(amount) =
+                    parameters.decode().ok_or("bad parameters")?;
+                match entrypoint.name() {
+                    "withdraw" => context
+                        .transfer_to_rollup(&MichelsonString(format!("{amount} back")))
+                        .map_err(|e| e.to_string()),
+                    name => Err(format!("no entrypoint {name}")),
+                }
+            },
+        );
+
+        let mut mock_host = MockHost::default();
+        mock_host.set_simulated_l1(l1);
+
+        let outbox_level = mock_host.run_level(kernel_run);
+        assert_eq!(3, mock_host.outbox_at(outbox_level).len());
+
+        // Not cemented yet
+        mock_host.run_level(kernel_run);
+        assert!(mock_host.simulated_l1().unwrap().executions().is_empty());
+        assert_eq!(Ok(None), mock_host.store_has(&DEPOSIT));
+
+        // Cemented, the only transfer back is read by the kernel in the same
+        // level
+        let executed_at = mock_host.run_level(kernel_run);
+        assert_eq!(outbox_level + 2, executed_at);
+        assert_eq!(Ok(b"tez back".to_vec()), mock_host.store_read_all(&DEPOSIT));
+        assert_eq!(3, calls.get());
+
+        let results: Vec<_> = mock_host
+            .simulated_l1()
+            .unwrap()
+            .executions()
+            .iter()
+            .map(|execution| {
+                (
+                    execution.outbox_level,
+                    execution.executed_at,
+                    &execution.result,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (outbox_level, executed_at, &Ok(())),
+                (
+                    outbox_level,
+                    executed_at,
+                    &Err(L1Error::UnknownContract(
+                        ContractKt1Hash::from_b58check(UNKNOWN).unwrap()
+                    ))
+                ),
+                (
+                    outbox_level,
+                    executed_at,
+                    &Err(L1Error::ContractFailed("no entrypoint fail".to_string()))
+                ),
+            ],
+            results
+        );
+    }
+
+    #[cfg(feature = "proto-alpha")]
+    #[test]
+    fn simulated_l1_whitelist_update() {
+        use crate::SimulatedL1;
+        use tezos_data_encoding::enc::BinWriter;
+        use tezos_smart_rollup_encoding::michelson::MichelsonUnit;
+        use tezos_smart_rollup_encoding::outbox::{
+            OutboxMessage, OutboxMessageWhitelistUpdate,
+        };
+        use tezos_smart_rollup_encoding::public_key_hash::PublicKeyHash;
+
+        const STAKER: &str = "tz1RjtZUVeLhADFHDL8UwDZA6vjWWhojpu5w";
+
+        fn kernel_run(host: &mut MockHost) {
+            let update = OutboxMessage::<MichelsonUnit>::WhitelistUpdate(
+                OutboxMessageWhitelistUpdate {
+                    whitelist: Some(vec![PublicKeyHash::from_b58check(STAKER).unwrap()]),
+                },
+            );
+            let mut output = Vec::new();
+            update.bin_write(&mut output).unwrap();
+            host.write_output(&output).unwrap();
+        }
+
+        let mut mock_host = MockHost::default();
+        mock_host.set_simulated_l1(SimulatedL1::new(1));
+        mock_host.run_level(kernel_run);
+        assert_eq!(None, mock_host.simulated_l1().unwrap().whitelist());
+
+        mock_host.run_level(|_| {});
+        assert_eq!(
+            Some(&[PublicKeyHash::from_b58check(STAKER).unwrap()][..]),
+            mock_host.simulated_l1().unwrap().whitelist()
+        );
+    }
+
+    #[test]
+    fn run_level_structured_logs() {
+        use tezos_smart_rollup_debug::log::{self, FieldValue, Level, LogFilter};
+        use tezos_smart_rollup_debug::{debug_str, info, trace, warn};
+
+        fn kernel_run(host: &mut MockHost) {
+            debug_str!(host, "raw message\n");
+            info!(host, amount = 5u64, delta = -3, ok = true, to = "tz1"; "Deposit {}", 1);
+            warn!(host, target: "ledger", payload = &[0xca, 0xfe]; "Low balance");
+            trace!(host, "Level done");
+        }
+
+        let mut mock_host = MockHost::default();
+        mock_host.set_log_filter("warn,ledger=off".parse().unwrap());
+        mock_host.run_level(kernel_run);
+
+        // Enabling the `max-level-*` features (eg with `--all-features`)
+        // removes records at compile time.
+        if log::STATIC_MAX_LEVEL != Some(Level::Trace) {
+            assert!(mock_host.logs().len() < 3);
+            return;
+        }
+
+        // Records are kept whether printed or not
+        let logs = mock_host.logs();
+        assert_eq!(3, logs.len());
+        assert_eq!(
+            (Level::Info, module_path!(), "Deposit 1"),
+            (
+                logs[0].level,
+                logs[0].target.as_str(),
+                logs[0].message.as_str()
+            )
+        );
+        assert_eq!(Some(&FieldValue::Nat(5)), logs[0].field("amount"));
+        assert_eq!(Some(&FieldValue::Int(-3)), logs[0].field("delta"));
+        assert_eq!(Some(&FieldValue::Bool(true)), logs[0].field("ok"));
+        assert_eq!(
+            Some(&FieldValue::Str("tz1".to_string())),
+            logs[0].field("to")
+        );
+        assert_eq!(
+            "WARN  ledger: Low balance payload=0xcafe",
+            logs[1].to_string()
+        );
+
+        let filter: LogFilter = "warn,ledger=off".parse().unwrap();
+        assert!(!filter.matches(&logs[0]));
+        assert!(!filter.matches(&logs[1]));
+        assert!(filter.enabled(Level::Error, "kernel"));
+        assert!("verbose".parse::<LogFilter>().is_err());
+
+        // Only the first record over the cap is replaced by a notice, until the
+        // next run.
+        mock_host.set_log_max_bytes_per_run(100);
+        mock_host.run_level(kernel_run);
+        let logs = &mock_host.logs()[3..];
+        assert_eq!(2, logs.len());
+        assert_eq!("Deposit 1", logs[0].message);
+        assert_eq!(log::CAP_TARGET, logs[1].target);
+        assert_eq!(Some(&FieldValue::Nat(100)), logs[1].field("max_bytes"));
+    }
 }
@@ -0,0 +1,263 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Simulated L1, executing the outbox of the rollup.
+//!
+//! The outbox messages written at a given level can be executed once the
+//! commitment for that level is cemented, `cementation_delay` levels later.
+//! Transactions are delivered to *contracts*, Rust closures registered with
+//! [`SimulatedL1::register_contract`], which can themselves send transfers
+//! back to the rollup. Transfers are only sent if the whole outbox message
+//! succeeds; other effects of the contracts are not rolled back.
+
+use crypto::hash::ContractKt1Hash;
+use std::collections::BTreeMap;
+use std::fmt;
+use tezos_data_encoding::enc::BinError;
+use tezos_data_encoding::nom::NomReader;
+use tezos_smart_rollup_encoding::contract::Contract;
+use tezos_smart_rollup_encoding::entrypoint::Entrypoint;
+use tezos_smart_rollup_encoding::michelson::{Michelson, MichelsonExpr};
+use tezos_smart_rollup_encoding::outbox::{OutboxMessage, OutboxMessageTransaction};
+use tezos_smart_rollup_encoding::public_key_hash::PublicKeyHash;
+
+/// Implicit account executing outbox messages, and sending transfers to the
+/// rollup on behalf of contracts, unless set with [`SimulatedL1::with_source`].
+const DEFAULT_SOURCE: &str = "tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx";
+
+/// Contract of the simulated L1, called with the entrypoint & parameters of
+/// a transaction.
+pub type L1Contract =
+    Box<dyn FnMut(&mut L1Context, &Entrypoint, MichelsonExpr) -> Result<(), String>>;
+
+/// Environment of a contract call.
+pub struct L1Context<'a> {
+    address: &'a ContractKt1Hash,
+    level: u32,
+    transfers: &'a mut Vec<MichelsonExpr>,
+}
+
+impl<'a> L1Context<'a> {
+    /// Address of the called contract.
+    pub fn address(&self) -> &ContractKt1Hash {
+        self.address
+    }
+
+    /// L1 level at which the contract is called.
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    /// Send a transfer to the rollup. It is only added to the inbox if the
+    /// whole outbox message is executed successfully.
+    pub fn transfer_to_rollup(
+        &mut self,
+        payload: &impl Michelson,
+    ) -> Result<(), BinError> {
+        self.transfers.push(MichelsonExpr::encode(payload)?);
+        Ok(())
+    }
+}
+
+/// Reason an outbox message could not be executed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum L1Error {
+    /// The outbox message could not be decoded.
+    InvalidMessage,
+    /// A transaction was sent to a contract which is not registered.
+    UnknownContract(ContractKt1Hash),
+    /// A contract failed, with the given reason.
+    ContractFailed(String),
+}
+
+impl fmt::Display for L1Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMessage => write!(f, "invalid outbox message"),
+            Self::UnknownContract(address) => write!(f, "unknown contract {address}"),
+            Self::ContractFailed(reason) => write!(f, "contract failed: {reason}"),
+        }
+    }
+}
+
+/// Outcome of the execution of an outbox message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboxExecution {
+    /// Level at which the message was written to the outbox.
+    pub outbox_level: u32,
+    /// Index of the message in the outbox of its level.
+    pub index: usize,
+    /// Level at which the message was executed.
+    pub executed_at: u32,
+    /// Result of the execution. A failed message sends no transfers to the
+    /// rollup, but contracts called before the failing transaction of its
+    /// batch keep any other side effect, see [`SimulatedL1::register_contract`].
+    pub result: Result<(), L1Error>,
+}
+
+/// Transfers to the rollup resulting from the execution of outbox messages,
+/// along with the contract which sent them.
+pub(crate) type L1Transfers = Vec<(ContractKt1Hash, MichelsonExpr)>;
+
+/// Simulated L1, see the [module documentation](self).
+pub struct SimulatedL1 {
+    cementation_delay: u32,
+    source: PublicKeyHash,
+    contracts: BTreeMap<ContractKt1Hash, L1Contract>,
+    whitelist: Option<Vec<PublicKeyHash>>,
+    // Outbox levels below this one have been executed
+    next_outbox_level: u32,
+    executions: Vec<OutboxExecution>,
+}
+
+impl SimulatedL1 {
+    /// Create a simulated L1, cementing commitments `cementation_delay`
+    /// levels after the level they are for. The outbox of a level can only
+    /// be executed once the level is over, so the delay is at least 1.
+    pub fn new(cementation_delay: u32) -> Self {
+        Self {
+            cementation_delay,
+            source: PublicKeyHash::from_b58check(DEFAULT_SOURCE).unwrap(),
+            contracts: BTreeMap::new(),
+            whitelist: None,
+            next_outbox_level: 0,
+            executions: Vec::new(),
+        }
+    }
+
+    /// Set the implicit account sending transfers to the rollup.
+    pub fn with_source(mut self, source: PublicKeyHash) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Register a contract at `address`, replacing any previous one.
+    ///
+    /// The L1 cannot revert what a contract does to the state it captures.
+    /// When a later transaction of the same atomic batch fails, such effects
+    /// persist; only the transfers sent to the rollup are discarded.
+    pub fn register_contract(
+        &mut self,
+        address: ContractKt1Hash,
+        contract: impl FnMut(&mut L1Context, &Entrypoint, MichelsonExpr) -> Result<(), String>
+            + 'static,
+    ) {
+        self.contracts.insert(address, Box::new(contract));
+    }
+
+    /// The whitelist of stakers, as last updated by the rollup. `None` if
+    /// the rollup is public.
+    pub fn whitelist(&self) -> Option<&[PublicKeyHash]> {
+        self.whitelist.as_deref()
+    }
+
+    /// Outcomes of the outbox messages executed so far.
+    pub fn executions(&self) -> &[OutboxExecution] {
+        &self.executions
+    }
+
+    /// Implicit account sending transfers to the rollup.
+    pub(crate) fn source(&self) -> &PublicKeyHash {
+        &self.source
+    }
+
+    /// Ignore the outbox of the levels before `level`.
+    pub(crate) fn start_at(&mut self, level: u32) {
+        self.next_outbox_level = level;
+    }
+
+    /// Execute the outbox messages of every level cemented at `level`, using
+    /// `outbox_at` to retrieve them. Returns the transfers to the rollup.
+    pub(crate) fn execute_cemented(
+        &mut self,
+        level: u32,
+        outbox_at: impl Fn(u32) -> Vec<Vec<u8>>,
+    ) -> L1Transfers {
+        let mut transfers = Vec::new();
+        let Some(last_cemented) = level.checked_sub(self.cementation_delay.max(1)) else {
+            return transfers;
+        };
+
+        for outbox_level in self.next_outbox_level..=last_cemented {
+            for (index, message) in outbox_at(outbox_level).iter().enumerate() {
+                let result = self.execute(level, message, &mut transfers);
+                self.executions.push(OutboxExecution {
+                    outbox_level,
+                    index,
+                    executed_at: level,
+                    result,
+                });
+            }
+        }
+        self.next_outbox_level = self.next_outbox_level.max(last_cemented + 1);
+
+        transfers
+    }
+
+    fn execute(
+        &mut self,
+        level: u32,
+        message: &[u8],
+        transfers: &mut L1Transfers,
+    ) -> Result<(), L1Error> {
+        let message = match OutboxMessage::<MichelsonExpr>::nom_read(message) {
+            Ok(([], message)) => message,
+            _ => return Err(L1Error::InvalidMessage),
+        };
+
+        match message {
+            OutboxMessage::AtomicTransactionBatch(batch) => {
+                // Transfers are only sent if the whole batch succeeds.
+                let mut batch_transfers = Vec::new();
+                for index in 0..batch.len() {
+                    let OutboxMessageTransaction {
+                        parameters,
+                        destination,
+                        entrypoint,
+                    } = &batch[index];
+                    let Contract::Originated(address) = destination else {
+                        return Err(L1Error::InvalidMessage);
+                    };
+                    let contract = self
+                        .contracts
+                        .get_mut(address)
+                        .ok_or_else(|| L1Error::UnknownContract(address.clone()))?;
+
+                    let mut sent = Vec::new();
+                    let mut context = L1Context {
+                        address,
+                        level,
+                        transfers: &mut sent,
+                    };
+                    contract(&mut context, entrypoint, parameters.clone())
+                        .map_err(L1Error::ContractFailed)?;
+
+                    batch_transfers.extend(
+                        sent.into_iter().map(|payload| (address.clone(), payload)),
+                    );
+                }
+                transfers.append(&mut batch_transfers);
+            }
+            #[cfg(feature = "proto-alpha")]
+            OutboxMessage::WhitelistUpdate(update) => {
+                self.whitelist = update.whitelist;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for SimulatedL1 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimulatedL1")
+            .field("cementation_delay", &self.cementation_delay)
+            .field("source", &self.source)
+            .field("contracts", &self.contracts.keys().collect::<Vec<_>>())
+            .field("whitelist", &self.whitelist)
+            .field("next_outbox_level", &self.next_outbox_level)
+            .field("executions", &self.executions)
+            .finish()
+    }
+}
@@ -7,7 +7,11 @@
 #![deny(rustdoc::broken_intra_doc_links)]
 
 mod host;
+mod l1;
+mod logs;
+mod snapshot;
 mod state;
+mod ticks;
 
 extern crate tezos_crypto_rs as crypto;
 
@@ -22,10 +26,16 @@ use tezos_smart_rollup_encoding::michelson::MichelsonUnit;
 use tezos_smart_rollup_encoding::public_key_hash::PublicKeyHash;
 use tezos_smart_rollup_encoding::smart_rollup::SmartRollupAddress;
 use tezos_smart_rollup_encoding::timestamp::Timestamp;
+#[cfg(feature = "proto-alpha")]
+use tezos_smart_rollup_host::dal_parameters::RollupDalParameters;
 use tezos_smart_rollup_host::metadata::RollupMetadata;
 
+use logs::Logs;
 use state::HostState;
 use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+use tezos_smart_rollup_debug::log::{self, LogFilter, Record};
+use ticks::{TickAccounting, TickBudgetExceeded};
 
 const MAXIMUM_REBOOTS_PER_INPUT: i32 = 1000;
 
@@ -44,13 +54,19 @@ const NAIROBI_BLOCK_TIME: i64 = 15;
 // Nairobi activated approximately at 0:07AM UTC on June 24th 2023.
 const NAIROBI_ACTIVATION_TIMESTAMP: i64 = 1_687_561_630;
 
+pub use l1::{L1Context, L1Contract, L1Error, OutboxExecution, SimulatedL1};
+pub use snapshot::{DurableChange, DurableDiff, DurableSnapshot};
 pub use state::InMemoryStore;
+pub use ticks::{HostFunction, RunTicks, TickCosts, MAX_TICKS_PER_KERNEL_RUN};
 
 /// The runtime host when _not_ running in **wasm**.
 #[derive(Debug)]
 pub struct MockHost {
     state: RefCell<HostState>,
     info: inbox::InfoPerLevel,
+    ticks: RefCell<TickAccounting>,
+    logs: RefCell<Logs>,
+    l1: Option<SimulatedL1>,
 }
 
 impl Default for MockHost {
@@ -123,6 +139,9 @@ impl MockHost {
         let mut host = Self {
             state: state.into(),
             info,
+            ticks: Default::default(),
+            logs: Default::default(),
+            l1: None,
         };
 
         // Ensure inbox setup correctly
@@ -173,20 +192,135 @@ impl MockHost {
         self.as_mut().set_preimage(preimage)
     }
 
+    /// Publish a slot on the DAL at the given level. The slot can be revealed
+    /// page by page once it has been attested, `attestation_lag` levels later.
+    ///
+    /// Panics if `index` or the size of `bytes` exceed the DAL parameters.
+    #[cfg(feature = "proto-alpha")]
+    pub fn publish_dal_slot(&mut self, level: u32, index: u8, bytes: Vec<u8>) {
+        self.as_mut().dal.publish_slot(level, index, bytes)
+    }
+
+    /// Returns the parameters of the DAL.
+    #[cfg(feature = "proto-alpha")]
+    pub fn dal_parameters(&self) -> RollupDalParameters {
+        self.state.borrow().dal.parameters.clone()
+    }
+
+    /// Set the parameters of the DAL, revealed to the kernel by
+    /// `reveal_dal_parameters`. Defaults to the mainnet parameters.
+    #[cfg(feature = "proto-alpha")]
+    pub fn set_dal_parameters(&mut self, parameters: RollupDalParameters) {
+        self.as_mut().dal.parameters = parameters;
+    }
+
+    /// Take a snapshot of the durable storage.
+    pub fn durable_snapshot(&self) -> DurableSnapshot {
+        DurableSnapshot::from_store(&self.state.borrow().store.0)
+    }
+
+    /// Replace the durable storage with a snapshot.
+    pub fn restore_durable_snapshot(&mut self, snapshot: &DurableSnapshot) {
+        snapshot.restore(&mut self.as_mut().store.0)
+    }
+
+    /// Attach a simulated L1, executing the outbox messages written from now
+    /// on once they are cemented. Transfers sent by its contracts are added
+    /// to the inbox of the level at which they are executed.
+    pub fn set_simulated_l1(&mut self, mut l1: SimulatedL1) {
+        l1.start_at(self.level());
+        self.l1 = Some(l1);
+    }
+
+    /// The simulated L1, if any.
+    pub fn simulated_l1(&self) -> Option<&SimulatedL1> {
+        self.l1.as_ref()
+    }
+
+    /// The simulated L1, if any, e.g. to register more contracts.
+    pub fn simulated_l1_mut(&mut self) -> Option<&mut SimulatedL1> {
+        self.l1.as_mut()
+    }
+
+    /// Set the tick costs charged for host function calls.
+    pub fn set_tick_costs(&mut self, costs: TickCosts) {
+        self.ticks.get_mut().costs = costs;
+    }
+
+    /// Set the maximum number of ticks of a `kernel_run`. Defaults to
+    /// [`MAX_TICKS_PER_KERNEL_RUN`].
+    pub fn set_tick_budget(&mut self, budget: u64) {
+        self.ticks.get_mut().budget = budget;
+    }
+
+    /// Ticks spent by every `kernel_run` so far.
+    pub fn tick_report(&self) -> Vec<RunTicks> {
+        self.ticks.borrow().report.clone()
+    }
+
+    /// Set the filter of the log records printed to stderr. All records are
+    /// printed by default.
+    pub fn set_log_filter(&mut self, filter: LogFilter) {
+        self.logs.get_mut().filter = filter;
+    }
+
+    /// Bound the size of the log records kept during each `kernel_run`,
+    /// `usize::MAX` (the default) meaning no bound.
+    ///
+    /// The host applies the cap itself, as the cap set by the kernel with
+    /// [`set_max_bytes_per_run`] is shared by all the hosts of the process.
+    ///
+    /// [`set_max_bytes_per_run`]: tezos_smart_rollup_debug::log::set_max_bytes_per_run
+    pub fn set_log_max_bytes_per_run(&mut self, max_bytes: usize) {
+        self.logs.get_mut().cap.set_max_bytes(max_bytes);
+    }
+
+    /// Log records written by the kernel so far, whether printed or not.
+    pub fn logs(&self) -> Vec<Record> {
+        self.logs.borrow().records.clone()
+    }
+
     /// Runs `kernel_run` against the current level's inbox.
     ///
     /// - Includes the `StartOfLevel`, `InfoPerLevel` & `EndOfLevel` messages.
     /// - Returns the level the kernel was run at.
+    ///
+    /// Ticks are charged for host function calls according to the tick costs.
+    /// A `kernel_run` exceeding the tick budget is aborted: its changes are
+    /// discarded, and the rest of the level is skipped.
     pub fn run_level(&mut self, kernel_run: fn(&mut Self)) -> u32 {
+        self.execute_cemented_outbox();
         self.finalise_inputs();
 
+        let level = self.level();
         let mut reboots = MAXIMUM_REBOOTS_PER_INPUT;
 
-        loop {
+        for run in 0.. {
             let bytes = reboots.to_le_bytes().to_vec();
             self.as_mut().store.0.set_value(REBOOT_COUNTER_KEY, bytes);
 
-            kernel_run(self);
+            // Runs can only be aborted when host functions have a cost.
+            let snapshot = (self.ticks.get_mut().costs != TickCosts::default())
+                .then(|| self.state.get_mut().clone());
+
+            self.ticks.get_mut().start_run(level, run);
+            log::start_run();
+            self.logs.get_mut().cap.start_run();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| kernel_run(self)));
+            let aborted = match result {
+                Ok(()) => false,
+                Err(payload) if payload.is::<TickBudgetExceeded>() => true,
+                Err(payload) => panic::resume_unwind(payload),
+            };
+            self.ticks.get_mut().end_run(aborted);
+
+            if aborted {
+                if let Some(snapshot) = snapshot {
+                    *self.as_mut() = snapshot;
+                }
+                break;
+            }
+
             self.as_mut().store.0.node_delete(TOO_MANY_REBOOT_FLAG_KEY);
 
             reboots -= 1;
@@ -213,10 +347,9 @@ impl MockHost {
             break;
         }
 
-        let level_ran_at = self.state.borrow().curr_level;
         self.bump_level();
 
-        level_ran_at
+        level
     }
 
     /// Returns the level of the next `kernel_run`.
@@ -234,6 +367,34 @@ impl MockHost {
         self.state.borrow().store.0.outbox_at(level).to_vec()
     }
 
+    /// Execute the outbox messages cemented at the current level on the
+    /// simulated L1, if any.
+    fn execute_cemented_outbox(&mut self) {
+        let Some(l1) = self.l1.as_mut() else {
+            return;
+        };
+
+        let state = self.state.get_mut();
+        let transfers = l1.execute_cemented(state.curr_level, |level| {
+            state.store.0.outbox_at(level).to_vec()
+        });
+        let source = l1.source().clone();
+
+        for (sender, payload) in transfers {
+            self.add_transfer(payload, &TransferMetadata::new(sender, source.clone()));
+        }
+    }
+
+    /// Charge a host function call to the current `kernel_run`, aborting it if
+    /// the tick budget is exceeded.
+    fn charge(&self, function: HostFunction, bytes: usize) {
+        if !self.ticks.borrow_mut().charge(function, bytes) {
+            // Unwinding directly skips the panic hook, which would otherwise
+            // report the aborted run as a panic.
+            panic::resume_unwind(Box::new(TickBudgetExceeded));
+        }
+    }
+
     fn bump_level(&mut self) {
         let state = self.as_mut();
         state.curr_level += 1;
@@ -0,0 +1,41 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Decoding of the structured log records written to the debug log.
+
+use tezos_smart_rollup_debug::log::{self, LogFilter, OutputCap, Record};
+
+/// Log records written by the kernel, and the filter of those printed.
+#[derive(Debug, Default)]
+pub(crate) struct Logs {
+    pub filter: LogFilter,
+    pub records: Vec<Record>,
+    /// Output cap of the host's runs, rather than the global one shared by
+    /// all the hosts of the process.
+    pub cap: OutputCap,
+}
+
+impl Logs {
+    /// Print a message written to the debug log, decoding log records.
+    pub fn write_debug(&mut self, debug: &[u8]) {
+        if log::is_record(debug) {
+            if let (Ok((_, [])), Ok(encoded)) =
+                (log::decode(debug), std::str::from_utf8(debug))
+            {
+                if let Some(charged) = self.cap.charge(encoded.to_string()) {
+                    let (record, _) =
+                        log::decode(charged.as_bytes()).expect("valid log record");
+                    if self.filter.matches(&record) {
+                        eprintln!("{record}");
+                    }
+                    self.records.push(record);
+                }
+                return;
+            }
+        }
+
+        let debug = std::str::from_utf8(debug).expect("unexpected non-utf8 debug log");
+        eprint!("{}", debug);
+    }
+}
@@ -0,0 +1,461 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Snapshots of the durable storage, to inspect the whole store at once.
+//!
+//! Snapshots can be compared with each other, and exported to or imported
+//! from JSON or a directory tree. In a snapshot exported as JSON, values are
+//! hex-encoded and indexed by their path. In a directory tree, each step of a
+//! path is a directory, and the value at that path is the file `@` in it.
+
+use crate::state::store::{Node, Store, VALUE_NAME};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Number of bytes of a value shown in diffs.
+const PREVIEW_SIZE: usize = 16;
+
+/// Environment variable requesting golden files to be overwritten, instead
+/// of compared with.
+const UPDATE_GOLDEN_VAR: &str = "UPDATE_GOLDEN";
+
+/// Snapshot of the durable storage.
+///
+/// Taking a snapshot is cheap: the storage is shared with the host until
+/// either is modified.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DurableSnapshot {
+    root: Rc<Node>,
+}
+
+impl DurableSnapshot {
+    pub(crate) fn from_store(store: &Store) -> Self {
+        Self {
+            root: store.durable.clone(),
+        }
+    }
+
+    pub(crate) fn restore(&self, store: &mut Store) {
+        store.durable = self.root.clone();
+    }
+
+    /// Build a snapshot from the values at each path.
+    pub fn from_values<P: AsRef<str>>(
+        values: impl IntoIterator<Item = (P, Vec<u8>)>,
+    ) -> Self {
+        let mut store = Store::default();
+        for (path, value) in values {
+            store.set_value(path.as_ref(), value);
+        }
+        Self::from_store(&store)
+    }
+
+    /// All values in the snapshot, indexed by their path.
+    pub fn values(&self) -> BTreeMap<String, Vec<u8>> {
+        let mut values = BTreeMap::new();
+        collect_values("", &self.root, &mut values);
+        values
+    }
+
+    /// Value at `path`, if any.
+    pub fn get(&self, path: &str) -> Option<&[u8]> {
+        let mut node = &self.root;
+        for step in path.split('/').skip(1) {
+            node = node.inner.get(step)?;
+        }
+        node.value.as_ref().map(|value| value.as_slice())
+    }
+
+    /// Changes between `self` and a later snapshot `after`.
+    pub fn diff(&self, after: &DurableSnapshot) -> DurableDiff {
+        let mut changes = Vec::new();
+        diff_nodes("", Some(&self.root), Some(&after.root), &mut changes);
+        DurableDiff { changes }
+    }
+
+    /// Export the snapshot as a JSON object mapping paths to hex-encoded values.
+    pub fn to_json(&self) -> String {
+        let object: serde_json::Map<_, _> = self
+            .values()
+            .into_iter()
+            .map(|(path, value)| (path, hex::encode(value).into()))
+            .collect();
+        serde_json::to_string_pretty(&object).expect("serialisation of snapshot failed")
+    }
+
+    /// Import a snapshot exported by [`DurableSnapshot::to_json`].
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let object: BTreeMap<String, String> = serde_json::from_str(json)?;
+        let values = object
+            .into_iter()
+            .map(|(path, value)| match hex::decode(value) {
+                Ok(value) => Ok((path, value)),
+                Err(err) => Err(invalid_data(format!("value at {path}: {err}"))),
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Self::from_values(values))
+    }
+
+    /// Export the snapshot as a directory tree rooted at `dir`, which is
+    /// created if needed.
+    ///
+    /// Fails without writing anything if a path has a `.` or `..` step, as
+    /// these would escape their directory.
+    pub fn export_to_dir(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        let values = self
+            .values()
+            .into_iter()
+            .map(|(path, value)| {
+                let mut node_dir = dir.to_path_buf();
+                for step in path.split('/').skip(1) {
+                    if step == "." || step == ".." {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("cannot export path {path} with step {step}"),
+                        ));
+                    }
+                    node_dir.push(step);
+                }
+                Ok((node_dir, value))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        fs::create_dir_all(dir)?;
+        for (node_dir, value) in values {
+            fs::create_dir_all(&node_dir)?;
+            fs::write(node_dir.join(VALUE_NAME), value)?;
+        }
+        Ok(())
+    }
+
+    /// Import a snapshot exported by [`DurableSnapshot::export_to_dir`].
+    pub fn import_from_dir(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut values = Vec::new();
+        import_dir(dir.as_ref(), "", &mut values)?;
+        Ok(Self::from_values(values))
+    }
+
+    /// Compare the snapshot with the golden file at `path`, exported as JSON.
+    ///
+    /// Panics with the differences if they are not equal, or if the golden
+    /// file does not exist. The golden file is written instead if the
+    /// `UPDATE_GOLDEN` environment variable is set.
+    pub fn assert_golden(&self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        if std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+            fs::write(path, self.to_json())
+                .unwrap_or_else(|err| panic!("cannot write {}: {err}", path.display()));
+            return;
+        }
+
+        let golden = fs::read_to_string(path)
+            .and_then(|json| Self::from_json(&json))
+            .unwrap_or_else(|err| {
+                panic!(
+                    "cannot read {} (set {UPDATE_GOLDEN_VAR} to create it): {err}",
+                    path.display()
+                )
+            });
+
+        let diff = golden.diff(self);
+        if !diff.is_empty() {
+            panic!(
+                "durable storage differs from {} (set {UPDATE_GOLDEN_VAR} to update it):\n{diff}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// Change of a value between two snapshots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DurableChange {
+    /// A value was written at a path which had none.
+    Added {
+        /// Path of the value.
+        path: String,
+        /// The new value.
+        value: Vec<u8>,
+    },
+    /// A value was removed.
+    Removed {
+        /// Path of the value.
+        path: String,
+        /// The removed value.
+        value: Vec<u8>,
+    },
+    /// A value was overwritten with a different one.
+    Changed {
+        /// Path of the value.
+        path: String,
+        /// The previous value.
+        before: Vec<u8>,
+        /// The new value.
+        after: Vec<u8>,
+    },
+}
+
+impl DurableChange {
+    /// Path of the changed value.
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Added { path, .. }
+            | Self::Removed { path, .. }
+            | Self::Changed { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for DurableChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { path, value } => write!(f, "+ {path} {}", Preview(value)),
+            Self::Removed { path, value } => write!(f, "- {path} {}", Preview(value)),
+            Self::Changed {
+                path,
+                before,
+                after,
+            } => write!(f, "~ {path} {} -> {}", Preview(before), Preview(after)),
+        }
+    }
+}
+
+/// Changes between two snapshots, ordered by path.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DurableDiff {
+    /// The changed values.
+    pub changes: Vec<DurableChange>,
+}
+
+impl DurableDiff {
+    /// Whether the snapshots are identical.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Paths of the added values.
+    pub fn added(&self) -> impl Iterator<Item = &str> {
+        self.changes
+            .iter()
+            .filter(|change| matches!(change, DurableChange::Added { .. }))
+            .map(DurableChange::path)
+    }
+
+    /// Paths of the removed values.
+    pub fn removed(&self) -> impl Iterator<Item = &str> {
+        self.changes
+            .iter()
+            .filter(|change| matches!(change, DurableChange::Removed { .. }))
+            .map(DurableChange::path)
+    }
+
+    /// Paths of the overwritten values.
+    pub fn changed(&self) -> impl Iterator<Item = &str> {
+        self.changes
+            .iter()
+            .filter(|change| matches!(change, DurableChange::Changed { .. }))
+            .map(DurableChange::path)
+    }
+}
+
+impl fmt::Display for DurableDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in self.changes.iter() {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Hex preview of the start of a value.
+struct Preview<'a>(&'a [u8]);
+
+impl fmt::Display for Preview<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.len() <= PREVIEW_SIZE {
+            write!(f, "{}", hex::encode(self.0))
+        } else {
+            write!(
+                f,
+                "{}... ({} bytes)",
+                hex::encode(&self.0[..PREVIEW_SIZE]),
+                self.0.len()
+            )
+        }
+    }
+}
+
+fn collect_values(prefix: &str, node: &Node, values: &mut BTreeMap<String, Vec<u8>>) {
+    if let Some(value) = &node.value {
+        values.insert(prefix.to_string(), value.to_vec());
+    }
+
+    for (step, child) in node.inner.iter().filter(|(step, _)| *step != VALUE_NAME) {
+        collect_values(&format!("{prefix}/{step}"), child, values);
+    }
+}
+
+fn diff_nodes(
+    prefix: &str,
+    before: Option<&Rc<Node>>,
+    after: Option<&Rc<Node>>,
+    changes: &mut Vec<DurableChange>,
+) {
+    // Unmodified subtrees are still shared.
+    if let (Some(before), Some(after)) = (before, after) {
+        if Rc::ptr_eq(before, after) {
+            return;
+        }
+    }
+
+    let path = prefix.to_string();
+    match (
+        before.and_then(|node| node.value.as_ref()),
+        after.and_then(|node| node.value.as_ref()),
+    ) {
+        (None, Some(value)) => changes.push(DurableChange::Added {
+            path,
+            value: value.to_vec(),
+        }),
+        (Some(value), None) => changes.push(DurableChange::Removed {
+            path,
+            value: value.to_vec(),
+        }),
+        (Some(before), Some(after)) if before != after => {
+            changes.push(DurableChange::Changed {
+                path,
+                before: before.to_vec(),
+                after: after.to_vec(),
+            })
+        }
+        _ => {}
+    }
+
+    let steps: BTreeSet<&String> = before
+        .into_iter()
+        .chain(after)
+        .flat_map(|node| node.inner.keys())
+        .filter(|step| *step != VALUE_NAME)
+        .collect();
+
+    for step in steps {
+        diff_nodes(
+            &format!("{prefix}/{step}"),
+            before.and_then(|node| node.inner.get(step)),
+            after.and_then(|node| node.inner.get(step)),
+            changes,
+        );
+    }
+}
+
+fn import_dir(
+    dir: &Path,
+    prefix: &str,
+    values: &mut Vec<(String, Vec<u8>)>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string().map_err(|name| {
+            invalid_data(format!("invalid path step {}", name.to_string_lossy()))
+        })?;
+
+        if entry.file_type()?.is_dir() {
+            import_dir(&entry.path(), &format!("{prefix}/{name}"), values)?;
+        } else if name == VALUE_NAME {
+            values.push((prefix.to_string(), fs::read(entry.path())?));
+        }
+    }
+    Ok(())
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_diff() {
+        let before = DurableSnapshot::from_values([
+            ("/a/b", vec![1, 2]),
+            ("/a/c", vec![3]),
+            ("/d", vec![4]),
+        ]);
+        let after = DurableSnapshot::from_values([
+            ("/a/b", vec![1, 2]),
+            ("/a/c", vec![5; 20]),
+            ("/e", vec![]),
+        ]);
+
+        let diff = before.diff(&after);
+        assert_eq!(vec!["/e"], diff.added().collect::<Vec<_>>());
+        assert_eq!(vec!["/d"], diff.removed().collect::<Vec<_>>());
+        assert_eq!(vec!["/a/c"], diff.changed().collect::<Vec<_>>());
+        assert_eq!(
+            "~ /a/c 03 -> 05050505050505050505050505050505... (20 bytes)\n- /d 04\n+ /e \n",
+            diff.to_string()
+        );
+
+        assert!(after.diff(&after.clone()).is_empty());
+    }
+
+    #[test]
+    fn snapshot_json_roundtrip() {
+        let snapshot =
+            DurableSnapshot::from_values([("/a/b", vec![1, 2]), ("/a", vec![0xff])]);
+
+        let json = snapshot.to_json();
+        assert_eq!("{\n  \"/a\": \"ff\",\n  \"/a/b\": \"0102\"\n}", json);
+
+        let imported = DurableSnapshot::from_json(&json).unwrap();
+        assert!(snapshot.diff(&imported).is_empty());
+        assert_eq!(Some([1, 2].as_slice()), imported.get("/a/b"));
+    }
+
+    #[test]
+    fn snapshot_dir_roundtrip() {
+        let dir =
+            std::env::temp_dir().join(format!("mock-snapshot-{}", std::process::id()));
+        let snapshot = DurableSnapshot::from_values([
+            ("/a/b", vec![1, 2]),
+            ("/a", vec![3]),
+            ("/c/d/e", vec![]),
+        ]);
+
+        snapshot.export_to_dir(&dir).unwrap();
+        let imported = DurableSnapshot::import_from_dir(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(snapshot.values(), imported.unwrap().values());
+    }
+
+    #[test]
+    fn snapshot_dir_rejects_relative_steps() {
+        let dir = std::env::temp_dir()
+            .join(format!("mock-snapshot-steps-{}", std::process::id()));
+
+        for path in ["/a/../b", "/..", "/a/./b"] {
+            let snapshot =
+                DurableSnapshot::from_values([("/c", vec![1]), (path, vec![2])]);
+            let err = snapshot.export_to_dir(&dir).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+            assert!(!dir.exists());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "set UPDATE_GOLDEN to create it")]
+    fn snapshot_golden_missing() {
+        let path = std::env::temp_dir()
+            .join(format!("mock-snapshot-missing-{}.json", std::process::id()));
+        DurableSnapshot::default().assert_golden(path);
+    }
+}
@@ -0,0 +1,102 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Mock Data Availability Layer: slots published on the L1, revealed to the
+//! kernel page by page.
+
+use std::collections::BTreeMap;
+use tezos_smart_rollup_host::{dal_parameters::RollupDalParameters, Error};
+
+/// DAL parameters of the Tezos mainnet, used by default.
+pub(crate) const DEFAULT_DAL_PARAMETERS: RollupDalParameters = RollupDalParameters {
+    number_of_slots: 32,
+    attestation_lag: 8,
+    slot_size: 126_944,
+    page_size: 3967,
+};
+
+/// Slots published on the mock DAL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DalState {
+    pub(crate) parameters: RollupDalParameters,
+    // Slots are indexed by their published level, then their index.
+    slots: BTreeMap<(u32, u8), Vec<u8>>,
+}
+
+impl Default for DalState {
+    fn default() -> Self {
+        Self {
+            parameters: DEFAULT_DAL_PARAMETERS,
+            slots: BTreeMap::new(),
+        }
+    }
+}
+
+impl DalState {
+    /// Publish a slot at the given level. The slot is padded with zeros to
+    /// the slot size.
+    pub(crate) fn publish_slot(&mut self, level: u32, index: u8, mut bytes: Vec<u8>) {
+        let RollupDalParameters {
+            number_of_slots,
+            slot_size,
+            ..
+        } = self.parameters;
+
+        if index as u64 >= number_of_slots {
+            panic!("slot index out of range -index:{index} -number_of_slots:{number_of_slots}");
+        }
+
+        if bytes.len() as u64 > slot_size {
+            panic!("slot too big -size:{} -slot_size:{slot_size}", bytes.len());
+        }
+
+        bytes.resize(slot_size as usize, 0);
+        self.slots.insert((level, index), bytes);
+    }
+
+    /// Returns the page `page_index` of the slot `slot_index` published at
+    /// `published_level`, as seen by a kernel running at `curr_level`.
+    ///
+    /// Pages of slots that were not published, or that are not yet attested,
+    /// are empty. Indices outside the bounds given by the parameters are
+    /// rejected.
+    pub(crate) fn page(
+        &self,
+        curr_level: u32,
+        published_level: i32,
+        slot_index: u8,
+        page_index: i16,
+    ) -> Result<&[u8], Error> {
+        let RollupDalParameters {
+            number_of_slots,
+            attestation_lag,
+            slot_size,
+            page_size,
+        } = self.parameters;
+
+        let number_of_pages = slot_size / page_size;
+        if slot_index as u64 >= number_of_slots
+            || page_index < 0
+            || page_index as u64 >= number_of_pages
+        {
+            return Err(Error::GenericInvalidAccess);
+        }
+
+        let Ok(published_level) = u32::try_from(published_level) else {
+            return Err(Error::GenericInvalidAccess);
+        };
+
+        // A slot is only available once it has been attested.
+        if published_level as u64 + attestation_lag > curr_level as u64 {
+            return Ok(&[]);
+        }
+
+        let Some(slot) = self.slots.get(&(published_level, slot_index)) else {
+            return Ok(&[]);
+        };
+
+        let start = page_index as usize * page_size as usize;
+        Ok(&slot[start..start + page_size as usize])
+    }
+}
@@ -305,6 +305,14 @@ impl InMemoryStore {
         Ok(())
     }
 
+    /// Total size of the values under `path`, 0 if the path is invalid.
+    pub(crate) fn subtree_size(&self, path: &[u8]) -> usize {
+        validate_path(path)
+            .ok()
+            .and_then(|path| self.0.node_from_path(&path))
+            .map_or(0, |node| node.size())
+    }
+
     pub(crate) fn handle_store_value_size(&self, path: &[u8]) -> Result<i32, Error> {
         let path = validate_path(path)?;
         if !self.0.has_entry(&path) {
@@ -10,8 +10,14 @@ use crypto::hash::SmartRollupHash;
 use tezos_smart_rollup_core::{
     MAX_INPUT_MESSAGE_SIZE, MAX_OUTPUT_SIZE, PREIMAGE_HASH_SIZE,
 };
+#[cfg(feature = "proto-alpha")]
+use tezos_smart_rollup_host::{
+    dal_parameters::DAL_PARAMETERS_SIZE, metadata::METADATA_SIZE,
+};
 use tezos_smart_rollup_host::{metadata::RollupMetadata, Error};
 
+#[cfg(feature = "proto-alpha")]
+pub(crate) mod dal;
 pub(crate) mod in_memory_store;
 pub(crate) mod store;
 
@@ -36,6 +42,9 @@ pub(crate) struct HostState {
     pub(crate) curr_level: u32,
     pub(crate) curr_input_id: usize,
     pub(crate) input: Vec<Vec<u8>>,
+    /// Slots published on the DAL.
+    #[cfg(feature = "proto-alpha")]
+    pub(crate) dal: dal::DalState,
 }
 
 impl Default for HostState {
@@ -58,6 +67,8 @@ impl Default for HostState {
             curr_level: crate::NAIROBI_ACTIVATION_LEVEL,
             curr_input_id: 0,
             input: vec![],
+            #[cfg(feature = "proto-alpha")]
+            dal: dal::DalState::default(),
         }
     }
 }
@@ -133,6 +144,64 @@ impl HostState {
     pub(crate) fn get_metadata(&self) -> &RollupMetadata {
         &self.metadata
     }
+
+    /// Answer a raw reveal request, encoded as in the Tezos protocol. The
+    /// result is trimmed to `max_bytes`.
+    #[cfg(feature = "proto-alpha")]
+    pub(crate) fn handle_reveal(
+        &self,
+        payload: &[u8],
+        max_bytes: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let mut result = match payload.split_first() {
+            // Reveal raw data
+            Some((0, hash)) => {
+                let hash = hash.try_into().map_err(|_| Error::GenericInvalidAccess)?;
+                self.store.0.retrieve_preimage(hash).to_vec()
+            }
+
+            // Reveal metadata
+            Some((1, [])) => <[u8; METADATA_SIZE]>::from(self.metadata.clone()).to_vec(),
+
+            // Reveal a DAL page
+            Some((2, request)) => {
+                let (published_level, request) = split_array::<4>(request)?;
+                let ([slot_index], request) = split_array::<1>(request)?;
+                let (page_index, []) = split_array::<2>(request)? else {
+                    return Err(Error::GenericInvalidAccess);
+                };
+
+                self.dal
+                    .page(
+                        self.curr_level,
+                        i32::from_be_bytes(published_level),
+                        slot_index,
+                        i16::from_be_bytes(page_index),
+                    )?
+                    .to_vec()
+            }
+
+            // Reveal the DAL parameters
+            Some((3, [])) => {
+                <[u8; DAL_PARAMETERS_SIZE]>::from(self.dal.parameters.clone()).to_vec()
+            }
+
+            _ => return Err(Error::GenericInvalidAccess),
+        };
+
+        result.truncate(max_bytes);
+        Ok(result)
+    }
+}
+
+/// Split the first `N` bytes off a reveal request.
+#[cfg(feature = "proto-alpha")]
+fn split_array<const N: usize>(bytes: &[u8]) -> Result<([u8; N], &[u8]), Error> {
+    if bytes.len() < N {
+        return Err(Error::GenericInvalidAccess);
+    }
+    let (head, tail) = bytes.split_at(N);
+    Ok((head.try_into().unwrap(), tail))
 }
 
 #[cfg(test)]
@@ -43,6 +43,14 @@ impl Node {
     }
 }
 
+impl Node {
+    /// Total size of the values in the subtree.
+    pub(crate) fn size(&self) -> usize {
+        let value = self.value.as_ref().map_or(0, |v| v.len());
+        value + self.inner.values().map(|node| node.size()).sum::<usize>()
+    }
+}
+
 impl std::fmt::Display for Node {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.print("", f)
@@ -0,0 +1,178 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Tick accounting of host function calls.
+//!
+//! The PVM aborts a `kernel_run` that exceeds its tick budget. [`MockHost`]
+//! cannot measure the ticks spent by the kernel itself, but it charges
+//! configurable costs for each host function call, which is enough to
+//! regression-test the tick usage of a kernel's interactions with its host.
+//!
+//! [`MockHost`]: crate::MockHost
+
+use std::collections::BTreeMap;
+
+/// Maximum number of ticks of a `kernel_run` in the WASM PVM.
+pub const MAX_TICKS_PER_KERNEL_RUN: u64 = 11_000_000_000;
+
+/// Host functions available to a kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[allow(missing_docs)]
+pub enum HostFunction {
+    ReadInput,
+    WriteDebug,
+    WriteOutput,
+    StoreHas,
+    StoreRead,
+    StoreWrite,
+    StoreDelete,
+    StoreDeleteValue,
+    StoreListSize,
+    StoreMove,
+    StoreCopy,
+    StoreValueSize,
+    RevealPreimage,
+    RevealMetadata,
+    Reveal,
+}
+
+impl HostFunction {
+    /// Whether the host function accesses the durable storage.
+    pub fn is_storage_op(self) -> bool {
+        matches!(
+            self,
+            Self::StoreHas
+                | Self::StoreRead
+                | Self::StoreWrite
+                | Self::StoreDelete
+                | Self::StoreDeleteValue
+                | Self::StoreListSize
+                | Self::StoreMove
+                | Self::StoreCopy
+                | Self::StoreValueSize
+        )
+    }
+}
+
+/// Tick costs charged for host function calls.
+///
+/// By default, host functions are free.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TickCosts {
+    /// Fixed cost of each call to a host function. Missing functions are free.
+    pub per_call: BTreeMap<HostFunction, u64>,
+    /// Cost of each byte copied between the kernel and the host, or
+    /// duplicated in the durable storage by `store_copy`.
+    pub per_byte: u64,
+    /// Additional cost of each call accessing the durable storage.
+    pub per_storage_op: u64,
+}
+
+impl TickCosts {
+    /// Ticks charged for a call to `function` copying `bytes` bytes.
+    pub fn cost(&self, function: HostFunction, bytes: usize) -> u64 {
+        let per_call = self.per_call.get(&function).copied().unwrap_or_default();
+        let per_storage_op = if function.is_storage_op() {
+            self.per_storage_op
+        } else {
+            0
+        };
+
+        per_call
+            .saturating_add(self.per_byte.saturating_mul(bytes as u64))
+            .saturating_add(per_storage_op)
+    }
+}
+
+/// Ticks spent by one `kernel_run`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunTicks {
+    /// Level at which the kernel ran.
+    pub level: u32,
+    /// Index of the run within the level, increased on every reboot.
+    pub run: u32,
+    /// Total ticks charged.
+    pub ticks: u64,
+    /// Ticks charged per host function.
+    pub per_function: BTreeMap<HostFunction, u64>,
+    /// Whether the run was aborted for exceeding the tick budget.
+    pub aborted: bool,
+}
+
+/// Payload of the unwinding which aborts a `kernel_run` that exceeded its budget.
+pub(crate) struct TickBudgetExceeded;
+
+/// Tick accounting state of the mock host.
+#[derive(Debug, Clone)]
+pub(crate) struct TickAccounting {
+    pub(crate) costs: TickCosts,
+    pub(crate) budget: u64,
+    pub(crate) current: Option<RunTicks>,
+    pub(crate) report: Vec<RunTicks>,
+}
+
+impl Default for TickAccounting {
+    fn default() -> Self {
+        Self {
+            costs: TickCosts::default(),
+            budget: MAX_TICKS_PER_KERNEL_RUN,
+            current: None,
+            report: Vec::new(),
+        }
+    }
+}
+
+impl TickAccounting {
+    /// Start accounting for a new `kernel_run`.
+    pub(crate) fn start_run(&mut self, level: u32, run: u32) {
+        self.current = Some(RunTicks {
+            level,
+            run,
+            ticks: 0,
+            per_function: BTreeMap::new(),
+            aborted: false,
+        });
+    }
+
+    /// Stop accounting for the current `kernel_run`, and add it to the report.
+    pub(crate) fn end_run(&mut self, aborted: bool) {
+        if let Some(mut run) = self.current.take() {
+            run.aborted = aborted;
+            self.report.push(run);
+        }
+    }
+
+    /// Charge a host function call to the current run. Calls made outside of
+    /// a run are not accounted for. Returns `false` if the budget is exceeded.
+    pub(crate) fn charge(&mut self, function: HostFunction, bytes: usize) -> bool {
+        let Some(run) = self.current.as_mut() else {
+            return true;
+        };
+
+        let cost = self.costs.cost(function, bytes);
+        run.ticks = run.ticks.saturating_add(cost);
+        *run.per_function.entry(function).or_default() += cost;
+
+        run.ticks <= self.budget
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_costs() {
+        let costs = TickCosts {
+            per_call: BTreeMap::from([(HostFunction::StoreWrite, 100)]),
+            per_byte: 2,
+            per_storage_op: 10,
+        };
+
+        assert_eq!(0, TickCosts::default().cost(HostFunction::StoreWrite, 8));
+        assert_eq!(126, costs.cost(HostFunction::StoreWrite, 8));
+        assert_eq!(10, costs.cost(HostFunction::StoreHas, 0));
+        assert_eq!(16, costs.cost(HostFunction::WriteOutput, 8));
+    }
+}
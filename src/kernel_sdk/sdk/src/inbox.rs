@@ -0,0 +1,865 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! The *inbox* contains the messages sent to the rollup by layer 1 (internal messages), and by
+//! users (external messages).
+//!
+//! # Chunked external messages
+//!
+//! External messages are limited to [`MAX_INPUT_MESSAGE_SIZE`] bytes. Larger payloads are split
+//! by clients into a [`ChunkedMessage`] upload with [`ExternalMessageFrame::chunked`], and
+//! reassembled by the kernel with a [chunk reassembler]. Chunks are kept in durable storage
+//! until all of them have arrived, possibly over several levels. Each chunk is checked against
+//! the hash announced for it as soon as it arrives, so that a forged chunk can't take the place
+//! of the real one.
+//!
+//! Uploads which are not completed within a number of levels expire, and the total size of the
+//! uploads in progress - with their chunk hashes, plus a fixed overhead per upload - is bounded by
+//! a quota, so that a user can't fill the durable storage with incomplete uploads.
+//!
+//! ## Example usage
+//!
+//! ```rust
+//! use tezos_smart_rollup::prelude::*;
+//! use tezos_smart_rollup::inbox::*;
+//! use tezos_smart_rollup::types::SmartRollupAddress;
+//!
+//! fn kernel_run(host: &mut impl Runtime) {
+//!   let address = host.reveal_metadata().address();
+//!
+//!   while let Ok(Some(message)) = host.read_input() {
+//!     let Ok((_, InboxMessage::External(external))) =
+//!       InboxMessage::<tezos_smart_rollup::michelson::MichelsonUnit>::parse(message.as_ref())
+//!     else { continue };
+//!
+//!     let Ok(ExternalMessageFrame::Targetted { address: target, contents }) =
+//!       ExternalMessageFrame::parse(external)
+//!     else { continue };
+//!
+//!     if target.hash() != &address { continue };
+//!     let Ok(chunk) = ChunkedMessage::parse(contents) else { continue };
+//!
+//!     match CHUNK_REASSEMBLER.handle(host, message.level, &chunk) {
+//!       Ok(Some(payload)) => debug_msg!(host, "received {} bytes", payload.len()),
+//!       Ok(None) => (),
+//!       Err(err) => debug_msg!(host, "invalid chunk: {err}"),
+//!     }
+//!   }
+//! }
+//!
+//! # use tezos_smart_rollup::testing::prelude::*;
+//! # let mut host = MockHost::default();
+//! # let address = SmartRollupAddress::new(host.reveal_metadata().address());
+//! # for frame in ExternalMessageFrame::chunked(&address, &[1; 10000]).unwrap() {
+//! #   host.add_external(frame);
+//! # }
+//! # host.run_level(kernel_run);
+//! # assert!(CHUNK_REASSEMBLER.pending(&host).unwrap().is_empty());
+//! ```
+//!
+//! [`MAX_INPUT_MESSAGE_SIZE`]: tezos_smart_rollup_core::MAX_INPUT_MESSAGE_SIZE
+//! [chunk reassembler]: ChunkReassembler
+
+#[doc(inline)]
+pub use tezos_smart_rollup_encoding::inbox::*;
+
+use core::fmt::Display;
+use tezos_smart_rollup_host::path::{concat, Path, PathError};
+use tezos_smart_rollup_host::{
+    path::{OwnedPath, RefPath, PATH_MAX_SIZE},
+    runtime::{Runtime, RuntimeError},
+};
+
+use alloc::vec::Vec;
+
+const CHUNK_REASSEMBLER_ROOT: RefPath = RefPath::assert_from(b"/__sdk/chunked");
+const INDEX_SUFFIX: RefPath = RefPath::assert_from(b"/index");
+const BOUNDS_SUFFIX: RefPath = RefPath::assert_from(b"/bounds");
+const USED_SUFFIX: RefPath = RefPath::assert_from(b"/used");
+const META_SUFFIX: RefPath = RefPath::assert_from(b"/meta");
+const HASHES_SUFFIX: RefPath = RefPath::assert_from(b"/hashes");
+
+/// Size of the path segment of an upload: its hex-encoded hash.
+const UPLOAD_SEGMENT_SIZE: usize = 1 + 2 * CHUNKED_HASH_SIZE;
+/// Size of the path segment of a chunk, relative to its upload: its hex-encoded index.
+const CHUNK_SEGMENT_SIZE: usize = 1 + 2 * core::mem::size_of::<u16>();
+/// Size of the path segment of an index entry, relative to the index: its hex-encoded position.
+const ENTRY_SEGMENT_SIZE: usize = 1 + 2 * core::mem::size_of::<u64>();
+/// Size of an entry of the index: the hash & start level of an upload.
+const INDEX_ENTRY_SIZE: usize = CHUNKED_HASH_SIZE + core::mem::size_of::<u32>();
+
+/// Quota charged for each upload on top of its size & chunk hashes, for its metadata & index
+/// entry, so that empty uploads are not free.
+pub const UPLOAD_OVERHEAD: u64 = (Upload::SIZE + INDEX_ENTRY_SIZE) as u64;
+
+/// Number of levels after which incomplete uploads of the [`CHUNK_REASSEMBLER`] expire.
+pub const DEFAULT_EXPIRY_LEVELS: u32 = 100;
+
+/// Total size of the uploads in progress allowed by the [`CHUNK_REASSEMBLER`], including the
+/// [`UPLOAD_OVERHEAD`] of each one.
+pub const DEFAULT_QUOTA: u64 = 10 * 1024 * 1024;
+
+/// The default chunk reassembler.
+///
+/// Unless you have good reason to, you should probably use this instance. Uploads expire after
+/// [`DEFAULT_EXPIRY_LEVELS`], and the uploads in progress may use up to [`DEFAULT_QUOTA`] bytes.
+pub const CHUNK_REASSEMBLER: ChunkReassembler<'static, RefPath> = ChunkReassembler {
+    root: &CHUNK_REASSEMBLER_ROOT,
+    expiry_levels: DEFAULT_EXPIRY_LEVELS,
+    quota: DEFAULT_QUOTA,
+};
+
+/// Errors occurring when reassembling chunked messages.
+#[derive(Debug)]
+pub enum ReassemblyError {
+    /// Accessing the durable storage failed.
+    Runtime(RuntimeError),
+    /// An upload of the same payload is already in progress.
+    AlreadyStarted,
+    /// No upload of the payload is in progress - it may have expired.
+    UnknownUpload,
+    /// The announced size of the payload doesn't match its number of chunks.
+    InvalidSize,
+    /// The chunk is out of range, was already received, or exceeds the announced size.
+    InvalidChunk,
+    /// Starting the upload would exceed the quota.
+    QuotaExceeded,
+    /// The announced chunk hashes don't match the hash of the upload.
+    HashMismatch,
+    /// The chunk doesn't match the hash announced for it, and was ignored.
+    ChunkHashMismatch,
+}
+
+impl Display for ReassemblyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Runtime(err) => write!(f, "{err}"),
+            Self::AlreadyStarted => write!(f, "Upload already in progress"),
+            Self::UnknownUpload => write!(f, "No upload in progress for the payload"),
+            Self::InvalidSize => {
+                write!(f, "Payload size doesn't match its number of chunks")
+            }
+            Self::InvalidChunk => write!(f, "Invalid chunk"),
+            Self::QuotaExceeded => write!(f, "Quota of uploads in progress exceeded"),
+            Self::HashMismatch => write!(f, "Chunk hashes don't match the upload hash"),
+            Self::ChunkHashMismatch => write!(f, "Chunk doesn't match its hash"),
+        }
+    }
+}
+
+impl From<RuntimeError> for ReassemblyError {
+    fn from(err: RuntimeError) -> Self {
+        Self::Runtime(err)
+    }
+}
+
+/// Progress of an upload, stored under `<root>/<hash>/meta`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Upload {
+    level: u32,
+    num_chunks: u16,
+    size: u32,
+    received: u16,
+    stored: u32,
+}
+
+impl Upload {
+    const SIZE: usize = 16;
+
+    /// Quota used by the upload.
+    fn cost(&self) -> u64 {
+        self.size as u64
+            + self.num_chunks as u64 * CHUNKED_HASH_SIZE as u64
+            + UPLOAD_OVERHEAD
+    }
+
+    fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.level.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.num_chunks.to_le_bytes());
+        bytes[6..10].copy_from_slice(&self.size.to_le_bytes());
+        bytes[10..12].copy_from_slice(&self.received.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.stored.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        Self {
+            level: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            num_chunks: u16::from_le_bytes(bytes[4..6].try_into().unwrap()),
+            size: u32::from_le_bytes(bytes[6..10].try_into().unwrap()),
+            received: u16::from_le_bytes(bytes[10..12].try_into().unwrap()),
+            stored: u32::from_le_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+}
+
+/// Reassembles payloads uploaded as [`ChunkedMessage`]s into durable storage.
+///
+/// See [`CHUNK_REASSEMBLER`] for the default instance, and the [module documentation](self).
+#[derive(Debug)]
+pub struct ChunkReassembler<'a, P: Path> {
+    root: &'a P,
+    expiry_levels: u32,
+    quota: u64,
+}
+
+impl<'a, P: Path> ChunkReassembler<'a, P> {
+    /// Setup a chunk reassembler operating over non-default parameters.
+    ///
+    /// - `root` specifies the path in storage that uploads in progress are stored at.
+    /// - `expiry_levels` is the number of levels after which incomplete uploads are discarded.
+    /// - `quota` bounds the total size of the uploads in progress, including the
+    ///   [`UPLOAD_OVERHEAD`] of each one.
+    pub fn new(root: &'a P, expiry_levels: u32, quota: u64) -> Result<Self, PathError> {
+        // Ensure we have enough room for the hex-encoded hashes & indexes, and the suffixes
+        let suffix_size = CHUNK_SEGMENT_SIZE
+            .max(META_SUFFIX.size())
+            .max(HASHES_SUFFIX.size());
+        if root.size() > PATH_MAX_SIZE - UPLOAD_SEGMENT_SIZE - suffix_size {
+            return Err(PathError::PathTooLong);
+        }
+
+        Ok(Self {
+            root,
+            expiry_levels,
+            quota,
+        })
+    }
+
+    /// Handle a chunked message received at `level`.
+    ///
+    /// Returns the payload once all of its chunks have been received. Chunks not matching their
+    /// announced hash are rejected without affecting the upload.
+    /// Every message first discards the expired uploads - see [`expire`].
+    ///
+    /// [`expire`]: Self::expire
+    pub fn handle(
+        &self,
+        host: &mut impl Runtime,
+        level: u32,
+        message: &ChunkedMessage<impl AsRef<[u8]>>,
+    ) -> Result<Option<Vec<u8>>, ReassemblyError> {
+        match message {
+            ChunkedMessage::New {
+                hash,
+                size,
+                chunk_hashes,
+            } => {
+                self.start(host, level, *hash, *size, chunk_hashes)?;
+                Ok(None)
+            }
+            ChunkedMessage::Chunk { hash, index, data } => {
+                self.add_chunk(host, level, *hash, *index, data.as_ref())
+            }
+        }
+    }
+
+    /// Discard the uploads started more than `expiry_levels` before `level`.
+    ///
+    /// Uploads are indexed in the order they were started, which is expected to be the order of
+    /// their levels: only the oldest index entries are looked at, and the entries of uploads
+    /// completed since are dropped along the way.
+    ///
+    /// Returns the number of uploads discarded.
+    pub fn expire(
+        &self,
+        host: &mut impl Runtime,
+        level: u32,
+    ) -> Result<usize, ReassemblyError> {
+        let (mut head, tail) = self.read_bounds(host)?;
+        let start = head;
+        let mut expired = 0;
+        let mut released = 0;
+
+        while head < tail {
+            let (hash, start_level) = self.read_entry(host, head)?;
+            match self.read_upload(host, &hash)? {
+                Some(upload) if upload.level == start_level => {
+                    if level.saturating_sub(start_level) <= self.expiry_levels {
+                        break;
+                    }
+                    host.store_delete(&self.upload_path(&hash))?;
+                    released += upload.cost();
+                    expired += 1;
+                }
+                // The upload was completed, and possibly started again since.
+                _ => (),
+            }
+            host.store_delete(&self.entry_path(head))?;
+            head += 1;
+        }
+
+        if head != start {
+            self.write_bounds(host, head, tail)?;
+        }
+        if released > 0 {
+            let used = self.read_used(host)?;
+            self.write_used(host, used.saturating_sub(released))?;
+        }
+
+        Ok(expired)
+    }
+
+    /// Hashes of the uploads in progress, in the order they were started.
+    pub fn pending(
+        &self,
+        host: &impl Runtime,
+    ) -> Result<Vec<[u8; CHUNKED_HASH_SIZE]>, ReassemblyError> {
+        let (head, tail) = self.read_bounds(host)?;
+        let mut pending = Vec::new();
+
+        for position in head..tail {
+            let (hash, start_level) = self.read_entry(host, position)?;
+            if pending.contains(&hash) {
+                continue;
+            }
+            if let Some(upload) = self.read_upload(host, &hash)? {
+                if upload.level == start_level {
+                    pending.push(hash);
+                }
+            }
+        }
+
+        Ok(pending)
+    }
+
+    fn start(
+        &self,
+        host: &mut impl Runtime,
+        level: u32,
+        hash: [u8; CHUNKED_HASH_SIZE],
+        size: u32,
+        chunk_hashes: &[[u8; CHUNKED_HASH_SIZE]],
+    ) -> Result<(), ReassemblyError> {
+        let num_chunks = chunk_hashes.len();
+        let max_size = num_chunks as u64 * MAX_CHUNK_SIZE as u64;
+        if num_chunks == 0 || num_chunks > MAX_CHUNKS || size as u64 > max_size {
+            return Err(ReassemblyError::InvalidSize);
+        }
+
+        match ChunkedMessage::<&[u8]>::upload_hash(chunk_hashes) {
+            Ok(upload_hash) if upload_hash == hash => (),
+            _ => return Err(ReassemblyError::HashMismatch),
+        }
+
+        self.expire(host, level)?;
+
+        if self.read_upload(host, &hash)?.is_some() {
+            return Err(ReassemblyError::AlreadyStarted);
+        }
+
+        let upload = Upload {
+            level,
+            num_chunks: num_chunks as u16,
+            size,
+            received: 0,
+            stored: 0,
+        };
+
+        let used = self.read_used(host)?;
+        if used.saturating_add(upload.cost()) > self.quota {
+            return Err(ReassemblyError::QuotaExceeded);
+        }
+
+        self.write_upload(host, &hash, upload)?;
+        let hashes_path = concat(&self.upload_path(&hash), &HASHES_SUFFIX).unwrap();
+        host.store_write_all(&hashes_path, &chunk_hashes.concat())?;
+
+        let (head, tail) = self.read_bounds(host)?;
+        let mut entry = [0; INDEX_ENTRY_SIZE];
+        entry[..CHUNKED_HASH_SIZE].copy_from_slice(&hash);
+        entry[CHUNKED_HASH_SIZE..].copy_from_slice(&level.to_le_bytes());
+        host.store_write_all(&self.entry_path(tail), &entry)?;
+        self.write_bounds(host, head, tail + 1)?;
+
+        self.write_used(host, used + upload.cost())?;
+
+        Ok(())
+    }
+
+    fn add_chunk(
+        &self,
+        host: &mut impl Runtime,
+        level: u32,
+        hash: [u8; CHUNKED_HASH_SIZE],
+        index: u16,
+        data: &[u8],
+    ) -> Result<Option<Vec<u8>>, ReassemblyError> {
+        self.expire(host, level)?;
+
+        let Some(mut upload) = self.read_upload(host, &hash)? else {
+            return Err(ReassemblyError::UnknownUpload);
+        };
+
+        if index >= upload.num_chunks {
+            return Err(ReassemblyError::InvalidChunk);
+        }
+
+        let mut chunk_hash = [0; CHUNKED_HASH_SIZE];
+        let hashes_path = concat(&self.upload_path(&hash), &HASHES_SUFFIX).unwrap();
+        let offset = index as usize * CHUNKED_HASH_SIZE;
+        host.store_read_slice(&hashes_path, offset, &mut chunk_hash)?;
+        match ChunkedMessage::<&[u8]>::chunk_hash(data) {
+            Ok(data_hash) if data_hash == chunk_hash => (),
+            _ => return Err(ReassemblyError::ChunkHashMismatch),
+        }
+
+        let chunk_path = self.chunk_path(&hash, index);
+        let stored = upload.stored as u64 + data.len() as u64;
+        if stored > upload.size as u64 || host.store_has(&chunk_path)?.is_some() {
+            return Err(ReassemblyError::InvalidChunk);
+        }
+
+        host.store_write_all(&chunk_path, data)?;
+        upload.received += 1;
+        upload.stored = stored as u32;
+
+        if upload.received < upload.num_chunks {
+            self.write_upload(host, &hash, upload)?;
+            return Ok(None);
+        }
+
+        let mut payload = Vec::with_capacity(upload.size as usize);
+        for index in 0..upload.num_chunks {
+            let chunk = host.store_read_all(&self.chunk_path(&hash, index))?;
+            payload.extend_from_slice(&chunk);
+        }
+
+        self.remove(host, level, &hash, upload)?;
+        Ok(Some(payload))
+    }
+
+    /// Remove a completed upload. Its index entry is dropped once it is the oldest one.
+    fn remove(
+        &self,
+        host: &mut impl Runtime,
+        level: u32,
+        hash: &[u8; CHUNKED_HASH_SIZE],
+        upload: Upload,
+    ) -> Result<(), ReassemblyError> {
+        host.store_delete(&self.upload_path(hash))?;
+
+        let used = self.read_used(host)?;
+        self.write_used(host, used.saturating_sub(upload.cost()))?;
+
+        self.expire(host, level)?;
+        Ok(())
+    }
+
+    fn upload_path(&self, hash: &[u8; CHUNKED_HASH_SIZE]) -> OwnedPath {
+        let mut path = [b'/'; UPLOAD_SEGMENT_SIZE];
+        let _ = hex::encode_to_slice(hash.as_slice(), &mut path[1..]);
+
+        let path = RefPath::assert_from(path.as_slice());
+
+        // We know this path fits into `PATH_MAX_SIZE` from `Self::new`
+        concat(self.root, &path).unwrap()
+    }
+
+    fn chunk_path(&self, hash: &[u8; CHUNKED_HASH_SIZE], index: u16) -> OwnedPath {
+        let mut path = [b'/'; CHUNK_SEGMENT_SIZE];
+        let _ = hex::encode_to_slice(index.to_be_bytes().as_slice(), &mut path[1..]);
+
+        let path = RefPath::assert_from(path.as_slice());
+
+        // We know this path fits into `PATH_MAX_SIZE` from `Self::new`
+        concat(&self.upload_path(hash), &path).unwrap()
+    }
+
+    fn entry_path(&self, position: u64) -> OwnedPath {
+        let mut path = [b'/'; ENTRY_SEGMENT_SIZE];
+        let _ = hex::encode_to_slice(position.to_be_bytes().as_slice(), &mut path[1..]);
+
+        let path = RefPath::assert_from(path.as_slice());
+
+        // We know this path fits into `PATH_MAX_SIZE` from `Self::new`
+        let index = concat(self.root, &INDEX_SUFFIX).unwrap();
+        concat(&index, &path).unwrap()
+    }
+
+    fn read_upload(
+        &self,
+        host: &impl Runtime,
+        hash: &[u8; CHUNKED_HASH_SIZE],
+    ) -> Result<Option<Upload>, ReassemblyError> {
+        let path = concat(&self.upload_path(hash), &META_SUFFIX).unwrap();
+        if host.store_has(&path)?.is_none() {
+            return Ok(None);
+        }
+
+        let mut buffer = [0; Upload::SIZE];
+        host.store_read_slice(&path, 0, &mut buffer)?;
+        Ok(Some(Upload::from_bytes(&buffer)))
+    }
+
+    fn write_upload(
+        &self,
+        host: &mut impl Runtime,
+        hash: &[u8; CHUNKED_HASH_SIZE],
+        upload: Upload,
+    ) -> Result<(), ReassemblyError> {
+        let path = concat(&self.upload_path(hash), &META_SUFFIX).unwrap();
+        Ok(host.store_write_all(&path, &upload.to_bytes())?)
+    }
+
+    /// Positions of the oldest index entry, and of the next one.
+    fn read_bounds(&self, host: &impl Runtime) -> Result<(u64, u64), ReassemblyError> {
+        let index = concat(self.root, &INDEX_SUFFIX).unwrap();
+        let path = concat(&index, &BOUNDS_SUFFIX).unwrap();
+        if host.store_has(&path)?.is_none() {
+            return Ok((0, 0));
+        }
+
+        let mut buffer = [0; 2 * core::mem::size_of::<u64>()];
+        host.store_read_slice(&path, 0, &mut buffer)?;
+        let (head, tail) = buffer.split_at(core::mem::size_of::<u64>());
+        Ok((
+            u64::from_le_bytes(head.try_into().unwrap()),
+            u64::from_le_bytes(tail.try_into().unwrap()),
+        ))
+    }
+
+    fn write_bounds(
+        &self,
+        host: &mut impl Runtime,
+        head: u64,
+        tail: u64,
+    ) -> Result<(), ReassemblyError> {
+        let index = concat(self.root, &INDEX_SUFFIX).unwrap();
+        if head == tail {
+            return self.delete(host, &index);
+        }
+
+        let path = concat(&index, &BOUNDS_SUFFIX).unwrap();
+        let mut bytes = [0; 2 * core::mem::size_of::<u64>()];
+        bytes[..8].copy_from_slice(&head.to_le_bytes());
+        bytes[8..].copy_from_slice(&tail.to_le_bytes());
+        Ok(host.store_write_all(&path, &bytes)?)
+    }
+
+    fn read_entry(
+        &self,
+        host: &impl Runtime,
+        position: u64,
+    ) -> Result<([u8; CHUNKED_HASH_SIZE], u32), ReassemblyError> {
+        let mut entry = [0; INDEX_ENTRY_SIZE];
+        host.store_read_slice(&self.entry_path(position), 0, &mut entry)?;
+
+        let (hash, level) = entry.split_at(CHUNKED_HASH_SIZE);
+        Ok((
+            hash.try_into().unwrap(),
+            u32::from_le_bytes(level.try_into().unwrap()),
+        ))
+    }
+
+    fn read_used(&self, host: &impl Runtime) -> Result<u64, ReassemblyError> {
+        let path = concat(self.root, &USED_SUFFIX).unwrap();
+        if host.store_has(&path)?.is_none() {
+            return Ok(0);
+        }
+
+        let mut buffer = [0; core::mem::size_of::<u64>()];
+        host.store_read_slice(&path, 0, &mut buffer)?;
+        Ok(u64::from_le_bytes(buffer))
+    }
+
+    fn write_used(
+        &self,
+        host: &mut impl Runtime,
+        used: u64,
+    ) -> Result<(), ReassemblyError> {
+        let path = concat(self.root, &USED_SUFFIX).unwrap();
+        if used == 0 {
+            return self.delete(host, &path);
+        }
+        Ok(host.store_write_all(&path, &used.to_le_bytes())?)
+    }
+
+    fn delete(
+        &self,
+        host: &mut impl Runtime,
+        path: &impl Path,
+    ) -> Result<(), ReassemblyError> {
+        if host.store_has(path)?.is_some() {
+            host.store_delete(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::prelude::*;
+    use crate::types::SmartRollupAddress;
+
+    fn chunks(payload: &[u8]) -> Vec<ChunkedMessage<Vec<u8>>> {
+        let address =
+            SmartRollupAddress::from_b58check("sr163Lv22CdE8QagCwf48PWDTquk6isQwv57")
+                .unwrap();
+
+        ExternalMessageFrame::chunked(&address, payload)
+            .unwrap()
+            .into_iter()
+            .map(|ExternalMessageFrame::Targetted { contents, .. }| {
+                match ChunkedMessage::parse(&contents).unwrap() {
+                    ChunkedMessage::New {
+                        hash,
+                        size,
+                        chunk_hashes,
+                    } => ChunkedMessage::New {
+                        hash,
+                        size,
+                        chunk_hashes,
+                    },
+                    ChunkedMessage::Chunk { hash, index, data } => {
+                        ChunkedMessage::Chunk {
+                            hash,
+                            index,
+                            data: data.to_vec(),
+                        }
+                    }
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reassemble_out_of_order_across_levels() {
+        let mut host = MockHost::default();
+        let payload: Vec<u8> = (0..3 * MAX_CHUNK_SIZE).map(|i| (i % 251) as u8).collect();
+        let mut messages = chunks(&payload).into_iter();
+
+        let new = messages.next().unwrap();
+        let chunks: Vec<_> = messages.collect();
+        assert_eq!(3, chunks.len());
+
+        assert!(matches!(
+            CHUNK_REASSEMBLER.handle(&mut host, 1, &new),
+            Ok(None)
+        ));
+        assert_eq!(1, CHUNK_REASSEMBLER.pending(&host).unwrap().len());
+
+        let mut chunks = chunks.into_iter().rev();
+        for level in 2..4 {
+            let chunk = chunks.next().unwrap();
+            assert!(matches!(
+                CHUNK_REASSEMBLER.handle(&mut host, level, &chunk),
+                Ok(None)
+            ));
+        }
+
+        let result = CHUNK_REASSEMBLER.handle(&mut host, 4, &chunks.next().unwrap());
+        assert_eq!(payload, result.unwrap().unwrap());
+
+        assert!(CHUNK_REASSEMBLER.pending(&host).unwrap().is_empty());
+        assert!(matches!(host.store_has(&CHUNK_REASSEMBLER_ROOT), Ok(None)));
+    }
+
+    /// Hash identifying the upload announced by `new`.
+    fn upload_hash(new: &ChunkedMessage<Vec<u8>>) -> [u8; CHUNKED_HASH_SIZE] {
+        match new {
+            ChunkedMessage::New { hash, .. } => *hash,
+            _ => panic!("expected a new upload"),
+        }
+    }
+
+    #[test]
+    fn reassemble_forged_chunk() {
+        let mut host = MockHost::default();
+        let payload = [1; 100];
+        let messages = chunks(&payload);
+        let forged = ChunkedMessage::Chunk {
+            hash: upload_hash(&messages[0]),
+            index: 0,
+            data: vec![2; 100],
+        };
+
+        CHUNK_REASSEMBLER
+            .handle(&mut host, 1, &messages[0])
+            .unwrap();
+
+        // A forged chunk is rejected without taking the place of the real one
+        assert!(matches!(
+            CHUNK_REASSEMBLER.handle(&mut host, 1, &forged),
+            Err(ReassemblyError::ChunkHashMismatch)
+        ));
+        let result = CHUNK_REASSEMBLER.handle(&mut host, 2, &messages[1]);
+        assert_eq!(payload.to_vec(), result.unwrap().unwrap());
+        assert!(matches!(host.store_has(&CHUNK_REASSEMBLER_ROOT), Ok(None)));
+    }
+
+    #[test]
+    fn reassemble_forged_new() {
+        let mut host = MockHost::default();
+        let payload = [1; 100];
+        let messages = chunks(&payload);
+
+        // Announcing other chunk hashes under the hash of an upload is rejected, and doesn't
+        // prevent the real upload from starting.
+        let forged = ChunkedMessage::<&[u8]>::New {
+            hash: upload_hash(&messages[0]),
+            size: 100,
+            chunk_hashes: vec![[0; CHUNKED_HASH_SIZE]],
+        };
+        assert!(matches!(
+            CHUNK_REASSEMBLER.handle(&mut host, 1, &forged),
+            Err(ReassemblyError::HashMismatch)
+        ));
+        assert!(CHUNK_REASSEMBLER.pending(&host).unwrap().is_empty());
+
+        CHUNK_REASSEMBLER
+            .handle(&mut host, 1, &messages[0])
+            .unwrap();
+        let result = CHUNK_REASSEMBLER.handle(&mut host, 1, &messages[1]);
+        assert_eq!(payload.to_vec(), result.unwrap().unwrap());
+    }
+
+    #[test]
+    fn reassemble_invalid_messages() {
+        let mut host = MockHost::default();
+        let mut messages = chunks(&[1; 2 * MAX_CHUNK_SIZE]).into_iter();
+        let new = messages.next().unwrap();
+        let chunk = messages.next().unwrap();
+
+        // Chunks of unknown uploads
+        assert!(matches!(
+            CHUNK_REASSEMBLER.handle(&mut host, 1, &chunk),
+            Err(ReassemblyError::UnknownUpload)
+        ));
+
+        CHUNK_REASSEMBLER.handle(&mut host, 1, &new).unwrap();
+        assert!(matches!(
+            CHUNK_REASSEMBLER.handle(&mut host, 1, &new),
+            Err(ReassemblyError::AlreadyStarted)
+        ));
+
+        // Duplicated chunks
+        CHUNK_REASSEMBLER.handle(&mut host, 1, &chunk).unwrap();
+        assert!(matches!(
+            CHUNK_REASSEMBLER.handle(&mut host, 1, &chunk),
+            Err(ReassemblyError::InvalidChunk)
+        ));
+
+        // Sizes not matching the number of chunks
+        let chunk_hashes = vec![[0; CHUNKED_HASH_SIZE]];
+        let new = ChunkedMessage::<&[u8]>::New {
+            hash: ChunkedMessage::<&[u8]>::upload_hash(&chunk_hashes).unwrap(),
+            size: MAX_CHUNK_SIZE as u32 + 1,
+            chunk_hashes,
+        };
+        assert!(matches!(
+            CHUNK_REASSEMBLER.handle(&mut host, 1, &new),
+            Err(ReassemblyError::InvalidSize)
+        ));
+    }
+
+    #[test]
+    fn reassemble_expiry_and_quota() {
+        let mut host = MockHost::default();
+        let root = RefPath::assert_from(b"/chunked");
+        let reassembler =
+            ChunkReassembler::new(&root, 10, 150 + 2 * (UPLOAD_OVERHEAD + 32)).unwrap();
+
+        let first = chunks(&[1; 100]);
+        let second = chunks(&[2; 100]);
+
+        reassembler.handle(&mut host, 1, &first[0]).unwrap();
+        // The quota is reserved for the announced size & chunk hashes
+        assert!(matches!(
+            reassembler.handle(&mut host, 5, &second[0]),
+            Err(ReassemblyError::QuotaExceeded)
+        ));
+
+        // Starting an upload discards the expired ones, releasing their quota
+        reassembler.handle(&mut host, 12, &second[0]).unwrap();
+        assert_eq!(
+            vec![upload_hash(&second[0])],
+            reassembler.pending(&host).unwrap()
+        );
+        assert!(matches!(
+            reassembler.handle(&mut host, 12, &first[1]),
+            Err(ReassemblyError::UnknownUpload)
+        ));
+
+        assert_eq!(1, reassembler.expire(&mut host, 23).unwrap());
+        assert!(matches!(host.store_has(&root), Ok(None)));
+    }
+
+    #[test]
+    fn reassemble_empty_uploads_use_quota() {
+        let mut host = MockHost::default();
+        let root = RefPath::assert_from(b"/chunked");
+        let reassembler =
+            ChunkReassembler::new(&root, 10, 2 * (UPLOAD_OVERHEAD + 32)).unwrap();
+
+        let new = |byte| {
+            let chunk_hashes = vec![[byte; CHUNKED_HASH_SIZE]];
+            ChunkedMessage::<Vec<u8>>::New {
+                hash: ChunkedMessage::<&[u8]>::upload_hash(&chunk_hashes).unwrap(),
+                size: 0,
+                chunk_hashes,
+            }
+        };
+
+        reassembler.handle(&mut host, 1, &new(1)).unwrap();
+        reassembler.handle(&mut host, 1, &new(2)).unwrap();
+        assert!(matches!(
+            reassembler.handle(&mut host, 1, &new(3)),
+            Err(ReassemblyError::QuotaExceeded)
+        ));
+        assert_eq!(
+            vec![upload_hash(&new(1)), upload_hash(&new(2))],
+            reassembler.pending(&host).unwrap()
+        );
+    }
+
+    #[test]
+    fn reassemble_chunks_expire_uploads() {
+        let mut host = MockHost::default();
+        let root = RefPath::assert_from(b"/chunked");
+        let reassembler = ChunkReassembler::new(&root, 10, DEFAULT_QUOTA).unwrap();
+
+        let messages = chunks(&[1; 100]);
+        reassembler.handle(&mut host, 1, &messages[0]).unwrap();
+
+        // A chunk arriving after the expiry of its upload discards it
+        assert!(matches!(
+            reassembler.handle(&mut host, 12, &messages[1]),
+            Err(ReassemblyError::UnknownUpload)
+        ));
+        assert!(matches!(host.store_has(&root), Ok(None)));
+    }
+
+    #[test]
+    fn reassemble_completed_entries_are_dropped() {
+        let mut host = MockHost::default();
+        let root = RefPath::assert_from(b"/chunked");
+        let reassembler = ChunkReassembler::new(&root, 10, DEFAULT_QUOTA).unwrap();
+
+        let first = chunks(&[1; 100]);
+        let second = chunks(&[2; 100]);
+
+        reassembler.handle(&mut host, 1, &first[0]).unwrap();
+        reassembler.handle(&mut host, 2, &second[0]).unwrap();
+
+        // The entry of the second upload stays behind the first one
+        reassembler
+            .handle(&mut host, 3, &second[1])
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            vec![upload_hash(&first[0])],
+            reassembler.pending(&host).unwrap()
+        );
+
+        // Both are dropped once the first one completes
+        reassembler
+            .handle(&mut host, 4, &first[1])
+            .unwrap()
+            .unwrap();
+        assert!(matches!(host.store_has(&root), Ok(None)));
+    }
+}
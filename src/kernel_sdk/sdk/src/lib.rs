@@ -82,14 +82,17 @@ pub mod types {
 pub use tezos_smart_rollup_encoding::dac;
 #[cfg(feature = "data-encoding")]
 #[doc(inline)]
-pub use tezos_smart_rollup_encoding::inbox;
-#[cfg(feature = "data-encoding")]
-#[doc(inline)]
 pub use tezos_smart_rollup_encoding::michelson;
 
+#[cfg(feature = "data-encoding")]
+pub mod inbox;
 #[cfg(feature = "data-encoding")]
 pub mod outbox;
 
+#[cfg(feature = "debug_alloc")]
+#[doc(inline)]
+pub use tezos_smart_rollup_debug::{debug, error, info, log, trace, warn};
+
 pub mod storage {
     //! Durable Storage allows state to be persisted between
     //! multiple calls to the kernel.
@@ -0,0 +1,286 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+use super::{
+    delete_subtree, encode, index_path, read_u64, read_value, write_u64, write_value,
+};
+use crate::StorageError;
+use core::marker::PhantomData;
+use host::path::{concat, OwnedPath, Path, RefPath};
+use host::runtime::Runtime;
+use tezos_crypto_rs::blake2b::digest_256;
+use tezos_data_encoding::enc::BinWriter;
+use tezos_data_encoding::nom::NomReader;
+
+const LENGTH: RefPath = RefPath::assert_from(b"/length");
+const INDEX: RefPath = RefPath::assert_from(b"/index");
+const ENTRIES: RefPath = RefPath::assert_from(b"/entries");
+const KEY: RefPath = RefPath::assert_from(b"/key");
+const VALUE: RefPath = RefPath::assert_from(b"/value");
+const POSITION: RefPath = RefPath::assert_from(b"/position");
+
+/// Maps the keys of a [StorageMap] to path segments.
+pub trait KeyHasher {
+    /// Path segment, including its leading `/`, of the entry of the key with
+    /// the given binary encoding.
+    fn key_path(encoded_key: &[u8]) -> Result<OwnedPath, StorageError>;
+}
+
+/// Hashes keys with blake2b, giving path segments of fixed size whatever
+/// the size of the keys.
+pub struct Blake2bKeys;
+
+impl KeyHasher for Blake2bKeys {
+    fn key_path(encoded_key: &[u8]) -> Result<OwnedPath, StorageError> {
+        let hash = digest_256(encoded_key).map_err(|_| StorageError::EncodingError)?;
+        HexKeys::key_path(&hash)
+    }
+}
+
+/// Uses the hex encoding of keys as path segments, which is cheaper than
+/// hashing them, but only works for keys small enough to fit in a path.
+pub struct HexKeys;
+
+impl KeyHasher for HexKeys {
+    fn key_path(encoded_key: &[u8]) -> Result<OwnedPath, StorageError> {
+        let segment = format!("/{}", hex::encode(encoded_key));
+        OwnedPath::try_from(segment.into_bytes()).map_err(StorageError::from)
+    }
+}
+
+/// Map in durable storage.
+///
+/// Stored under its path as:
+/// - `/length`: the number of entries, as a little-endian `u64`
+/// - `/entries/<hash>/{key,value}`: the key & value of the entry whose key
+///   hashes to `<hash>`
+/// - `/entries/<hash>/position`: the position of the entry in the index
+/// - `/index/<position>`: the `<hash>` of the entry at `position`, for
+///   `position < length`
+///
+/// The index allows iterating over the entries. Removing an entry moves the
+/// last entry of the index in its place, so that insertion & removal only
+/// touch a bounded number of values.
+pub struct StorageMap<K, V, H = Blake2bKeys> {
+    length: OwnedPath,
+    index: OwnedPath,
+    entries: OwnedPath,
+    _type: PhantomData<(K, V, H)>,
+}
+
+impl<K, V, H> StorageMap<K, V, H>
+where
+    K: BinWriter + NomReader,
+    V: BinWriter + NomReader,
+    H: KeyHasher,
+{
+    /// View on the map stored under `path`, which is empty if nothing is
+    /// stored there yet.
+    pub fn new(path: &impl Path) -> Result<Self, StorageError> {
+        Ok(Self {
+            length: concat(path, &LENGTH)?,
+            index: concat(path, &INDEX)?,
+            entries: concat(path, &ENTRIES)?,
+            _type: PhantomData,
+        })
+    }
+
+    /// Number of entries.
+    pub fn len(&self, host: &impl Runtime) -> Result<u64, StorageError> {
+        read_u64(host, &self.length)
+    }
+
+    /// Whether the map has no entries.
+    pub fn is_empty(&self, host: &impl Runtime) -> Result<bool, StorageError> {
+        Ok(self.len(host)? == 0)
+    }
+
+    /// Value associated to `key`, if any.
+    pub fn get(&self, host: &impl Runtime, key: &K) -> Result<Option<V>, StorageError> {
+        let entry = self.entry_path(key)?;
+        read_value(host, &concat(&entry, &VALUE)?)
+    }
+
+    /// Whether the map has an entry for `key`.
+    pub fn contains_key(
+        &self,
+        host: &impl Runtime,
+        key: &K,
+    ) -> Result<bool, StorageError> {
+        let entry = self.entry_path(key)?;
+        Ok(host.store_has(&concat(&entry, &VALUE)?)?.is_some())
+    }
+
+    /// Associate `value` to `key`, replacing any previous value.
+    pub fn insert(
+        &mut self,
+        host: &mut impl Runtime,
+        key: &K,
+        value: &V,
+    ) -> Result<(), StorageError> {
+        let segment = H::key_path(&encode(key)?)?;
+        let entry = concat(&self.entries, &segment)?;
+        let value_path = concat(&entry, &VALUE)?;
+
+        if host.store_has(&value_path)?.is_none() {
+            let length = self.len(host)?;
+            write_value(host, &concat(&entry, &KEY)?, key)?;
+            write_u64(host, &concat(&entry, &POSITION)?, length)?;
+            host.store_write_all(&index_path(&self.index, length)?, segment.as_bytes())?;
+            write_u64(host, &self.length, length + 1)?;
+        }
+
+        write_value(host, &value_path, value)
+    }
+
+    /// Remove the entry for `key`. Returns whether there was one.
+    pub fn remove(
+        &mut self,
+        host: &mut impl Runtime,
+        key: &K,
+    ) -> Result<bool, StorageError> {
+        let entry = self.entry_path(key)?;
+        if host.store_has(&entry)?.is_none() {
+            return Ok(false);
+        }
+
+        let position = read_u64(host, &concat(&entry, &POSITION)?)?;
+        let last = self
+            .len(host)?
+            .checked_sub(1)
+            .ok_or(StorageError::DecodingError)?;
+        let last_path = index_path(&self.index, last)?;
+
+        if position != last {
+            // Move the last entry of the index to the removed position
+            let last_segment = host.store_read_all(&last_path)?;
+            let last_entry = concat(&self.entries, &segment_path(last_segment.clone())?)?;
+            host.store_write_all(&index_path(&self.index, position)?, &last_segment)?;
+            write_u64(host, &concat(&last_entry, &POSITION)?, position)?;
+        }
+
+        host.store_delete_value(&last_path)?;
+        delete_subtree(host, &entry)?;
+        write_u64(host, &self.length, last)?;
+        Ok(true)
+    }
+
+    /// Remove all entries.
+    pub fn clear(&mut self, host: &mut impl Runtime) -> Result<(), StorageError> {
+        delete_subtree(host, &self.entries)?;
+        delete_subtree(host, &self.index)?;
+        delete_subtree(host, &self.length)
+    }
+
+    /// Iterate over the entries. The order is the insertion order, except
+    /// that removing an entry moves the last one in its place. Each step
+    /// reads one entry.
+    pub fn iter<'a>(
+        &'a self,
+        host: &'a impl Runtime,
+    ) -> Result<impl Iterator<Item = Result<(K, V), StorageError>> + 'a, StorageError>
+    {
+        let length = self.len(host)?;
+        Ok((0..length).map(move |position| self.read_entry(host, position)))
+    }
+
+    fn entry_path(&self, key: &K) -> Result<OwnedPath, StorageError> {
+        let segment = H::key_path(&encode(key)?)?;
+        concat(&self.entries, &segment).map_err(StorageError::from)
+    }
+
+    fn read_entry(
+        &self,
+        host: &impl Runtime,
+        position: u64,
+    ) -> Result<(K, V), StorageError> {
+        let segment = host.store_read_all(&index_path(&self.index, position)?)?;
+        let entry = concat(&self.entries, &segment_path(segment)?)?;
+        let key = read_value(host, &concat(&entry, &KEY)?)?;
+        let value = read_value(host, &concat(&entry, &VALUE)?)?;
+        key.zip(value).ok_or(StorageError::DecodingError)
+    }
+}
+
+/// Path segment of an entry, as stored in the index.
+fn segment_path(segment: Vec<u8>) -> Result<OwnedPath, StorageError> {
+    OwnedPath::try_from(segment).map_err(|_| StorageError::DecodingError)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HexKeys, StorageMap};
+    use host::path::RefPath;
+    use std::collections::BTreeMap;
+    use tezos_smart_rollup_encoding::michelson::{MichelsonInt, MichelsonString};
+    use tezos_smart_rollup_mock::MockHost;
+
+    const MAP_PATH: RefPath = RefPath::assert_from(b"/map");
+
+    fn string(s: &str) -> MichelsonString {
+        MichelsonString(s.to_string())
+    }
+
+    fn entries<H: super::KeyHasher>(
+        host: &MockHost,
+        map: &StorageMap<MichelsonString, MichelsonInt, H>,
+    ) -> BTreeMap<String, i32> {
+        map.iter(host)
+            .unwrap()
+            .map(|entry| {
+                let (key, value) = entry.unwrap();
+                (key.0, i32::try_from(&value.0 .0).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn insert_get_remove() {
+        let mut host = MockHost::default();
+        let mut map =
+            StorageMap::<MichelsonString, MichelsonInt>::new(&MAP_PATH).unwrap();
+
+        map.insert(&mut host, &string("a"), &1.into()).unwrap();
+        map.insert(&mut host, &string("b"), &2.into()).unwrap();
+        map.insert(&mut host, &string("c"), &3.into()).unwrap();
+        map.insert(&mut host, &string("a"), &4.into()).unwrap();
+
+        assert_eq!(Ok(3), map.len(&host));
+        assert_eq!(Ok(Some(4.into())), map.get(&host, &string("a")));
+        assert_eq!(Ok(None), map.get(&host, &string("d")));
+        assert_eq!(Ok(true), map.contains_key(&host, &string("b")));
+
+        // Removing the first entry moves the last one in its place
+        assert_eq!(Ok(true), map.remove(&mut host, &string("a")));
+        assert_eq!(Ok(false), map.remove(&mut host, &string("a")));
+        assert_eq!(
+            BTreeMap::from([("b".to_string(), 2), ("c".to_string(), 3)]),
+            entries(&host, &map)
+        );
+
+        // The moved entry can still be removed
+        assert_eq!(Ok(true), map.remove(&mut host, &string("c")));
+        assert_eq!(BTreeMap::from([("b".to_string(), 2)]), entries(&host, &map));
+
+        map.clear(&mut host).unwrap();
+        assert_eq!(Ok(true), map.is_empty(&host));
+        assert_eq!(Ok(None), map.get(&host, &string("b")));
+    }
+
+    #[test]
+    fn hex_keys() {
+        let mut host = MockHost::default();
+        let mut map =
+            StorageMap::<MichelsonString, MichelsonInt, HexKeys>::new(&MAP_PATH).unwrap();
+
+        map.insert(&mut host, &string("a"), &1.into()).unwrap();
+        assert_eq!(Ok(Some(1.into())), map.get(&host, &string("a")));
+
+        // Keys too large to fit in a path are rejected
+        assert!(map
+            .insert(&mut host, &string(&"a".repeat(200)), &2.into())
+            .is_err());
+        assert_eq!(BTreeMap::from([("a".to_string(), 1)]), entries(&host, &map));
+    }
+}
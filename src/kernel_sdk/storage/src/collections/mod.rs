@@ -0,0 +1,116 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Typed collections in durable storage.
+//!
+//! Each collection lives under a path of the durable storage, and stores its
+//! elements - encoded with [BinWriter] and decoded with [NomReader] - in
+//! separate values under that path. Collections keep track of their length,
+//! so that it can be read, and the collection iterated over, without relying
+//! on `store_list_size` & friends:
+//!
+//! - [StorageVec]: a growable array, indexed by `u64`.
+//! - [StorageMap]: a map, whose keys are hashed to path segments by a
+//!   [KeyHasher].
+//! - [StorageQueue]: a FIFO queue.
+//!
+//! Collections hold no state other than their path: several instances for
+//! the same path are views on the same collection.
+
+mod map;
+mod queue;
+mod vec;
+
+pub use map::{Blake2bKeys, HexKeys, KeyHasher, StorageMap};
+pub use queue::StorageQueue;
+pub use vec::StorageVec;
+
+use crate::StorageError;
+use host::path::{concat, OwnedPath, Path, RefPath};
+use host::runtime::{Runtime, RuntimeError};
+use tezos_data_encoding::enc::BinWriter;
+use tezos_data_encoding::nom::NomReader;
+
+/// Whether the error is the one returned when reading a missing value.
+fn is_missing(error: &RuntimeError) -> bool {
+    matches!(
+        error,
+        RuntimeError::PathNotFound | RuntimeError::HostErr(host::Error::StoreNotAValue)
+    )
+}
+
+/// Path of the `index`th element of a collection stored under `prefix`.
+fn index_path(prefix: &impl Path, index: u64) -> Result<OwnedPath, StorageError> {
+    let index = format!("/{index}");
+    // An integer is always a valid path segment.
+    concat(prefix, &RefPath::assert_from(index.as_bytes())).map_err(StorageError::from)
+}
+
+/// Read a little-endian `u64`, which is 0 if missing.
+fn read_u64(host: &impl Runtime, path: &impl Path) -> Result<u64, StorageError> {
+    let mut buffer = [0_u8; core::mem::size_of::<u64>()];
+    match host.store_read_slice(path, 0, &mut buffer) {
+        Ok(size) if size == buffer.len() => Ok(u64::from_le_bytes(buffer)),
+        Ok(_) => Err(StorageError::DecodingError),
+        Err(error) if is_missing(&error) => Ok(0),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Write a little-endian `u64`.
+fn write_u64(
+    host: &mut impl Runtime,
+    path: &impl Path,
+    value: u64,
+) -> Result<(), StorageError> {
+    host.store_write_all(path, &value.to_le_bytes())
+        .map_err(StorageError::from)
+}
+
+/// Encode a value with its binary encoding.
+fn encode(value: &impl BinWriter) -> Result<Vec<u8>, StorageError> {
+    let mut bytes = Vec::new();
+    value
+        .bin_write(&mut bytes)
+        .map_err(|_| StorageError::EncodingError)?;
+    Ok(bytes)
+}
+
+/// Decode a value, which must span all of `bytes`.
+fn decode<T: NomReader>(bytes: &[u8]) -> Result<T, StorageError> {
+    match T::nom_read(bytes) {
+        Ok(([], value)) => Ok(value),
+        _ => Err(StorageError::DecodingError),
+    }
+}
+
+/// Read and decode the value at `path`, if any.
+fn read_value<T: NomReader>(
+    host: &impl Runtime,
+    path: &impl Path,
+) -> Result<Option<T>, StorageError> {
+    match host.store_read_all(path) {
+        Ok(bytes) => decode(&bytes).map(Some),
+        Err(error) if is_missing(&error) => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Encode and write a value at `path`, replacing any previous one.
+fn write_value(
+    host: &mut impl Runtime,
+    path: &impl Path,
+    value: &impl BinWriter,
+) -> Result<(), StorageError> {
+    host.store_write_all(path, &encode(value)?)
+        .map_err(StorageError::from)
+}
+
+/// Delete the subtree at `path`, if any.
+fn delete_subtree(host: &mut impl Runtime, path: &impl Path) -> Result<(), StorageError> {
+    match host.store_delete(path) {
+        Ok(()) | Err(RuntimeError::PathNotFound) => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
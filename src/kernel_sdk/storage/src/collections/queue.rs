@@ -0,0 +1,166 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+use super::{delete_subtree, index_path, read_u64, read_value, write_u64, write_value};
+use crate::StorageError;
+use core::marker::PhantomData;
+use host::path::{concat, OwnedPath, Path, RefPath};
+use host::runtime::Runtime;
+use tezos_data_encoding::enc::BinWriter;
+use tezos_data_encoding::nom::NomReader;
+
+const FRONT: RefPath = RefPath::assert_from(b"/front");
+const BACK: RefPath = RefPath::assert_from(b"/back");
+const ELEMENTS: RefPath = RefPath::assert_from(b"/elements");
+
+/// FIFO queue in durable storage.
+///
+/// Stored under its path as:
+/// - `/front`: the index of the first element, as a little-endian `u64`
+/// - `/back`: the index following the last element, as a little-endian `u64`
+/// - `/elements/<index>`: the element at `index`, for `front <= index < back`
+///
+/// Indices keep increasing as elements are pushed, and are reset when the
+/// queue is emptied.
+pub struct StorageQueue<T> {
+    front: OwnedPath,
+    back: OwnedPath,
+    elements: OwnedPath,
+    _type: PhantomData<T>,
+}
+
+impl<T: BinWriter + NomReader> StorageQueue<T> {
+    /// View on the queue stored under `path`, which is empty if nothing is
+    /// stored there yet.
+    pub fn new(path: &impl Path) -> Result<Self, StorageError> {
+        Ok(Self {
+            front: concat(path, &FRONT)?,
+            back: concat(path, &BACK)?,
+            elements: concat(path, &ELEMENTS)?,
+            _type: PhantomData,
+        })
+    }
+
+    /// Number of elements.
+    pub fn len(&self, host: &impl Runtime) -> Result<u64, StorageError> {
+        let (front, back) = self.bounds(host)?;
+        Ok(back - front)
+    }
+
+    /// Whether the queue has no elements.
+    pub fn is_empty(&self, host: &impl Runtime) -> Result<bool, StorageError> {
+        Ok(self.len(host)? == 0)
+    }
+
+    /// First element, if any.
+    pub fn front(&self, host: &impl Runtime) -> Result<Option<T>, StorageError> {
+        let (front, back) = self.bounds(host)?;
+        if front == back {
+            return Ok(None);
+        }
+        self.read(host, front).map(Some)
+    }
+
+    /// Add an element at the back of the queue.
+    pub fn push_back(
+        &mut self,
+        host: &mut impl Runtime,
+        value: &T,
+    ) -> Result<(), StorageError> {
+        let back = read_u64(host, &self.back)?;
+        write_value(host, &index_path(&self.elements, back)?, value)?;
+        write_u64(host, &self.back, back + 1)
+    }
+
+    /// Remove the first element, and return it.
+    pub fn pop_front(
+        &mut self,
+        host: &mut impl Runtime,
+    ) -> Result<Option<T>, StorageError> {
+        let (front, back) = self.bounds(host)?;
+        if front == back {
+            return Ok(None);
+        }
+
+        let value = self.read(host, front)?;
+        if front + 1 == back {
+            self.clear(host)?;
+        } else {
+            host.store_delete_value(&index_path(&self.elements, front)?)?;
+            write_u64(host, &self.front, front + 1)?;
+        }
+        Ok(Some(value))
+    }
+
+    /// Remove all elements.
+    pub fn clear(&mut self, host: &mut impl Runtime) -> Result<(), StorageError> {
+        delete_subtree(host, &self.elements)?;
+        delete_subtree(host, &self.front)?;
+        delete_subtree(host, &self.back)
+    }
+
+    /// Iterate over the elements, from front to back. Each step reads one
+    /// element.
+    pub fn iter<'a>(
+        &'a self,
+        host: &'a impl Runtime,
+    ) -> Result<impl Iterator<Item = Result<T, StorageError>> + 'a, StorageError> {
+        let (front, back) = self.bounds(host)?;
+        Ok((front..back).map(move |index| self.read(host, index)))
+    }
+
+    fn bounds(&self, host: &impl Runtime) -> Result<(u64, u64), StorageError> {
+        let front = read_u64(host, &self.front)?;
+        let back = read_u64(host, &self.back)?;
+        if front > back {
+            return Err(StorageError::DecodingError);
+        }
+        Ok((front, back))
+    }
+
+    fn read(&self, host: &impl Runtime, index: u64) -> Result<T, StorageError> {
+        read_value(host, &index_path(&self.elements, index)?)?
+            .ok_or(StorageError::DecodingError)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StorageQueue;
+    use host::path::RefPath;
+    use host::runtime::Runtime;
+    use tezos_smart_rollup_encoding::michelson::MichelsonString;
+    use tezos_smart_rollup_mock::MockHost;
+
+    const QUEUE_PATH: RefPath = RefPath::assert_from(b"/queue");
+
+    fn string(s: &str) -> MichelsonString {
+        MichelsonString(s.to_string())
+    }
+
+    #[test]
+    fn push_and_pop_in_order() {
+        let mut host = MockHost::default();
+        let mut queue = StorageQueue::<MichelsonString>::new(&QUEUE_PATH).unwrap();
+
+        assert_eq!(Ok(None), queue.pop_front(&mut host));
+
+        queue.push_back(&mut host, &string("a")).unwrap();
+        queue.push_back(&mut host, &string("b")).unwrap();
+        assert_eq!(Ok(Some(string("a"))), queue.pop_front(&mut host));
+        queue.push_back(&mut host, &string("c")).unwrap();
+
+        assert_eq!(Ok(2), queue.len(&host));
+        assert_eq!(Ok(Some(string("b"))), queue.front(&host));
+        let elements: Result<Vec<_>, _> = queue.iter(&host).unwrap().collect();
+        assert_eq!(Ok(vec![string("b"), string("c")]), elements);
+
+        assert_eq!(Ok(Some(string("b"))), queue.pop_front(&mut host));
+        assert_eq!(Ok(Some(string("c"))), queue.pop_front(&mut host));
+        assert_eq!(Ok(true), queue.is_empty(&host));
+
+        // Emptying the queue removes it from the storage
+        assert_eq!(Ok(None), host.store_has(&QUEUE_PATH));
+    }
+}
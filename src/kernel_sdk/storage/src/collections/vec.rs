@@ -0,0 +1,165 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+use super::{delete_subtree, index_path, read_u64, read_value, write_u64, write_value};
+use crate::StorageError;
+use core::marker::PhantomData;
+use host::path::{concat, OwnedPath, Path, RefPath};
+use host::runtime::Runtime;
+use tezos_data_encoding::enc::BinWriter;
+use tezos_data_encoding::nom::NomReader;
+
+const LENGTH: RefPath = RefPath::assert_from(b"/length");
+const ELEMENTS: RefPath = RefPath::assert_from(b"/elements");
+
+/// Growable array in durable storage.
+///
+/// Stored under its path as:
+/// - `/length`: the number of elements, as a little-endian `u64`
+/// - `/elements/<index>`: the element at `index`, for `index < length`
+pub struct StorageVec<T> {
+    length: OwnedPath,
+    elements: OwnedPath,
+    _type: PhantomData<T>,
+}
+
+impl<T: BinWriter + NomReader> StorageVec<T> {
+    /// View on the vector stored under `path`, which is empty if nothing is
+    /// stored there yet.
+    pub fn new(path: &impl Path) -> Result<Self, StorageError> {
+        Ok(Self {
+            length: concat(path, &LENGTH)?,
+            elements: concat(path, &ELEMENTS)?,
+            _type: PhantomData,
+        })
+    }
+
+    /// Number of elements.
+    pub fn len(&self, host: &impl Runtime) -> Result<u64, StorageError> {
+        read_u64(host, &self.length)
+    }
+
+    /// Whether the vector has no elements.
+    pub fn is_empty(&self, host: &impl Runtime) -> Result<bool, StorageError> {
+        Ok(self.len(host)? == 0)
+    }
+
+    /// Element at `index`, or `None` if out of bounds.
+    pub fn get(
+        &self,
+        host: &impl Runtime,
+        index: u64,
+    ) -> Result<Option<T>, StorageError> {
+        if index >= self.len(host)? {
+            return Ok(None);
+        }
+        self.read(host, index).map(Some)
+    }
+
+    /// Replace the element at `index`.
+    pub fn set(
+        &mut self,
+        host: &mut impl Runtime,
+        index: u64,
+        value: &T,
+    ) -> Result<(), StorageError> {
+        if index >= self.len(host)? {
+            return Err(StorageError::IndexOutOfBounds);
+        }
+        write_value(host, &index_path(&self.elements, index)?, value)
+    }
+
+    /// Append an element.
+    pub fn push(
+        &mut self,
+        host: &mut impl Runtime,
+        value: &T,
+    ) -> Result<(), StorageError> {
+        let length = self.len(host)?;
+        write_value(host, &index_path(&self.elements, length)?, value)?;
+        write_u64(host, &self.length, length + 1)
+    }
+
+    /// Remove the last element, and return it.
+    pub fn pop(&mut self, host: &mut impl Runtime) -> Result<Option<T>, StorageError> {
+        let length = self.len(host)?;
+        if length == 0 {
+            return Ok(None);
+        }
+
+        let path = index_path(&self.elements, length - 1)?;
+        let value = read_value(host, &path)?.ok_or(StorageError::DecodingError)?;
+        host.store_delete_value(&path)?;
+        write_u64(host, &self.length, length - 1)?;
+        Ok(Some(value))
+    }
+
+    /// Remove all elements.
+    pub fn clear(&mut self, host: &mut impl Runtime) -> Result<(), StorageError> {
+        delete_subtree(host, &self.elements)?;
+        delete_subtree(host, &self.length)
+    }
+
+    /// Iterate over the elements, in order. Each step reads one element.
+    pub fn iter<'a>(
+        &'a self,
+        host: &'a impl Runtime,
+    ) -> Result<impl Iterator<Item = Result<T, StorageError>> + 'a, StorageError> {
+        let length = self.len(host)?;
+        Ok((0..length).map(move |index| self.read(host, index)))
+    }
+
+    fn read(&self, host: &impl Runtime, index: u64) -> Result<T, StorageError> {
+        read_value(host, &index_path(&self.elements, index)?)?
+            .ok_or(StorageError::DecodingError)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StorageVec;
+    use crate::StorageError;
+    use host::path::RefPath;
+    use tezos_smart_rollup_encoding::michelson::MichelsonString;
+    use tezos_smart_rollup_mock::MockHost;
+
+    const VEC_PATH: RefPath = RefPath::assert_from(b"/vec");
+
+    fn string(s: &str) -> MichelsonString {
+        MichelsonString(s.to_string())
+    }
+
+    #[test]
+    fn push_get_set_pop() {
+        let mut host = MockHost::default();
+        let mut vec = StorageVec::<MichelsonString>::new(&VEC_PATH).unwrap();
+
+        assert_eq!(Ok(true), vec.is_empty(&host));
+        assert_eq!(Ok(None), vec.pop(&mut host));
+
+        vec.push(&mut host, &string("a")).unwrap();
+        vec.push(&mut host, &string("b")).unwrap();
+        vec.set(&mut host, 0, &string("c")).unwrap();
+
+        assert_eq!(Ok(2), vec.len(&host));
+        assert_eq!(Ok(Some(string("c"))), vec.get(&host, 0));
+        assert_eq!(Ok(None), vec.get(&host, 2));
+        assert_eq!(
+            Err(StorageError::IndexOutOfBounds),
+            vec.set(&mut host, 2, &string("d"))
+        );
+
+        // Another view on the same path sees the same elements
+        let other = StorageVec::<MichelsonString>::new(&VEC_PATH).unwrap();
+        let elements: Result<Vec<_>, _> = other.iter(&host).unwrap().collect();
+        assert_eq!(Ok(vec![string("c"), string("b")]), elements);
+
+        assert_eq!(Ok(Some(string("b"))), vec.pop(&mut host));
+        assert_eq!(Ok(1), vec.len(&host));
+
+        vec.clear(&mut host).unwrap();
+        assert_eq!(Ok(true), vec.is_empty(&host));
+        assert_eq!(0, vec.iter(&host).unwrap().count());
+    }
+}
@@ -0,0 +1,858 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Journaling storage API for transactional storage updates.
+//!
+//! [Storage] copies the whole subtree of its objects on every
+//! [begin_transaction], so that the cost of a transaction grows with the size
+//! of the state. [JournaledStorage] instead keeps, for each transaction, a
+//! journal of the values written, the values deleted and the subtrees deleted
+//! in it:
+//!
+//! - reads fall through the journals, from the innermost transaction to the
+//!   durable storage,
+//! - committing a nested transaction merges its journal into the enclosing
+//!   one, and committing the outermost transaction writes the journal to the
+//!   durable storage,
+//! - rolling back a transaction drops its journal.
+//!
+//! Only the changed values are ever written, whatever the size of the state.
+//!
+//! Objects are the same as for [Storage], and are used through the [Runtime]
+//! returned by [JournaledStorage::runtime], which journals the changes made
+//! under the storage's path:
+//!
+//! ```
+//! use tezos_smart_rollup_host::path::{concat, OwnedPath, RefPath};
+//! use tezos_smart_rollup_host::runtime::Runtime;
+//! use tezos_smart_rollup_mock::MockHost;
+//! use tezos_smart_rollup_storage::journal::JournaledStorage;
+//!
+//! struct Account {
+//!     path: OwnedPath,
+//! }
+//!
+//! impl From<OwnedPath> for Account {
+//!     fn from(path: OwnedPath) -> Self {
+//!         Self { path }
+//!     }
+//! }
+//!
+//! const BALANCE: RefPath = RefPath::assert_from(b"/balance");
+//!
+//! impl Account {
+//!     fn set_balance(&mut self, host: &mut impl Runtime, balance: u64) {
+//!         let path = concat(&self.path, &BALANCE).unwrap();
+//!         host.store_write_all(&path, &balance.to_le_bytes()).unwrap();
+//!     }
+//! }
+//!
+//! const ACCOUNTS: RefPath = RefPath::assert_from(b"/accounts");
+//! const ALICE: RefPath = RefPath::assert_from(b"/alice");
+//!
+//! let mut host = MockHost::default();
+//! let mut storage = JournaledStorage::<Account>::init(&ACCOUNTS).unwrap();
+//!
+//! storage.begin_transaction(&mut host).unwrap();
+//! let mut alice = storage.create_new(&mut host, &ALICE).unwrap().unwrap();
+//! alice.set_balance(&mut storage.runtime(&mut host), 10);
+//!
+//! // Nothing is written to durable storage before the transaction commits.
+//! assert!(storage.get_original(&host, &ALICE).unwrap().is_none());
+//! assert!(storage.get(&host, &ALICE).unwrap().is_some());
+//!
+//! storage.commit_transaction(&mut host).unwrap();
+//! assert!(storage.get_original(&host, &ALICE).unwrap().is_some());
+//! ```
+//!
+//! Subtrees under the storage's path cannot be moved or copied while a
+//! transaction is in progress, as the durable subtrees cannot be listed.
+//!
+//! # Warning
+//!
+//! [JournaledStorage] is **not** a drop-in replacement for [Storage]. Objects
+//! hold their durable path rather than a path in a transaction's copy, so the
+//! journal only sees the accesses made through [JournaledStorage::runtime].
+//! Anything an object writes directly on the host goes to durable storage
+//! straight away, is invisible to the journal, and survives a rollback.
+//!
+//! [Storage]: crate::storage::Storage
+//! [begin_transaction]: crate::storage::Storage::begin_transaction
+
+use crate::StorageError;
+use core::marker::PhantomData;
+use std::collections::{BTreeMap, BTreeSet};
+use tezos_smart_rollup_core::{MAX_FILE_CHUNK_SIZE, PREIMAGE_HASH_SIZE};
+#[cfg(feature = "proto-alpha")]
+use tezos_smart_rollup_host::dal_parameters::RollupDalParameters;
+use tezos_smart_rollup_host::input::Message;
+use tezos_smart_rollup_host::metadata::RollupMetadata;
+use tezos_smart_rollup_host::path::{concat, OwnedPath, Path, RefPath};
+use tezos_smart_rollup_host::runtime::{Runtime, RuntimeError, ValueType};
+use tezos_smart_rollup_host::Error;
+
+/// Changes made to the storage by a transaction, indexed by absolute path.
+///
+/// Subtrees are deleted before values are written: deleting a subtree drops
+/// the changes made under it earlier in the same transaction.
+#[derive(Debug, Default)]
+struct Changes {
+    deleted: BTreeSet<Vec<u8>>,
+    values: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl Changes {
+    fn delete_subtree(&mut self, path: &[u8]) {
+        self.values.retain(|p, _| !is_within(p, path));
+        self.deleted.retain(|p| !is_within(p, path));
+        self.deleted.insert(path.to_vec());
+    }
+
+    /// Whether `path` is in a subtree deleted by the transaction.
+    fn hides(&self, path: &[u8]) -> bool {
+        ancestors(path).any(|p| self.deleted.contains(p))
+    }
+
+    /// Merge the changes of a nested transaction, which override these ones.
+    fn merge(&mut self, nested: Changes) {
+        for path in nested.deleted {
+            self.delete_subtree(&path);
+        }
+        self.values.extend(nested.values);
+    }
+
+    fn apply(self, host: &mut impl Runtime) -> Result<(), RuntimeError> {
+        for path in self.deleted {
+            let path = RefPath::assert_from(&path);
+            if host.store_has(&path)?.is_some() {
+                host.store_delete(&path)?;
+            }
+        }
+
+        for (path, value) in self.values {
+            let path = RefPath::assert_from(&path);
+            match value {
+                Some(value) => host.store_write_all(&path, &value)?,
+                None => host.store_delete_value(&path)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Stack of the changes made by the transactions in progress.
+#[derive(Debug)]
+struct Journal {
+    prefix: Vec<u8>,
+    transactions: Vec<Changes>,
+}
+
+impl Journal {
+    /// Whether changes to `path` are journaled rather than written.
+    fn journals(&self, path: &[u8]) -> bool {
+        !self.transactions.is_empty() && is_within(path, &self.prefix)
+    }
+
+    fn current(&mut self) -> &mut Changes {
+        self.transactions
+            .last_mut()
+            .expect("Changes are only journaled during a transaction")
+    }
+
+    fn value(
+        &self,
+        host: &impl Runtime,
+        path: &[u8],
+    ) -> Result<Option<Vec<u8>>, RuntimeError> {
+        for changes in self.transactions.iter().rev() {
+            if let Some(value) = changes.values.get(path) {
+                return Ok(value.clone());
+            }
+            if changes.hides(path) {
+                return Ok(None);
+            }
+        }
+
+        let path = RefPath::assert_from(path);
+        match host.store_has(&path)? {
+            Some(ValueType::Value | ValueType::ValueWithSubtree) => {
+                Ok(Some(host.store_read_all(&path)?))
+            }
+            Some(ValueType::Subtree) | None => Ok(None),
+        }
+    }
+
+    /// Number of subkeys of `path`, its value included.
+    ///
+    /// Subkeys untouched by the transactions are counted in durable storage,
+    /// the others are looked up through the journals.
+    fn count_subkeys(
+        &self,
+        host: &impl Runtime,
+        path: &[u8],
+    ) -> Result<u64, RuntimeError> {
+        let mut touched = BTreeSet::new();
+        for changes in self.transactions.iter() {
+            let paths = changes.deleted.iter().chain(changes.values.keys());
+            touched.extend(paths.filter_map(|p| child_of(p, path)));
+        }
+
+        let mut count = 0;
+
+        if !self.transactions.iter().any(|changes| changes.hides(path)) {
+            let durable = RefPath::assert_from(path);
+            if let Some(value_type) = host.store_has(&durable)? {
+                count += host.store_count_subkeys(&durable)?;
+                if let ValueType::Value | ValueType::ValueWithSubtree = value_type {
+                    count -= 1;
+                }
+            }
+            for child in touched.iter() {
+                if host.store_has(&RefPath::assert_from(child))?.is_some() {
+                    count -= 1;
+                }
+            }
+        }
+
+        if self.value(host, path)?.is_some() {
+            count += 1;
+        }
+        for child in touched.iter() {
+            if self.count_subkeys(host, child)? > 0 {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn value_type(
+        &self,
+        host: &impl Runtime,
+        path: &[u8],
+    ) -> Result<Option<ValueType>, RuntimeError> {
+        let has_value = self.value(host, path)?.is_some();
+        let has_subtree = self.count_subkeys(host, path)? > u64::from(has_value);
+
+        Ok(match (has_value, has_subtree) {
+            (false, false) => None,
+            (true, false) => Some(ValueType::Value),
+            (false, true) => Some(ValueType::Subtree),
+            (true, true) => Some(ValueType::ValueWithSubtree),
+        })
+    }
+}
+
+/// Whether `path` is `base` or below it.
+fn is_within(path: &[u8], base: &[u8]) -> bool {
+    path.starts_with(base) && matches!(path.get(base.len()), None | Some(b'/'))
+}
+
+/// `path` and its ancestors, from the longest.
+fn ancestors(path: &[u8]) -> impl Iterator<Item = &[u8]> {
+    (1..=path.len())
+        .rev()
+        .filter(move |&end| end == path.len() || path[end] == b'/')
+        .map(move |end| &path[..end])
+}
+
+/// The child of `parent` that `path` is in, if `path` is below `parent`.
+fn child_of(path: &[u8], parent: &[u8]) -> Option<Vec<u8>> {
+    let below = path.strip_prefix(parent)?.strip_prefix(b"/")?;
+    let step = below.split(|&b| b == b'/').next()?;
+    Some(path[..parent.len() + 1 + step.len()].to_vec())
+}
+
+/// Transactional storage, journaling the changes made by each transaction
+pub struct JournaledStorage<T: From<OwnedPath>> {
+    prefix: OwnedPath,
+    journal: Journal,
+    phantom: PhantomData<T>,
+}
+
+impl<T: From<OwnedPath>> JournaledStorage<T> {
+    /// Create the storage for objects under `name`
+    pub fn init(name: &impl Path) -> Result<Self, StorageError> {
+        Ok(Self {
+            prefix: OwnedPath::from(name),
+            journal: Journal {
+                prefix: name.as_bytes().to_vec(),
+                transactions: Vec::new(),
+            },
+            phantom: PhantomData,
+        })
+    }
+
+    /// Runtime through which objects see, and journal changes to, the state
+    /// of the current transaction.
+    ///
+    /// Storage outside of the storage's path, and everything else, is
+    /// accessed directly on `host`.
+    pub fn runtime<'a, Host: Runtime>(
+        &'a mut self,
+        host: &'a mut Host,
+    ) -> JournaledHost<'a, Host> {
+        JournaledHost {
+            host,
+            journal: &mut self.journal,
+        }
+    }
+
+    /// Get storage's given object in state given by current storage
+    /// state/transaction and id
+    ///
+    /// The object must be accessed through [Self::runtime] for its changes
+    /// to be part of the transaction.
+    pub fn get(
+        &self,
+        host: &impl Runtime,
+        id: &impl Path,
+    ) -> Result<Option<T>, StorageError> {
+        let path = concat(&self.prefix, id)?;
+
+        if has_subtree(self.journal.value_type(host, path.as_bytes())?) {
+            Ok(Some(T::from(path)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Get object in state before any transaction began. Its values are
+    /// the original ones when read directly on the host.
+    pub fn get_original(
+        &self,
+        host: &impl Runtime,
+        id: &impl Path,
+    ) -> Result<Option<T>, StorageError> {
+        let path = concat(&self.prefix, id)?;
+
+        if has_subtree(host.store_has(&path)?) {
+            Ok(Some(T::from(path)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Create a new object as part of current storage state/transaction
+    ///
+    /// The object must be accessed through [Self::runtime] for its changes
+    /// to be part of the transaction.
+    pub fn create_new(
+        &mut self,
+        host: &mut impl Runtime,
+        id: &impl Path,
+    ) -> Result<Option<T>, StorageError> {
+        match self.get(host, id)? {
+            Some(_) => Ok(None),
+            None => Ok(Some(T::from(concat(&self.prefix, id)?))),
+        }
+    }
+
+    /// Get storage's given object whether or not it exists yet. It only
+    /// exists in the current storage state/transaction once a value is
+    /// written to it through [Self::runtime].
+    pub fn get_or_create(
+        &self,
+        _host: &impl Runtime,
+        id: &impl Path,
+    ) -> Result<T, StorageError> {
+        Ok(T::from(concat(&self.prefix, id)?))
+    }
+
+    /// Delete an object as part of current storage state/transaction
+    pub fn delete(
+        &mut self,
+        host: &mut impl Runtime,
+        id: &impl Path,
+    ) -> Result<(), StorageError> {
+        let path = concat(&self.prefix, id)?;
+
+        self.runtime(host)
+            .store_delete(&path)
+            .map_err(StorageError::from)
+    }
+
+    /// Begin a new transaction
+    pub fn begin_transaction(
+        &mut self,
+        _host: &mut impl Runtime,
+    ) -> Result<(), StorageError> {
+        self.journal.transactions.push(Changes::default());
+        Ok(())
+    }
+
+    /// Commit current storage state
+    pub fn commit_transaction(
+        &mut self,
+        host: &mut impl Runtime,
+    ) -> Result<(), StorageError> {
+        let changes = self
+            .journal
+            .transactions
+            .pop()
+            .ok_or(StorageError::NoCurrentTransaction)?;
+
+        match self.journal.transactions.last_mut() {
+            Some(enclosing) => {
+                enclosing.merge(changes);
+                Ok(())
+            }
+            None => changes.apply(host).map_err(StorageError::from),
+        }
+    }
+
+    /// Abort current storage state
+    pub fn rollback_transaction(
+        &mut self,
+        _host: &mut impl Runtime,
+    ) -> Result<(), StorageError> {
+        self.journal
+            .transactions
+            .pop()
+            .map(|_| ())
+            .ok_or(StorageError::NoCurrentTransaction)
+    }
+
+    /// Get the number of active storage transactions, ie, the stack's depth.
+    pub fn stack_depth(&self) -> usize {
+        self.journal.transactions.len()
+    }
+}
+
+fn has_subtree(value_type: Option<ValueType>) -> bool {
+    matches!(
+        value_type,
+        Some(ValueType::Subtree | ValueType::ValueWithSubtree)
+    )
+}
+
+/// Runtime journaling the changes made under the path of a [JournaledStorage]
+/// while a transaction is in progress.
+///
+/// See [JournaledStorage::runtime].
+pub struct JournaledHost<'a, Host> {
+    host: &'a mut Host,
+    journal: &'a mut Journal,
+}
+
+impl<'a, Host: Runtime> JournaledHost<'a, Host> {
+    fn read_value(&self, path: &impl Path) -> Result<Vec<u8>, RuntimeError> {
+        self.journal
+            .value(&*self.host, path.as_bytes())?
+            .ok_or(RuntimeError::PathNotFound)
+    }
+}
+
+impl<'a, Host: Runtime> Runtime for JournaledHost<'a, Host> {
+    fn write_output(&mut self, from: &[u8]) -> Result<(), RuntimeError> {
+        self.host.write_output(from)
+    }
+
+    fn write_debug(&self, msg: &str) {
+        self.host.write_debug(msg)
+    }
+
+    fn read_input(&mut self) -> Result<Option<Message>, RuntimeError> {
+        self.host.read_input()
+    }
+
+    fn store_has<T: Path>(&self, path: &T) -> Result<Option<ValueType>, RuntimeError> {
+        if self.journal.journals(path.as_bytes()) {
+            self.journal.value_type(&*self.host, path.as_bytes())
+        } else {
+            self.host.store_has(path)
+        }
+    }
+
+    fn store_read<T: Path>(
+        &self,
+        path: &T,
+        from_offset: usize,
+        max_bytes: usize,
+    ) -> Result<Vec<u8>, RuntimeError> {
+        if !self.journal.journals(path.as_bytes()) {
+            return self.host.store_read(path, from_offset, max_bytes);
+        }
+
+        let value = self.read_value(path)?;
+        let bytes = value
+            .get(from_offset..)
+            .ok_or(RuntimeError::HostErr(Error::StoreInvalidAccess))?;
+        let len = bytes.len().min(max_bytes).min(MAX_FILE_CHUNK_SIZE);
+
+        Ok(bytes[..len].to_vec())
+    }
+
+    fn store_read_slice<T: Path>(
+        &self,
+        path: &T,
+        from_offset: usize,
+        buffer: &mut [u8],
+    ) -> Result<usize, RuntimeError> {
+        if !self.journal.journals(path.as_bytes()) {
+            return self.host.store_read_slice(path, from_offset, buffer);
+        }
+
+        let value = self.read_value(path)?;
+        let bytes = value
+            .get(from_offset..)
+            .ok_or(RuntimeError::HostErr(Error::StoreInvalidAccess))?;
+        let len = bytes.len().min(buffer.len()).min(MAX_FILE_CHUNK_SIZE);
+        buffer[..len].copy_from_slice(&bytes[..len]);
+
+        Ok(len)
+    }
+
+    fn store_read_all(&self, path: &impl Path) -> Result<Vec<u8>, RuntimeError> {
+        if self.journal.journals(path.as_bytes()) {
+            self.read_value(path)
+        } else {
+            self.host.store_read_all(path)
+        }
+    }
+
+    fn store_write<T: Path>(
+        &mut self,
+        path: &T,
+        src: &[u8],
+        at_offset: usize,
+    ) -> Result<(), RuntimeError> {
+        if !self.journal.journals(path.as_bytes()) {
+            return self.host.store_write(path, src, at_offset);
+        }
+
+        let mut value = self
+            .journal
+            .value(&*self.host, path.as_bytes())?
+            .unwrap_or_default();
+        if at_offset > value.len() {
+            return Err(RuntimeError::HostErr(Error::StoreInvalidAccess));
+        }
+
+        let end = at_offset + src.len();
+        if end > value.len() {
+            value.resize(end, 0);
+        }
+        value[at_offset..end].copy_from_slice(src);
+
+        self.journal
+            .current()
+            .values
+            .insert(path.as_bytes().to_vec(), Some(value));
+        Ok(())
+    }
+
+    fn store_write_all<T: Path>(
+        &mut self,
+        path: &T,
+        src: &[u8],
+    ) -> Result<(), RuntimeError> {
+        if !self.journal.journals(path.as_bytes()) {
+            return self.host.store_write_all(path, src);
+        }
+
+        self.journal
+            .current()
+            .values
+            .insert(path.as_bytes().to_vec(), Some(src.to_vec()));
+        Ok(())
+    }
+
+    fn store_delete<T: Path>(&mut self, path: &T) -> Result<(), RuntimeError> {
+        if !self.journal.journals(path.as_bytes()) {
+            return self.host.store_delete(path);
+        }
+
+        if self.store_has(path)?.is_none() {
+            return Err(RuntimeError::PathNotFound);
+        }
+
+        self.journal.current().delete_subtree(path.as_bytes());
+        Ok(())
+    }
+
+    fn store_delete_value<T: Path>(&mut self, path: &T) -> Result<(), RuntimeError> {
+        if !self.journal.journals(path.as_bytes()) {
+            return self.host.store_delete_value(path);
+        }
+
+        self.journal
+            .current()
+            .values
+            .insert(path.as_bytes().to_vec(), None);
+        Ok(())
+    }
+
+    fn store_count_subkeys<T: Path>(&self, prefix: &T) -> Result<u64, RuntimeError> {
+        if self.journal.journals(prefix.as_bytes()) {
+            self.journal.count_subkeys(&*self.host, prefix.as_bytes())
+        } else {
+            self.host.store_count_subkeys(prefix)
+        }
+    }
+
+    fn store_move(
+        &mut self,
+        from_path: &impl Path,
+        to_path: &impl Path,
+    ) -> Result<(), RuntimeError> {
+        if self.journal.journals(from_path.as_bytes())
+            || self.journal.journals(to_path.as_bytes())
+        {
+            return Err(RuntimeError::HostErr(Error::GenericInvalidAccess));
+        }
+
+        self.host.store_move(from_path, to_path)
+    }
+
+    fn store_copy(
+        &mut self,
+        from_path: &impl Path,
+        to_path: &impl Path,
+    ) -> Result<(), RuntimeError> {
+        if self.journal.journals(from_path.as_bytes())
+            || self.journal.journals(to_path.as_bytes())
+        {
+            return Err(RuntimeError::HostErr(Error::GenericInvalidAccess));
+        }
+
+        self.host.store_copy(from_path, to_path)
+    }
+
+    fn reveal_preimage(
+        &self,
+        hash: &[u8; PREIMAGE_HASH_SIZE],
+        destination: &mut [u8],
+    ) -> Result<usize, RuntimeError> {
+        self.host.reveal_preimage(hash, destination)
+    }
+
+    #[cfg(feature = "proto-alpha")]
+    fn reveal_dal_page(
+        &self,
+        published_level: i32,
+        slot_index: u8,
+        page_index: i16,
+        destination: &mut [u8],
+    ) -> Result<usize, RuntimeError> {
+        self.host
+            .reveal_dal_page(published_level, slot_index, page_index, destination)
+    }
+
+    #[cfg(feature = "proto-alpha")]
+    fn reveal_dal_parameters(&self) -> RollupDalParameters {
+        self.host.reveal_dal_parameters()
+    }
+
+    fn store_value_size(&self, path: &impl Path) -> Result<usize, RuntimeError> {
+        if self.journal.journals(path.as_bytes()) {
+            self.read_value(path).map(|value| value.len())
+        } else {
+            self.host.store_value_size(path)
+        }
+    }
+
+    fn mark_for_reboot(&mut self) -> Result<(), RuntimeError> {
+        self.host.mark_for_reboot()
+    }
+
+    fn reveal_metadata(&self) -> RollupMetadata {
+        self.host.reveal_metadata()
+    }
+
+    fn last_run_aborted(&self) -> Result<bool, RuntimeError> {
+        self.host.last_run_aborted()
+    }
+
+    fn upgrade_failed(&self) -> Result<bool, RuntimeError> {
+        self.host.upgrade_failed()
+    }
+
+    fn restart_forced(&self) -> Result<bool, RuntimeError> {
+        self.host.restart_forced()
+    }
+
+    fn reboot_left(&self) -> Result<u32, RuntimeError> {
+        self.host.reboot_left()
+    }
+
+    fn runtime_version(&self) -> Result<String, RuntimeError> {
+        self.host.runtime_version()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::journal::JournaledStorage;
+    use crate::StorageError;
+    use host::path::{concat, OwnedPath, RefPath};
+    use host::runtime::{Runtime, RuntimeError, ValueType};
+    use tezos_smart_rollup_mock::MockHost;
+
+    struct TestAccount {
+        path: OwnedPath,
+    }
+
+    const VALUE_A_PATH: RefPath = RefPath::assert_from(b"/a");
+    const VALUE_B_PATH: RefPath = RefPath::assert_from(b"/b");
+
+    impl TestAccount {
+        fn set(&mut self, host: &mut impl Runtime, key: &RefPath, v: &str) {
+            let value_path = concat(&self.path, key).unwrap();
+            host.store_write_all(&value_path, v.as_bytes()).unwrap()
+        }
+
+        fn get(&self, host: &impl Runtime, key: &RefPath) -> Option<Vec<u8>> {
+            let value_path = concat(&self.path, key).unwrap();
+            match host.store_read_all(&value_path) {
+                Ok(value) => Some(value),
+                Err(RuntimeError::PathNotFound) => None,
+                Err(e) => panic!("Cannot read value: {e}"),
+            }
+        }
+    }
+
+    impl From<OwnedPath> for TestAccount {
+        fn from(path: OwnedPath) -> Self {
+            Self { path }
+        }
+    }
+
+    const ACCOUNTS_PATH: RefPath = RefPath::assert_from(b"/accounts");
+    const ALPHA: RefPath = RefPath::assert_from(b"/alpha");
+    const BETA: RefPath = RefPath::assert_from(b"/beta");
+
+    #[test]
+    fn nested_commit() {
+        let mut host = MockHost::default();
+        let mut storage = JournaledStorage::<TestAccount>::init(&ACCOUNTS_PATH)
+            .expect("Could not create storage");
+
+        let mut alpha = storage.create_new(&mut host, &ALPHA).unwrap().unwrap();
+        alpha.set(&mut storage.runtime(&mut host), &VALUE_A_PATH, "a0");
+        alpha.set(&mut storage.runtime(&mut host), &VALUE_B_PATH, "b0");
+
+        storage.begin_transaction(&mut host).unwrap();
+        alpha.set(&mut storage.runtime(&mut host), &VALUE_A_PATH, "a1");
+
+        storage.begin_transaction(&mut host).unwrap();
+        assert_eq!(2, storage.stack_depth());
+        let mut beta = storage.create_new(&mut host, &BETA).unwrap().unwrap();
+        beta.set(&mut storage.runtime(&mut host), &VALUE_A_PATH, "beta");
+
+        // Reads fall through to the enclosing transaction & durable storage
+        let runtime = storage.runtime(&mut host);
+        assert_eq!(Some(b"a1".to_vec()), alpha.get(&runtime, &VALUE_A_PATH));
+        assert_eq!(Some(b"b0".to_vec()), alpha.get(&runtime, &VALUE_B_PATH));
+        storage.commit_transaction(&mut host).unwrap();
+
+        // Nothing is written until the outermost transaction is committed
+        assert!(storage.get(&host, &BETA).unwrap().is_some());
+        assert!(storage.get_original(&host, &BETA).unwrap().is_none());
+        assert_eq!(Some(b"a0".to_vec()), alpha.get(&host, &VALUE_A_PATH));
+
+        storage.commit_transaction(&mut host).unwrap();
+        assert_eq!(0, storage.stack_depth());
+        assert_eq!(Some(b"a1".to_vec()), alpha.get(&host, &VALUE_A_PATH));
+        assert_eq!(Some(b"beta".to_vec()), beta.get(&host, &VALUE_A_PATH));
+    }
+
+    #[test]
+    fn nested_rollback() {
+        let mut host = MockHost::default();
+        let mut storage = JournaledStorage::<TestAccount>::init(&ACCOUNTS_PATH)
+            .expect("Could not create storage");
+
+        let mut alpha = storage.create_new(&mut host, &ALPHA).unwrap().unwrap();
+        alpha.set(&mut host, &VALUE_A_PATH, "a0");
+
+        storage.begin_transaction(&mut host).unwrap();
+        alpha.set(&mut storage.runtime(&mut host), &VALUE_B_PATH, "b1");
+
+        storage.begin_transaction(&mut host).unwrap();
+        alpha.set(&mut storage.runtime(&mut host), &VALUE_A_PATH, "a2");
+        storage.delete(&mut host, &ALPHA).unwrap();
+        assert!(storage.get(&host, &ALPHA).unwrap().is_none());
+        storage.rollback_transaction(&mut host).unwrap();
+
+        let runtime = storage.runtime(&mut host);
+        assert_eq!(Some(b"a0".to_vec()), alpha.get(&runtime, &VALUE_A_PATH));
+        assert_eq!(Some(b"b1".to_vec()), alpha.get(&runtime, &VALUE_B_PATH));
+
+        storage.rollback_transaction(&mut host).unwrap();
+        let runtime = storage.runtime(&mut host);
+        assert_eq!(None, alpha.get(&runtime, &VALUE_B_PATH));
+        assert_eq!(
+            Err(StorageError::NoCurrentTransaction),
+            storage.rollback_transaction(&mut host)
+        );
+    }
+
+    #[test]
+    fn direct_write_is_not_rolled_back() {
+        let mut host = MockHost::default();
+        let mut storage = JournaledStorage::<TestAccount>::init(&ACCOUNTS_PATH)
+            .expect("Could not create storage");
+
+        storage.begin_transaction(&mut host).unwrap();
+        let mut alpha = storage.get_or_create(&host, &ALPHA).unwrap();
+        alpha.set(&mut storage.runtime(&mut host), &VALUE_A_PATH, "a1");
+
+        // Writing on the host rather than the journaled runtime bypasses the
+        // journal: the value is durable straight away.
+        alpha.set(&mut host, &VALUE_B_PATH, "b1");
+        assert!(storage.get_original(&host, &ALPHA).unwrap().is_some());
+
+        storage.rollback_transaction(&mut host).unwrap();
+        assert_eq!(None, alpha.get(&host, &VALUE_A_PATH));
+        assert_eq!(Some(b"b1".to_vec()), alpha.get(&host, &VALUE_B_PATH));
+    }
+
+    #[test]
+    fn subtree_delete_and_subkeys() {
+        let mut host = MockHost::default();
+        let mut storage = JournaledStorage::<TestAccount>::init(&ACCOUNTS_PATH)
+            .expect("Could not create storage");
+
+        let mut alpha = storage.create_new(&mut host, &ALPHA).unwrap().unwrap();
+        alpha.set(&mut host, &VALUE_A_PATH, "a0");
+        alpha.set(&mut host, &VALUE_B_PATH, "b0");
+        let mut beta = storage.create_new(&mut host, &BETA).unwrap().unwrap();
+        beta.set(&mut host, &VALUE_A_PATH, "beta");
+
+        storage.begin_transaction(&mut host).unwrap();
+        storage.delete(&mut host, &ALPHA).unwrap();
+        assert_eq!(
+            Ok(1),
+            storage
+                .runtime(&mut host)
+                .store_count_subkeys(&ACCOUNTS_PATH)
+        );
+
+        // Values written after deleting the subtree are kept, but not the
+        // deleted ones.
+        alpha.set(&mut storage.runtime(&mut host), &VALUE_B_PATH, "b1");
+        let runtime = storage.runtime(&mut host);
+        assert_eq!(Ok(2), runtime.store_count_subkeys(&ACCOUNTS_PATH));
+        assert_eq!(Ok(1), runtime.store_count_subkeys(&alpha.path));
+        assert_eq!(None, alpha.get(&runtime, &VALUE_A_PATH));
+        assert_eq!(Some(b"b1".to_vec()), alpha.get(&runtime, &VALUE_B_PATH));
+        assert_eq!(Ok(Some(ValueType::Subtree)), runtime.store_has(&alpha.path));
+
+        // Durable storage is untouched until commit
+        assert_eq!(Ok(2), host.store_count_subkeys(&alpha.path));
+
+        storage.begin_transaction(&mut host).unwrap();
+        storage.delete(&mut host, &BETA).unwrap();
+        assert!(storage.create_new(&mut host, &BETA).unwrap().is_some());
+        storage.commit_transaction(&mut host).unwrap();
+        storage.commit_transaction(&mut host).unwrap();
+
+        assert_eq!(None, alpha.get(&host, &VALUE_A_PATH));
+        assert_eq!(Some(b"b1".to_vec()), alpha.get(&host, &VALUE_B_PATH));
+        assert_eq!(Ok(None), host.store_has(&beta.path));
+        assert_eq!(Ok(1), host.store_count_subkeys(&ACCOUNTS_PATH));
+    }
+}
@@ -41,6 +41,16 @@ pub enum StorageError {
     /// happen when doing some transaction operation.
     #[error("Runtrime error")]
     RuntimeError(host::runtime::RuntimeError),
+    /// A value or key of a storage collection could not be encoded.
+    #[error("Encoding error")]
+    EncodingError,
+    /// A value read from a storage collection could not be decoded, or is
+    /// missing although the collection's length says otherwise.
+    #[error("Decoding error")]
+    DecodingError,
+    /// An index past the end of a storage collection was used.
+    #[error("Index out of bounds")]
+    IndexOutOfBounds,
 }
 
 impl From<host::path::PathError> for StorageError {
@@ -55,5 +65,7 @@ impl From<host::runtime::RuntimeError> for StorageError {
     }
 }
 
+pub mod collections;
+pub mod journal;
 mod layer;
 pub mod storage;
@@ -0,0 +1,108 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Ticks spent by nested transactions with [Storage], which copies the state
+//! on every transaction, and with [JournaledStorage], which only records the
+//! changed values.
+
+use std::collections::BTreeMap;
+use tezos_smart_rollup_host::path::{concat, OwnedPath, RefPath};
+use tezos_smart_rollup_host::runtime::Runtime;
+use tezos_smart_rollup_mock::{HostFunction, MockHost, TickCosts};
+use tezos_smart_rollup_storage::journal::JournaledStorage;
+use tezos_smart_rollup_storage::storage::Storage;
+
+const ACCOUNTS_PATH: RefPath = RefPath::assert_from(b"/accounts");
+const BALANCE_PATH: RefPath = RefPath::assert_from(b"/balance");
+const FIRST: RefPath = RefPath::assert_from(b"/account0");
+const SECOND: RefPath = RefPath::assert_from(b"/account1");
+
+struct Account {
+    path: OwnedPath,
+}
+
+impl From<OwnedPath> for Account {
+    fn from(path: OwnedPath) -> Self {
+        Self { path }
+    }
+}
+
+impl Account {
+    fn set_balance(&mut self, host: &mut impl Runtime, balance: u64) {
+        let path = concat(&self.path, &BALANCE_PATH).unwrap();
+        host.store_write_all(&path, &balance.to_le_bytes()).unwrap();
+    }
+}
+
+fn copy_transactions(host: &mut MockHost) {
+    let mut storage = Storage::<Account>::init(&ACCOUNTS_PATH).unwrap();
+
+    storage.begin_transaction(host).unwrap();
+    let mut first = storage.get_or_create(host, &FIRST).unwrap();
+    first.set_balance(host, 1);
+
+    storage.begin_transaction(host).unwrap();
+    let mut second = storage.get_or_create(host, &SECOND).unwrap();
+    second.set_balance(host, 2);
+    storage.commit_transaction(host).unwrap();
+
+    storage.commit_transaction(host).unwrap();
+}
+
+fn journaled_transactions(host: &mut MockHost) {
+    let mut storage = JournaledStorage::<Account>::init(&ACCOUNTS_PATH).unwrap();
+
+    storage.begin_transaction(host).unwrap();
+    let mut first = storage.get_or_create(host, &FIRST).unwrap();
+    first.set_balance(&mut storage.runtime(host), 1);
+
+    storage.begin_transaction(host).unwrap();
+    let mut second = storage.get_or_create(host, &SECOND).unwrap();
+    second.set_balance(&mut storage.runtime(host), 2);
+    storage.commit_transaction(host).unwrap();
+
+    storage.commit_transaction(host).unwrap();
+}
+
+/// Ticks spent by `kernel_run` on a state of `accounts` accounts.
+fn ticks(accounts: u64, kernel_run: fn(&mut MockHost)) -> u64 {
+    let mut host = MockHost::default();
+    host.set_tick_costs(TickCosts {
+        per_call: BTreeMap::from([
+            (HostFunction::StoreCopy, 1_000),
+            (HostFunction::StoreMove, 1_000),
+        ]),
+        per_byte: 1,
+        per_storage_op: 1_000,
+    });
+
+    for account in 0..accounts {
+        let path = format!("/accounts/account{account}/balance");
+        let path = OwnedPath::try_from(path).unwrap();
+        host.store_write_all(&path, &[0; 32]).unwrap();
+    }
+
+    host.run_level(kernel_run);
+    host.tick_report()[0].ticks
+}
+
+#[test]
+fn nested_transactions() {
+    let mut copy_ticks = Vec::new();
+    let mut journaled_ticks = Vec::new();
+
+    for accounts in [10, 100, 1_000, 10_000] {
+        let copy = ticks(accounts, copy_transactions);
+        let journaled = ticks(accounts, journaled_transactions);
+
+        assert!(journaled < copy);
+        copy_ticks.push(copy);
+        journaled_ticks.push(journaled);
+    }
+
+    // The cost of copy transactions grows with the state size, while the cost
+    // of journaled transactions does not depend on it.
+    assert!(copy_ticks.windows(2).all(|w| w[0] < w[1]));
+    assert!(journaled_ticks.windows(2).all(|w| w[0] == w[1]));
+}
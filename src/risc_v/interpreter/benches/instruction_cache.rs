@@ -0,0 +1,99 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Compare the throughput of [`MachineState::step_many`], which runs cached
+//! basic blocks, against the reference stepper which decodes every
+//! instruction it runs.
+//!
+//! Run with `cargo bench --bench instruction_cache`.
+
+use risc_v_interpreter::{
+    machine_state::{
+        bus::{main_memory::M1G, start_of_main_memory, Addressable},
+        mode::Mode,
+        registers::{a1, t0},
+        MachineState, MachineStateLayout, StepManyResult,
+    },
+    state_backend::{
+        memory_backend::{InMemoryBackend, SliceManager},
+        Backend,
+    },
+    traps::EnvironException,
+};
+use std::time::{Duration, Instant};
+
+type L = MachineStateLayout<M1G>;
+
+/// Sums up the loop counter in memory a million times.
+const PROGRAM: [u32; 10] = [
+    0x00100337, // lui t1, 0x100
+    0x00000293, // li t0, 0
+    0x00002517, // auipc a0, 2
+    0x00053583, // ld a1, 0(a0)
+    0x005585b3, // add a1, a1, t0
+    0x0055c633, // xor a2, a1, t0
+    0x00b53023, // sd a1, 0(a0)
+    0x00128293, // addi t0, t0, 1
+    0xfe6296e3, // bne t0, t1, -20
+    0x00000073, // ecall
+];
+
+/// Observable outcome of a run
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    steps: usize,
+    exception: Option<EnvironException>,
+    pc: u64,
+    counter: u64,
+    sum: u64,
+}
+
+fn run(
+    step_many: fn(&mut MachineState<M1G, SliceManager<'_>>) -> StepManyResult,
+) -> (Outcome, Duration) {
+    let (mut backend, placed) = InMemoryBackend::<L>::new();
+    let mut machine = MachineState::<M1G, _>::bind(backend.allocate(placed));
+
+    let start = start_of_main_memory::<M1G>();
+    machine.bus.write_all(start, &PROGRAM).unwrap();
+    machine.hart.mode.write(Mode::Machine);
+    machine.hart.pc.write(start);
+
+    let time = Instant::now();
+    let result = step_many(&mut machine);
+    let elapsed = time.elapsed();
+
+    let outcome = Outcome {
+        steps: result.steps,
+        exception: result.exception,
+        pc: machine.hart.pc.read(),
+        counter: machine.hart.xregisters.read(t0),
+        sum: machine.hart.xregisters.read(a1),
+    };
+    (outcome, elapsed)
+}
+
+fn report(name: &str, outcome: &Outcome, elapsed: Duration) {
+    let mips = outcome.steps as f64 / elapsed.as_secs_f64() / 1_000_000.0;
+    println!(
+        "{name:>10}: {} steps in {:.3}s ({mips:.2} MIPS)",
+        outcome.steps,
+        elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let (reference, reference_time) =
+        run(|machine| machine.step_many_uncached(usize::MAX, |_| true));
+    report("uncached", &reference, reference_time);
+
+    let (cached, cached_time) = run(|machine| machine.step_many(usize::MAX, |_| true));
+    report("cached", &cached, cached_time);
+
+    assert_eq!(cached, reference, "Cached run diverged from the reference");
+    println!(
+        "   speedup: {:.2}x",
+        reference_time.as_secs_f64() / cached_time.as_secs_f64()
+    );
+}
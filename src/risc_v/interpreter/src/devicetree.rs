@@ -1,4 +1,4 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
@@ -6,9 +6,50 @@
 //!   - https://elinux.org/Device_Tree_Usage
 //!   - https://github.com/devicetree-org/devicetree-specification/releases/tag/v0.4
 
-use crate::machine_state::bus::{self, main_memory::MainMemoryLayout};
+use crate::{
+    machine_state::{
+        bus::{
+            self,
+            devices::{clint, uart},
+            main_memory::MainMemoryLayout,
+        },
+        extensions::Extensions,
+    },
+    traps::Interrupt,
+};
 use vm_fdt::FdtWriter;
 
+/// Phandle of the interrupt controller local to the first hart
+const CPU0_INTC_PHANDLE: u32 = 0x2;
+
+/// Single-letter extensions reported by `misa`, in canonical order
+const ISA_BASE_EXTENSIONS: [&str; 6] = ["i", "m", "a", "f", "d", "c"];
+
+/// Multi-letter extensions which are always available
+const ISA_FIXED_EXTENSIONS: [&str; 2] = ["zicsr", "zifencei"];
+
+/// Extensions of the ISA, given that the optional `extensions` are enabled
+fn isa_extensions(extensions: Extensions) -> Vec<String> {
+    ISA_BASE_EXTENSIONS
+        .iter()
+        .chain(ISA_FIXED_EXTENSIONS.iter())
+        .map(|ext| ext.to_string())
+        .chain(extensions.iter().map(|ext| ext.to_string()))
+        .collect()
+}
+
+/// ISA string, e.g. `rv64imafdc_zicsr_zifencei_zba`
+fn isa_string(extensions: Extensions) -> String {
+    let (single, multi): (Vec<_>, Vec<_>) = isa_extensions(extensions)
+        .into_iter()
+        .partition(|ext| ext.len() == 1);
+    multi
+        .iter()
+        .fold(format!("rv64{}", single.concat()), |isa, ext| {
+            isa + "_" + ext
+        })
+}
+
 /// Information about the initial ramdisk.
 pub struct InitialRamDisk {
     /// Start address of the initrd
@@ -44,6 +85,7 @@ pub fn generate_custom(
     main_memory_start: u64,
     main_memory_length: u64,
     initrd: Option<InitialRamDisk>,
+    extensions: Extensions,
 ) -> Result<Vec<u8>, vm_fdt::Error> {
     let mut fdt = FdtWriter::new()?;
 
@@ -70,6 +112,10 @@ pub fn generate_custom(
 
         // /chosen
         node!(fdt, "chosen", {
+            // Early console and default console for supervisors such as Linux
+            let uart_path = format!("/soc/serial@{:x}", uart::UART_START);
+            fdt.property_string("stdout-path", uart_path.as_str())?;
+
             // HermitOS loader wants an initial ramdisk.
             if let Some(initrd) = initrd {
                 // End pointer is exclusive (i.e. after the initrd).
@@ -101,6 +147,55 @@ pub fn generate_custom(
                 fdt.property_u32("reg", 0x0)?;
                 fdt.property_string("status", "okay")?;
                 fdt.property_string("compatible", "riscv")?;
+
+                // Supervisors use these to find out which instructions they
+                // may use.
+                fdt.property_string("riscv,isa", isa_string(extensions).as_str())?;
+                fdt.property_string("riscv,isa-base", "rv64i")?;
+                fdt.property_string_list("riscv,isa-extensions", isa_extensions(extensions))?;
+
+                // /cpus/cpu@0/interrupt-controller
+                node!(fdt, "interrupt-controller", {
+                    fdt.property_phandle(CPU0_INTC_PHANDLE)?;
+                    fdt.property_u32("#interrupt-cells", 1)?;
+                    fdt.property_null("interrupt-controller")?;
+                    fdt.property_string("compatible", "riscv,cpu-intc")?;
+                });
+            });
+        });
+
+        // /soc
+        node!(fdt, "soc", {
+            fdt.property_u32("#address-cells", 2)?;
+            fdt.property_u32("#size-cells", 2)?;
+            fdt.property_string("compatible", "simple-bus")?;
+            fdt.property_null("ranges")?;
+
+            // /soc/clint@?
+            node!(fdt, "clint", clint::CLINT_START, {
+                fdt.property_string_list(
+                    "compatible",
+                    vec!["sifive,clint0".to_string(), "riscv,clint0".to_string()],
+                )?;
+                fdt.property_array_u64("reg", &[clint::CLINT_START, clint::CLINT_LENGTH])?;
+                fdt.property_array_u32(
+                    "interrupts-extended",
+                    &[
+                        CPU0_INTC_PHANDLE,
+                        Interrupt::MachineSoftware.exception_code_const() as u32,
+                        CPU0_INTC_PHANDLE,
+                        Interrupt::MachineTimer.exception_code_const() as u32,
+                    ],
+                )?;
+            });
+
+            // /soc/serial@?
+            node!(fdt, "serial", uart::UART_START, {
+                fdt.property_string("compatible", "ns16550a")?;
+                fdt.property_array_u64("reg", &[uart::UART_START, uart::UART_LENGTH])?;
+                fdt.property_u32("clock-frequency", uart::UART_CLOCK_FREQUENCY)?;
+                fdt.property_u32("reg-shift", 0)?;
+                fdt.property_u32("reg-io-width", 1)?;
             });
         });
     });
@@ -111,7 +206,27 @@ pub fn generate_custom(
 /// Generate a Flattened Device Tree for the given hardware configuration.
 pub fn generate<ML: MainMemoryLayout>(
     initrd: Option<InitialRamDisk>,
+    extensions: Extensions,
 ) -> Result<Vec<u8>, vm_fdt::Error> {
     let main_memory_start = bus::start_of_main_memory::<ML>();
-    generate_custom(main_memory_start, ML::BYTES as u64, initrd)
+    generate_custom(main_memory_start, ML::BYTES as u64, initrd, extensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::isa_string;
+    use crate::machine_state::extensions::{Extension, Extensions};
+
+    #[test]
+    fn test_isa_string() {
+        assert_eq!(isa_string(Extensions::NONE), "rv64imafdc_zicsr_zifencei");
+        assert_eq!(
+            isa_string(Extensions::NONE.with(Extension::Zbs).with(Extension::Zba)),
+            "rv64imafdc_zicsr_zifencei_zba_zbs"
+        );
+        assert_eq!(
+            isa_string(Extensions::ALL),
+            "rv64imafdc_zicsr_zifencei_zba_zbb_zbs"
+        );
+    }
 }
@@ -3,6 +3,7 @@
 // SPDX-License-Identifier: MIT
 
 pub mod posix;
+pub mod sbi;
 
 use crate::{
     machine_state::{bus::main_memory::MainMemoryLayout, MachineState},
@@ -47,4 +48,31 @@ pub trait ExecutionEnvironmentState<M: Manager> {
         machine: &mut MachineState<ML, M>,
         exception: EnvironException,
     ) -> EcallOutcome;
+
+    /// Is the execution environment waiting for input?
+    fn awaits_input(&self) -> bool {
+        false
+    }
+
+    /// Provide input that has been requested by the guest. Returns `false` if
+    /// no input has been requested.
+    fn provide_input<ML: MainMemoryLayout>(
+        &mut self,
+        _machine: &mut MachineState<ML, M>,
+        _level: u64,
+        _counter: u64,
+        _payload: &[u8],
+    ) -> bool {
+        false
+    }
+
+    /// Number of messages the guest has written to the outbox.
+    fn outbox_len(&self) -> usize {
+        0
+    }
+
+    /// Retrieve the message at position `index` of the outbox.
+    fn outbox_message(&self, _index: usize) -> Option<Vec<u8>> {
+        None
+    }
 }
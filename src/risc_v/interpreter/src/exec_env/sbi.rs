@@ -0,0 +1,651 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Execution environment implementing the Supervisor Binary Interface
+//!
+//! Besides the legacy console and shutdown extensions, the Tezos firmware
+//! extension is implemented so that rollup kernels can run directly on the
+//! interpreter. All state needed to serve these calls lives in the EE state,
+//! which makes it part of the PVM state hash. This includes the outbox, which
+//! collects the messages written by the guest during the current level.
+//!
+//! Errors of the Tezos extension are reported with the rollup host's error
+//! codes. Durable storage and reveals are not implemented yet: their functions
+//! fail with [`TEZOS_NOT_SUPPORTED`], which is not a host error code.
+
+use super::{EcallOutcome, ExecutionEnvironment, ExecutionEnvironmentState};
+use crate::{
+    machine_state::{
+        bus::{main_memory::MainMemoryLayout, Address, Addressable},
+        registers::{a0, a1, a2, a3, a6, a7},
+        MachineState,
+    },
+    state_backend::{AllocatedOf, Array, Atom, Cell, Manager, Region},
+    traps::EnvironException,
+};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use tezos_smart_rollup_constants::riscv::{
+    SBI_CONSOLE_PUTCHAR, SBI_FIRMWARE_TEZOS, SBI_SHUTDOWN, SBI_TEZOS_BLAKE2B_HASH256,
+    SBI_TEZOS_ED25519_SIGN, SBI_TEZOS_ED25519_VERIFY, SBI_TEZOS_INBOX_NEXT, SBI_TEZOS_META_ADDRESS,
+    SBI_TEZOS_META_ORIGINATION_LEVEL, SBI_TEZOS_WRITE_OUTPUT,
+};
+
+/// Size of a rollup address in bytes
+pub const ROLLUP_ADDRESS_SIZE: usize = 20;
+
+/// Maximum number of bytes the guest may pass to a single call
+const MAX_READ_LENGTH: u64 = 64 * 1024;
+
+/// Maximum size of an outbox message in bytes
+pub const MAX_OUTPUT_SIZE: usize = 4096;
+
+/// Maximum number of messages in the outbox of a level
+pub const OUTBOX_CAPACITY: usize = 100;
+
+/// Error code for inaccessible memory, matching the rollup host's
+/// `MEMORY_INVALID_ACCESS`
+const MEMORY_INVALID_ACCESS: i64 = -6;
+
+/// Error code for outbox messages larger than [`MAX_OUTPUT_SIZE`] and buffers
+/// larger than [`MAX_READ_LENGTH`], matching the rollup host's
+/// `INPUT_OUTPUT_TOO_LARGE`
+const INPUT_OUTPUT_TOO_LARGE: i64 = -7;
+
+/// Error code for invalid parameters, matching the rollup host's
+/// `GENERIC_INVALID_ACCESS`
+const GENERIC_INVALID_ACCESS: i64 = -8;
+
+/// Error code for writes to a full outbox, matching the rollup host's
+/// `FULL_OUTBOX`
+const FULL_OUTBOX: i64 = -11;
+
+/// Error code for functions of the Tezos extension which are not implemented,
+/// such as durable storage and reveals. The rollup host's error codes range
+/// from -1 to -11, this code is distinct from all of them so that kernels
+/// can't mistake it for e.g. `STORE_INVALID_KEY`. The SDK reports it as
+/// `GenericInvalidAccess`.
+pub const TEZOS_NOT_SUPPORTED: i64 = -1000;
+
+/// SBI error code for unknown extensions
+const SBI_ERR_NOT_SUPPORTED: i64 = -2;
+
+/// The guest is running.
+const STATUS_RUNNING: u8 = 0;
+
+/// The guest waits for the next inbox message.
+const STATUS_AWAITS_INPUT: u8 = 1;
+
+/// The guest has requested a shutdown.
+const STATUS_SHUT_DOWN: u8 = 2;
+
+/// SBI execution environment
+pub enum Sbi {}
+
+/// Layout of [`SbiState`]
+pub type SbiLayout = (
+    Atom<u8>,
+    (Atom<u64>, Atom<u64>),
+    Array<u8, ROLLUP_ADDRESS_SIZE>,
+    Atom<u64>,
+    OutboxLayout,
+);
+
+/// Layout of the outbox: level, number of messages, their lengths and their
+/// contents in slots of [`MAX_OUTPUT_SIZE`] bytes
+type OutboxLayout = (
+    Atom<u64>,
+    Atom<u64>,
+    Array<u64, OUTBOX_CAPACITY>,
+    Array<u8, { OUTBOX_CAPACITY * MAX_OUTPUT_SIZE }>,
+);
+
+impl ExecutionEnvironment for Sbi {
+    type Layout = SbiLayout;
+
+    type State<M: Manager> = SbiState<M>;
+}
+
+/// SBI execution environment state
+pub struct SbiState<M: Manager> {
+    status: Cell<u8, M>,
+    input_buffer: Cell<u64, M>,
+    input_capacity: Cell<u64, M>,
+    rollup_address: M::Region<u8, ROLLUP_ADDRESS_SIZE>,
+    origination_level: Cell<u64, M>,
+    outbox_level: Cell<u64, M>,
+    outbox_len: Cell<u64, M>,
+    outbox_lengths: M::Region<u64, OUTBOX_CAPACITY>,
+    outbox_data: M::Region<u8, { OUTBOX_CAPACITY * MAX_OUTPUT_SIZE }>,
+}
+
+impl<M: Manager> SbiState<M> {
+    /// Configure the metadata reported to the guest.
+    pub fn set_metadata(
+        &mut self,
+        rollup_address: &[u8; ROLLUP_ADDRESS_SIZE],
+        origination_level: u64,
+    ) {
+        self.rollup_address.write_all(rollup_address);
+        self.origination_level.write(origination_level);
+    }
+
+    /// Has the guest requested a shutdown?
+    pub fn is_shut_down(&self) -> bool {
+        self.status.read() == STATUS_SHUT_DOWN
+    }
+
+    /// Append a message of at most [`MAX_OUTPUT_SIZE`] bytes to the outbox.
+    fn write_output(&mut self, message: &[u8]) -> Result<(), i64> {
+        let index = self.outbox_len.read() as usize;
+        if index >= OUTBOX_CAPACITY {
+            return Err(FULL_OUTBOX);
+        }
+
+        // The whole slot is written so that no bytes of a previous level's
+        // message linger after the end of the new one.
+        let mut slot = [0u8; MAX_OUTPUT_SIZE];
+        slot[..message.len()].copy_from_slice(message);
+        self.outbox_data.write_some(index * MAX_OUTPUT_SIZE, &slot);
+        self.outbox_lengths.write(index, message.len() as u64);
+        self.outbox_len.write(index as u64 + 1);
+        Ok(())
+    }
+
+    /// Handle a call to the Tezos firmware extension.
+    fn handle_tezos<ML: MainMemoryLayout>(
+        &mut self,
+        machine: &mut MachineState<ML, M>,
+    ) -> Result<bool, i64> {
+        let function = machine.hart.xregisters.read(a6);
+        match function {
+            SBI_TEZOS_INBOX_NEXT => {
+                self.input_buffer.write(machine.hart.xregisters.read(a0));
+                self.input_capacity.write(machine.hart.xregisters.read(a1));
+                self.status.write(STATUS_AWAITS_INPUT);
+                Ok(false)
+            }
+
+            SBI_TEZOS_META_ORIGINATION_LEVEL => {
+                machine
+                    .hart
+                    .xregisters
+                    .write(a0, self.origination_level.read());
+                Ok(true)
+            }
+
+            SBI_TEZOS_META_ADDRESS => {
+                let addr = machine.hart.xregisters.read(a0);
+                let max_len = machine.hart.xregisters.read(a1);
+
+                let address = self.rollup_address.read_all();
+                let length = address.len().min(max_len as usize);
+                write_bytes(machine, addr, &address[..length])?;
+
+                machine.hart.xregisters.write(a0, length as u64);
+                Ok(true)
+            }
+
+            SBI_TEZOS_ED25519_SIGN => {
+                let secret_key = read_bytes(machine, machine.hart.xregisters.read(a0), 32)?;
+                let secret_key = SigningKey::try_from(secret_key.as_slice())
+                    .map_err(|_| GENERIC_INVALID_ACCESS)?;
+
+                let message = read_bytes(
+                    machine,
+                    machine.hart.xregisters.read(a1),
+                    machine.hart.xregisters.read(a2),
+                )?;
+                let signature = secret_key.sign(&message).to_bytes();
+
+                write_bytes(machine, machine.hart.xregisters.read(a3), &signature)?;
+                machine.hart.xregisters.write(a0, 0);
+                Ok(true)
+            }
+
+            SBI_TEZOS_ED25519_VERIFY => {
+                let public_key = read_bytes(machine, machine.hart.xregisters.read(a0), 32)?;
+                let signature = read_bytes(machine, machine.hart.xregisters.read(a1), 64)?;
+                let message = read_bytes(
+                    machine,
+                    machine.hart.xregisters.read(a2),
+                    machine.hart.xregisters.read(a3),
+                )?;
+
+                let valid = VerifyingKey::try_from(public_key.as_slice())
+                    .ok()
+                    .zip(Signature::from_slice(&signature).ok())
+                    .map_or(false, |(public_key, signature)| {
+                        public_key.verify_strict(&message, &signature).is_ok()
+                    });
+
+                machine.hart.xregisters.write(a0, valid as u64);
+                Ok(true)
+            }
+
+            SBI_TEZOS_BLAKE2B_HASH256 => {
+                let message = read_bytes(
+                    machine,
+                    machine.hart.xregisters.read(a1),
+                    machine.hart.xregisters.read(a2),
+                )?;
+                let digest = tezos_crypto_rs::blake2b::digest_256(&message)
+                    .map_err(|_| GENERIC_INVALID_ACCESS)?;
+
+                write_bytes(machine, machine.hart.xregisters.read(a0), &digest)?;
+                machine.hart.xregisters.write(a0, 0);
+                Ok(true)
+            }
+
+            SBI_TEZOS_WRITE_OUTPUT => {
+                let length = machine.hart.xregisters.read(a1);
+                if length > MAX_OUTPUT_SIZE as u64 {
+                    return Err(INPUT_OUTPUT_TOO_LARGE);
+                }
+
+                let message = read_bytes(machine, machine.hart.xregisters.read(a0), length)?;
+                self.write_output(&message)?;
+
+                machine.hart.xregisters.write(a0, 0);
+                Ok(true)
+            }
+
+            _ => Err(TEZOS_NOT_SUPPORTED),
+        }
+    }
+}
+
+/// Read `length` bytes of guest memory starting at `addr`. Lengths above
+/// [`MAX_READ_LENGTH`] are rejected before allocating the buffer.
+fn read_bytes<ML: MainMemoryLayout, M: Manager>(
+    machine: &MachineState<ML, M>,
+    addr: Address,
+    length: u64,
+) -> Result<Vec<u8>, i64> {
+    if length > MAX_READ_LENGTH {
+        return Err(INPUT_OUTPUT_TOO_LARGE);
+    }
+
+    let mut buffer = vec![0u8; length as usize];
+    machine
+        .bus
+        .read_all(addr, &mut buffer)
+        .map_err(|_| MEMORY_INVALID_ACCESS)?;
+    Ok(buffer)
+}
+
+/// Write `bytes` to guest memory starting at `addr`.
+fn write_bytes<ML: MainMemoryLayout, M: Manager>(
+    machine: &mut MachineState<ML, M>,
+    addr: Address,
+    bytes: &[u8],
+) -> Result<(), i64> {
+    machine
+        .bus
+        .write_all(addr, bytes)
+        .map_err(|_| MEMORY_INVALID_ACCESS)
+}
+
+/// Resume the guest after the ECALL instruction.
+fn skip_ecall<ML: MainMemoryLayout, M: Manager>(machine: &mut MachineState<ML, M>) {
+    let pc = machine.hart.pc.read();
+    machine.hart.pc.write(pc.wrapping_add(4));
+}
+
+impl<M: Manager> ExecutionEnvironmentState<M> for SbiState<M> {
+    type ExecutionEnvironment = Sbi;
+
+    fn bind(space: AllocatedOf<SbiLayout, M>) -> Self {
+        let (
+            status,
+            (input_buffer, input_capacity),
+            rollup_address,
+            origination_level,
+            (outbox_level, outbox_len, outbox_lengths, outbox_data),
+        ) = space;
+        Self {
+            status,
+            input_buffer,
+            input_capacity,
+            rollup_address,
+            origination_level,
+            outbox_level,
+            outbox_len,
+            outbox_lengths,
+            outbox_data,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.status.write(STATUS_RUNNING);
+        self.input_buffer.write(0);
+        self.input_capacity.write(0);
+        self.rollup_address.write_all(&[0; ROLLUP_ADDRESS_SIZE]);
+        self.origination_level.write(0);
+        self.outbox_level.write(0);
+        self.outbox_len.write(0);
+        self.outbox_lengths.write_all(&[0; OUTBOX_CAPACITY]);
+
+        let zeros = [0u8; MAX_OUTPUT_SIZE];
+        for index in 0..OUTBOX_CAPACITY {
+            self.outbox_data.write_some(index * MAX_OUTPUT_SIZE, &zeros);
+        }
+    }
+
+    fn handle_call<ML: MainMemoryLayout>(
+        &mut self,
+        machine: &mut MachineState<ML, M>,
+        env_exception: EnvironException,
+    ) -> EcallOutcome {
+        match self.status.read() {
+            STATUS_RUNNING => {}
+
+            // A machine that has been shut down stays parked on the ECALL.
+            STATUS_SHUT_DOWN => {
+                return EcallOutcome::Handled {
+                    continue_eval: false,
+                }
+            }
+
+            _ => return EcallOutcome::Fatal,
+        }
+
+        // Calls from user mode are system calls for the guest's own kernel.
+        if let EnvironException::EnvCallFromUMode = env_exception {
+            let return_pc = machine.hart.pc.read();
+            let new_pc = machine
+                .hart
+                .take_trap(env_exception.as_exception(), return_pc);
+            machine.hart.pc.write(new_pc);
+
+            return EcallOutcome::Handled {
+                continue_eval: true,
+            };
+        }
+
+        let extension = machine.hart.xregisters.read(a7);
+        let result = match extension {
+            SBI_CONSOLE_PUTCHAR => {
+                let byte = machine.hart.xregisters.read(a0) as u8;
+                machine.bus.devices.uart.transmit(byte);
+                machine.hart.xregisters.write(a0, 0);
+                Ok(true)
+            }
+
+            SBI_SHUTDOWN => {
+                self.status.write(STATUS_SHUT_DOWN);
+                return EcallOutcome::Handled {
+                    continue_eval: false,
+                };
+            }
+
+            SBI_FIRMWARE_TEZOS => self.handle_tezos(machine),
+
+            _ => Err(SBI_ERR_NOT_SUPPORTED),
+        };
+
+        let continue_eval = result.unwrap_or_else(|error| {
+            machine.hart.xregisters.write(a0, error as u64);
+            true
+        });
+
+        skip_ecall(machine);
+        EcallOutcome::Handled { continue_eval }
+    }
+
+    fn awaits_input(&self) -> bool {
+        self.status.read() == STATUS_AWAITS_INPUT
+    }
+
+    fn provide_input<ML: MainMemoryLayout>(
+        &mut self,
+        machine: &mut MachineState<ML, M>,
+        level: u64,
+        counter: u64,
+        payload: &[u8],
+    ) -> bool {
+        if !self.awaits_input() {
+            return false;
+        }
+
+        let capacity = self.input_capacity.read();
+        let length = payload.len().min(capacity as usize);
+
+        // The guest asked for its buffer to be filled, therefore it must deal
+        // with the buffer being inaccessible.
+        let length = match write_bytes(machine, self.input_buffer.read(), &payload[..length]) {
+            Ok(()) => length,
+            Err(_) => 0,
+        };
+
+        // The outbox only holds the messages of the current level.
+        if level != self.outbox_level.read() {
+            self.outbox_level.write(level);
+            self.outbox_len.write(0);
+        }
+
+        machine.hart.xregisters.write(a0, level);
+        machine.hart.xregisters.write(a1, counter);
+        machine.hart.xregisters.write(a2, length as u64);
+        self.status.write(STATUS_RUNNING);
+
+        true
+    }
+
+    fn outbox_len(&self) -> usize {
+        self.outbox_len.read() as usize
+    }
+
+    fn outbox_message(&self, index: usize) -> Option<Vec<u8>> {
+        if index >= self.outbox_len() {
+            return None;
+        }
+
+        let length = self.outbox_lengths.read(index) as usize;
+        let mut message = vec![0u8; length];
+        self.outbox_data
+            .read_some(index * MAX_OUTPUT_SIZE, &mut message);
+        Some(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        machine_state::{bus::main_memory::M1K, mode::Mode, registers::a0},
+        state_backend::{memory_backend::InMemoryBackend, Backend},
+    };
+    use tezos_smart_rollup_constants::riscv::{SBI_TEZOS_REVEAL, SBI_TEZOS_STORE_HAS};
+
+    type TestLayout = (crate::machine_state::MachineStateLayout<M1K>, SbiLayout);
+
+    const ECALL: u32 = 0x00000073;
+
+    fn call<M: Manager>(machine: &mut MachineState<M1K, M>, sbi: &mut SbiState<M>) -> bool {
+        let pc = machine.hart.pc.read();
+        match sbi.handle_call(machine, EnvironException::EnvCallFromMMode) {
+            EcallOutcome::Handled { continue_eval } => {
+                assert_eq!(machine.hart.pc.read(), pc + 4);
+                continue_eval
+            }
+            EcallOutcome::Fatal => panic!("Unexpected fatal outcome"),
+        }
+    }
+
+    #[test]
+    fn test_sbi_calls() {
+        let (mut backend, placed) = InMemoryBackend::<TestLayout>::new();
+        let (machine_space, sbi_space) = backend.allocate(placed);
+        let mut machine = MachineState::<M1K, _>::bind(machine_space);
+        let mut sbi = SbiState::bind(sbi_space);
+
+        machine.reset();
+        sbi.reset();
+        machine.hart.mode.write(Mode::Machine);
+
+        let base = crate::machine_state::bus::start_of_main_memory::<M1K>();
+        machine.hart.pc.write(base);
+        machine.bus.write(base, ECALL).unwrap();
+
+        // Metadata
+        sbi.set_metadata(&[7; ROLLUP_ADDRESS_SIZE], 42);
+        machine.hart.xregisters.write(a7, SBI_FIRMWARE_TEZOS);
+        machine
+            .hart
+            .xregisters
+            .write(a6, SBI_TEZOS_META_ORIGINATION_LEVEL);
+        assert!(call(&mut machine, &mut sbi));
+        assert_eq!(machine.hart.xregisters.read(a0), 42);
+
+        let buffer = base + 0x100;
+        machine.hart.xregisters.write(a6, SBI_TEZOS_META_ADDRESS);
+        machine.hart.xregisters.write(a0, buffer);
+        machine.hart.xregisters.write(a1, 64);
+        assert!(call(&mut machine, &mut sbi));
+        assert_eq!(machine.hart.xregisters.read(a0), ROLLUP_ADDRESS_SIZE as u64);
+        assert_eq!(
+            read_bytes(&machine, buffer, ROLLUP_ADDRESS_SIZE as u64),
+            Ok(vec![7; ROLLUP_ADDRESS_SIZE])
+        );
+
+        // Hashing
+        let message = b"risc-v";
+        machine.bus.write_all(buffer, message).unwrap();
+        machine.hart.xregisters.write(a6, SBI_TEZOS_BLAKE2B_HASH256);
+        machine.hart.xregisters.write(a0, buffer + 0x40);
+        machine.hart.xregisters.write(a1, buffer);
+        machine.hart.xregisters.write(a2, message.len() as u64);
+        assert!(call(&mut machine, &mut sbi));
+        assert_eq!(
+            read_bytes(&machine, buffer + 0x40, 32),
+            Ok(tezos_crypto_rs::blake2b::digest_256(message).unwrap())
+        );
+
+        // Signing and verifying
+        let secret_key = [3; 32];
+        let public_key = SigningKey::from_bytes(&secret_key).verifying_key();
+        machine.bus.write_all(buffer + 0x80, &secret_key).unwrap();
+        machine
+            .bus
+            .write_all(buffer + 0xA0, public_key.as_bytes())
+            .unwrap();
+        machine.hart.xregisters.write(a6, SBI_TEZOS_ED25519_SIGN);
+        machine.hart.xregisters.write(a0, buffer + 0x80);
+        machine.hart.xregisters.write(a1, buffer);
+        machine.hart.xregisters.write(a2, message.len() as u64);
+        machine.hart.xregisters.write(a3, buffer + 0xC0);
+        assert!(call(&mut machine, &mut sbi));
+
+        machine.hart.xregisters.write(a6, SBI_TEZOS_ED25519_VERIFY);
+        machine.hart.xregisters.write(a0, buffer + 0xA0);
+        machine.hart.xregisters.write(a1, buffer + 0xC0);
+        machine.hart.xregisters.write(a2, buffer);
+        machine.hart.xregisters.write(a3, message.len() as u64);
+        assert!(call(&mut machine, &mut sbi));
+        assert_eq!(machine.hart.xregisters.read(a0), 1);
+
+        // Oversized buffers are rejected
+        machine.hart.xregisters.write(a6, SBI_TEZOS_BLAKE2B_HASH256);
+        machine.hart.xregisters.write(a0, buffer + 0x40);
+        machine.hart.xregisters.write(a1, buffer);
+        machine.hart.xregisters.write(a2, u64::MAX);
+        assert!(call(&mut machine, &mut sbi));
+        assert_eq!(
+            machine.hart.xregisters.read(a0),
+            INPUT_OUTPUT_TOO_LARGE as u64
+        );
+
+        // Outbox
+        machine.hart.xregisters.write(a6, SBI_TEZOS_WRITE_OUTPUT);
+        machine.hart.xregisters.write(a0, buffer);
+        machine.hart.xregisters.write(a1, message.len() as u64);
+        assert!(call(&mut machine, &mut sbi));
+        assert_eq!(machine.hart.xregisters.read(a0), 0);
+        assert_eq!(sbi.outbox_len(), 1);
+        assert_eq!(sbi.outbox_message(0), Some(message.to_vec()));
+        assert_eq!(sbi.outbox_message(1), None);
+
+        machine.hart.xregisters.write(a0, buffer);
+        machine
+            .hart
+            .xregisters
+            .write(a1, MAX_OUTPUT_SIZE as u64 + 1);
+        assert!(call(&mut machine, &mut sbi));
+        assert_eq!(
+            machine.hart.xregisters.read(a0),
+            INPUT_OUTPUT_TOO_LARGE as u64
+        );
+        assert_eq!(sbi.outbox_len(), 1);
+
+        machine.hart.xregisters.write(a1, 1);
+        for _ in 1..OUTBOX_CAPACITY {
+            machine.hart.xregisters.write(a0, buffer);
+            assert!(call(&mut machine, &mut sbi));
+            assert_eq!(machine.hart.xregisters.read(a0), 0);
+        }
+        machine.hart.xregisters.write(a0, buffer);
+        assert!(call(&mut machine, &mut sbi));
+        assert_eq!(machine.hart.xregisters.read(a0), FULL_OUTBOX as u64);
+        assert_eq!(sbi.outbox_len(), OUTBOX_CAPACITY);
+        assert_eq!(sbi.outbox_message(1), Some(message[..1].to_vec()));
+
+        // Unknown and unimplemented functions are reported to the caller with
+        // a code that isn't a host error code
+        for function in [0xFFFF, SBI_TEZOS_STORE_HAS, SBI_TEZOS_REVEAL] {
+            machine.hart.xregisters.write(a6, function);
+            assert!(call(&mut machine, &mut sbi));
+            assert_eq!(machine.hart.xregisters.read(a0), TEZOS_NOT_SUPPORTED as u64);
+        }
+
+        // Inaccessible buffers are reported to the caller
+        machine.hart.xregisters.write(a6, SBI_TEZOS_WRITE_OUTPUT);
+        machine.hart.xregisters.write(a0, u64::MAX);
+        machine.hart.xregisters.write(a1, 1);
+        assert!(call(&mut machine, &mut sbi));
+        assert_eq!(
+            machine.hart.xregisters.read(a0),
+            MEMORY_INVALID_ACCESS as u64
+        );
+
+        // So are unknown extensions
+        machine.hart.xregisters.write(a7, 0xFFFF);
+        assert!(call(&mut machine, &mut sbi));
+        assert_eq!(
+            machine.hart.xregisters.read(a0),
+            SBI_ERR_NOT_SUPPORTED as u64
+        );
+        machine.hart.xregisters.write(a7, SBI_FIRMWARE_TEZOS);
+
+        // Inbox
+        machine.hart.xregisters.write(a6, SBI_TEZOS_INBOX_NEXT);
+        machine.hart.xregisters.write(a0, buffer);
+        machine.hart.xregisters.write(a1, 4);
+        assert!(!sbi.awaits_input());
+        assert!(!call(&mut machine, &mut sbi));
+        assert!(sbi.awaits_input());
+
+        assert!(sbi.provide_input(&mut machine, 5, 1, b"message"));
+        assert!(!sbi.awaits_input());
+        assert!(!sbi.provide_input(&mut machine, 5, 2, b"message"));
+        assert_eq!(machine.hart.xregisters.read(a0), 5);
+        assert_eq!(machine.hart.xregisters.read(a1), 1);
+        assert_eq!(machine.hart.xregisters.read(a2), 4);
+        assert_eq!(read_bytes(&machine, buffer, 4), Ok(b"mess".to_vec()));
+
+        // Input of a new level starts a new outbox
+        assert_eq!(sbi.outbox_len(), 0);
+
+        // Shutdown
+        let pc = machine.hart.pc.read();
+        machine.hart.xregisters.write(a7, SBI_SHUTDOWN);
+        assert!(matches!(
+            sbi.handle_call(&mut machine, EnvironException::EnvCallFromMMode),
+            EcallOutcome::Handled {
+                continue_eval: false
+            }
+        ));
+        assert!(sbi.is_shut_down());
+        assert_eq!(machine.hart.pc.read(), pc);
+    }
+}
@@ -8,5 +8,8 @@ pub mod rv32m;
 pub mod rv64i;
 pub mod rv64m;
 pub mod rv64priv;
+pub mod rv64zba;
+pub mod rv64zbb;
+pub mod rv64zbs;
 pub mod rv64zicsr;
 pub mod rv64zifencei;
@@ -9,12 +9,14 @@
 use crate::{
     machine_state::{
         bus::{main_memory::MainMemoryLayout, Addressable, OutOfBounds},
+        csregisters::pmp::AccessType,
         registers::{XRegister, XRegisters},
         MachineState,
     },
     state_backend as backend,
     traps::Exception,
 };
+use std::mem;
 
 impl<M> XRegisters<M>
 where
@@ -237,10 +239,18 @@ where
     M: backend::Manager,
 {
     /// Generic read function for loading `mem::size_of<T>` bytes from address val(rs1) + imm
-    fn read_from_bus<T: backend::Elem>(&self, imm: i64, rs1: XRegister) -> Result<T, Exception> {
+    fn read_from_bus<T: backend::Elem>(
+        &mut self,
+        imm: i64,
+        rs1: XRegister,
+    ) -> Result<T, Exception> {
         let address = self.hart.xregisters.read(rs1).wrapping_add(imm as u64);
+        if !self.pmp_allows(address, mem::size_of::<T>(), AccessType::Load) {
+            return Err(Exception::LoadAccessFault(address));
+        }
+
         self.bus
-            .read(address)
+            .load(address)
             .map_err(|_: OutOfBounds| Exception::LoadAccessFault(address))
     }
 
@@ -328,6 +338,10 @@ where
         value: T,
     ) -> Result<(), Exception> {
         let address = self.hart.xregisters.read(rs1).wrapping_add(imm as u64);
+        if !self.pmp_allows(address, mem::size_of::<T>(), AccessType::Store) {
+            return Err(Exception::StoreAccessFault(address));
+        }
+
         self.bus
             .write(address, value)
             .map_err(|_: OutOfBounds| Exception::StoreAccessFault(address))
@@ -785,6 +799,7 @@ mod tests {
         {
             let mut backend = create_backend!(MachineStateLayout<T1K>, F);
             let mut state = create_state!(MachineState, MachineStateLayout<T1K>, F, backend, T1K);
+            state.hart.csregisters.pmp_allow_all();
 
             let mut perform_test = |offset: u64, signed: bool| -> Result<(), Exception> {
                 // Save test values v_i in registers ai
@@ -0,0 +1,129 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Implementation of Zba extension for RISC-V
+//!
+//! Chapter 28.4.1 - Unprivileged spec
+
+use crate::{
+    machine_state::registers::{XRegister, XRegisters},
+    state_backend as backend,
+};
+
+impl<M> XRegisters<M>
+where
+    M: backend::Manager,
+{
+    /// `ADD.UW` R-type instruction
+    ///
+    /// Add val(rs2) to the zero-extended lower 32 bits of val(rs1)
+    /// and store the result in `rd`
+    pub fn run_add_uw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let index = self.read(rs1) as u32 as u64;
+        self.write(rd, self.read(rs2).wrapping_add(index))
+    }
+
+    /// `SH1ADD` R-type instruction
+    ///
+    /// Shift val(rs1) left by 1 bit, add val(rs2) and store the result in `rd`
+    pub fn run_sh1add(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs2).wrapping_add(self.read(rs1) << 1))
+    }
+
+    /// `SH1ADD.UW` R-type instruction
+    ///
+    /// Shift the zero-extended lower 32 bits of val(rs1) left by 1 bit,
+    /// add val(rs2) and store the result in `rd`
+    pub fn run_sh1add_uw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let index = self.read(rs1) as u32 as u64;
+        self.write(rd, self.read(rs2).wrapping_add(index << 1))
+    }
+
+    /// `SH2ADD` R-type instruction
+    ///
+    /// Shift val(rs1) left by 2 bits, add val(rs2) and store the result in `rd`
+    pub fn run_sh2add(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs2).wrapping_add(self.read(rs1) << 2))
+    }
+
+    /// `SH2ADD.UW` R-type instruction
+    ///
+    /// Shift the zero-extended lower 32 bits of val(rs1) left by 2 bits,
+    /// add val(rs2) and store the result in `rd`
+    pub fn run_sh2add_uw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let index = self.read(rs1) as u32 as u64;
+        self.write(rd, self.read(rs2).wrapping_add(index << 2))
+    }
+
+    /// `SH3ADD` R-type instruction
+    ///
+    /// Shift val(rs1) left by 3 bits, add val(rs2) and store the result in `rd`
+    pub fn run_sh3add(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs2).wrapping_add(self.read(rs1) << 3))
+    }
+
+    /// `SH3ADD.UW` R-type instruction
+    ///
+    /// Shift the zero-extended lower 32 bits of val(rs1) left by 3 bits,
+    /// add val(rs2) and store the result in `rd`
+    pub fn run_sh3add_uw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let index = self.read(rs1) as u32 as u64;
+        self.write(rd, self.read(rs2).wrapping_add(index << 3))
+    }
+
+    /// `SLLI.UW` I-type instruction
+    ///
+    /// Shift the zero-extended lower 32 bits of val(rs1) left by `imm` bits
+    /// and store the result in `rd`
+    pub fn run_slli_uw(&mut self, imm: i64, rs1: XRegister, rd: XRegister) {
+        let index = self.read(rs1) as u32 as u64;
+        self.write(rd, index << (imm & 0b11_1111))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend_test, create_backend, create_state,
+        machine_state::registers::{a0, a1, a2, XRegisters, XRegistersLayout},
+    };
+    use proptest::{prelude::any, prop_assert_eq, proptest};
+
+    backend_test!(test_sh_add, F, {
+        proptest!(|(base in any::<u64>(), index in any::<u64>())| {
+            let mut backend = create_backend!(XRegistersLayout, F);
+            let mut state = create_state!(XRegisters, F, backend);
+            state.write(a0, index);
+            state.write(a1, base);
+
+            state.run_sh1add(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), base.wrapping_add(index.wrapping_mul(2)));
+            state.run_sh2add(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), base.wrapping_add(index.wrapping_mul(4)));
+            state.run_sh3add(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), base.wrapping_add(index.wrapping_mul(8)));
+
+            let index_uw = index & 0xFFFF_FFFF;
+            state.run_add_uw(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), base.wrapping_add(index_uw));
+            state.run_sh1add_uw(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), base.wrapping_add(index_uw * 2));
+            state.run_sh2add_uw(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), base.wrapping_add(index_uw * 4));
+            state.run_sh3add_uw(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), base.wrapping_add(index_uw * 8));
+        });
+    });
+
+    backend_test!(test_slli_uw, F, {
+        let mut backend = create_backend!(XRegistersLayout, F);
+        let mut state = create_state!(XRegisters, F, backend);
+
+        state.write(a0, 0xFFFF_FFFF_8000_0001);
+        state.run_slli_uw(4, a0, a1);
+        assert_eq!(state.read(a1), 0x8_0000_0010);
+        state.run_slli_uw(32, a0, a1);
+        assert_eq!(state.read(a1), 0x8000_0001_0000_0000);
+    });
+}
@@ -0,0 +1,327 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Implementation of Zbb extension for RISC-V
+//!
+//! Chapter 28.4.2 - Unprivileged spec
+
+use crate::{
+    machine_state::registers::{XRegister, XRegisters},
+    state_backend as backend,
+};
+
+impl<M> XRegisters<M>
+where
+    M: backend::Manager,
+{
+    /// `ANDN` R-type instruction
+    ///
+    /// Saves in `rd` the bitwise AND between val(rs1) and the inverse of val(rs2)
+    pub fn run_andn(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs1) & !self.read(rs2))
+    }
+
+    /// `ORN` R-type instruction
+    ///
+    /// Saves in `rd` the bitwise OR between val(rs1) and the inverse of val(rs2)
+    pub fn run_orn(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs1) | !self.read(rs2))
+    }
+
+    /// `XNOR` R-type instruction
+    ///
+    /// Saves in `rd` the inverse of the bitwise XOR between val(rs1) and val(rs2)
+    pub fn run_xnor(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        self.write(rd, !(self.read(rs1) ^ self.read(rs2)))
+    }
+
+    /// `CLZ` instruction
+    ///
+    /// Saves in `rd` the number of leading zero bits of val(rs1)
+    pub fn run_clz(&mut self, rs1: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs1).leading_zeros() as u64)
+    }
+
+    /// `CLZW` instruction
+    ///
+    /// Saves in `rd` the number of leading zero bits of the lower 32 bits of val(rs1)
+    pub fn run_clzw(&mut self, rs1: XRegister, rd: XRegister) {
+        self.write(rd, (self.read(rs1) as u32).leading_zeros() as u64)
+    }
+
+    /// `CTZ` instruction
+    ///
+    /// Saves in `rd` the number of trailing zero bits of val(rs1)
+    pub fn run_ctz(&mut self, rs1: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs1).trailing_zeros() as u64)
+    }
+
+    /// `CTZW` instruction
+    ///
+    /// Saves in `rd` the number of trailing zero bits of the lower 32 bits of val(rs1)
+    pub fn run_ctzw(&mut self, rs1: XRegister, rd: XRegister) {
+        self.write(rd, (self.read(rs1) as u32).trailing_zeros() as u64)
+    }
+
+    /// `CPOP` instruction
+    ///
+    /// Saves in `rd` the number of bits set in val(rs1)
+    pub fn run_cpop(&mut self, rs1: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs1).count_ones() as u64)
+    }
+
+    /// `CPOPW` instruction
+    ///
+    /// Saves in `rd` the number of bits set in the lower 32 bits of val(rs1)
+    pub fn run_cpopw(&mut self, rs1: XRegister, rd: XRegister) {
+        self.write(rd, (self.read(rs1) as u32).count_ones() as u64)
+    }
+
+    /// `MAX` R-type instruction
+    ///
+    /// Saves in `rd` the larger of val(rs1) and val(rs2), compared as signed integers
+    pub fn run_max(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let result = (self.read(rs1) as i64).max(self.read(rs2) as i64);
+        self.write(rd, result as u64)
+    }
+
+    /// `MAXU` R-type instruction
+    ///
+    /// Saves in `rd` the larger of val(rs1) and val(rs2), compared as unsigned integers
+    pub fn run_maxu(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs1).max(self.read(rs2)))
+    }
+
+    /// `MIN` R-type instruction
+    ///
+    /// Saves in `rd` the smaller of val(rs1) and val(rs2), compared as signed integers
+    pub fn run_min(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let result = (self.read(rs1) as i64).min(self.read(rs2) as i64);
+        self.write(rd, result as u64)
+    }
+
+    /// `MINU` R-type instruction
+    ///
+    /// Saves in `rd` the smaller of val(rs1) and val(rs2), compared as unsigned integers
+    pub fn run_minu(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs1).min(self.read(rs2)))
+    }
+
+    /// `SEXT.B` instruction
+    ///
+    /// Saves in `rd` the sign-extension of the lowest byte of val(rs1)
+    pub fn run_sext_b(&mut self, rs1: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs1) as i8 as u64)
+    }
+
+    /// `SEXT.H` instruction
+    ///
+    /// Saves in `rd` the sign-extension of the lowest 16 bits of val(rs1)
+    pub fn run_sext_h(&mut self, rs1: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs1) as i16 as u64)
+    }
+
+    /// `ZEXT.H` instruction
+    ///
+    /// Saves in `rd` the zero-extension of the lowest 16 bits of val(rs1)
+    pub fn run_zext_h(&mut self, rs1: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs1) as u16 as u64)
+    }
+
+    /// `ROL` R-type instruction
+    ///
+    /// Rotate val(rs1) left by the amount in the lowest 6 bits of val(rs2)
+    pub fn run_rol(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let shift = self.read(rs2) as u32 & 0b11_1111;
+        self.write(rd, self.read(rs1).rotate_left(shift))
+    }
+
+    /// `ROLW` R-type instruction
+    ///
+    /// Rotate the lower 32 bits of val(rs1) left by the amount in the lowest
+    /// 5 bits of val(rs2) and store the sign-extended result in `rd`
+    pub fn run_rolw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let shift = self.read(rs2) as u32 & 0b1_1111;
+        let result = (self.read(rs1) as u32).rotate_left(shift);
+        self.write(rd, result as i32 as u64)
+    }
+
+    /// `ROR` R-type instruction
+    ///
+    /// Rotate val(rs1) right by the amount in the lowest 6 bits of val(rs2)
+    pub fn run_ror(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let shift = self.read(rs2) as u32 & 0b11_1111;
+        self.write(rd, self.read(rs1).rotate_right(shift))
+    }
+
+    /// `RORI` I-type instruction
+    ///
+    /// Rotate val(rs1) right by `imm`
+    pub fn run_rori(&mut self, imm: i64, rs1: XRegister, rd: XRegister) {
+        let shift = imm as u32 & 0b11_1111;
+        self.write(rd, self.read(rs1).rotate_right(shift))
+    }
+
+    /// `RORIW` I-type instruction
+    ///
+    /// Rotate the lower 32 bits of val(rs1) right by `imm` and store the
+    /// sign-extended result in `rd`
+    pub fn run_roriw(&mut self, imm: i64, rs1: XRegister, rd: XRegister) {
+        let shift = imm as u32 & 0b1_1111;
+        let result = (self.read(rs1) as u32).rotate_right(shift);
+        self.write(rd, result as i32 as u64)
+    }
+
+    /// `RORW` R-type instruction
+    ///
+    /// Rotate the lower 32 bits of val(rs1) right by the amount in the lowest
+    /// 5 bits of val(rs2) and store the sign-extended result in `rd`
+    pub fn run_rorw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let shift = self.read(rs2) as u32 & 0b1_1111;
+        let result = (self.read(rs1) as u32).rotate_right(shift);
+        self.write(rd, result as i32 as u64)
+    }
+
+    /// `ORC.B` instruction
+    ///
+    /// Each byte of `rd` is set to all ones if the corresponding byte of
+    /// val(rs1) is non-zero, and to zero otherwise
+    pub fn run_orc_b(&mut self, rs1: XRegister, rd: XRegister) {
+        let bytes = self
+            .read(rs1)
+            .to_le_bytes()
+            .map(|byte| if byte == 0 { 0 } else { 0xFF });
+        self.write(rd, u64::from_le_bytes(bytes))
+    }
+
+    /// `REV8` instruction
+    ///
+    /// Saves in `rd` the value of val(rs1) with its bytes in reverse order
+    pub fn run_rev8(&mut self, rs1: XRegister, rd: XRegister) {
+        self.write(rd, self.read(rs1).swap_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend_test, create_backend, create_state,
+        machine_state::registers::{a0, a1, a2, XRegisters, XRegistersLayout},
+    };
+    use proptest::{prelude::any, prop_assert_eq, proptest};
+
+    backend_test!(test_logical_with_negate, F, {
+        proptest!(|(v1 in any::<u64>(), v2 in any::<u64>())| {
+            let mut backend = create_backend!(XRegistersLayout, F);
+            let mut state = create_state!(XRegisters, F, backend);
+            state.write(a0, v1);
+            state.write(a1, v2);
+
+            state.run_andn(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), v1 & !v2);
+            state.run_orn(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), v1 | !v2);
+            state.run_xnor(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), !(v1 ^ v2));
+        });
+    });
+
+    backend_test!(test_count, F, {
+        let mut backend = create_backend!(XRegistersLayout, F);
+        let mut state = create_state!(XRegisters, F, backend);
+
+        for (value, clz, ctz, cpop, clzw, ctzw, cpopw) in [
+            (0, 64, 64, 0, 32, 32, 0),
+            (1, 63, 0, 1, 31, 0, 1),
+            (0x8000_0000_0000_0000, 0, 63, 1, 32, 32, 0),
+            (0x0000_00F0_8000_0100, 24, 8, 6, 0, 8, 2),
+        ] {
+            state.write(a0, value);
+            state.run_clz(a0, a1);
+            assert_eq!(state.read(a1), clz);
+            state.run_ctz(a0, a1);
+            assert_eq!(state.read(a1), ctz);
+            state.run_cpop(a0, a1);
+            assert_eq!(state.read(a1), cpop);
+            state.run_clzw(a0, a1);
+            assert_eq!(state.read(a1), clzw);
+            state.run_ctzw(a0, a1);
+            assert_eq!(state.read(a1), ctzw);
+            state.run_cpopw(a0, a1);
+            assert_eq!(state.read(a1), cpopw);
+        }
+    });
+
+    backend_test!(test_min_max, F, {
+        proptest!(|(v1 in any::<u64>(), v2 in any::<u64>())| {
+            let mut backend = create_backend!(XRegistersLayout, F);
+            let mut state = create_state!(XRegisters, F, backend);
+            state.write(a0, v1);
+            state.write(a1, v2);
+
+            state.run_max(a0, a1, a2);
+            prop_assert_eq!(state.read(a2) as i64, (v1 as i64).max(v2 as i64));
+            state.run_min(a0, a1, a2);
+            prop_assert_eq!(state.read(a2) as i64, (v1 as i64).min(v2 as i64));
+            state.run_maxu(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), v1.max(v2));
+            state.run_minu(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), v1.min(v2));
+        });
+    });
+
+    backend_test!(test_extend, F, {
+        let mut backend = create_backend!(XRegistersLayout, F);
+        let mut state = create_state!(XRegisters, F, backend);
+
+        state.write(a0, 0x1234_5678_9ABC_DEF0);
+        state.run_sext_b(a0, a1);
+        assert_eq!(state.read(a1), 0xFFFF_FFFF_FFFF_FFF0);
+        state.run_sext_h(a0, a1);
+        assert_eq!(state.read(a1), 0xFFFF_FFFF_FFFF_DEF0);
+        state.run_zext_h(a0, a1);
+        assert_eq!(state.read(a1), 0xDEF0);
+
+        state.write(a0, 0x7F);
+        state.run_sext_b(a0, a1);
+        assert_eq!(state.read(a1), 0x7F);
+    });
+
+    backend_test!(test_rotate, F, {
+        proptest!(|(value in any::<u64>(), shift in 0..64_u32)| {
+            let mut backend = create_backend!(XRegistersLayout, F);
+            let mut state = create_state!(XRegisters, F, backend);
+            state.write(a0, value);
+            // Only the lowest bits of the shift amount are used
+            state.write(a1, shift as u64 | 0xFF00);
+
+            state.run_rol(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), value.rotate_left(shift));
+            state.run_ror(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), value.rotate_right(shift));
+            state.run_rori(shift as i64, a0, a2);
+            prop_assert_eq!(state.read(a2), value.rotate_right(shift));
+
+            let word = value as u32;
+            let word_shift = shift % 32;
+            state.run_rolw(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), word.rotate_left(word_shift) as i32 as u64);
+            state.run_rorw(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), word.rotate_right(word_shift) as i32 as u64);
+            state.run_roriw(word_shift as i64, a0, a2);
+            prop_assert_eq!(state.read(a2), word.rotate_right(word_shift) as i32 as u64);
+        });
+    });
+
+    backend_test!(test_bytes, F, {
+        let mut backend = create_backend!(XRegistersLayout, F);
+        let mut state = create_state!(XRegisters, F, backend);
+
+        state.write(a0, 0x0102_0030_0000_FF00);
+        state.run_orc_b(a0, a1);
+        assert_eq!(state.read(a1), 0xFFFF_00FF_0000_FF00);
+        state.run_rev8(a0, a1);
+        assert_eq!(state.read(a1), 0x00FF_0000_3000_0201);
+    });
+}
@@ -0,0 +1,118 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Implementation of Zbs extension for RISC-V
+//!
+//! Chapter 28.4.4 - Unprivileged spec
+
+use crate::{
+    machine_state::registers::{XRegister, XRegisters},
+    state_backend as backend,
+};
+
+impl<M> XRegisters<M>
+where
+    M: backend::Manager,
+{
+    /// `BCLR` R-type instruction
+    ///
+    /// Clear the bit of val(rs1) indexed by the lowest 6 bits of val(rs2)
+    pub fn run_bclr(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let index = self.read(rs2) & 0b11_1111;
+        self.write(rd, self.read(rs1) & !(1 << index))
+    }
+
+    /// `BCLRI` I-type instruction
+    ///
+    /// Clear the bit of val(rs1) indexed by `imm`
+    pub fn run_bclri(&mut self, imm: i64, rs1: XRegister, rd: XRegister) {
+        let index = imm & 0b11_1111;
+        self.write(rd, self.read(rs1) & !(1 << index))
+    }
+
+    /// `BEXT` R-type instruction
+    ///
+    /// Extract the bit of val(rs1) indexed by the lowest 6 bits of val(rs2)
+    pub fn run_bext(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let index = self.read(rs2) & 0b11_1111;
+        self.write(rd, (self.read(rs1) >> index) & 1)
+    }
+
+    /// `BEXTI` I-type instruction
+    ///
+    /// Extract the bit of val(rs1) indexed by `imm`
+    pub fn run_bexti(&mut self, imm: i64, rs1: XRegister, rd: XRegister) {
+        let index = imm & 0b11_1111;
+        self.write(rd, (self.read(rs1) >> index) & 1)
+    }
+
+    /// `BINV` R-type instruction
+    ///
+    /// Invert the bit of val(rs1) indexed by the lowest 6 bits of val(rs2)
+    pub fn run_binv(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let index = self.read(rs2) & 0b11_1111;
+        self.write(rd, self.read(rs1) ^ (1 << index))
+    }
+
+    /// `BINVI` I-type instruction
+    ///
+    /// Invert the bit of val(rs1) indexed by `imm`
+    pub fn run_binvi(&mut self, imm: i64, rs1: XRegister, rd: XRegister) {
+        let index = imm & 0b11_1111;
+        self.write(rd, self.read(rs1) ^ (1 << index))
+    }
+
+    /// `BSET` R-type instruction
+    ///
+    /// Set the bit of val(rs1) indexed by the lowest 6 bits of val(rs2)
+    pub fn run_bset(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let index = self.read(rs2) & 0b11_1111;
+        self.write(rd, self.read(rs1) | (1 << index))
+    }
+
+    /// `BSETI` I-type instruction
+    ///
+    /// Set the bit of val(rs1) indexed by `imm`
+    pub fn run_bseti(&mut self, imm: i64, rs1: XRegister, rd: XRegister) {
+        let index = imm & 0b11_1111;
+        self.write(rd, self.read(rs1) | (1 << index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend_test, create_backend, create_state,
+        machine_state::registers::{a0, a1, a2, XRegisters, XRegistersLayout},
+    };
+    use proptest::{prelude::any, prop_assert_eq, proptest};
+
+    backend_test!(test_single_bit, F, {
+        proptest!(|(value in any::<u64>(), index in 0..64_u64)| {
+            let mut backend = create_backend!(XRegistersLayout, F);
+            let mut state = create_state!(XRegisters, F, backend);
+            state.write(a0, value);
+            // Only the lowest 6 bits of the index are used
+            state.write(a1, index | 0x1C0);
+
+            let bit = 1 << index;
+            state.run_bclr(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), value & !bit);
+            state.run_bclri(index as i64, a0, a2);
+            prop_assert_eq!(state.read(a2), value & !bit);
+            state.run_bset(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), value | bit);
+            state.run_bseti(index as i64, a0, a2);
+            prop_assert_eq!(state.read(a2), value | bit);
+            state.run_binv(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), value ^ bit);
+            state.run_binvi(index as i64, a0, a2);
+            prop_assert_eq!(state.read(a2), value ^ bit);
+            state.run_bext(a0, a1, a2);
+            prop_assert_eq!(state.read(a2), (value & bit != 0) as u64);
+            state.run_bexti(index as i64, a0, a2);
+            prop_assert_eq!(state.read(a2), (value & bit != 0) as u64);
+        });
+    });
+}
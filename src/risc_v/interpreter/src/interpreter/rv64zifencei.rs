@@ -21,6 +21,8 @@ where
         // memory are seen by the Hart. The Hart will cache instruction
         // memory and therefore won't bother re-reading the cache from memory
         // until `fence.i` is called.
-        // Because we have no instruction cache yet, this operation is a no-op.
+        // Writes through the bus already invalidate the blocks they overlap,
+        // flushing the cache here makes sure nothing stale survives.
+        self.bus.instruction_cache.flush();
     }
 }
@@ -14,8 +14,12 @@ pub mod traps;
 
 use crate::{
     machine_state::{
-        bus::main_memory::M1G, mode, registers::XRegister, MachineError, MachineState,
-        MachineStateLayout, StepManyResult,
+        bus::{devices::uart::TransmitHandler, main_memory::M1G, Addressable, OutOfBounds},
+        csregisters::CSRegister,
+        extensions::Extensions,
+        mode,
+        registers::XRegister,
+        MachineError, MachineState, MachineStateLayout, StepManyResult,
     },
     program::Program,
     state_backend::{
@@ -69,6 +73,7 @@ impl<'a> Interpreter<'a> {
     fn init(
         backend: &'a mut InMemoryBackend<StateLayout>,
         mode: mode::Mode,
+        extensions: Extensions,
     ) -> (
         PosixState<SliceManager<'a>>,
         MachineState<M1G, SliceManager<'a>>,
@@ -76,19 +81,22 @@ impl<'a> Interpreter<'a> {
         let alloc = backend.allocate(StateLayout::placed().into_location());
         let mut posix_state = PosixState::bind(alloc.0);
         posix_state.set_exit_mode(mode);
-        let machine_state = MachineState::<M1G, SliceManager<'a>>::bind(alloc.1);
+        let mut machine_state = MachineState::<M1G, SliceManager<'a>>::bind(alloc.1);
+        machine_state.hart.extensions.write(extensions);
         (posix_state, machine_state)
     }
 
     /// Initialise an interpreter with a given [program], starting execution in [mode].
-    /// An initial ramdisk can also optionally be passed.
+    /// An initial ramdisk can also optionally be passed. Only the optional
+    /// [extensions] given are enabled.
     pub fn new(
         backend: &'a mut InMemoryBackend<StateLayout>,
         program: &[u8],
         initrd: Option<&[u8]>,
         mode: mode::Mode,
+        extensions: Extensions,
     ) -> Result<Self, InterpreterError> {
-        let (posix_state, mut machine_state) = Self::init(backend, mode);
+        let (posix_state, mut machine_state) = Self::init(backend, mode, extensions);
         let elf_program = Program::<M1G>::from_elf(program)?;
         machine_state.setup_boot(&elf_program, initrd, mode::Mode::Machine)?;
         Ok(Self {
@@ -97,7 +105,15 @@ impl<'a> Interpreter<'a> {
         })
     }
 
-    fn handle_step_result(&mut self, mut result: StepManyResult, max: usize) -> InterpreterResult {
+    fn handle_step_result<F>(
+        &mut self,
+        mut result: StepManyResult,
+        max: usize,
+        should_continue: &mut F,
+    ) -> InterpreterResult
+    where
+        F: FnMut(&MachineState<M1G, SliceManager<'a>>) -> bool,
+    {
         match result.exception {
             Some(exc) => match self.posix_state.handle_call(&mut self.machine_state, exc) {
                 exec_env::EcallOutcome::Fatal => Exception(exc, result.steps),
@@ -112,7 +128,7 @@ impl<'a> Interpreter<'a> {
                             steps: result.steps,
                         }
                     } else if continue_eval && steps_left > 0 {
-                        self.run_accum(result.steps, steps_left)
+                        self.run_accum(result.steps, steps_left, should_continue)
                     } else {
                         Running(result.steps)
                     }
@@ -124,46 +140,94 @@ impl<'a> Interpreter<'a> {
     }
 
     pub fn run(&mut self, max: usize) -> InterpreterResult {
-        self.run_accum(0, max)
+        self.step_many(max, |_| true)
     }
 
     /// This function only exists to make the funneling of [steps_done]
     /// tail-recursive.
-    fn run_accum(&mut self, steps_done: usize, max: usize) -> InterpreterResult {
-        let mut result = self.machine_state.step_many(max, |_| true);
+    fn run_accum<F>(
+        &mut self,
+        steps_done: usize,
+        max: usize,
+        should_continue: &mut F,
+    ) -> InterpreterResult
+    where
+        F: FnMut(&MachineState<M1G, SliceManager<'a>>) -> bool,
+    {
+        let mut result = self.machine_state.step_many(max, &mut *should_continue);
         result.steps = result.steps.saturating_add(steps_done);
-        self.handle_step_result(result, max)
+        self.handle_step_result(result, max, should_continue)
     }
 
-    pub fn step_many<F>(&mut self, max: usize, should_continue: F) -> InterpreterResult
+    pub fn step_many<F>(&mut self, max: usize, mut should_continue: F) -> InterpreterResult
     where
         F: FnMut(&MachineState<M1G, SliceManager<'a>>) -> bool,
     {
-        let result = self.machine_state.step_many(max, should_continue);
-        self.handle_step_result(result, max)
+        self.run_accum(0, max, &mut should_continue)
     }
 
     pub fn read_register(&self, reg: XRegister) -> u64 {
         self.machine_state.hart.xregisters.read(reg)
     }
 
+    pub fn write_register(&mut self, reg: XRegister, value: u64) {
+        self.machine_state.hart.xregisters.write(reg, value)
+    }
+
     pub fn read_pc(&self) -> u64 {
         self.machine_state.hart.pc.read()
     }
+
+    pub fn write_pc(&mut self, value: u64) {
+        self.machine_state.hart.pc.write(value)
+    }
+
+    pub fn read_csr(&self, csr: CSRegister) -> u64 {
+        self.machine_state.hart.csregisters.read(csr)
+    }
+
+    /// Privilege mode the hart is currently running in
+    pub fn read_mode(&self) -> mode::Mode {
+        self.machine_state.hart.mode.read()
+    }
+
+    /// Fill `buffer` with the bytes found at `addr` on the bus.
+    pub fn read_memory(&self, addr: u64, buffer: &mut [u8]) -> Result<(), OutOfBounds> {
+        for (offset, byte) in buffer.iter_mut().enumerate() {
+            let addr = addr.checked_add(offset as u64).ok_or(OutOfBounds)?;
+            *byte = self.machine_state.bus.read(addr)?;
+        }
+        Ok(())
+    }
+
+    /// Write `bytes` to the bus starting at `addr`.
+    pub fn write_memory(&mut self, addr: u64, bytes: &[u8]) -> Result<(), OutOfBounds> {
+        self.machine_state.bus.write_all(addr, bytes)
+    }
+
+    /// Install the handler which receives the bytes written to the UART console.
+    pub fn set_console_output(&mut self, handler: TransmitHandler) {
+        self.machine_state
+            .bus
+            .devices
+            .uart
+            .set_transmit_handler(handler);
+    }
 }
 
 /// Debugger-specific functions
 impl<'a> Interpreter<'a> {
     /// Initialise an interpreter with a given [program], starting execution in [mode].
-    /// An initial ramdisk can also optionally be passed. Returns both the interpreter
-    /// and the fully parsed program.
+    /// An initial ramdisk can also optionally be passed. Returns the interpreter,
+    /// the fully parsed program and the symbols of the program.
     pub fn new_with_parsed_program(
         backend: &'a mut InMemoryBackend<StateLayout>,
         program: &[u8],
         initrd: Option<&[u8]>,
         mode: mode::Mode,
-    ) -> Result<(Self, BTreeMap<u64, String>), InterpreterError> {
-        let (posix_state, mut machine_state) = Self::init(backend, mode);
+        extensions: Extensions,
+    ) -> Result<(Self, BTreeMap<u64, String>, kernel_loader::SymbolTable), InterpreterError> {
+        let (posix_state, mut machine_state) = Self::init(backend, mode, extensions);
         let elf_program = Program::<M1G>::from_elf(program)?;
         machine_state.setup_boot(&elf_program, initrd, mode::Mode::Machine)?;
         Ok((
@@ -172,22 +236,7 @@ impl<'a> Interpreter<'a> {
                 machine_state,
             },
             elf_program.parsed(),
+            elf_program.symbols,
         ))
     }
 }
-
-// TODO: remove after updating OCaml bindings
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
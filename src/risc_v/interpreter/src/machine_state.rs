@@ -6,9 +6,13 @@
 
 pub mod bus;
 pub mod csregisters;
+pub mod diff;
+pub mod extensions;
 pub mod hart_state;
+pub mod instruction_cache;
 pub mod mode;
 pub mod registers;
+pub mod trace;
 
 #[cfg(test)]
 extern crate proptest;
@@ -16,9 +20,16 @@ extern crate proptest;
 use crate::{
     devicetree,
     machine_state::{
-        bus::{main_memory, Address, Addressable, Bus, OutOfBounds},
-        csregisters::CSRegister,
+        bus::{devices, main_memory, Address, Addressable, Bus, OutOfBounds},
+        csregisters::{
+            pmp::AccessType,
+            xstatus::{self, MPPValue},
+            CSRegister,
+        },
+        extensions::Extension,
         hart_state::{HartState, HartStateLayout},
+        instruction_cache::{Block, MAX_BLOCK_LENGTH, PAGE_SIZE},
+        mode::Mode,
     },
     parser::{instruction::Instr, parse},
     program::Program,
@@ -52,6 +63,17 @@ pub struct StepManyResult {
     pub exception: Option<EnvironException>,
 }
 
+/// Position of [`MachineState::step_many`] within a cached [`Block`]
+struct BlockCursor {
+    block: Block,
+    /// Index of the next instruction to run
+    index: usize,
+    /// Address of the next instruction to run
+    pc: Address,
+    /// Generation of the instruction cache the block was obtained from
+    generation: u64,
+}
+
 /// Runs an R-type instruction over [`XRegisters`]
 macro_rules! run_r_type_instr {
     ($state: ident, $instr: ident, $args: ident, $run_fn: ident) => {{
@@ -74,6 +96,14 @@ macro_rules! run_i_type_instr {
     }};
 }
 
+/// Runs an instruction over [`XRegisters`] which only reads `rs1`
+macro_rules! run_unary_instr {
+    ($state: ident, $instr: ident, $args: ident, $run_fn: ident) => {{
+        $state.hart.xregisters.$run_fn($args.rs1, $args.rd);
+        Ok(Add($instr.width()))
+    }};
+}
+
 /// Runs a B-type instruction over [`HartState`]
 macro_rules! run_b_type_instr {
     ($state: ident, $args: ident, $run_fn: ident) => {{
@@ -166,19 +196,67 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> MachineState<ML, M>
         self.bus.reset();
     }
 
+    /// Privilege mode that loads and stores are performed in. Section 3.1.6.3:
+    /// in M-mode with mstatus.MPRV set, they use the mode held in mstatus.MPP.
+    #[inline(always)]
+    fn effective_data_mode(&self) -> Mode {
+        let mode = self.hart.mode.read();
+        if mode != Mode::Machine {
+            return mode;
+        }
+
+        let mstatus = self.hart.csregisters.read(CSRegister::mstatus);
+        if !xstatus::get_MPRV(mstatus) {
+            return mode;
+        }
+
+        match xstatus::get_MPP(mstatus) {
+            MPPValue::User => Mode::User,
+            MPPValue::Supervisor => Mode::Supervisor,
+            MPPValue::Machine => Mode::Machine,
+        }
+    }
+
+    /// Whether physical memory protection lets the effective mode of `access`
+    /// access the `len` bytes at `address`. Fetches always use the current
+    /// mode, loads and stores honour mstatus.MPRV.
+    #[inline(always)]
+    pub(crate) fn pmp_allows(&self, address: Address, len: usize, access: AccessType) -> bool {
+        let mode = match access {
+            AccessType::Instruction => self.hart.mode.read(),
+            AccessType::Load | AccessType::Store => self.effective_data_mode(),
+        };
+        self.hart.csregisters.pmp_allows(mode, address, len, access)
+    }
+
+    /// Whether physical memory protection lets the current mode fetch an
+    /// instruction of `width` bytes at `pc`. Instructions are fetched in
+    /// halves of 2 bytes, which are checked separately.
+    #[inline(always)]
+    fn pmp_allows_fetch(&self, pc: Address, width: u64) -> bool {
+        self.pmp_allows(pc, 2, AccessType::Instruction)
+            && (width == 2 || self.pmp_allows(pc + 2, 2, AccessType::Instruction))
+    }
+
     /// Fetch instruction from the address given by program counter
     fn fetch_instr(&self, pc: Address) -> Result<Instr, Exception> {
         // The resons to provide the second half in the lambda is
         // because those bytes may be inaccessible or may trigger an exception when read.
         // Hence we can't read eagerly all 4 bytes.
-        let fetch_result = (|| {
-            let half_instr = self.bus.read(pc)?;
-            parse(half_instr, || self.bus.read(pc + 2))
-        })();
-
-        // Transform the out of bounds read error into a
-        // RISC-V instruction access fault exception
-        fetch_result.map_err(|_: OutOfBounds| Exception::InstructionAccessFault(pc))
+        let fetch_half = |addr: Address| {
+            if !self.pmp_allows(addr, 2, AccessType::Instruction) {
+                return Err(Exception::InstructionAccessFault(pc));
+            }
+
+            // Transform the out of bounds read error into a
+            // RISC-V instruction access fault exception
+            self.bus
+                .read(addr)
+                .map_err(|_: OutOfBounds| Exception::InstructionAccessFault(pc))
+        };
+
+        let half_instr = fetch_half(pc)?;
+        parse(half_instr, || fetch_half(pc + 2))
     }
 
     /// Advance [`MachineState`] by executing an [`Instr`]
@@ -260,6 +338,107 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> MachineState<ML, M>
             Instr::Remw(args) => run_r_type_instr!(self, instr, args, run_remw),
             Instr::Remuw(args) => run_r_type_instr!(self, instr, args, run_remuw),
 
+            // Instructions of optional extensions are illegal unless the
+            // extension is enabled
+            Instr::AddUw(_)
+            | Instr::Sh1add(_)
+            | Instr::Sh1addUw(_)
+            | Instr::Sh2add(_)
+            | Instr::Sh2addUw(_)
+            | Instr::Sh3add(_)
+            | Instr::Sh3addUw(_)
+            | Instr::SlliUw(_)
+                if !self.hart.extensions.read().contains(Extension::Zba) =>
+            {
+                Err(Exception::IllegalInstruction)
+            }
+            Instr::Andn(_)
+            | Instr::Orn(_)
+            | Instr::Xnor(_)
+            | Instr::Clz(_)
+            | Instr::Clzw(_)
+            | Instr::Ctz(_)
+            | Instr::Ctzw(_)
+            | Instr::Cpop(_)
+            | Instr::Cpopw(_)
+            | Instr::Max(_)
+            | Instr::Maxu(_)
+            | Instr::Min(_)
+            | Instr::Minu(_)
+            | Instr::SextB(_)
+            | Instr::SextH(_)
+            | Instr::ZextH(_)
+            | Instr::Rol(_)
+            | Instr::Rolw(_)
+            | Instr::Ror(_)
+            | Instr::Rori(_)
+            | Instr::Roriw(_)
+            | Instr::Rorw(_)
+            | Instr::OrcB(_)
+            | Instr::Rev8(_)
+                if !self.hart.extensions.read().contains(Extension::Zbb) =>
+            {
+                Err(Exception::IllegalInstruction)
+            }
+            Instr::Bclr(_)
+            | Instr::Bclri(_)
+            | Instr::Bext(_)
+            | Instr::Bexti(_)
+            | Instr::Binv(_)
+            | Instr::Binvi(_)
+            | Instr::Bset(_)
+            | Instr::Bseti(_)
+                if !self.hart.extensions.read().contains(Extension::Zbs) =>
+            {
+                Err(Exception::IllegalInstruction)
+            }
+
+            // Zba extension instructions
+            Instr::AddUw(args) => run_r_type_instr!(self, instr, args, run_add_uw),
+            Instr::Sh1add(args) => run_r_type_instr!(self, instr, args, run_sh1add),
+            Instr::Sh1addUw(args) => run_r_type_instr!(self, instr, args, run_sh1add_uw),
+            Instr::Sh2add(args) => run_r_type_instr!(self, instr, args, run_sh2add),
+            Instr::Sh2addUw(args) => run_r_type_instr!(self, instr, args, run_sh2add_uw),
+            Instr::Sh3add(args) => run_r_type_instr!(self, instr, args, run_sh3add),
+            Instr::Sh3addUw(args) => run_r_type_instr!(self, instr, args, run_sh3add_uw),
+            Instr::SlliUw(args) => run_i_type_instr!(self, instr, args, run_slli_uw),
+
+            // Zbb extension instructions
+            Instr::Andn(args) => run_r_type_instr!(self, instr, args, run_andn),
+            Instr::Orn(args) => run_r_type_instr!(self, instr, args, run_orn),
+            Instr::Xnor(args) => run_r_type_instr!(self, instr, args, run_xnor),
+            Instr::Clz(args) => run_unary_instr!(self, instr, args, run_clz),
+            Instr::Clzw(args) => run_unary_instr!(self, instr, args, run_clzw),
+            Instr::Ctz(args) => run_unary_instr!(self, instr, args, run_ctz),
+            Instr::Ctzw(args) => run_unary_instr!(self, instr, args, run_ctzw),
+            Instr::Cpop(args) => run_unary_instr!(self, instr, args, run_cpop),
+            Instr::Cpopw(args) => run_unary_instr!(self, instr, args, run_cpopw),
+            Instr::Max(args) => run_r_type_instr!(self, instr, args, run_max),
+            Instr::Maxu(args) => run_r_type_instr!(self, instr, args, run_maxu),
+            Instr::Min(args) => run_r_type_instr!(self, instr, args, run_min),
+            Instr::Minu(args) => run_r_type_instr!(self, instr, args, run_minu),
+            Instr::SextB(args) => run_unary_instr!(self, instr, args, run_sext_b),
+            Instr::SextH(args) => run_unary_instr!(self, instr, args, run_sext_h),
+            Instr::ZextH(args) => run_unary_instr!(self, instr, args, run_zext_h),
+            Instr::Rol(args) => run_r_type_instr!(self, instr, args, run_rol),
+            Instr::Rolw(args) => run_r_type_instr!(self, instr, args, run_rolw),
+            Instr::Ror(args) => run_r_type_instr!(self, instr, args, run_ror),
+            Instr::Rori(args) => run_i_type_instr!(self, instr, args, run_rori),
+            Instr::Roriw(args) => run_i_type_instr!(self, instr, args, run_roriw),
+            Instr::Rorw(args) => run_r_type_instr!(self, instr, args, run_rorw),
+            Instr::OrcB(args) => run_unary_instr!(self, instr, args, run_orc_b),
+            Instr::Rev8(args) => run_unary_instr!(self, instr, args, run_rev8),
+
+            // Zbs extension instructions
+            Instr::Bclr(args) => run_r_type_instr!(self, instr, args, run_bclr),
+            Instr::Bclri(args) => run_i_type_instr!(self, instr, args, run_bclri),
+            Instr::Bext(args) => run_r_type_instr!(self, instr, args, run_bext),
+            Instr::Bexti(args) => run_i_type_instr!(self, instr, args, run_bexti),
+            Instr::Binv(args) => run_r_type_instr!(self, instr, args, run_binv),
+            Instr::Binvi(args) => run_i_type_instr!(self, instr, args, run_binvi),
+            Instr::Bset(args) => run_r_type_instr!(self, instr, args, run_bset),
+            Instr::Bseti(args) => run_i_type_instr!(self, instr, args, run_bseti),
+
             // Zicsr instructions
             Instr::Csrrw(args) => run_csr_instr!(self, instr, args, run_csrrw),
             Instr::Csrrs(args) => run_csr_instr!(self, instr, args, run_csrrs),
@@ -285,10 +464,89 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> MachineState<ML, M>
         }
     }
 
-    /// Fetch & run the instruction located at address `instr_pc`
-    fn run_instr_at(&mut self, instr_pc: u64) -> Result<ProgramCounterUpdate, Exception> {
-        let instr = self.fetch_instr(instr_pc)?;
-        self.run_instr(instr)
+    /// Decode the basic block starting at `pc`. Returns [`None`] if not even
+    /// its first instruction can be cached.
+    fn decode_block(&self, pc: Address) -> Option<Block> {
+        if !bus::in_main_memory::<ML>(pc) {
+            return None;
+        }
+
+        let page_end = (pc / PAGE_SIZE + 1) * PAGE_SIZE;
+        let mut instrs = Vec::new();
+        let mut addr = pc;
+
+        while instrs.len() < MAX_BLOCK_LENGTH && addr < page_end {
+            let Ok(instr) = self.fetch_instr(addr) else {
+                break;
+            };
+
+            // Instructions straddling a page boundary are not cached, as
+            // writes to the next page would not invalidate them.
+            if addr + instr.width() > page_end {
+                break;
+            }
+
+            instrs.push(instr);
+            addr += instr.width();
+
+            if instruction_cache::ends_block(&instr) {
+                break;
+            }
+        }
+
+        (!instrs.is_empty()).then(|| instrs.into())
+    }
+
+    /// Fetch the instruction at `pc`, continuing within the block under
+    /// `cursor` if possible.
+    fn fetch_cached_instr(
+        &mut self,
+        cursor: &mut Option<BlockCursor>,
+        pc: Address,
+    ) -> Result<Instr, Exception> {
+        let generation = self.bus.instruction_cache.generation();
+
+        if let Some(cursor) = cursor {
+            if cursor.pc == pc && cursor.generation == generation {
+                if let Some(instr) = cursor.block.get(cursor.index).copied() {
+                    if !self.pmp_allows_fetch(pc, instr.width()) {
+                        return Err(Exception::InstructionAccessFault(pc));
+                    }
+
+                    cursor.index += 1;
+                    cursor.pc += instr.width();
+                    return Ok(instr);
+                }
+            }
+        }
+
+        let block = match self.bus.instruction_cache.get(pc) {
+            Some(block) => block.clone(),
+            None => match self.decode_block(pc) {
+                Some(block) => {
+                    self.bus.instruction_cache.insert(pc, block.clone());
+                    block
+                }
+                None => {
+                    *cursor = None;
+                    return self.fetch_instr(pc);
+                }
+            },
+        };
+
+        let instr = block[0];
+        if !self.pmp_allows_fetch(pc, instr.width()) {
+            *cursor = None;
+            return Err(Exception::InstructionAccessFault(pc));
+        }
+
+        *cursor = Some(BlockCursor {
+            block,
+            index: 1,
+            pc: pc + instr.width(),
+            generation,
+        });
+        Ok(instr)
     }
 
     /// Return the current [`Interrupt`] with highest priority to be handled
@@ -301,10 +559,9 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> MachineState<ML, M>
             possible => possible,
         };
 
-        // Normally, interrupts from devices / external sources are signaled to the CPU
+        // Interrupts from devices / external sources are signaled to the CPU
         // by updating the MEIP,MTIP,MSIP,SEIP,STIP,SSIP interrupt bits in the MIP register.
-        // In the hardware world, these CSRs updates would be done by PLIC / CLINT
-        // based on memory written by devices on the bus
+        // The CLINT drives MTIP and MSIP, see [`MachineState::tick_devices`].
 
         // Section 3.1.9 MIP & MIE registers
         // Multiple simultaneous interrupts destined for M-mode are handled in the
@@ -337,6 +594,25 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> MachineState<ML, M>
         None
     }
 
+    /// Advance the devices by one retired instruction and update the state
+    /// they drive, see [`MachineState::sync_devices`].
+    fn tick_devices(&mut self) {
+        self.bus.devices.tick();
+        self.sync_devices();
+    }
+
+    /// Update the interrupt lines the devices drive in `mip`. The `time` CSR
+    /// mirrors the CLINT's `mtime` register.
+    fn sync_devices(&mut self) {
+        let mtime = self.bus.devices.clint.mtime();
+        self.hart.csregisters.write(CSRegister::time, mtime);
+
+        let mip = self.hart.csregisters.read(CSRegister::mip);
+        let mip =
+            mip & !devices::Devices::<M>::INTERRUPT_MASK | self.bus.devices.pending_interrupts();
+        self.hart.csregisters.write(CSRegister::mip, mip);
+    }
+
     /// Handle interrupts (also known as asynchronous exceptions)
     /// by taking a trap for the given interrupt.
     ///
@@ -382,7 +658,25 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> MachineState<ML, M>
     ///
     /// The [`Err`] case represents an [`Exception`] to be handled by
     /// the execution environment, narrowed down by the type [`EnvironException`].
+    ///
+    /// Instructions are always fetched from memory, making this the reference
+    /// for [`MachineState::step_many`].
     pub fn step(&mut self) -> Result<(), EnvironException> {
+        self.step_with(|state, pc| state.fetch_instr(pc))
+            .map(|_| ())
+    }
+
+    /// Perform one step like [`MachineState::step`], obtaining the
+    /// instruction to run from `fetch`. Returns whether the instruction
+    /// retired, as opposed to raising an exception.
+    #[inline(always)]
+    fn step_with<F>(&mut self, fetch: F) -> Result<bool, EnvironException>
+    where
+        F: FnOnce(&mut Self, Address) -> Result<Instr, Exception>,
+    {
+        // Devices may have been written to since the last step
+        self.sync_devices();
+
         // Try to take an interrupt if available, and then
         // obtain the pc for the next instruction to be executed
         let instr_pc = match self.get_pending_interrupt() {
@@ -391,9 +685,16 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> MachineState<ML, M>
         };
 
         // Fetch & run the instruction
-        let instr_result = self.run_instr_at(instr_pc);
+        let instr_result = fetch(self, instr_pc).and_then(|instr| self.run_instr(instr));
 
         // Take exception if needed
+        let retired = instr_result.is_ok();
+
+        // Time passes with every retired instruction, which may cause
+        // device interrupts
+        if retired {
+            self.tick_devices();
+        }
         let pc_update = match instr_result {
             Err(exc) => ProgramCounterUpdate::Set(self.address_on_exception(exc, instr_pc)?),
             Ok(upd) => upd,
@@ -405,20 +706,51 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> MachineState<ML, M>
             ProgramCounterUpdate::Add(width) => self.hart.pc.write(instr_pc + width),
         };
 
-        Ok(())
+        Ok(retired)
     }
 
     /// Perform at most `max` instructions. Returns the number of retired instructions.
     ///
+    /// Instructions are decoded once per basic block and cached. The outcome
+    /// is the same as that of [`MachineState::step_many_uncached`].
+    ///
     /// See `octez_risc_v_pvm::state::Pvm`
-    pub fn step_many<F>(&mut self, max: usize, mut should_continue: F) -> StepManyResult
+    pub fn step_many<F>(&mut self, max: usize, should_continue: F) -> StepManyResult
+    where
+        F: FnMut(&Self) -> bool,
+    {
+        let mut cursor = None;
+        self.step_many_with(max, should_continue, |state| {
+            state
+                .step_with(|state, pc| state.fetch_cached_instr(&mut cursor, pc))
+                .map(|_| ())
+        })
+    }
+
+    /// Perform at most `max` instructions using [`MachineState::step`].
+    /// Returns the number of retired instructions.
+    pub fn step_many_uncached<F>(&mut self, max: usize, should_continue: F) -> StepManyResult
+    where
+        F: FnMut(&Self) -> bool,
+    {
+        self.step_many_with(max, should_continue, Self::step)
+    }
+
+    #[inline(always)]
+    fn step_many_with<F, S>(
+        &mut self,
+        max: usize,
+        mut should_continue: F,
+        mut step: S,
+    ) -> StepManyResult
     where
         F: FnMut(&Self) -> bool,
+        S: FnMut(&mut Self) -> Result<(), EnvironException>,
     {
         let mut steps_done = 0;
 
         while steps_done < max && should_continue(self) {
-            match self.step() {
+            match step(self) {
                 Ok(_) => {}
                 Err(e) => {
                     return StepManyResult {
@@ -443,6 +775,9 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> MachineState<ML, M>
         initrd: Option<&[u8]>,
         mode: mode::Mode,
     ) -> Result<(), MachineError> {
+        // Devices start off in their reset state
+        self.bus.devices.reset();
+
         // Write program to main memory and point the PC at its start
         for (addr, data) in program.segments.iter() {
             self.bus.write_all(*addr, data)?;
@@ -452,6 +787,11 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> MachineState<ML, M>
         // Set booting Hart ID (a0) to 0
         self.hart.xregisters.write(registers::a0, 0);
 
+        // Point the thread pointer at the initial TLS block, if any
+        if let Some(thread_pointer) = program.thread_pointer {
+            self.hart.xregisters.write(registers::tp, thread_pointer);
+        }
+
         // Load the initial program into memory
         let initrd_addr = program
             .segments
@@ -475,7 +815,7 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> MachineState<ML, M>
         };
 
         // Write device tree to memory
-        let fdt = devicetree::generate::<ML>(initrd)?;
+        let fdt = devicetree::generate::<ML>(initrd, self.hart.extensions.read())?;
         self.bus.write_all(dtb_addr, fdt.as_slice())?;
 
         // Point DTB boot argument (a1) at the written device tree
@@ -484,6 +824,13 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> MachineState<ML, M>
         // Start in supervisor mode
         self.hart.mode.write(mode);
 
+        // Lower privilege modes may only access memory regions granted by
+        // physical memory protection. Grant them everything, as firmware
+        // would before handing over.
+        if mode != mode::Mode::Machine {
+            self.hart.csregisters.pmp_allow_all();
+        }
+
         // Make sure to forward all exceptions and interrupts to supervisor mode
         self.hart
             .csregisters
@@ -507,21 +854,28 @@ pub enum MachineError {
 #[cfg(test)]
 mod tests {
     use super::{
-        backend::tests::{test_determinism, ManagerFor},
+        backend::{
+            memory_backend::InMemoryBackend,
+            proof::Proof,
+            tests::{randomise_backend, test_determinism, ManagerFor},
+            AllocatedOf, Backend, Layout, Manager,
+        },
         bus,
-        bus::main_memory::tests::T1K,
+        bus::{devices::clint, main_memory::tests::T1K, Addressable},
         MachineState, MachineStateLayout,
     };
     use crate::{
         backend_test, create_backend, create_state,
         machine_state::{
             csregisters::{xstatus, CSRegister},
+            extensions::{Extension, Extensions},
             mode::Mode,
-            registers::{a1, a2, t0, t2},
+            registers::{self, a0, a1, a2, t0, t2},
         },
         traps::{EnvironException, Exception, Interrupt, TrapContext},
     };
     use proptest::{prop_assert_eq, proptest};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
     use twiddle::Twiddle;
 
     backend_test!(test_machine_state_reset, F, {
@@ -532,6 +886,88 @@ mod tests {
         });
     });
 
+    type ProofLayout = MachineStateLayout<T1K>;
+
+    /// Reset the machine and run a few steps
+    fn reset_and_step<M: Manager>(space: AllocatedOf<ProofLayout, M>) {
+        let mut machine = MachineState::<T1K, M>::bind(space);
+        machine.reset();
+        machine.bus.devices.reset();
+        for _ in 0..4 {
+            let _ = machine.step();
+        }
+    }
+
+    backend_test!(test_step_deterministic, F, {
+        // Proofs rely on steps being determined by the initial state alone.
+        test_determinism::<F, ProofLayout, _>(|space| reset_and_step(space));
+    });
+
+    #[test]
+    fn test_step_proof() {
+        type L = ProofLayout;
+
+        fn step<M: Manager>(space: AllocatedOf<L, M>) {
+            let _ = MachineState::<T1K, M>::bind(space).step();
+        }
+
+        // Steps starting from arbitrary states can be proven and verified.
+        for mode in [Mode::User, Mode::Supervisor, Mode::Machine].repeat(4) {
+            let (mut backend, placed) = InMemoryBackend::<L>::new();
+            randomise_backend(&mut backend);
+
+            // Not all values are valid privilege modes.
+            MachineState::<T1K, _>::bind(backend.allocate(placed))
+                .hart
+                .mode
+                .write(mode);
+
+            let proof = Proof::produce(&mut backend, |space| step(space)).unwrap();
+            let proof = Proof::decode::<L>(&proof.encode()).unwrap();
+            assert_eq!(proof.verify::<L, _>(|space| step(space)), Ok(()));
+        }
+
+        // So can steps which rewrite the whole state.
+        let (mut backend, _) = InMemoryBackend::<L>::new();
+        randomise_backend(&mut backend);
+        let proof = Proof::produce(&mut backend, |space| reset_and_step(space)).unwrap();
+        assert_eq!(proof.verify::<L, _>(|space| reset_and_step(space)), Ok(()));
+    }
+
+    backend_test!(test_optional_extensions, F, {
+        let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+        let mut state = create_state!(MachineState, MachineStateLayout<T1K>, F, backend, T1K);
+        state.reset();
+
+        // sh1add a0, a1, a2
+        let start = bus::start_of_main_memory::<T1K>();
+        state.bus.write(start, 0x20c5a533_u32).unwrap();
+        state.hart.xregisters.write(a1, 3);
+        state.hart.xregisters.write(a2, 10);
+
+        state.step().expect("should not raise trap to EE");
+        assert_eq!(state.hart.xregisters.read(a0), 16);
+        assert_eq!(state.hart.pc.read(), start + 4);
+
+        // The instruction is illegal once Zba is disabled
+        let mtvec = start + 0x100;
+        state.hart.csregisters.write(CSRegister::mtvec, mtvec);
+        state
+            .hart
+            .extensions
+            .write(Extensions::ALL.without(Extension::Zba));
+        state.hart.xregisters.write(a0, 0);
+        state.hart.pc.write(start);
+
+        state.step().expect("should not raise trap to EE");
+        assert_eq!(state.hart.xregisters.read(a0), 0);
+        assert_eq!(state.hart.pc.read(), mtvec);
+        assert_eq!(
+            state.hart.csregisters.read(CSRegister::mcause),
+            Exception::IllegalInstruction.exception_code()
+        );
+    });
+
     backend_test!(test_step, F, {
         proptest!(|(
             pc_addr_offset in 0..250_u64,
@@ -539,6 +975,8 @@ mod tests {
         )| {
             let mut backend = create_backend!(MachineStateLayout<T1K>, F);
             let mut state = create_state!(MachineState, MachineStateLayout<T1K>, F, backend, T1K);
+            state.bus.devices.reset();
+            state.hart.csregisters.pmp_allow_all();
 
             let init_pc_addr = bus::start_of_main_memory::<T1K>() + pc_addr_offset * 4;
             let jump_addr = bus::start_of_main_memory::<T1K>() + jump_addr * 4;
@@ -579,6 +1017,7 @@ mod tests {
         )| {
             let mut backend = create_backend!(MachineStateLayout<T1K>, F);
             let mut state = create_state!(MachineState, MachineStateLayout<T1K>, F, backend, T1K);
+            state.bus.devices.reset();
 
             let init_pc_addr = bus::start_of_main_memory::<T1K>() + pc_addr_offset * 4;
             let stvec_addr = init_pc_addr + 4 * stvec_offset;
@@ -611,6 +1050,7 @@ mod tests {
         )| {
             let mut backend = create_backend!(MachineStateLayout<T1K>, F);
             let mut state = create_state!(MachineState, MachineStateLayout<T1K>, F, backend, T1K);
+            state.bus.devices.reset();
 
             let init_pc_addr = bus::start_of_main_memory::<T1K>() + pc_addr_offset * 4;
             let mtvec_addr = init_pc_addr + 4 * mtvec_offset;
@@ -654,6 +1094,7 @@ mod tests {
             // (test delegation doesn't take place even if enabled by registers)
             let mut backend = create_backend!(MachineStateLayout<T1K>, F);
             let mut state = create_state!(MachineState, MachineStateLayout<T1K>, F, backend, T1K);
+            state.bus.devices.reset();
 
             let init_pc_addr = bus::start_of_main_memory::<T1K>() + pc_addr_offset * 4;
             let stvec_addr = init_pc_addr + 4 * stvec_offset;
@@ -707,6 +1148,7 @@ mod tests {
             // Raise exception, take trap from U-mode to S-mode (test delegation takes place)
             let mut backend = create_backend!(MachineStateLayout<T1K>, F);
             let mut state = create_state!(MachineState, MachineStateLayout<T1K>, F, backend, T1K);
+            state.bus.devices.reset();
 
             let init_pc_addr = bus::start_of_main_memory::<T1K>() + pc_addr_offset * 4;
             let stvec_addr = init_pc_addr + 4 * stvec_offset;
@@ -747,6 +1189,8 @@ mod tests {
             // interrupt delegation will delegate the SEI, but not MSI, testing the priority as well
             let mut backend = create_backend!(MachineStateLayout<T1K>, F);
             let mut state = create_state!(MachineState, MachineStateLayout<T1K>, F, backend, T1K);
+            state.hart.csregisters.pmp_allow_all();
+            state.bus.devices.reset();
 
             let init_pc_addr = bus::start_of_main_memory::<T1K>() + pc_addr_offset * 4;
             let stvec_addr = init_pc_addr + 4 * stvec_offset;
@@ -812,4 +1256,264 @@ mod tests {
             assert_eq!(state.hart.csregisters.read(CSRegister::mip), mip ^ 1 << 9);
         });
     });
+
+    backend_test!(test_step_clint_timer, F, {
+        proptest!(|(
+            pc_addr_offset in 0..200_u64,
+            mtvec_offset in 25..35_u64,
+            timeout in 1..10_u64,
+        )| {
+            // The CLINT raises a machine timer interrupt once mtime reaches mtimecmp
+            let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+            let mut state = create_state!(MachineState, MachineStateLayout<T1K>, F, backend, T1K);
+            state.hart.csregisters.pmp_allow_all();
+            state.bus.devices.reset();
+
+            let init_pc_addr = bus::start_of_main_memory::<T1K>() + pc_addr_offset * 4;
+            let mtvec_addr = init_pc_addr + 4 * mtvec_offset;
+            const ADDI: u64 = 0b001_0011;
+            const MTIMECMP: u64 = clint::CLINT_START + 0x4000;
+
+            // Fill the code with no-ops
+            state.hart.xregisters.write(a1, ADDI);
+            for i in 0..mtvec_offset + 8 {
+                state.hart.xregisters.write(a2, init_pc_addr + 4 * i);
+                state.run_sw(0, a2, a1).expect("Storing instruction should succeed");
+            }
+
+            state.hart.csregisters.write(CSRegister::mtvec, mtvec_addr);
+            let mie = 0.set_bit(Interrupt::MachineTimer.exception_code() as usize, true);
+            state.hart.csregisters.write(CSRegister::mie, mie);
+            let mstatus = xstatus::set_MIE(state.hart.csregisters.read(CSRegister::mstatus), true);
+            state.hart.csregisters.write(CSRegister::mstatus, mstatus);
+            state.hart.mode.write(Mode::Machine);
+            state.hart.pc.write(init_pc_addr);
+
+            state.bus.write(MTIMECMP, timeout).expect("mtimecmp should be writable");
+
+            for step in 1..=timeout {
+                state.step().expect("should not raise environment exception");
+                prop_assert_eq!(state.hart.pc.read(), init_pc_addr + 4 * step);
+                prop_assert_eq!(state.hart.csregisters.read(CSRegister::time), step);
+            }
+
+            // The timer expired with the last retired instruction
+            state.step().expect("should not raise environment exception");
+            prop_assert_eq!(state.hart.pc.read(), mtvec_addr + 4);
+            prop_assert_eq!(state.hart.csregisters.read(CSRegister::mcause), 1 << 63 | 7);
+            prop_assert_eq!(
+                state.hart.csregisters.read(CSRegister::mepc),
+                init_pc_addr + 4 * timeout
+            );
+
+            // Re-arming the timer clears the pending interrupt
+            state.bus.write(MTIMECMP, u64::MAX).expect("mtimecmp should be writable");
+            state.step().expect("should not raise environment exception");
+            prop_assert_eq!(state.hart.csregisters.read(CSRegister::mip), 0);
+
+            // Instructions that raise an exception do not retire, hence time
+            // does not advance
+            let time = state.hart.csregisters.read(CSRegister::time);
+            state.hart.xregisters.write(a1, 0);
+            state.hart.xregisters.write(a2, state.hart.pc.read());
+            state.run_sw(0, a2, a1).expect("Storing instruction should succeed");
+            state.step().expect("should not raise environment exception");
+            prop_assert_eq!(state.hart.csregisters.read(CSRegister::mcause), 2);
+            prop_assert_eq!(state.hart.csregisters.read(CSRegister::time), time);
+        });
+    });
+
+    backend_test!(test_step_masked_timer_interrupt, F, {
+        // A pending machine timer interrupt is not taken in S-mode while
+        // disabled in mie, even though the CLINT keeps raising it
+        let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+        let mut state = create_state!(MachineState, MachineStateLayout<T1K>, F, backend, T1K);
+        state.hart.csregisters.pmp_allow_all();
+        state.bus.devices.reset();
+
+        let init_pc_addr = bus::start_of_main_memory::<T1K>();
+        const ADDI: u64 = 0b001_0011;
+        const MTIMECMP: u64 = clint::CLINT_START + 0x4000;
+
+        state.hart.xregisters.write(a1, ADDI);
+        for i in 0..4 {
+            state.hart.xregisters.write(a2, init_pc_addr + 4 * i);
+            state
+                .run_sw(0, a2, a1)
+                .expect("Storing instruction should succeed");
+        }
+
+        state.hart.csregisters.write(CSRegister::mie, 0);
+        state.hart.mode.write(Mode::Supervisor);
+        state.hart.pc.write(init_pc_addr);
+        state
+            .bus
+            .write(MTIMECMP, 0_u64)
+            .expect("mtimecmp should be writable");
+
+        let mtip = 0.set_bit(Interrupt::MachineTimer.exception_code() as usize, true);
+        for step in 1..=4 {
+            state
+                .step()
+                .expect("should not raise environment exception");
+            assert_eq!(state.hart.csregisters.read(CSRegister::mip), mtip);
+            assert_eq!(state.hart.mode.read(), Mode::Supervisor);
+            assert_eq!(state.hart.pc.read(), init_pc_addr + 4 * step);
+        }
+    });
+
+    backend_test!(test_pmp_modify_privilege, F, {
+        // With mstatus.MPRV set, M-mode loads and stores are checked against
+        // PMP as if made from the mode in mstatus.MPP, while fetches are not
+        let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+        let mut state = create_state!(MachineState, MachineStateLayout<T1K>, F, backend, T1K);
+        state.bus.devices.reset();
+
+        let init_pc_addr = bus::start_of_main_memory::<T1K>();
+        let data_addr = init_pc_addr + 0x100;
+        const ADDI: u64 = 0b001_0011;
+
+        state.hart.mode.write(Mode::Machine);
+        state.hart.xregisters.write(a1, ADDI);
+        state.hart.xregisters.write(a2, init_pc_addr);
+        state
+            .run_sw(0, a2, a1)
+            .expect("Storing instruction should succeed");
+
+        // No PMP entries are set up: only M-mode has access to memory
+        state.hart.xregisters.write(a2, data_addr);
+        state
+            .run_sd(0, a2, a1)
+            .expect("M-mode store should succeed");
+        state.run_ld(0, a2, a0).expect("M-mode load should succeed");
+
+        let mstatus = state.hart.csregisters.read(CSRegister::mstatus);
+        let mstatus = xstatus::set_MPP(mstatus, xstatus::MPPValue::User);
+        let mstatus = xstatus::set_MPRV(mstatus, true);
+        state.hart.csregisters.write(CSRegister::mstatus, mstatus);
+
+        assert_eq!(
+            state.run_ld(0, a2, a0),
+            Err(Exception::LoadAccessFault(data_addr))
+        );
+        assert_eq!(
+            state.run_sd(0, a2, a1),
+            Err(Exception::StoreAccessFault(data_addr))
+        );
+
+        state.hart.pc.write(init_pc_addr);
+        state
+            .step()
+            .expect("should not raise environment exception");
+        assert_eq!(state.hart.pc.read(), init_pc_addr + 4);
+        assert_eq!(state.hart.mode.read(), Mode::Machine);
+
+        // MPP = M makes MPRV have no effect
+        let mstatus = xstatus::set_MPP(mstatus, xstatus::MPPValue::Machine);
+        state.hart.csregisters.write(CSRegister::mstatus, mstatus);
+        state.run_ld(0, a2, a0).expect("M-mode load should succeed");
+        assert_eq!(state.hart.xregisters.read(a0), ADDI);
+    });
+
+    #[test]
+    fn test_step_many_cached() {
+        type L = MachineStateLayout<T1K>;
+
+        const LENGTH: u64 = 32;
+        const TEMPS: [u32; 7] = [5, 6, 7, 28, 29, 30, 31];
+        const S0: u32 = 8;
+
+        let i_type = |opcode: u32, funct3: u32, rd: u32, rs1: u32, imm: u32| {
+            (imm & 0xfff) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+        };
+        let s_type = |funct3: u32, rs2: u32, imm: u32| {
+            (imm >> 5) << 25 | rs2 << 20 | S0 << 15 | funct3 << 12 | (imm & 0x1f) << 7 | 0x23
+        };
+
+        // Loops which keep storing random values over their own code produce
+        // the same states with and without the instruction cache. Traps
+        // restart the loop.
+        for seed in 0..32 {
+            let (mut backend, placed) = InMemoryBackend::<L>::new();
+            let mut state = MachineState::<T1K, _>::bind(backend.allocate(placed));
+            let start = bus::start_of_main_memory::<T1K>();
+            let mut rng = StdRng::seed_from_u64(seed);
+
+            for offset in (0..LENGTH - 1).map(|i| i * 4) {
+                let rd = TEMPS[rng.gen_range(0..TEMPS.len())];
+                let rs = TEMPS[rng.gen_range(0..TEMPS.len())];
+                let target = rng.gen_range(0..LENGTH as u32) * 4;
+                let instr = match rng.gen_range(0..5) {
+                    0 => i_type(0x13, 0, rd, rs, rng.gen()),
+                    1 => i_type(0x33, 4, rd, rs, TEMPS[rng.gen_range(0..TEMPS.len())]),
+                    2 => i_type(0x03, 2, rd, S0, target),
+                    3 => s_type(2, rs, target),
+                    _ => s_type(0, rs, target + rng.gen_range(0..4)),
+                };
+                state.bus.write(start + offset, instr).unwrap();
+            }
+
+            // j start
+            let back = (LENGTH as u32 - 1) * 4;
+            let jump = 1 << 31 | ((back.wrapping_neg() >> 1) & 0x3ff) << 21 | 1 << 20 | 0xff << 12;
+            state
+                .bus
+                .write(start + (LENGTH - 1) * 4, jump | 0x6f)
+                .unwrap();
+
+            for reg in TEMPS {
+                state
+                    .hart
+                    .xregisters
+                    .write(registers::parse_xregister(reg), rng.gen());
+            }
+            state
+                .hart
+                .xregisters
+                .write(registers::parse_xregister(S0), start);
+            state.hart.csregisters.write(CSRegister::mtvec, start);
+            state.hart.mode.write(Mode::Machine);
+            state.hart.pc.write(start);
+
+            let mut reference = backend.clone();
+
+            let result =
+                MachineState::<T1K, _>::bind(backend.allocate(L::placed().into_location()))
+                    .step_many(10_000, |_| true);
+            let expected =
+                MachineState::<T1K, _>::bind(reference.allocate(L::placed().into_location()))
+                    .step_many_uncached(10_000, |_| true);
+
+            assert_eq!(result.steps, expected.steps);
+            assert_eq!(result.exception, expected.exception);
+            assert!(backend.borrow() == reference.borrow());
+        }
+    }
+
+    backend_test!(test_step_many_self_modifying, F, {
+        let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+        let mut state = create_state!(MachineState, MachineStateLayout<T1K>, F, backend, T1K);
+
+        const ADDI_T0_1: u32 = 0x00128293; // addi t0, t0, 1
+        const ADDI_T0_16: u32 = 0x01028293; // addi t0, t0, 16
+        const SW_A1_A2: u32 = 0x00b62023; // sw a1, 0(a2)
+        const J_BACK: u32 = 0xff9ff06f; // j -8
+
+        let start = bus::start_of_main_memory::<T1K>();
+        state
+            .bus
+            .write_all(start, &[ADDI_T0_1, SW_A1_A2, J_BACK])
+            .expect("Storing instructions should succeed");
+        state.hart.mode.write(Mode::Machine);
+        state.hart.pc.write(start);
+        state.hart.xregisters.write(a1, ADDI_T0_16 as u64);
+        state.hart.xregisters.write(a2, start);
+
+        // The first store replaces the instruction at the start of the loop,
+        // which must be observed by the following iterations.
+        let result = state.step_many(7, |_| true);
+        assert_eq!(result.steps, 7);
+        assert_eq!(state.hart.xregisters.read(t0), 1 + 16 + 16);
+        assert_eq!(state.hart.pc.read(), start + 4);
+    });
 }
@@ -1,11 +1,11 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
 pub mod devices;
 pub mod main_memory;
 
-use crate::machine_state::{backend, registers};
+use crate::machine_state::{backend, instruction_cache::InstructionCache, registers};
 use derive_more::Error;
 use std::mem;
 
@@ -21,6 +21,24 @@ pub trait Addressable<E: backend::Elem> {
     /// Read an element of type `E` from the given address.
     fn read(&self, addr: Address) -> Result<E, OutOfBounds>;
 
+    /// Read consecutive elements of type `E` starting from the given address.
+    fn read_all(&self, addr: Address, values: &mut [E]) -> Result<(), OutOfBounds> {
+        let width = mem::size_of::<E>() as Address;
+        for (i, value) in values.iter_mut().enumerate() {
+            let addr = addr.checked_add(i as Address * width).ok_or(OutOfBounds)?;
+            *value = self.read(addr)?;
+        }
+        Ok(())
+    }
+
+    /// Load an element of type `E` from the given address on behalf of the
+    /// hart. Unlike [`Addressable::read`], loads may have side effects, e.g.
+    /// consuming input from a device.
+    #[inline(always)]
+    fn load(&mut self, addr: Address) -> Result<E, OutOfBounds> {
+        self.read(addr)
+    }
+
     /// Write an element of type `E` to the given address.
     fn write(&mut self, addr: Address, value: E) -> Result<(), OutOfBounds>;
 
@@ -76,8 +94,11 @@ pub type BusLayout<ML> = (devices::DevicesLayout, ML);
 
 /// Bus connects to the main memory and other devices.
 pub struct Bus<ML: main_memory::MainMemoryLayout, M: backend::Manager> {
-    devices: devices::Devices<M>,
+    pub devices: devices::Devices<M>,
     memory: main_memory::MainMemory<ML, M>,
+
+    /// Blocks decoded from main memory, invalidated by writes to it
+    pub(crate) instruction_cache: InstructionCache,
 }
 
 impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> Bus<ML, M> {
@@ -86,6 +107,7 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> Bus<ML, M> {
         Self {
             devices: devices::Devices::bind(space.0),
             memory: main_memory::MainMemory::bind(space.1),
+            instruction_cache: InstructionCache::default(),
         }
     }
 
@@ -93,6 +115,7 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> Bus<ML, M> {
     pub fn reset(&mut self) {
         self.devices.reset();
         self.memory.reset();
+        self.instruction_cache.flush();
     }
 }
 
@@ -101,6 +124,11 @@ pub fn start_of_main_memory<ML: main_memory::MainMemoryLayout>() -> Address {
     AddressSpace::MainMemory.start::<ML>()
 }
 
+/// Whether the address belongs to main memory.
+pub fn in_main_memory<ML: main_memory::MainMemoryLayout>(addr: Address) -> bool {
+    AddressSpace::locate::<ML>(addr).0 == AddressSpace::MainMemory
+}
+
 impl<E, ML, M> Addressable<E> for Bus<ML, M>
 where
     E: backend::Elem,
@@ -119,12 +147,46 @@ where
         }
     }
 
+    #[inline(always)]
+    fn read_all(&self, addr: Address, values: &mut [E]) -> Result<(), OutOfBounds> {
+        let end_addr = addr
+            .checked_add(mem::size_of_val(values).saturating_sub(1) as Address)
+            .ok_or(OutOfBounds)?;
+
+        let (addr_space, local_addr) = AddressSpace::locate::<ML>(addr);
+        let (end_addr_space, _) = AddressSpace::locate::<ML>(end_addr);
+
+        if addr_space != end_addr_space {
+            // We don't allow cross-address space reads
+            return Err(OutOfBounds);
+        }
+
+        match addr_space {
+            AddressSpace::Devices => self.devices.read_all(local_addr, values),
+            AddressSpace::MainMemory => self.memory.read_all(local_addr, values),
+            AddressSpace::OutOfBounds => Err(OutOfBounds),
+        }
+    }
+
+    #[inline(always)]
+    fn load(&mut self, addr: Address) -> Result<E, OutOfBounds> {
+        let (addr_space, local_address) = AddressSpace::locate::<ML>(addr);
+        match addr_space {
+            AddressSpace::Devices => self.devices.load(local_address),
+            AddressSpace::MainMemory => self.memory.load(local_address),
+            AddressSpace::OutOfBounds => Err(OutOfBounds),
+        }
+    }
+
     #[inline(always)]
     fn write(&mut self, addr: Address, value: E) -> Result<(), OutOfBounds> {
         let (addr_space, local_address) = AddressSpace::locate::<ML>(addr);
         match addr_space {
             AddressSpace::Devices => self.devices.write(local_address, value),
-            AddressSpace::MainMemory => self.memory.write(local_address, value),
+            AddressSpace::MainMemory => {
+                self.instruction_cache.invalidate(addr, mem::size_of::<E>());
+                self.memory.write(local_address, value)
+            }
             AddressSpace::OutOfBounds => Err(OutOfBounds),
         }
     }
@@ -143,7 +205,11 @@ where
 
         match addr_space {
             AddressSpace::Devices => self.devices.write_all(local_addr, values),
-            AddressSpace::MainMemory => self.memory.write_all(local_addr, values),
+            AddressSpace::MainMemory => {
+                self.instruction_cache
+                    .invalidate(addr, mem::size_of_val(values));
+                self.memory.write_all(local_addr, values)
+            }
             AddressSpace::OutOfBounds => Err(OutOfBounds),
         }
     }
@@ -1,12 +1,16 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
 //! We reserve an address space of size [DEVICES_ADDRESS_SPACE_LENGTH] dedicated
 //! to devices.
 
-use super::Addressable;
-use crate::machine_state::backend::{self, Region};
+pub mod clint;
+pub mod uart;
+
+use super::{Address, Addressable, OutOfBounds};
+use crate::machine_state::{backend, csregisters::CSRValue};
+use std::mem;
 
 /// Length of the devices address space
 pub const DEVICES_ADDRESS_SPACE_LENGTH: u64 = 2 * 1024 * 1024 * 1024;
@@ -15,51 +19,147 @@ pub const DEVICES_ADDRESS_SPACE_LENGTH: u64 = 2 * 1024 * 1024 * 1024;
 // Note, the [DevicesLayout] is not required to have the same size as the
 // address space it represents. The address space is an interface detail,
 // the layout size is a state implementation detail.
-pub type DevicesLayout = backend::Array<u8, 1>;
+pub type DevicesLayout = (clint::ClintLayout, uart::UartLayout);
 
 /// Devices state
 pub struct Devices<M: backend::Manager> {
-    _placeholder: M::Region<u8, 1>,
+    pub clint: clint::Clint<M>,
+    pub uart: uart::Uart<M>,
 }
 
 impl<M: backend::Manager> Devices<M> {
     /// Bind the devices state.
     pub fn bind(space: backend::AllocatedOf<DevicesLayout, M>) -> Self {
         Self {
-            _placeholder: space,
+            clint: clint::Clint::bind(space.0),
+            uart: uart::Uart::bind(space.1),
         }
     }
 
     /// Reset the devices state.
     pub fn reset(&mut self) {
-        self._placeholder.write(0, 0);
+        self.clint.reset();
+        self.uart.reset();
+    }
+
+    /// Advance the devices by one instruction retired by the machine.
+    pub fn tick(&mut self) {
+        self.clint.tick();
+    }
+
+    /// Interrupt bits, as they appear in `mip`, that are pending due to devices.
+    pub fn pending_interrupts(&self) -> CSRValue {
+        self.clint.pending_interrupts()
+    }
+
+    /// Bits in `mip` that are driven by devices
+    pub const INTERRUPT_MASK: CSRValue = clint::Clint::<M>::INTERRUPT_MASK;
+}
+
+/// If `addr` lies within the device mapped at `start` with `length` bytes,
+/// return the address relative to the start of that device.
+#[inline(always)]
+fn device_address(addr: Address, start: Address, length: Address) -> Option<Address> {
+    addr.checked_sub(start).filter(|offset| *offset < length)
+}
+
+/// Read an element of type `E` at `offset` from a register of `width` bytes
+/// holding `value`. Accesses must not cross the boundary of the register.
+pub(crate) fn read_register<E: backend::Elem>(
+    value: u64,
+    width: usize,
+    offset: usize,
+) -> Result<E, OutOfBounds> {
+    if offset + mem::size_of::<E>() > width.min(mem::size_of::<u64>()) {
+        return Err(OutOfBounds);
+    }
+
+    // Registers are little-endian, just like the stored representation.
+    let bytes = value.to_le_bytes();
+    let stored = unsafe { bytes.as_ptr().add(offset).cast::<E>().read_unaligned() };
+    Ok(E::from_stored(&stored))
+}
+
+/// Write an element of type `E` at `offset` into a register of `width` bytes
+/// holding `value`. Returns the updated register value. Accesses must not cross
+/// the boundary of the register.
+pub(crate) fn write_register<E: backend::Elem>(
+    value: u64,
+    width: usize,
+    offset: usize,
+    mut elem: E,
+) -> Result<u64, OutOfBounds> {
+    if offset + mem::size_of::<E>() > width.min(mem::size_of::<u64>()) {
+        return Err(OutOfBounds);
     }
+
+    elem.to_stored_in_place();
+    let mut bytes = value.to_le_bytes();
+    unsafe {
+        bytes
+            .as_mut_ptr()
+            .add(offset)
+            .cast::<E>()
+            .write_unaligned(elem)
+    };
+    Ok(u64::from_le_bytes(bytes))
 }
 
 impl<E: backend::Elem, M: backend::Manager> Addressable<E> for Devices<M> {
-    fn read(&self, _addr: super::Address) -> Result<E, super::OutOfBounds> {
-        Err(super::OutOfBounds)
+    fn read(&self, addr: Address) -> Result<E, OutOfBounds> {
+        if let Some(addr) = device_address(addr, clint::CLINT_START, clint::CLINT_LENGTH) {
+            return self.clint.read(addr);
+        }
+
+        if let Some(addr) = device_address(addr, uart::UART_START, uart::UART_LENGTH) {
+            return self.uart.read(addr);
+        }
+
+        Err(OutOfBounds)
     }
 
-    fn write(&mut self, _addr: super::Address, _value: E) -> Result<(), super::OutOfBounds> {
-        Err(super::OutOfBounds)
+    fn load(&mut self, addr: Address) -> Result<E, OutOfBounds> {
+        if let Some(addr) = device_address(addr, clint::CLINT_START, clint::CLINT_LENGTH) {
+            return self.clint.load(addr);
+        }
+
+        if let Some(addr) = device_address(addr, uart::UART_START, uart::UART_LENGTH) {
+            return self.uart.load(addr);
+        }
+
+        Err(OutOfBounds)
     }
 
-    fn write_all(
-        &mut self,
-        _addr: super::Address,
-        _values: &[E],
-    ) -> Result<(), super::OutOfBounds> {
-        Err(super::OutOfBounds)
+    fn write(&mut self, addr: Address, value: E) -> Result<(), OutOfBounds> {
+        if let Some(addr) = device_address(addr, clint::CLINT_START, clint::CLINT_LENGTH) {
+            return self.clint.write(addr, value);
+        }
+
+        if let Some(addr) = device_address(addr, uart::UART_START, uart::UART_LENGTH) {
+            return self.uart.write(addr, value);
+        }
+
+        Err(OutOfBounds)
+    }
+
+    fn write_all(&mut self, addr: Address, values: &[E]) -> Result<(), OutOfBounds> {
+        let width = mem::size_of::<E>() as Address;
+        for (i, value) in values.iter().enumerate() {
+            self.write(addr + i as Address * width, *value)?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Devices, DevicesLayout};
+    use super::{clint, read_register, uart, write_register, Devices, DevicesLayout};
     use crate::{
-        backend_test,
-        machine_state::backend::tests::{test_determinism, ManagerFor},
+        backend_test, create_backend, create_state,
+        machine_state::{
+            backend::tests::{test_determinism, ManagerFor},
+            bus::{Addressable, OutOfBounds},
+        },
     };
 
     backend_test!(test_reset, F, {
@@ -68,4 +168,51 @@ mod tests {
             devices.reset();
         });
     });
+
+    #[test]
+    fn test_register_access() {
+        let value = 0x1122_3344_5566_7788u64;
+        assert_eq!(read_register::<u8>(value, 8, 7), Ok(0x11));
+        assert_eq!(read_register::<u16>(value, 8, 2), Ok(0x5566));
+        assert_eq!(read_register::<u32>(value, 4, 0), Ok(0x5566_7788));
+        assert_eq!(read_register::<u32>(value, 4, 2), Err(OutOfBounds));
+        assert_eq!(
+            write_register(value, 8, 4, 0xAABBu16),
+            Ok(0x1122_AABB_5566_7788)
+        );
+        assert_eq!(write_register(value, 8, 6, 0u32), Err(OutOfBounds));
+    }
+
+    backend_test!(test_devices_address_map, F, {
+        let mut backend = create_backend!(DevicesLayout, F);
+        let mut devices = create_state!(Devices, DevicesLayout, F, backend);
+        devices.reset();
+
+        // The CLINT's mtime register is reachable through the devices.
+        let mtime = clint::CLINT_START + 0xBFF8;
+        devices.tick();
+        assert_eq!(Addressable::<u64>::read(&devices, mtime), Ok(1));
+
+        // The UART's line status register is reachable through the devices.
+        let lsr = uart::UART_START + 5;
+        assert_eq!(Addressable::<u8>::read(&devices, lsr), Ok(0x60));
+        devices.uart.receive(b"a");
+        assert_eq!(Addressable::<u8>::read(&devices, lsr), Ok(0x61));
+        assert_eq!(devices.load(uart::UART_START), Ok(b'a'));
+        assert_eq!(Addressable::<u8>::read(&devices, lsr), Ok(0x60));
+
+        // Nothing is mapped right before or after the devices.
+        assert_eq!(
+            Addressable::<u8>::read(&devices, clint::CLINT_START - 1),
+            Err(OutOfBounds)
+        );
+        assert_eq!(
+            devices.write(clint::CLINT_START + clint::CLINT_LENGTH, 0u8),
+            Err(OutOfBounds)
+        );
+        assert_eq!(
+            Addressable::<u8>::read(&devices, uart::UART_START + uart::UART_LENGTH),
+            Err(OutOfBounds)
+        );
+    });
 }
@@ -0,0 +1,265 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Core-Local Interruptor (CLINT)
+//!
+//! The CLINT provides the machine-level software interrupt (`msip`) and the
+//! machine-level timer (`mtime` and `mtimecmp`). Its memory map follows the
+//! SiFive CLINT / RISC-V ACLINT layout used by QEMU's `virt` machine and
+//! firmware such as OpenSBI.
+//!
+//! Additional resources:
+//!   - https://github.com/riscv/riscv-aclint/blob/main/riscv-aclint.adoc
+
+use super::{read_register, write_register};
+use crate::{
+    machine_state::{
+        bus::{Address, Addressable, OutOfBounds},
+        csregisters::CSRValue,
+    },
+    state_backend::{self as backend, Atom, Cell},
+    traps::Interrupt,
+};
+
+/// Start of the CLINT in the devices address space
+pub const CLINT_START: Address = 0x0200_0000;
+
+/// Length of the CLINT memory map
+pub const CLINT_LENGTH: Address = 0x1_0000;
+
+/// Offset of the `msip` register for hart 0
+const MSIP_OFFSET: Address = 0x0;
+
+/// Offset of the `mtimecmp` register for hart 0
+const MTIMECMP_OFFSET: Address = 0x4000;
+
+/// Offset of the `mtime` register
+const MTIME_OFFSET: Address = 0xBFF8;
+
+/// Number of `mtime` ticks per instruction retired by the machine
+///
+/// Time is derived from the number of instructions retired by the machine, which keeps
+/// it deterministic. The device tree advertises the corresponding timebase
+/// frequency.
+pub const TICKS_PER_INSTRUCTION: u64 = 1;
+
+/// Memory-mapped CLINT registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Register {
+    Msip,
+    Mtimecmp,
+    Mtime,
+}
+
+impl Register {
+    /// Determine the register that contains the given offset. Returns the
+    /// register, the offset relative to the start of the register and the
+    /// width of the register in bytes.
+    fn locate(offset: Address) -> Option<(Self, usize, usize)> {
+        let within = |start: Address, width: usize| {
+            offset
+                .checked_sub(start)
+                .filter(|rel| *rel < width as Address)
+                .map(|rel| rel as usize)
+        };
+
+        if let Some(rel) = within(MSIP_OFFSET, 4) {
+            return Some((Register::Msip, rel, 4));
+        }
+
+        if let Some(rel) = within(MTIMECMP_OFFSET, 8) {
+            return Some((Register::Mtimecmp, rel, 8));
+        }
+
+        if let Some(rel) = within(MTIME_OFFSET, 8) {
+            return Some((Register::Mtime, rel, 8));
+        }
+
+        None
+    }
+}
+
+/// Layout of the CLINT state
+pub type ClintLayout = (
+    Atom<u32>, // msip
+    Atom<u64>, // mtimecmp
+    Atom<u64>, // mtime
+);
+
+/// CLINT state
+pub struct Clint<M: backend::Manager> {
+    msip: Cell<u32, M>,
+    mtimecmp: Cell<u64, M>,
+    mtime: Cell<u64, M>,
+}
+
+impl<M: backend::Manager> Clint<M> {
+    /// Bind the CLINT state to the allocated space.
+    pub fn bind(space: backend::AllocatedOf<ClintLayout, M>) -> Self {
+        Self {
+            msip: space.0,
+            mtimecmp: space.1,
+            mtime: space.2,
+        }
+    }
+
+    /// Reset the CLINT state.
+    pub fn reset(&mut self) {
+        self.msip.write(0);
+        // The timer interrupt shall not fire until software programs it.
+        self.mtimecmp.write(u64::MAX);
+        self.mtime.write(0);
+    }
+
+    /// Advance the timer by one retired instruction. Returns the new value of `mtime`.
+    pub fn tick(&mut self) -> u64 {
+        let mtime = self.mtime.read().wrapping_add(TICKS_PER_INSTRUCTION);
+        self.mtime.write(mtime);
+        mtime
+    }
+
+    /// Current value of `mtime`
+    pub fn mtime(&self) -> u64 {
+        self.mtime.read()
+    }
+
+    /// Current value of `mtimecmp`
+    pub fn mtimecmp(&self) -> u64 {
+        self.mtimecmp.read()
+    }
+
+    /// Interrupt bits, as they appear in `mip`, that are pending due to the CLINT.
+    pub fn pending_interrupts(&self) -> CSRValue {
+        let software = if self.msip.read() & 1 != 0 {
+            1 << Interrupt::MachineSoftware.exception_code_const()
+        } else {
+            0
+        };
+
+        let timer = if self.mtime.read() >= self.mtimecmp.read() {
+            1 << Interrupt::MachineTimer.exception_code_const()
+        } else {
+            0
+        };
+
+        software | timer
+    }
+
+    /// Bits in `mip` that are driven by the CLINT
+    pub const INTERRUPT_MASK: CSRValue = 1 << Interrupt::MachineSoftware.exception_code_const()
+        | 1 << Interrupt::MachineTimer.exception_code_const();
+}
+
+impl<E: backend::Elem, M: backend::Manager> Addressable<E> for Clint<M> {
+    fn read(&self, addr: Address) -> Result<E, OutOfBounds> {
+        let (reg, offset, width) = Register::locate(addr).ok_or(OutOfBounds)?;
+        let value = match reg {
+            Register::Msip => self.msip.read() as u64,
+            Register::Mtimecmp => self.mtimecmp.read(),
+            Register::Mtime => self.mtime.read(),
+        };
+        read_register(value, width, offset)
+    }
+
+    fn write(&mut self, addr: Address, value: E) -> Result<(), OutOfBounds> {
+        let (reg, offset, width) = Register::locate(addr).ok_or(OutOfBounds)?;
+        match reg {
+            Register::Msip => {
+                let msip = write_register(self.msip.read() as u64, width, offset, value)?;
+                // Only the lowest bit is writable, all other bits are hardwired to 0.
+                self.msip.write(msip as u32 & 1);
+            }
+            Register::Mtimecmp => {
+                let mtimecmp = write_register(self.mtimecmp.read(), width, offset, value)?;
+                self.mtimecmp.write(mtimecmp);
+            }
+            Register::Mtime => {
+                let mtime = write_register(self.mtime.read(), width, offset, value)?;
+                self.mtime.write(mtime);
+            }
+        }
+        Ok(())
+    }
+
+    fn write_all(&mut self, addr: Address, values: &[E]) -> Result<(), OutOfBounds> {
+        let width = std::mem::size_of::<E>() as Address;
+        for (i, value) in values.iter().enumerate() {
+            self.write(addr + i as Address * width, *value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clint, ClintLayout, MSIP_OFFSET, MTIMECMP_OFFSET, MTIME_OFFSET};
+    use crate::{
+        backend_test, create_backend, create_state,
+        machine_state::{
+            backend::tests::{test_determinism, ManagerFor},
+            bus::{Addressable, OutOfBounds},
+        },
+        traps::{Interrupt, TrapContext},
+    };
+
+    backend_test!(test_clint_reset, F, {
+        test_determinism::<F, ClintLayout, _>(|space| {
+            let mut clint: Clint<ManagerFor<'_, F, ClintLayout>> = Clint::bind(space);
+            clint.reset();
+        });
+    });
+
+    backend_test!(test_clint_timer, F, {
+        let mut backend = create_backend!(ClintLayout, F);
+        let mut clint = create_state!(Clint, ClintLayout, F, backend);
+        clint.reset();
+
+        let mtip = 1 << Interrupt::MachineTimer.exception_code();
+        assert_eq!(clint.pending_interrupts(), 0);
+
+        clint.write(MTIMECMP_OFFSET, 3u64).unwrap();
+        assert_eq!(Addressable::<u64>::read(&clint, MTIMECMP_OFFSET), Ok(3));
+
+        clint.tick();
+        clint.tick();
+        assert_eq!(Addressable::<u64>::read(&clint, MTIME_OFFSET), Ok(2));
+        assert_eq!(clint.pending_interrupts(), 0);
+
+        clint.tick();
+        assert_eq!(clint.pending_interrupts(), mtip);
+
+        // Rearming the timer clears the pending interrupt.
+        clint.write(MTIMECMP_OFFSET + 4, 1u32).unwrap();
+        assert_eq!(clint.mtimecmp(), 1 << 32 | 3);
+        assert_eq!(clint.pending_interrupts(), 0);
+
+        // Software may set the time directly.
+        clint.write(MTIME_OFFSET, u64::MAX).unwrap();
+        assert_eq!(clint.pending_interrupts(), mtip);
+    });
+
+    backend_test!(test_clint_msip, F, {
+        let mut backend = create_backend!(ClintLayout, F);
+        let mut clint = create_state!(Clint, ClintLayout, F, backend);
+        clint.reset();
+
+        let msip = 1 << Interrupt::MachineSoftware.exception_code();
+
+        // Only the lowest bit of msip is writable.
+        clint.write(MSIP_OFFSET, 0xFFFF_FFFFu32).unwrap();
+        assert_eq!(Addressable::<u32>::read(&clint, MSIP_OFFSET), Ok(1));
+        assert_eq!(clint.pending_interrupts(), msip);
+
+        clint.write(MSIP_OFFSET, 0u8).unwrap();
+        assert_eq!(clint.pending_interrupts(), 0);
+
+        // Accesses must not cross register boundaries.
+        assert_eq!(clint.write(MSIP_OFFSET + 2, 0u32), Err(OutOfBounds));
+        assert_eq!(
+            Addressable::<u64>::read(&clint, MSIP_OFFSET),
+            Err(OutOfBounds)
+        );
+        assert_eq!(Addressable::<u8>::read(&clint, 0x100), Err(OutOfBounds));
+    });
+}
@@ -0,0 +1,469 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! NS16550A-compatible UART
+//!
+//! The UART is a console device: bytes written to the transmitter are passed
+//! to a host-provided callback, bytes received are taken from a receive FIFO.
+//! The receive FIFO is part of the machine state, which keeps input
+//! deterministic. It is only fed in loopback mode for now: there is no
+//! interrupt controller to signal received data to the kernel.
+//!
+//! Registers are one byte wide and laid out without gaps (`reg-shift` of 0),
+//! just like the UART of QEMU's `virt` machine.
+//!
+//! Additional resources:
+//!   - https://www.ti.com/lit/ds/symlink/pc16550d.pdf
+
+use super::{read_register, write_register};
+use crate::{
+    machine_state::bus::{Address, Addressable, OutOfBounds},
+    state_backend::{self as backend, Array, Atom, Cell, Region},
+};
+
+/// Start of the UART in the devices address space
+pub const UART_START: Address = 0x1000_0000;
+
+/// Length of the UART memory map
+pub const UART_LENGTH: Address = 0x100;
+
+/// Frequency of the clock driving the UART, advertised in the device tree
+pub const UART_CLOCK_FREQUENCY: u32 = 3686400;
+
+/// Capacity of the receive FIFO
+pub const RX_FIFO_LENGTH: usize = 16;
+
+/// Receiver Buffer Register (read) / Transmitter Holding Register (write),
+/// or Divisor Latch LSB when DLAB is set
+const RBR_THR_DLL: Address = 0;
+
+/// Interrupt Enable Register, or Divisor Latch MSB when DLAB is set
+const IER_DLM: Address = 1;
+
+/// Interrupt Identification Register (read) / FIFO Control Register (write)
+const IIR_FCR: Address = 2;
+
+/// Line Control Register
+const LCR: Address = 3;
+
+/// Modem Control Register
+const MCR: Address = 4;
+
+/// Line Status Register
+const LSR: Address = 5;
+
+/// Modem Status Register
+const MSR: Address = 6;
+
+/// Scratch Register
+const SCR: Address = 7;
+
+/// Divisor Latch Access Bit in LCR
+const LCR_DLAB: u8 = 1 << 7;
+
+/// Receiver data ready bit in LSR
+const LSR_DR: u8 = 1 << 0;
+
+/// Transmitter holding register empty bit in LSR
+const LSR_THRE: u8 = 1 << 5;
+
+/// Transmitter empty bit in LSR
+const LSR_TEMT: u8 = 1 << 6;
+
+/// Received data available interrupt enable bit in IER
+const IER_ERBFI: u8 = 1 << 0;
+
+/// Transmitter holding register empty interrupt enable bit in IER
+const IER_ETBEI: u8 = 1 << 1;
+
+/// Only the lower 4 bits of IER are implemented.
+const IER_MASK: u8 = 0x0F;
+
+/// FIFO enable bit in FCR
+const FCR_FIFO_ENABLE: u8 = 1 << 0;
+
+/// Receiver FIFO reset bit in FCR
+const FCR_RX_RESET: u8 = 1 << 1;
+
+/// No interrupt is pending.
+const IIR_NO_INTERRUPT: u8 = 0x01;
+
+/// Transmitter holding register empty interrupt is pending.
+const IIR_THR_EMPTY: u8 = 0x02;
+
+/// Received data available interrupt is pending.
+const IIR_RX_DATA: u8 = 0x04;
+
+/// IIR bits indicating that FIFOs are enabled
+const IIR_FIFO_ENABLED: u8 = 0xC0;
+
+/// Loopback bit in MCR
+const MCR_LOOP: u8 = 1 << 4;
+
+/// Only the lower 5 bits of MCR are implemented.
+const MCR_MASK: u8 = 0x1F;
+
+/// MSR value in loopback mode is derived from these MCR bits.
+const MSR_LOOPBACK: u8 = 0xF0;
+
+/// MSR reports clear-to-send, data-set-ready and data-carrier-detect when not
+/// in loopback mode. There is always someone on the other end.
+const MSR_CONNECTED: u8 = 0xB0;
+
+/// Layout of the UART state
+pub type UartLayout = (
+    (
+        Atom<u8>, // IER
+        Atom<u8>, // FCR
+        Atom<u8>, // LCR
+        Atom<u8>, // MCR
+        Atom<u8>, // SCR
+    ),
+    Atom<u16>, // Divisor latch
+    (
+        Array<u8, RX_FIFO_LENGTH>, // Receive FIFO
+        Atom<u8>,                  // Receive FIFO head
+        Atom<u8>,                  // Receive FIFO length
+    ),
+);
+
+/// Receiver of the bytes transmitted by the UART
+pub type TransmitHandler = Box<dyn FnMut(u8)>;
+
+/// UART state
+pub struct Uart<M: backend::Manager> {
+    ier: Cell<u8, M>,
+    fcr: Cell<u8, M>,
+    lcr: Cell<u8, M>,
+    mcr: Cell<u8, M>,
+    scr: Cell<u8, M>,
+    divisor: Cell<u16, M>,
+    rx_fifo: M::Region<u8, RX_FIFO_LENGTH>,
+    rx_head: Cell<u8, M>,
+    rx_len: Cell<u8, M>,
+    on_transmit: Option<TransmitHandler>,
+}
+
+impl<M: backend::Manager> Uart<M> {
+    /// Bind the UART state to the allocated space.
+    pub fn bind(space: backend::AllocatedOf<UartLayout, M>) -> Self {
+        let ((ier, fcr, lcr, mcr, scr), divisor, (rx_fifo, rx_head, rx_len)) = space;
+        Self {
+            ier,
+            fcr,
+            lcr,
+            mcr,
+            scr,
+            divisor,
+            rx_fifo,
+            rx_head,
+            rx_len,
+            on_transmit: None,
+        }
+    }
+
+    /// Reset the UART state.
+    pub fn reset(&mut self) {
+        self.ier.write(0);
+        self.fcr.write(0);
+        self.lcr.write(0);
+        self.mcr.write(0);
+        self.scr.write(0);
+        self.divisor.write(0);
+        self.rx_fifo.write_all(&[0; RX_FIFO_LENGTH]);
+        self.clear_rx();
+    }
+
+    /// Install the handler which receives transmitted bytes. Without a handler,
+    /// transmitted bytes are discarded.
+    pub fn set_transmit_handler(&mut self, handler: TransmitHandler) {
+        self.on_transmit = Some(handler);
+    }
+
+    /// Queue bytes in the receive FIFO. Returns the number of bytes that were
+    /// queued, which is less than `bytes.len()` if the FIFO is full.
+    pub fn receive(&mut self, bytes: &[u8]) -> usize {
+        let head = self.rx_head();
+        let len = self.rx_pending();
+        let count = bytes.len().min(RX_FIFO_LENGTH - len);
+
+        for (i, byte) in bytes.iter().take(count).enumerate() {
+            self.rx_fifo.write((head + len + i) % RX_FIFO_LENGTH, *byte);
+        }
+
+        self.rx_len.write((len + count) as u8);
+        count
+    }
+
+    /// Number of bytes waiting in the receive FIFO
+    pub fn rx_pending(&self) -> usize {
+        (self.rx_len.read() as usize).min(RX_FIFO_LENGTH)
+    }
+
+    /// Index of the oldest byte in the receive FIFO
+    fn rx_head(&self) -> usize {
+        self.rx_head.read() as usize % RX_FIFO_LENGTH
+    }
+
+    /// Pop the oldest byte from the receive FIFO.
+    fn pop_rx(&mut self) -> Option<u8> {
+        let len = self.rx_pending();
+        if len == 0 {
+            return None;
+        }
+
+        let head = self.rx_head();
+        let byte = self.rx_fifo.read(head);
+        self.rx_head.write(((head + 1) % RX_FIFO_LENGTH) as u8);
+        self.rx_len.write((len - 1) as u8);
+        Some(byte)
+    }
+
+    /// Discard all bytes in the receive FIFO.
+    fn clear_rx(&mut self) {
+        self.rx_head.write(0);
+        self.rx_len.write(0);
+    }
+
+    /// Transmit a byte.
+    pub(crate) fn transmit(&mut self, byte: u8) {
+        if self.mcr.read() & MCR_LOOP != 0 {
+            // In loopback mode the transmitter is connected to the receiver.
+            self.receive(&[byte]);
+        } else if let Some(on_transmit) = self.on_transmit.as_mut() {
+            on_transmit(byte);
+        }
+    }
+
+    /// Is the divisor latch accessible?
+    fn dlab(&self) -> bool {
+        self.lcr.read() & LCR_DLAB != 0
+    }
+
+    /// Value of the Line Status Register
+    fn lsr(&self) -> u8 {
+        // Transmission happens instantly, hence the transmitter is always empty.
+        let ready = if self.rx_pending() > 0 { LSR_DR } else { 0 };
+        ready | LSR_THRE | LSR_TEMT
+    }
+
+    /// Value of the Interrupt Identification Register
+    fn iir(&self) -> u8 {
+        let ier = self.ier.read();
+        let id = if ier & IER_ERBFI != 0 && self.rx_pending() > 0 {
+            IIR_RX_DATA
+        } else if ier & IER_ETBEI != 0 {
+            IIR_THR_EMPTY
+        } else {
+            IIR_NO_INTERRUPT
+        };
+
+        if self.fcr.read() & FCR_FIFO_ENABLE != 0 {
+            id | IIR_FIFO_ENABLED
+        } else {
+            id
+        }
+    }
+
+    /// Value of the Modem Status Register
+    fn msr(&self) -> u8 {
+        let mcr = self.mcr.read();
+        if mcr & MCR_LOOP != 0 {
+            // CTS, DSR, RI and DCD reflect RTS, DTR, OUT1 and OUT2.
+            let lines = (mcr & 0x2) >> 1 | (mcr & 0x1) << 1 | (mcr & 0xC);
+            (lines << 4) & MSR_LOOPBACK
+        } else {
+            MSR_CONNECTED
+        }
+    }
+
+    /// Read the register at the given offset without side effects.
+    fn peek_byte(&self, offset: Address) -> Option<u8> {
+        let value = match offset {
+            RBR_THR_DLL if self.dlab() => self.divisor.read() as u8,
+            RBR_THR_DLL if self.rx_pending() > 0 => self.rx_fifo.read(self.rx_head()),
+            RBR_THR_DLL => 0,
+            IER_DLM if self.dlab() => (self.divisor.read() >> 8) as u8,
+            IER_DLM => self.ier.read(),
+            IIR_FCR => self.iir(),
+            LCR => self.lcr.read(),
+            MCR => self.mcr.read(),
+            LSR => self.lsr(),
+            MSR => self.msr(),
+            SCR => self.scr.read(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Read the register at the given offset. Reading the receive buffer
+    /// removes the byte from the receive FIFO.
+    fn load_byte(&mut self, offset: Address) -> Option<u8> {
+        if offset == RBR_THR_DLL && !self.dlab() {
+            return Some(self.pop_rx().unwrap_or(0));
+        }
+
+        self.peek_byte(offset)
+    }
+
+    /// Write the register at the given offset.
+    fn write_byte(&mut self, offset: Address, value: u8) -> Option<()> {
+        match offset {
+            RBR_THR_DLL if self.dlab() => {
+                let divisor = self.divisor.read() & 0xFF00 | value as u16;
+                self.divisor.write(divisor);
+            }
+            RBR_THR_DLL => self.transmit(value),
+            IER_DLM if self.dlab() => {
+                let divisor = self.divisor.read() & 0x00FF | (value as u16) << 8;
+                self.divisor.write(divisor);
+            }
+            IER_DLM => self.ier.write(value & IER_MASK),
+            IIR_FCR => {
+                if value & FCR_RX_RESET != 0 {
+                    self.clear_rx();
+                }
+                // Reset bits are self-clearing.
+                self.fcr.write(value & !0x06);
+            }
+            LCR => self.lcr.write(value),
+            MCR => self.mcr.write(value & MCR_MASK),
+            // The Line and Modem Status Registers are read-only.
+            LSR | MSR => {}
+            SCR => self.scr.write(value),
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+impl<E: backend::Elem, M: backend::Manager> Addressable<E> for Uart<M> {
+    fn read(&self, addr: Address) -> Result<E, OutOfBounds> {
+        let value = self.peek_byte(addr).ok_or(OutOfBounds)?;
+        read_register(value as u64, 1, 0)
+    }
+
+    fn load(&mut self, addr: Address) -> Result<E, OutOfBounds> {
+        // Check the access width before any side effects take place.
+        read_register::<E>(0, 1, 0)?;
+        let value = self.load_byte(addr).ok_or(OutOfBounds)?;
+        read_register(value as u64, 1, 0)
+    }
+
+    fn write(&mut self, addr: Address, value: E) -> Result<(), OutOfBounds> {
+        let value = write_register(0, 1, 0, value)? as u8;
+        self.write_byte(addr, value).ok_or(OutOfBounds)
+    }
+
+    fn write_all(&mut self, addr: Address, values: &[E]) -> Result<(), OutOfBounds> {
+        let width = std::mem::size_of::<E>() as Address;
+        for (i, value) in values.iter().enumerate() {
+            self.write(addr + i as Address * width, *value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Uart, UartLayout, IER_DLM, IIR_FCR, LCR, LCR_DLAB, LSR, MCR, MCR_LOOP, RBR_THR_DLL,
+        RX_FIFO_LENGTH, SCR,
+    };
+    use crate::{
+        backend_test, create_backend, create_state,
+        machine_state::{
+            backend::tests::{test_determinism, ManagerFor},
+            bus::{Addressable, OutOfBounds},
+        },
+    };
+    use std::{cell::RefCell, rc::Rc};
+
+    backend_test!(test_uart_reset, F, {
+        test_determinism::<F, UartLayout, _>(|space| {
+            let mut uart: Uart<ManagerFor<'_, F, UartLayout>> = Uart::bind(space);
+            uart.reset();
+        });
+    });
+
+    backend_test!(test_uart_transmit, F, {
+        let mut backend = create_backend!(UartLayout, F);
+        let mut uart = create_state!(Uart, UartLayout, F, backend);
+        uart.reset();
+
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let sink = output.clone();
+        uart.set_transmit_handler(Box::new(move |byte| sink.borrow_mut().push(byte)));
+
+        for byte in b"Hello" {
+            assert_eq!(Addressable::<u8>::read(&uart, LSR), Ok(0x60));
+            uart.write(RBR_THR_DLL, *byte).unwrap();
+        }
+        assert_eq!(output.borrow().as_slice(), b"Hello");
+
+        // Writes to the divisor latch are not transmitted.
+        uart.write(LCR, LCR_DLAB).unwrap();
+        uart.write(RBR_THR_DLL, 0x34u8).unwrap();
+        uart.write(IER_DLM, 0x12u8).unwrap();
+        assert_eq!(Addressable::<u8>::read(&uart, RBR_THR_DLL), Ok(0x34));
+        assert_eq!(Addressable::<u8>::read(&uart, IER_DLM), Ok(0x12));
+        uart.write(LCR, 0x03u8).unwrap();
+        assert_eq!(Addressable::<u8>::read(&uart, IER_DLM), Ok(0));
+        assert_eq!(output.borrow().as_slice(), b"Hello");
+
+        // Registers are only accessible byte-wise.
+        assert_eq!(uart.write(RBR_THR_DLL, 0u32), Err(OutOfBounds));
+        assert_eq!(Addressable::<u16>::read(&uart, LSR), Err(OutOfBounds));
+        assert_eq!(Addressable::<u8>::read(&uart, SCR + 1), Err(OutOfBounds));
+    });
+
+    backend_test!(test_uart_receive, F, {
+        let mut backend = create_backend!(UartLayout, F);
+        let mut uart = create_state!(Uart, UartLayout, F, backend);
+        uart.reset();
+
+        // The receive FIFO has a limited capacity.
+        let input: Vec<u8> = (0..RX_FIFO_LENGTH as u8 + 4).collect();
+        assert_eq!(uart.receive(&input), RX_FIFO_LENGTH);
+        assert_eq!(uart.receive(&input), 0);
+        assert_eq!(Addressable::<u8>::read(&uart, LSR), Ok(0x61));
+
+        // Interrupt identification reflects received data.
+        uart.write(IER_DLM, 0x01u8).unwrap();
+        assert_eq!(Addressable::<u8>::read(&uart, IIR_FCR), Ok(0x04));
+
+        // Reading is side-effect free, loading pops from the FIFO.
+        assert_eq!(Addressable::<u8>::read(&uart, RBR_THR_DLL), Ok(0));
+        assert_eq!(Addressable::<u8>::read(&uart, RBR_THR_DLL), Ok(0));
+        for expected in 0..RX_FIFO_LENGTH as u8 {
+            assert_eq!(uart.load(RBR_THR_DLL), Ok(expected));
+        }
+        assert_eq!(Addressable::<u8>::read(&uart, LSR), Ok(0x60));
+        assert_eq!(Addressable::<u8>::read(&uart, IIR_FCR), Ok(0x01));
+
+        // The FIFO wraps around.
+        assert_eq!(uart.receive(b"abc"), 3);
+        assert_eq!(uart.load(RBR_THR_DLL), Ok(b'a'));
+
+        // Resetting the receive FIFO discards pending bytes.
+        uart.write(IIR_FCR, 0x03u8).unwrap();
+        assert_eq!(uart.rx_pending(), 0);
+        assert_eq!(Addressable::<u8>::read(&uart, IIR_FCR), Ok(0xC1));
+    });
+
+    backend_test!(test_uart_loopback, F, {
+        let mut backend = create_backend!(UartLayout, F);
+        let mut uart = create_state!(Uart, UartLayout, F, backend);
+        uart.reset();
+
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let sink = output.clone();
+        uart.set_transmit_handler(Box::new(move |byte| sink.borrow_mut().push(byte)));
+
+        uart.write(MCR, MCR_LOOP).unwrap();
+        uart.write(RBR_THR_DLL, b'x').unwrap();
+        assert!(output.borrow().is_empty());
+        assert_eq!(uart.load(RBR_THR_DLL), Ok(b'x'));
+    });
+}
@@ -1,4 +1,4 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
@@ -75,6 +75,10 @@ impl<const BYTES: usize> backend::Layout for Sizes<BYTES> {
         let data = backend.allocate_dyn_region(placed);
         MainMemory { data }
     }
+
+    fn merkle_shape(placed: &Self::Placed) -> backend::merkle::MerkleShape {
+        backend::merkle::MerkleShape::region(placed.offset(), placed.size())
+    }
 }
 
 /// Main memory state for the given layout
@@ -90,8 +94,12 @@ impl<L: MainMemoryLayout, M: backend::Manager> MainMemory<L, M> {
 
     /// Reset to the initial state.
     pub fn reset(&mut self) {
-        for i in 0..L::BYTES {
-            self.data.write(i, 0u8);
+        const CHUNK_SIZE: usize = 4096;
+        let zeros = [0u8; CHUNK_SIZE];
+
+        for start in (0..L::BYTES).step_by(CHUNK_SIZE) {
+            let length = CHUNK_SIZE.min(L::BYTES - start);
+            self.data.write_all(start, &zeros[..length]);
         }
     }
 }
@@ -108,6 +116,18 @@ impl<E: backend::Elem, L: MainMemoryLayout, M: backend::Manager> Addressable<E>
         Ok(self.data.read(addr as usize))
     }
 
+    fn read_all(&self, addr: Address, values: &mut [E]) -> Result<(), super::OutOfBounds> {
+        let addr = addr as usize;
+
+        if addr + mem::size_of_val(values) > L::BYTES {
+            return Err(super::OutOfBounds);
+        }
+
+        self.data.read_all(addr, values);
+
+        Ok(())
+    }
+
     fn write(&mut self, addr: super::Address, value: E) -> Result<(), super::OutOfBounds> {
         if addr as usize + mem::size_of::<E>() > L::BYTES {
             return Err(super::OutOfBounds);
@@ -5,6 +5,7 @@
 #![allow(non_upper_case_globals)]
 
 mod fields;
+pub mod pmp;
 mod satp;
 pub mod xstatus;
 
@@ -626,6 +627,7 @@ impl CSRegister {
             CSRegister::mstatus => xstatus::apply_warl_mstatus(new_value),
             CSRegister::sstatus => xstatus::apply_warl_sstatus(new_value),
             CSRegister::mnstatus => xstatus::apply_warl_mnstatus(new_value),
+            reg if pmp::is_pmp_register(reg) => pmp::transform_warl(reg, new_value),
             _ => new_value,
         };
         Some(write_value)
@@ -1111,6 +1113,7 @@ impl<M: backend::Manager> CSRegisters<M> {
                 let mie_only = mie & !CSRegister::WARL_MASK_SIP_SIE;
                 (CSRegister::mie, sie_only | mie_only)
             }
+            reg if pmp::is_pmp_register(reg) => (reg, self.pmp_apply_locks(reg, value)),
             _ => (reg, value),
         }
     }
@@ -1218,10 +1221,14 @@ impl<M: backend::Manager> CSRegisters<M> {
         // higher-privilege modes, y>x, are always globally enabled
         // regardless of the setting of the global yIE
         // bit for the higher-privilege mode."
+        //
+        // Section 3.1.9: Regardless of the global enable, an interrupt can only
+        // be taken if its bit is set in mie.
 
         let mstatus = self.read(CSRegister::mstatus);
+        let mie = self.read(CSRegister::mie);
         let ie_machine = match xstatus::get_MIE(mstatus) {
-            true => self.read(CSRegister::mie),
+            true => mie,
             false => 0,
         };
         let ie_supervisor = match xstatus::get_SIE(mstatus) {
@@ -1230,8 +1237,8 @@ impl<M: backend::Manager> CSRegisters<M> {
         };
 
         match current_mode {
-            Mode::User => Interrupt::SUPERVISOR_BIT_MASK | Interrupt::MACHINE_BIT_MASK,
-            Mode::Supervisor => ie_supervisor | Interrupt::MACHINE_BIT_MASK,
+            Mode::User => mie,
+            Mode::Supervisor => ie_supervisor | mie & Interrupt::MACHINE_BIT_MASK,
             Mode::Machine => ie_machine,
         }
     }
@@ -0,0 +1,362 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Physical memory protection (PMP)
+//!
+//! The first 16 PMP entries are implemented. They are configured by `pmpcfg0`,
+//! `pmpcfg2` and `pmpaddr0` to `pmpaddr15`. The CSRs of the remaining entries
+//! are read-only zero. The PMP granularity is 4 bytes.
+//!
+//! Section 3.7 - privileged spec
+
+use super::{ones, CSRValue, CSRegister, CSRegisters};
+use crate::{
+    machine_state::{bus::Address, mode::Mode},
+    state_backend::{self as backend, Region},
+};
+use std::ops::Range;
+
+/// Number of implemented PMP entries
+pub const PMP_ENTRIES: usize = 16;
+
+/// Permission to read
+const R: u8 = 1 << 0;
+
+/// Permission to write
+const W: u8 = 1 << 1;
+
+/// Permission to execute
+const X: u8 = 1 << 2;
+
+/// `pmpcfg.A` field, selecting how the address is matched
+const A_OFFSET: u8 = 3;
+
+/// Bits 5 and 6 of an entry configuration are reserved
+const RESERVED: u8 = 0b11 << 5;
+
+/// Lock bit, which also enforces the permissions in M-mode
+const L: u8 = 1 << 7;
+
+/// Lock bits of all entries in a `pmpcfg` CSR
+const LOCKS: CSRValue = 0x8080_8080_8080_8080;
+
+/// `pmpaddr` holds bits 55 to 2 of a 56-bit physical address.
+const ADDRESS_MASK: CSRValue = ones(54);
+
+/// Kind of memory access being checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessType {
+    Instruction,
+    Load,
+    Store,
+}
+
+/// Address-matching mode of a PMP entry. Table 3.10
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AddressMatching {
+    Off,
+    /// Top of range
+    Tor,
+    /// Naturally aligned four-byte region
+    Na4,
+    /// Naturally aligned power-of-two region, of at least 8 bytes
+    Napot,
+}
+
+impl AddressMatching {
+    fn of_cfg(cfg: u8) -> Self {
+        match (cfg >> A_OFFSET) & 0b11 {
+            0 => Self::Off,
+            1 => Self::Tor,
+            2 => Self::Na4,
+            _ => Self::Napot,
+        }
+    }
+}
+
+/// Whether `reg` is one of the PMP CSRs
+#[inline(always)]
+pub fn is_pmp_register(reg: CSRegister) -> bool {
+    (CSRegister::pmpcfg0 as usize..=CSRegister::pmpaddr63 as usize).contains(&(reg as usize))
+}
+
+/// Index of the entry configured by `reg`, if it is an implemented `pmpaddr`
+fn pmpaddr_entry(reg: CSRegister) -> Option<usize> {
+    let entry = (reg as usize).checked_sub(CSRegister::pmpaddr0 as usize)?;
+    (entry < PMP_ENTRIES).then_some(entry)
+}
+
+/// Make a legal configuration for a single entry.
+fn legalise_cfg(cfg: u8) -> u8 {
+    let cfg = cfg & !RESERVED;
+
+    // The combination R = 0 and W = 1 is reserved.
+    if cfg & R == 0 {
+        cfg & !W
+    } else {
+        cfg
+    }
+}
+
+/// Apply the WARL rules of the PMP CSR `reg`.
+pub fn transform_warl(reg: CSRegister, value: CSRValue) -> CSRValue {
+    match reg {
+        CSRegister::pmpcfg0 | CSRegister::pmpcfg2 => {
+            let cfgs = value.to_le_bytes().map(legalise_cfg);
+            CSRValue::from_le_bytes(cfgs)
+        }
+        reg if pmpaddr_entry(reg).is_some() => value & ADDRESS_MASK,
+        _ => 0,
+    }
+}
+
+impl<M: backend::Manager> CSRegisters<M> {
+    /// Configuration of a PMP entry
+    #[inline(always)]
+    fn pmp_cfg(&self, entry: usize) -> u8 {
+        let reg = if entry < 8 {
+            CSRegister::pmpcfg0
+        } else {
+            CSRegister::pmpcfg2
+        };
+        (self.registers.read(reg as usize) >> (8 * (entry % 8))) as u8
+    }
+
+    /// Address register of a PMP entry
+    #[inline(always)]
+    fn pmp_addr(&self, entry: usize) -> CSRValue {
+        self.registers.read(CSRegister::pmpaddr0 as usize + entry)
+    }
+
+    /// Adjust a legal `value` written to the PMP CSR `reg` so that locked
+    /// entries are left untouched. Section 3.7.1.2
+    pub(super) fn pmp_apply_locks(&self, reg: CSRegister, value: CSRValue) -> CSRValue {
+        let old_value = self.registers.read(reg as usize);
+
+        match reg {
+            CSRegister::pmpcfg0 | CSRegister::pmpcfg2 => {
+                let locked = old_value
+                    .to_le_bytes()
+                    .map(|cfg| if cfg & L != 0 { u8::MAX } else { 0 });
+                let locked = CSRValue::from_le_bytes(locked);
+                (old_value & locked) | (value & !locked)
+            }
+
+            reg => match pmpaddr_entry(reg) {
+                Some(entry) => {
+                    // The address of an entry is also the bottom of the next
+                    // entry if the latter uses TOR matching.
+                    let locked_by_next = entry + 1 < PMP_ENTRIES && {
+                        let next = self.pmp_cfg(entry + 1);
+                        next & L != 0 && AddressMatching::of_cfg(next) == AddressMatching::Tor
+                    };
+
+                    if self.pmp_cfg(entry) & L != 0 || locked_by_next {
+                        old_value
+                    } else {
+                        value
+                    }
+                }
+                None => value,
+            },
+        }
+    }
+
+    /// Range of addresses matched by a PMP entry
+    fn pmp_range(&self, entry: usize, cfg: u8) -> Option<Range<u128>> {
+        let addr = self.pmp_addr(entry) as u128;
+
+        let range = match AddressMatching::of_cfg(cfg) {
+            AddressMatching::Off => return None,
+            AddressMatching::Tor => {
+                let bottom = match entry {
+                    0 => 0,
+                    _ => self.pmp_addr(entry - 1) as u128,
+                };
+                bottom << 2..addr << 2
+            }
+            AddressMatching::Na4 => addr << 2..(addr << 2) + 4,
+            AddressMatching::Napot => {
+                let size = 8u128 << (addr as u64).trailing_ones();
+                let base = (addr << 2) & !(size - 1);
+                base..base + size
+            }
+        };
+
+        (range.start < range.end).then_some(range)
+    }
+
+    /// Whether PMP lets `mode` perform an access of `len` bytes at `address`.
+    /// Section 3.7.1
+    #[inline(always)]
+    pub fn pmp_allows(&self, mode: Mode, address: Address, len: usize, access: AccessType) -> bool {
+        // Without locked entries, M-mode may access anything.
+        if mode == Mode::Machine {
+            let cfg0 = self.registers.read(CSRegister::pmpcfg0 as usize);
+            let cfg2 = self.registers.read(CSRegister::pmpcfg2 as usize);
+            if (cfg0 | cfg2) & LOCKS == 0 {
+                return true;
+            }
+        }
+
+        let start = address as u128;
+        let end = start + len as u128;
+
+        // The lowest-numbered entry matching any byte of the access decides.
+        for entry in 0..PMP_ENTRIES {
+            let cfg = self.pmp_cfg(entry);
+            let Some(range) = self.pmp_range(entry, cfg) else {
+                continue;
+            };
+
+            if start >= range.end || range.start >= end {
+                continue;
+            }
+
+            // Accesses matching an entry only partially fail.
+            if start < range.start || range.end < end {
+                return false;
+            }
+
+            if mode == Mode::Machine && cfg & L == 0 {
+                return true;
+            }
+
+            let permission = match access {
+                AccessType::Instruction => X,
+                AccessType::Load => R,
+                AccessType::Store => W,
+            };
+            return cfg & permission != 0;
+        }
+
+        // Accesses from S-mode and U-mode matching no entry fail.
+        mode == Mode::Machine
+    }
+
+    /// Grant all modes access to the entire address space, as firmware does
+    /// before handing over to a lower privilege mode.
+    pub fn pmp_allow_all(&mut self) {
+        self.write(CSRegister::pmpaddr0, ADDRESS_MASK);
+        self.write(
+            CSRegister::pmpcfg0,
+            (R | W | X | (AddressMatching::Napot as u8) << A_OFFSET) as CSRValue,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccessType::*, AddressMatching, A_OFFSET, L, R, W, X};
+    use crate::{
+        backend_test, create_backend, create_state,
+        machine_state::{
+            csregisters::{CSRegister, CSRegisters, CSRegistersLayout},
+            mode::Mode,
+        },
+    };
+
+    fn cfg(matching: AddressMatching, permissions: u8) -> u64 {
+        (permissions | (matching as u8) << A_OFFSET) as u64
+    }
+
+    backend_test!(test_pmp_warl, F, {
+        let mut backend = create_backend!(CSRegistersLayout, F);
+        let mut csrs = create_state!(CSRegisters, CSRegistersLayout, F, backend);
+
+        // Reserved bits and the R = 0, W = 1 combination are cleared.
+        csrs.write(CSRegister::pmpcfg0, 0x0000_0000_0000_E2FF);
+        assert_eq!(csrs.read(CSRegister::pmpcfg0), 0x809F);
+
+        // Only bits 55 to 2 of the address are kept.
+        csrs.write(CSRegister::pmpaddr3, u64::MAX);
+        assert_eq!(csrs.read(CSRegister::pmpaddr3), (1 << 54) - 1);
+
+        // Entries beyond the first 16 are read-only zero.
+        csrs.write(CSRegister::pmpcfg4, u64::MAX);
+        csrs.write(CSRegister::pmpaddr16, u64::MAX);
+        assert_eq!(csrs.read(CSRegister::pmpcfg4), 0);
+        assert_eq!(csrs.read(CSRegister::pmpaddr16), 0);
+    });
+
+    backend_test!(test_pmp_locks, F, {
+        let mut backend = create_backend!(CSRegistersLayout, F);
+        let mut csrs = create_state!(CSRegisters, CSRegistersLayout, F, backend);
+
+        csrs.write(CSRegister::pmpaddr0, 0x100);
+        csrs.write(CSRegister::pmpaddr1, 0x200);
+        csrs.write(CSRegister::pmpaddr2, 0x300);
+        csrs.write(
+            CSRegister::pmpcfg0,
+            cfg(AddressMatching::Na4, R | L) | cfg(AddressMatching::Tor, R | W | L) << 16,
+        );
+
+        // Locked entries can't be reconfigured, others can.
+        csrs.write(CSRegister::pmpcfg0, 0xFF_FFFF);
+        let cfgs = csrs.read(CSRegister::pmpcfg0).to_le_bytes();
+        assert_eq!(cfgs[0] as u64, cfg(AddressMatching::Na4, R | L));
+        assert_eq!(cfgs[1] as u64, cfg(AddressMatching::Napot, R | W | X | L));
+        assert_eq!(cfgs[2] as u64, cfg(AddressMatching::Tor, R | W | L));
+
+        // Entry 1 got locked by the write above, entry 2 is locked and is the
+        // top of a TOR range starting at entry 1.
+        for reg in [
+            CSRegister::pmpaddr0,
+            CSRegister::pmpaddr1,
+            CSRegister::pmpaddr2,
+        ] {
+            let before = csrs.read(reg);
+            csrs.write(reg, 0x42);
+            assert_eq!(csrs.read(reg), before);
+        }
+
+        csrs.write(CSRegister::pmpaddr3, 0x42);
+        assert_eq!(csrs.read(CSRegister::pmpaddr3), 0x42);
+    });
+
+    backend_test!(test_pmp_matching, F, {
+        let mut backend = create_backend!(CSRegistersLayout, F);
+        let mut csrs = create_state!(CSRegisters, CSRegistersLayout, F, backend);
+
+        // Nothing is accessible from lower modes until PMP is configured.
+        assert!(csrs.pmp_allows(Mode::Machine, 0x1000, 8, Store));
+        assert!(!csrs.pmp_allows(Mode::Supervisor, 0x1000, 8, Load));
+
+        // Entry 0: NA4 at 0x1000, read-only
+        // Entry 1: TOR from 0x1000 to 0x2000, read-write
+        // Entry 2: NAPOT of 0x1000 bytes at 0x4000, execute-only and locked
+        csrs.write(CSRegister::pmpaddr0, 0x1000 >> 2);
+        csrs.write(CSRegister::pmpaddr1, 0x2000 >> 2);
+        csrs.write(CSRegister::pmpaddr2, (0x4000 | 0x7FF) >> 2);
+        csrs.write(
+            CSRegister::pmpcfg0,
+            cfg(AddressMatching::Na4, R)
+                | cfg(AddressMatching::Tor, R | W) << 8
+                | cfg(AddressMatching::Napot, X | L) << 16,
+        );
+
+        assert!(csrs.pmp_allows(Mode::User, 0x1000, 4, Load));
+        assert!(!csrs.pmp_allows(Mode::User, 0x1000, 4, Store));
+        assert!(csrs.pmp_allows(Mode::User, 0x1004, 8, Store));
+        assert!(!csrs.pmp_allows(Mode::User, 0x1004, 8, Instruction));
+        assert!(!csrs.pmp_allows(Mode::User, 0x2000, 1, Load));
+
+        // The lowest-numbered entry decides, even if it only matches partially.
+        assert!(!csrs.pmp_allows(Mode::User, 0x1000, 8, Load));
+        assert!(!csrs.pmp_allows(Mode::User, 0x1FFC, 8, Load));
+
+        assert!(csrs.pmp_allows(Mode::User, 0x4FFC, 4, Instruction));
+        assert!(!csrs.pmp_allows(Mode::User, 0x4FFC, 4, Load));
+        assert!(!csrs.pmp_allows(Mode::User, 0x4FFC, 8, Instruction));
+
+        // M-mode bypasses unlocked entries only.
+        assert!(csrs.pmp_allows(Mode::Machine, 0x1000, 4, Store));
+        assert!(csrs.pmp_allows(Mode::Machine, 0x8000, 4, Store));
+        assert!(!csrs.pmp_allows(Mode::Machine, 0x4000, 4, Load));
+
+        csrs.pmp_allow_all();
+        assert!(csrs.pmp_allows(Mode::User, 0x8000_0000, 8, Store));
+        assert!(csrs.pmp_allows(Mode::User, (1 << 56) - 8, 8, Instruction));
+    });
+}
@@ -0,0 +1,241 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Structural comparison of machine states
+
+use super::{
+    bus::{self, main_memory::MainMemoryLayout, Address, Addressable},
+    csregisters::{CSRValue, CSRegister},
+    mode::Mode,
+    registers::{self, FRegister, FValue, XRegister, XValue},
+    MachineState,
+};
+use crate::state_backend as backend;
+use std::{fmt, ops::Range};
+use strum::IntoEnumIterator;
+
+/// Value which differs between two states
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change<T> {
+    pub before: T,
+    pub after: T,
+}
+
+impl<T: PartialEq> Change<T> {
+    fn compare(before: T, after: T) -> Option<Self> {
+        (before != after).then_some(Self { before, after })
+    }
+}
+
+/// Differences between two machine states
+#[derive(Debug, Default, PartialEq)]
+pub struct StateDiff {
+    pub pc: Option<Change<Address>>,
+    pub mode: Option<Change<Mode>>,
+    pub xregisters: Vec<(XRegister, Change<XValue>)>,
+    pub fregisters: Vec<(FRegister, Change<FValue>)>,
+    pub csregisters: Vec<(CSRegister, Change<CSRValue>)>,
+
+    /// Ranges of main memory whose contents differ
+    pub memory: Vec<Range<Address>>,
+}
+
+impl StateDiff {
+    /// Whether the states compared equal
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(Change { before, after }) = self.pc {
+            writeln!(f, "pc: {before:#x} -> {after:#x}")?;
+        }
+
+        if let Some(Change { before, after }) = self.mode {
+            writeln!(f, "mode: {before:?} -> {after:?}")?;
+        }
+
+        for (reg, Change { before, after }) in &self.xregisters {
+            writeln!(f, "{reg} ({reg:?}): {before:#x} -> {after:#x}")?;
+        }
+
+        for (reg, Change { before, after }) in &self.fregisters {
+            let (before, after) = (u64::from(*before), u64::from(*after));
+            writeln!(f, "{reg} ({reg:?}): {before:#x} -> {after:#x}")?;
+        }
+
+        for (reg, Change { before, after }) in &self.csregisters {
+            writeln!(f, "{reg:?}: {before:#x} -> {after:#x}")?;
+        }
+
+        for range in &self.memory {
+            let length = range.end - range.start;
+            writeln!(
+                f,
+                "memory: {:#x}..{:#x} ({length} bytes)",
+                range.start, range.end
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<ML: MainMemoryLayout, M: backend::Manager> MachineState<ML, M> {
+    /// Compare the registers and main memory of two machine states.
+    pub fn diff<M2: backend::Manager>(&self, other: &MachineState<ML, M2>) -> StateDiff {
+        let xregisters = (1..32)
+            .map(registers::parse_xregister)
+            .filter_map(|reg| {
+                let change = Change::compare(
+                    self.hart.xregisters.read(reg),
+                    other.hart.xregisters.read(reg),
+                )?;
+                Some((reg, change))
+            })
+            .collect();
+
+        let fregisters = (0..32)
+            .map(registers::parse_fregister)
+            .filter_map(|reg| {
+                let change = Change::compare(
+                    self.hart.fregisters.read(reg),
+                    other.hart.fregisters.read(reg),
+                )?;
+                Some((reg, change))
+            })
+            .collect();
+
+        let csregisters = CSRegister::iter()
+            .filter_map(|reg| {
+                let change = Change::compare(
+                    self.hart.csregisters.read(reg),
+                    other.hart.csregisters.read(reg),
+                )?;
+                Some((reg, change))
+            })
+            .collect();
+
+        StateDiff {
+            pc: Change::compare(self.hart.pc.read(), other.hart.pc.read()),
+            mode: Change::compare(self.hart.mode.read(), other.hart.mode.read()),
+            xregisters,
+            fregisters,
+            csregisters,
+            memory: self.diff_memory(other),
+        }
+    }
+
+    /// Find the ranges of main memory whose contents differ.
+    fn diff_memory<M2: backend::Manager>(
+        &self,
+        other: &MachineState<ML, M2>,
+    ) -> Vec<Range<Address>> {
+        let start = bus::start_of_main_memory::<ML>();
+        let mut ranges: Vec<Range<Address>> = Vec::new();
+
+        // Memory is compared a double-word at a time, only differing
+        // double-words are compared byte by byte.
+        for addr in (start..start + ML::BYTES as Address).step_by(8) {
+            let ours: u64 = self.bus.read(addr).unwrap_or_default();
+            let theirs: u64 = other.bus.read(addr).unwrap_or_default();
+            if ours == theirs {
+                continue;
+            }
+
+            let (ours, theirs) = (ours.to_le_bytes(), theirs.to_le_bytes());
+            for (offset, _) in ours
+                .iter()
+                .zip(theirs)
+                .enumerate()
+                .filter(|(_, (a, b))| *a != b)
+            {
+                let byte = addr + offset as Address;
+                match ranges.last_mut() {
+                    Some(range) if range.end == byte => range.end += 1,
+                    _ => ranges.push(byte..byte + 1),
+                }
+            }
+        }
+
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Change;
+    use crate::{
+        backend_test, create_backend, create_state,
+        machine_state::{
+            bus::{main_memory::tests::T1K, start_of_main_memory, Addressable},
+            csregisters::CSRegister,
+            mode::Mode,
+            registers::a0,
+            MachineState, MachineStateLayout,
+        },
+    };
+
+    backend_test!(test_diff, F, {
+        let mut backend1 = create_backend!(MachineStateLayout<T1K>, F);
+        let mut state1 = create_state!(MachineState, MachineStateLayout<T1K>, F, backend1, T1K);
+        let mut backend2 = create_backend!(MachineStateLayout<T1K>, F);
+        let mut state2 = create_state!(MachineState, MachineStateLayout<T1K>, F, backend2, T1K);
+
+        state1.reset();
+        state2.reset();
+        assert!(state1.diff(&state2).is_empty());
+
+        let start = start_of_main_memory::<T1K>();
+        state2.hart.pc.write(start + 8);
+        state2.hart.mode.write(Mode::User);
+        state2.hart.xregisters.write(a0, 42);
+        state2.hart.csregisters.write(CSRegister::mscratch, 7);
+        state2.bus.write(start + 6, 0xFFFF_u32).unwrap();
+        state2.bus.write(start + 100, 1_u8).unwrap();
+
+        let diff = state1.diff(&state2);
+        assert_eq!(
+            diff.pc,
+            Some(Change {
+                before: start,
+                after: start + 8
+            })
+        );
+        assert_eq!(
+            diff.mode,
+            Some(Change {
+                before: Mode::Machine,
+                after: Mode::User
+            })
+        );
+        assert_eq!(
+            diff.xregisters,
+            vec![(
+                a0,
+                Change {
+                    before: 0,
+                    after: 42
+                }
+            )]
+        );
+        assert_eq!(
+            diff.csregisters,
+            vec![(
+                CSRegister::mscratch,
+                Change {
+                    before: 0,
+                    after: 7
+                }
+            )]
+        );
+        assert!(diff.fregisters.is_empty());
+        assert_eq!(
+            diff.memory,
+            vec![start + 6..start + 8, start + 100..start + 101]
+        );
+    });
+}
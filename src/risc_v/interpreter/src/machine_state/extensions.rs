@@ -0,0 +1,118 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Optional ISA extensions
+//!
+//! Extensions listed here may be enabled or disabled per machine. Their
+//! instructions are always decoded, but raise an illegal instruction
+//! exception when the extension is disabled.
+
+use crate::state_backend::{self as backend, Cell};
+
+/// Optional extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::EnumIter, strum::EnumString, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+#[repr(u8)]
+pub enum Extension {
+    /// Address generation instructions
+    Zba = 0,
+    /// Basic bit-manipulation
+    Zbb = 1,
+    /// Single-bit instructions
+    Zbs = 2,
+}
+
+/// Set of enabled optional extensions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Extensions(u8);
+
+impl Extensions {
+    /// No optional extension is enabled
+    pub const NONE: Self = Self(0);
+
+    /// Every optional extension is enabled
+    pub const ALL: Self = Self(0b111);
+
+    /// Enable `extension` in addition to the ones already enabled.
+    pub const fn with(self, extension: Extension) -> Self {
+        Self(self.0 | 1 << extension as u8)
+    }
+
+    /// Disable `extension`.
+    pub const fn without(self, extension: Extension) -> Self {
+        Self(self.0 & !(1 << extension as u8))
+    }
+
+    /// Whether `extension` is enabled
+    #[inline(always)]
+    pub const fn contains(self, extension: Extension) -> bool {
+        self.0 & 1 << extension as u8 != 0
+    }
+
+    /// Enabled extensions, in canonical order
+    pub fn iter(self) -> impl Iterator<Item = Extension> {
+        <Extension as strum::IntoEnumIterator>::iter().filter(move |ext| self.contains(*ext))
+    }
+}
+
+impl FromIterator<Extension> for Extensions {
+    fn from_iter<T: IntoIterator<Item = Extension>>(iter: T) -> Self {
+        iter.into_iter().fold(Self::NONE, Self::with)
+    }
+}
+
+/// Layout for [`ExtensionsCell`]
+pub type ExtensionsLayout = backend::Atom<u8>;
+
+/// Extensions enabled on a hart
+pub struct ExtensionsCell<M: backend::Manager> {
+    cell: Cell<u8, M>,
+}
+
+impl<M: backend::Manager> ExtensionsCell<M> {
+    /// Bind the extensions cell to the given allocated space.
+    pub fn bind(space: backend::AllocatedOf<ExtensionsLayout, M>) -> Self {
+        Self { cell: space }
+    }
+
+    /// Reset to the initial state, in which all extensions are enabled.
+    pub fn reset(&mut self) {
+        self.write(Extensions::ALL);
+    }
+
+    #[inline(always)]
+    pub fn read(&self) -> Extensions {
+        // Bits not belonging to any extension are ignored.
+        Extensions(self.cell.read() & Extensions::ALL.0)
+    }
+
+    #[inline(always)]
+    pub fn write(&mut self, extensions: Extensions) {
+        self.cell.write(extensions.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Extension, Extensions};
+
+    #[test]
+    fn test_extensions() {
+        let exts = Extensions::NONE.with(Extension::Zba).with(Extension::Zbs);
+        assert!(exts.contains(Extension::Zba));
+        assert!(!exts.contains(Extension::Zbb));
+        assert_eq!(
+            exts.iter().collect::<Vec<_>>(),
+            [Extension::Zba, Extension::Zbs]
+        );
+        assert_eq!(exts.without(Extension::Zba).iter().count(), 1);
+
+        let all: Extensions = "zba,zbb,zbs"
+            .split(',')
+            .map(|ext| ext.parse::<Extension>().unwrap())
+            .collect();
+        assert_eq!(all, Extensions::ALL);
+        assert_eq!(Extension::Zbb.to_string(), "zbb");
+    }
+}
@@ -2,6 +2,7 @@ use crate::{
     machine_state::{
         bus::Address,
         csregisters::{self, xstatus, CSRegister},
+        extensions,
         mode::{self, Mode, TrapMode},
         registers,
     },
@@ -25,6 +26,9 @@ pub struct HartState<M: backend::Manager> {
 
     /// Program counter
     pub pc: Cell<Address, M>,
+
+    /// Enabled optional extensions
+    pub extensions: extensions::ExtensionsCell<M>,
 }
 
 /// Layout of [HartState]
@@ -34,6 +38,7 @@ pub type HartStateLayout = (
     csregisters::CSRegistersLayout,
     mode::ModeLayout,
     Atom<Address>, // Program counter layout
+    extensions::ExtensionsLayout,
 );
 
 impl<M: backend::Manager> HartState<M> {
@@ -45,6 +50,7 @@ impl<M: backend::Manager> HartState<M> {
             csregisters: csregisters::CSRegisters::bind(space.2),
             mode: mode::ModeCell::bind(space.3),
             pc: Cell::bind(space.4),
+            extensions: extensions::ExtensionsCell::bind(space.5),
         }
     }
 
@@ -55,6 +61,7 @@ impl<M: backend::Manager> HartState<M> {
         self.csregisters.reset();
         self.mode.reset();
         self.pc.write(pc);
+        self.extensions.reset();
     }
 
     /// Given a trap source and a return address, take a trap on the machine.
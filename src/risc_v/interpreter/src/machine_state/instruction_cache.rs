@@ -0,0 +1,211 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Cache of decoded instructions, grouped into basic blocks
+//!
+//! The cache is not part of the machine state. It only holds instructions
+//! decoded from main memory, which is why writes through the
+//! [`Bus`](super::bus::Bus) must invalidate the blocks they overlap.
+
+use super::bus::Address;
+use crate::parser::instruction::Instr;
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+/// Granularity at which cached blocks are invalidated
+pub const PAGE_SIZE: u64 = 4096;
+
+/// Maximum number of instructions in a basic block
+pub const MAX_BLOCK_LENGTH: usize = 64;
+
+/// Number of blocks the cache can hold
+const ENTRIES: usize = 1 << 12;
+
+/// Sequence of decoded instructions laid out contiguously in memory. Only the
+/// last instruction of a block may transfer control elsewhere. Blocks never
+/// cross a page boundary.
+pub type Block = Rc<[Instr]>;
+
+/// Direct-mapped cache of decoded basic blocks, keyed by the physical address
+/// of their first instruction
+pub struct InstructionCache {
+    entries: Box<[Option<(Address, Block)>]>,
+
+    /// Bit set of the pages which blocks have been decoded from. Stores to
+    /// other pages don't need to look any further.
+    code_pages: Vec<u64>,
+
+    /// Entries holding blocks of a given page
+    page_entries: HashMap<u64, HashSet<usize>>,
+
+    generation: u64,
+}
+
+impl Default for InstructionCache {
+    fn default() -> Self {
+        Self {
+            entries: vec![None; ENTRIES].into_boxed_slice(),
+            code_pages: Vec::new(),
+            page_entries: HashMap::new(),
+            generation: 0,
+        }
+    }
+}
+
+#[inline(always)]
+fn entry_index(addr: Address) -> usize {
+    (addr >> 1) as usize % ENTRIES
+}
+
+impl InstructionCache {
+    /// Look up the block starting at `addr`.
+    #[inline(always)]
+    pub fn get(&self, addr: Address) -> Option<&Block> {
+        match &self.entries[entry_index(addr)] {
+            Some((start, block)) if *start == addr => Some(block),
+            _ => None,
+        }
+    }
+
+    /// Cache the block starting at `addr`, evicting the block it conflicts
+    /// with, if any.
+    pub fn insert(&mut self, addr: Address, block: Block) {
+        let index = entry_index(addr);
+        let page = addr / PAGE_SIZE;
+
+        if let Some((evicted, _)) = self.entries[index].replace((addr, block)) {
+            let evicted_page = evicted / PAGE_SIZE;
+            if let Some(entries) = self.page_entries.get_mut(&evicted_page) {
+                entries.remove(&index);
+            }
+        }
+
+        let (word, bit) = (page as usize / 64, page % 64);
+        if word >= self.code_pages.len() {
+            self.code_pages.resize(word + 1, 0);
+        }
+        self.code_pages[word] |= 1 << bit;
+        self.page_entries.entry(page).or_default().insert(index);
+    }
+
+    /// Drop the blocks of all pages overlapping the `len` bytes starting at
+    /// `addr`.
+    #[inline(always)]
+    pub fn invalidate(&mut self, addr: Address, len: usize) {
+        if len == 0 {
+            return;
+        }
+
+        let first = addr / PAGE_SIZE;
+        let last = addr.saturating_add(len as u64 - 1) / PAGE_SIZE;
+        for page in first..=last {
+            if self.is_code_page(page) {
+                self.invalidate_page(page);
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn is_code_page(&self, page: u64) -> bool {
+        let (word, bit) = (page as usize / 64, page % 64);
+        self.code_pages
+            .get(word)
+            .map_or(false, |bits| bits & (1 << bit) != 0)
+    }
+
+    fn invalidate_page(&mut self, page: u64) {
+        self.code_pages[page as usize / 64] &= !(1 << (page % 64));
+
+        for index in self.page_entries.remove(&page).unwrap_or_default() {
+            self.entries[index] = None;
+        }
+
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Drop all cached blocks.
+    pub fn flush(&mut self) {
+        self.entries.fill(None);
+        self.code_pages.clear();
+        self.page_entries.clear();
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Counter which changes whenever cached blocks are dropped. Blocks
+    /// obtained before a change may be stale.
+    #[inline(always)]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+/// Whether `instr` must be the last instruction of a block. This is the case
+/// for instructions which may not fall through to the next one.
+pub fn ends_block(instr: &Instr) -> bool {
+    matches!(
+        instr,
+        Instr::Beq(_)
+            | Instr::Bne(_)
+            | Instr::Blt(_)
+            | Instr::Bge(_)
+            | Instr::Bltu(_)
+            | Instr::Bgeu(_)
+            | Instr::Jal(_)
+            | Instr::Jalr(_)
+            | Instr::Ecall
+            | Instr::Ebreak
+            | Instr::Mret
+            | Instr::Sret
+            | Instr::Mnret
+            | Instr::Wfi
+            | Instr::FenceI
+            | Instr::FenceTso(_)
+            | Instr::Unknown { .. }
+            | Instr::UnknownCompressed { .. }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Block, InstructionCache, ENTRIES, PAGE_SIZE};
+    use crate::parser::instruction::Instr;
+
+    fn block() -> Block {
+        vec![Instr::Ecall].into()
+    }
+
+    #[test]
+    fn test_eviction_updates_pages() {
+        let mut cache = InstructionCache::default();
+
+        // Both addresses map to the same entry but lie on different pages.
+        let first = 0x1000;
+        let second = first + 2 * ENTRIES as u64;
+        assert_ne!(first / PAGE_SIZE, second / PAGE_SIZE);
+
+        // Re-inserting blocks doesn't grow the page index.
+        for _ in 0..100 {
+            cache.insert(first, block());
+            cache.insert(second, block());
+        }
+        assert_eq!(
+            cache.page_entries.values().map(|e| e.len()).sum::<usize>(),
+            1
+        );
+        assert!(cache.get(first).is_none());
+        assert!(cache.get(second).is_some());
+
+        // Invalidating the evicted block's page keeps the new block.
+        cache.invalidate(first, 4);
+        assert!(cache.get(second).is_some());
+
+        let generation = cache.generation();
+        cache.invalidate(second, 4);
+        assert!(cache.get(second).is_none());
+        assert_ne!(cache.generation(), generation);
+        assert!(cache.page_entries.is_empty());
+    }
+}
@@ -0,0 +1,359 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Tracing of retired instructions
+//!
+//! A [`Commit`] describes the effects of a retired instruction. It is
+//! displayed in the format of Spike's `--log-commits` option, which makes
+//! traces comparable with those of Spike.
+
+use super::{
+    bus::{main_memory::MainMemoryLayout, Address, Addressable},
+    csregisters::{CSRValue, CSRegister},
+    mode::Mode,
+    registers::{x0, XRegister, XValue},
+    MachineState,
+};
+use crate::{parser::instruction::Instr, state_backend as backend, traps::EnvironException};
+use std::fmt;
+
+/// Encoding of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawInstr {
+    Compressed(u16),
+    Uncompressed(u32),
+}
+
+impl fmt::Display for RawInstr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawInstr::Compressed(raw) => write!(f, "0x{raw:04x}"),
+            RawInstr::Uncompressed(raw) => write!(f, "0x{raw:08x}"),
+        }
+    }
+}
+
+/// Memory access performed by an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAccess {
+    Load {
+        address: Address,
+        width: u8,
+    },
+    Store {
+        address: Address,
+        width: u8,
+        value: u64,
+    },
+}
+
+/// Effects of a retired instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Commit {
+    pub mode: Mode,
+    pub pc: Address,
+    pub raw: RawInstr,
+    pub instr: Instr,
+    pub xregister_write: Option<(XRegister, XValue)>,
+    pub csregister_write: Option<(CSRegister, CSRValue)>,
+    pub memory_access: Option<MemoryAccess>,
+}
+
+impl Commit {
+    /// Line describing the instruction itself, in the format of Spike's
+    /// instruction log
+    pub fn disassembly(&self) -> impl fmt::Display + '_ {
+        struct Disassembly<'a>(&'a Commit);
+
+        impl fmt::Display for Disassembly<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let Commit { pc, raw, instr, .. } = self.0;
+                write!(f, "core   0: 0x{pc:016x} ({raw}) {instr}")
+            }
+        }
+
+        Disassembly(self)
+    }
+}
+
+impl fmt::Display for Commit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "core   0: {} 0x{:016x} ({})",
+            self.mode as u8, self.pc, self.raw
+        )?;
+
+        if let Some((reg, value)) = self.xregister_write {
+            // The representation of `x1` to `x31` is their index minus one.
+            write!(f, " x{:<2} 0x{value:016x}", reg as usize + 1)?;
+        }
+
+        if let Some((reg, value)) = self.csregister_write {
+            write!(f, " c{}_{reg} 0x{value:016x}", reg as usize)?;
+        }
+
+        match self.memory_access {
+            Some(MemoryAccess::Load { address, .. }) => write!(f, " mem 0x{address:016x}"),
+            Some(MemoryAccess::Store {
+                address,
+                width,
+                value,
+            }) => write!(
+                f,
+                " mem 0x{address:016x} 0x{value:0digits$x}",
+                digits = 2 * width as usize
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Part of a [`Commit`] which is known before the instruction runs
+struct PendingCommit {
+    mode: Mode,
+    pc: Address,
+    raw: RawInstr,
+    instr: Instr,
+    memory_access: Option<MemoryAccess>,
+}
+
+/// Destination register of an instruction
+fn destination(instr: &Instr) -> Option<XRegister> {
+    use Instr::*;
+
+    let rd = match instr {
+        Add(args) | Sub(args) | Xor(args) | Or(args) | And(args) | Sll(args) | Srl(args)
+        | Sra(args) | Slt(args) | Sltu(args) | Addw(args) | Subw(args) | Sllw(args)
+        | Srlw(args) | Sraw(args) | Rem(args) | Remu(args) | Remw(args) | Remuw(args) => args.rd,
+
+        AddUw(args) | Sh1add(args) | Sh1addUw(args) | Sh2add(args) | Sh2addUw(args)
+        | Sh3add(args) | Sh3addUw(args) | Andn(args) | Orn(args) | Xnor(args) | Max(args)
+        | Maxu(args) | Min(args) | Minu(args) | Rol(args) | Rolw(args) | Ror(args) | Rorw(args)
+        | Bclr(args) | Bext(args) | Binv(args) | Bset(args) => args.rd,
+
+        SlliUw(args) | Rori(args) | Roriw(args) | Bclri(args) | Bexti(args) | Binvi(args)
+        | Bseti(args) => args.rd,
+
+        Clz(args) | Clzw(args) | Ctz(args) | Ctzw(args) | Cpop(args) | Cpopw(args)
+        | SextB(args) | SextH(args) | ZextH(args) | OrcB(args) | Rev8(args) => args.rd,
+
+        Addi(args) | Addiw(args) | Xori(args) | Ori(args) | Andi(args) | Slli(args)
+        | Srli(args) | Srai(args) | Slliw(args) | Srliw(args) | Sraiw(args) | Slti(args)
+        | Sltiu(args) | Lb(args) | Lh(args) | Lw(args) | Lbu(args) | Lhu(args) | Lwu(args)
+        | Ld(args) | Jalr(args) => args.rd,
+
+        Lui(args) | Auipc(args) | Jal(args) => args.rd,
+
+        Csrrw(args) | Csrrs(args) | Csrrc(args) => args.rd,
+        Csrrwi(args) | Csrrsi(args) | Csrrci(args) => args.rd,
+
+        _ => return None,
+    };
+
+    (rd != x0).then_some(rd)
+}
+
+/// CSR written by an instruction. Set and clear instructions don't write
+/// the CSR when their source is zero.
+fn csr_destination(instr: &Instr) -> Option<CSRegister> {
+    use Instr::*;
+
+    match instr {
+        Csrrw(args) => Some(args.csr),
+        Csrrs(args) | Csrrc(args) => (args.rs1 != x0).then_some(args.csr),
+        Csrrwi(args) => Some(args.csr),
+        Csrrsi(args) | Csrrci(args) => (args.imm != 0).then_some(args.csr),
+        _ => None,
+    }
+}
+
+impl<ML: MainMemoryLayout, M: backend::Manager> MachineState<ML, M> {
+    /// Read the encoding of the instruction at `pc`.
+    fn fetch_raw_instr(&self, pc: Address) -> Option<RawInstr> {
+        let low: u16 = self.bus.read(pc).ok()?;
+        if low & 0b11 != 0b11 {
+            return Some(RawInstr::Compressed(low));
+        }
+
+        let high: u16 = self.bus.read(pc + 2).ok()?;
+        Some(RawInstr::Uncompressed(low as u32 | (high as u32) << 16))
+    }
+
+    /// Memory access `instr` is about to perform
+    fn memory_access(&self, instr: &Instr) -> Option<MemoryAccess> {
+        use Instr::*;
+
+        let load = |args: &crate::parser::instruction::ITypeArgs, width| MemoryAccess::Load {
+            address: self
+                .hart
+                .xregisters
+                .read(args.rs1)
+                .wrapping_add(args.imm as u64),
+            width,
+        };
+
+        let store = |args: &crate::parser::instruction::SBTypeArgs, width: u8| {
+            let value = self.hart.xregisters.read(args.rs2);
+            MemoryAccess::Store {
+                address: self
+                    .hart
+                    .xregisters
+                    .read(args.rs1)
+                    .wrapping_add(args.imm as u64),
+                width,
+                value: value & (u64::MAX >> (64 - 8 * width as u32)),
+            }
+        };
+
+        let access = match instr {
+            Lb(args) | Lbu(args) => load(args, 1),
+            Lh(args) | Lhu(args) => load(args, 2),
+            Lw(args) | Lwu(args) => load(args, 4),
+            Ld(args) => load(args, 8),
+            Sb(args) => store(args, 1),
+            Sh(args) => store(args, 2),
+            Sw(args) => store(args, 4),
+            Sd(args) => store(args, 8),
+            _ => return None,
+        };
+
+        Some(access)
+    }
+
+    /// Perform one step like [`MachineState::step`]. If an instruction
+    /// retired, describe its effects.
+    pub fn step_traced(&mut self) -> Result<Option<Commit>, EnvironException> {
+        let mut pending = None;
+
+        let retired = self.step_with(|state, pc| {
+            let instr = state.fetch_instr(pc)?;
+            pending = state.fetch_raw_instr(pc).map(|raw| PendingCommit {
+                mode: state.hart.mode.read(),
+                pc,
+                raw,
+                instr,
+                memory_access: state.memory_access(&instr),
+            });
+            Ok(instr)
+        })?;
+
+        let commit = pending.filter(|_| retired).map(|pending| Commit {
+            mode: pending.mode,
+            pc: pending.pc,
+            raw: pending.raw,
+            instr: pending.instr,
+            xregister_write: destination(&pending.instr)
+                .map(|reg| (reg, self.hart.xregisters.read(reg))),
+            csregister_write: csr_destination(&pending.instr)
+                .map(|reg| (reg, self.hart.csregisters.read(reg))),
+            memory_access: pending.memory_access,
+        });
+
+        Ok(commit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Commit, MemoryAccess, RawInstr};
+    use crate::{
+        backend_test, create_backend, create_state,
+        machine_state::{
+            bus::{main_memory::tests::T1K, start_of_main_memory, Addressable},
+            csregisters::CSRegister,
+            mode::Mode,
+            registers::{t0, t1},
+            MachineState, MachineStateLayout,
+        },
+    };
+
+    #[test]
+    fn test_commit_format() {
+        let commit = Commit {
+            mode: Mode::Machine,
+            pc: 0x8000_0000,
+            raw: RawInstr::Uncompressed(0x00000297),
+            instr: crate::parser::parse::<()>(0x0297, || Ok(0)).unwrap(),
+            xregister_write: Some((t0, 0x8000_0000)),
+            csregister_write: None,
+            memory_access: None,
+        };
+        assert_eq!(
+            commit.to_string(),
+            "core   0: 3 0x0000000080000000 (0x00000297) x5  0x0000000080000000"
+        );
+        assert_eq!(
+            commit.disassembly().to_string(),
+            "core   0: 0x0000000080000000 (0x00000297) auipc t0,0x0"
+        );
+
+        let commit = Commit {
+            mode: Mode::User,
+            raw: RawInstr::Compressed(0x4501),
+            xregister_write: None,
+            memory_access: Some(MemoryAccess::Store {
+                address: 0x8000_0018,
+                width: 2,
+                value: 0xbeef,
+            }),
+            ..commit
+        };
+        assert_eq!(
+            commit.to_string(),
+            "core   0: 0 0x0000000080000000 (0x4501) mem 0x0000000080000018 0xbeef"
+        );
+    }
+
+    backend_test!(test_step_traced, F, {
+        let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+        let mut state = create_state!(MachineState, MachineStateLayout<T1K>, F, backend, T1K);
+
+        let start = start_of_main_memory::<T1K>();
+        let program: [u32; 4] = [
+            0x00000297, // auipc t0, 0
+            0x0102b303, // ld t1, 16(t0)
+            0x0062b823, // sd t1, 16(t0)
+            0x34031073, // csrw mscratch, t1
+        ];
+        state.bus.write_all(start, &program).unwrap();
+        state.bus.write(start + 16, 0x1234_u64).unwrap();
+        state.hart.mode.write(Mode::Machine);
+        state.hart.pc.write(start);
+
+        let commits: Vec<_> = (0..4)
+            .map(|_| state.step_traced().unwrap().unwrap())
+            .collect();
+
+        assert_eq!(commits[0].xregister_write, Some((t0, start)));
+        assert_eq!(commits[1].xregister_write, Some((t1, 0x1234)));
+        assert_eq!(
+            commits[1].memory_access,
+            Some(MemoryAccess::Load {
+                address: start + 16,
+                width: 8
+            })
+        );
+        assert_eq!(
+            commits[2].memory_access,
+            Some(MemoryAccess::Store {
+                address: start + 16,
+                width: 8,
+                value: 0x1234
+            })
+        );
+        assert_eq!(commits[3].xregister_write, None);
+        assert_eq!(
+            commits[3].csregister_write,
+            Some((CSRegister::mscratch, 0x1234))
+        );
+        assert_eq!(
+            commits[3].to_string(),
+            format!(
+                "core   0: 3 0x{:016x} (0x34031073) c832_mscratch 0x0000000000001234",
+                start + 12
+            )
+        );
+    });
+}
@@ -138,6 +138,27 @@ macro_rules! i_instr {
     };
 }
 
+macro_rules! unary_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        $enum_variant(instruction::UnaryArgs {
+            rd: rd($instr),
+            rs1: rs1($instr),
+        })
+    };
+}
+
+// Shift amount of bit-manipulation instructions, whose upper immediate bits
+// only select the operation
+macro_rules! shamt_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        $enum_variant(instruction::ITypeArgs {
+            rd: rd($instr),
+            rs1: rs1($instr),
+            imm: bits($instr, 20, 6) as i64,
+        })
+    };
+}
+
 macro_rules! s_instr {
     ($enum_variant:ident, $instr:expr) => {
         $enum_variant(instruction::SBTypeArgs {
@@ -246,16 +267,27 @@ const F3_7: u32 = 0b111;
 
 const F7_0: u32 = 0b0;
 const F7_1: u32 = 0b1;
+const F7_4: u32 = 0b000_0100;
+const F7_5: u32 = 0b000_0101;
 const F7_8: u32 = 0b000_1000;
-const F7_20: u32 = 0b10_0000;
+const F7_16: u32 = 0b001_0000;
+const F7_20: u32 = 0b001_0100;
 const F7_24: u32 = 0b001_1000;
+const F7_32: u32 = 0b010_0000;
+const F7_36: u32 = 0b010_0100;
+const F7_48: u32 = 0b011_0000;
+const F7_52: u32 = 0b011_0100;
+const F7_53: u32 = 0b011_0101;
 const F7_56: u32 = 0b011_1000;
 
 const RS1_0: u32 = 0b0;
 const RS2_0: u32 = 0b0;
 const RS2_1: u32 = 0b1;
 const RS2_2: u32 = 0b10;
+const RS2_4: u32 = 0b100;
 const RS2_5: u32 = 0b101;
+const RS2_7: u32 = 0b111;
+const RS2_24: u32 = 0b1_1000;
 
 const FM_0: u32 = 0b0;
 const FM_8: u32 = 0b1000;
@@ -267,35 +299,51 @@ fn parse_uncompressed_instruction(instr: u32) -> Instr {
         OP_ARITH => match funct3(instr) {
             F3_0 => match funct7(instr) {
                 F7_0 => r_instr!(Add, instr),
-                F7_20 => r_instr!(Sub, instr),
+                F7_32 => r_instr!(Sub, instr),
                 _ => Unknown { instr },
             },
             F3_4 => match funct7(instr) {
                 F7_0 => r_instr!(Xor, instr),
+                F7_5 => r_instr!(Min, instr),
+                F7_16 => r_instr!(Sh2add, instr),
+                F7_32 => r_instr!(Xnor, instr),
                 _ => Unknown { instr },
             },
             F3_6 => match funct7(instr) {
                 F7_0 => r_instr!(Or, instr),
                 F7_1 => r_instr!(Rem, instr),
+                F7_5 => r_instr!(Max, instr),
+                F7_16 => r_instr!(Sh3add, instr),
+                F7_32 => r_instr!(Orn, instr),
                 _ => Unknown { instr },
             },
             F3_7 => match funct7(instr) {
                 F7_0 => r_instr!(And, instr),
                 F7_1 => r_instr!(Remu, instr),
+                F7_5 => r_instr!(Maxu, instr),
+                F7_32 => r_instr!(Andn, instr),
                 _ => Unknown { instr },
             },
             F3_1 => match funct7(instr) {
                 F7_0 => r_instr!(Sll, instr),
+                F7_20 => r_instr!(Bset, instr),
+                F7_36 => r_instr!(Bclr, instr),
+                F7_48 => r_instr!(Rol, instr),
+                F7_52 => r_instr!(Binv, instr),
                 _ => Unknown { instr },
             },
             F3_5 => match funct7(instr) {
                 F7_0 => r_instr!(Srl, instr),
-                F7_20 => r_instr!(Sra, instr),
+                F7_5 => r_instr!(Minu, instr),
+                F7_32 => r_instr!(Sra, instr),
+                F7_36 => r_instr!(Bext, instr),
+                F7_48 => r_instr!(Ror, instr),
                 _ => Unknown { instr },
             },
 
             F3_2 => match funct7(instr) {
                 F7_0 => r_instr!(Slt, instr),
+                F7_16 => r_instr!(Sh1add, instr),
                 _ => Unknown { instr },
             },
 
@@ -309,18 +357,34 @@ fn parse_uncompressed_instruction(instr: u32) -> Instr {
         OP_ARITH_W => match funct3(instr) {
             F3_0 => match funct7(instr) {
                 F7_0 => r_instr!(Addw, instr),
-                F7_20 => r_instr!(Subw, instr),
+                F7_4 => r_instr!(AddUw, instr),
+                F7_32 => r_instr!(Subw, instr),
+                _ => Unknown { instr },
+            },
+            F3_1 => match funct7(instr) {
+                F7_0 => r_instr!(Sllw, instr),
+                F7_48 => r_instr!(Rolw, instr),
+                _ => Unknown { instr },
+            },
+            F3_2 => match funct7(instr) {
+                F7_16 => r_instr!(Sh1addUw, instr),
+                _ => Unknown { instr },
+            },
+            F3_4 => match (funct7(instr), rs2_bits(instr)) {
+                (F7_4, RS2_0) => unary_instr!(ZextH, instr),
+                (F7_16, _) => r_instr!(Sh2addUw, instr),
                 _ => Unknown { instr },
             },
-            F3_1 => r_instr!(Sllw, instr),
             F3_5 => match funct7(instr) {
                 F7_0 => r_instr!(Srlw, instr),
-                F7_20 => r_instr!(Sraw, instr),
+                F7_32 => r_instr!(Sraw, instr),
+                F7_48 => r_instr!(Rorw, instr),
                 _ => Unknown { instr },
             },
 
             F3_6 => match funct7(instr) {
                 F7_1 => r_instr!(Remw, instr),
+                F7_16 => r_instr!(Sh3addUw, instr),
                 _ => Unknown { instr },
             },
             F3_7 => match funct7(instr) {
@@ -340,12 +404,35 @@ fn parse_uncompressed_instruction(instr: u32) -> Instr {
             F3_1 => match imm_11_6(instr) {
                 // imm[0:5] -> shift amount
                 F7_0 => i_instr!(Slli, instr),
+                F7_20 => shamt_instr!(Bseti, instr),
+                F7_36 => shamt_instr!(Bclri, instr),
+                F7_52 => shamt_instr!(Binvi, instr),
+                // imm[0:4] -> type of count or sign-extension
+                F7_48 => match (funct7(instr), rs2_bits(instr)) {
+                    (F7_48, RS2_0) => unary_instr!(Clz, instr),
+                    (F7_48, RS2_1) => unary_instr!(Ctz, instr),
+                    (F7_48, RS2_2) => unary_instr!(Cpop, instr),
+                    (F7_48, RS2_4) => unary_instr!(SextB, instr),
+                    (F7_48, RS2_5) => unary_instr!(SextH, instr),
+                    _ => Unknown { instr },
+                },
                 _ => Unknown { instr },
             },
             F3_5 => match imm_11_6(instr) {
                 // imm[6:11] -> type of shift, imm[0:5] -> shift amount
                 F7_0 => i_instr!(Srli, instr),
-                F7_20 => i_instr!(Srai, instr),
+                F7_32 => i_instr!(Srai, instr),
+                F7_36 => shamt_instr!(Bexti, instr),
+                F7_48 => shamt_instr!(Rori, instr),
+                // The whole immediate selects the operation
+                F7_20 => match (funct7(instr), rs2_bits(instr)) {
+                    (F7_20, RS2_7) => unary_instr!(OrcB, instr),
+                    _ => Unknown { instr },
+                },
+                F7_52 => match (funct7(instr), rs2_bits(instr)) {
+                    (F7_53, RS2_24) => unary_instr!(Rev8, instr),
+                    _ => Unknown { instr },
+                },
                 _ => Unknown { instr },
             },
             F3_2 => i_instr!(Slti, instr),
@@ -367,12 +454,25 @@ fn parse_uncompressed_instruction(instr: u32) -> Instr {
             F3_1 => match imm_11_6(instr) {
                 // imm[0:4] -> shift amount
                 F7_0 => i_instr!(Slliw, instr),
+                // imm[0:5] -> shift amount
+                F7_4 => shamt_instr!(SlliUw, instr),
+                // imm[0:4] -> type of count
+                F7_48 => match (funct7(instr), rs2_bits(instr)) {
+                    (F7_48, RS2_0) => unary_instr!(Clzw, instr),
+                    (F7_48, RS2_1) => unary_instr!(Ctzw, instr),
+                    (F7_48, RS2_2) => unary_instr!(Cpopw, instr),
+                    _ => Unknown { instr },
+                },
                 _ => Unknown { instr },
             },
             F3_5 => match imm_11_6(instr) {
                 // imm[6:11] -> type of shift, imm[0:4] -> shift amount
                 F7_0 => i_instr!(Srliw, instr),
-                F7_20 => i_instr!(Sraiw, instr),
+                F7_32 => i_instr!(Sraiw, instr),
+                F7_48 => match funct7(instr) {
+                    F7_48 => shamt_instr!(Roriw, instr),
+                    _ => Unknown { instr },
+                },
                 _ => Unknown { instr },
             },
             _ => Unknown { instr },
@@ -623,4 +723,47 @@ mod tests {
         let instructions = parse_block(&bytes);
         assert_eq!(instructions, expected)
     }
+
+    // Bit-manipulation instructions, as assembled by
+    // `llvm-mc -triple=riscv64 -mattr=+zba,+zbb,+zbs`
+    #[test]
+    fn test_bitmanip() {
+        let cases: [([u8; 4], &str); 20] = [
+            ([0x3b, 0x85, 0xc5, 0x08], "add.uw a0,a1,a2"),
+            ([0x33, 0xa5, 0xc5, 0x20], "sh1add a0,a1,a2"),
+            ([0x3b, 0xc5, 0xc5, 0x20], "sh2add.uw a0,a1,a2"),
+            ([0x1b, 0x95, 0x85, 0x0a], "slli.uw a0,a1,0x28"),
+            ([0x33, 0xf5, 0xc5, 0x40], "andn a0,a1,a2"),
+            ([0x13, 0x95, 0x05, 0x60], "clz a0,a1"),
+            ([0x1b, 0x95, 0x25, 0x60], "cpopw a0,a1"),
+            ([0x33, 0xe5, 0xc5, 0x0a], "max a0,a1,a2"),
+            ([0x33, 0xd5, 0xc5, 0x0a], "minu a0,a1,a2"),
+            ([0x13, 0x95, 0x55, 0x60], "sext.h a0,a1"),
+            ([0x3b, 0xc5, 0x05, 0x08], "zext.h a0,a1"),
+            ([0x3b, 0x95, 0xc5, 0x60], "rolw a0,a1,a2"),
+            ([0x13, 0xd5, 0xf5, 0x63], "rori a0,a1,0x3f"),
+            ([0x1b, 0xd5, 0xf5, 0x61], "roriw a0,a1,0x1f"),
+            ([0x13, 0xd5, 0x75, 0x28], "orc.b a0,a1"),
+            ([0x13, 0xd5, 0x85, 0x6b], "rev8 a0,a1"),
+            ([0x13, 0x95, 0x15, 0x4a], "bclri a0,a1,0x21"),
+            ([0x13, 0xd5, 0x55, 0x48], "bexti a0,a1,0x5"),
+            ([0x33, 0x95, 0xc5, 0x68], "binv a0,a1,a2"),
+            ([0x13, 0x95, 0xf5, 0x2b], "bseti a0,a1,0x3f"),
+        ];
+
+        for (bytes, expected) in cases {
+            let instructions = parse_block(&bytes);
+            assert_eq!(instructions.len(), 1);
+            assert_eq!(instructions[0].to_string(), expected);
+        }
+
+        // `zext.h` requires rs2 to be zero
+        let bytes = [0x3b, 0xc5, 0x15, 0x08];
+        assert_eq!(
+            parse_block(&bytes),
+            [Unknown {
+                instr: u32::from_le_bytes(bytes)
+            }]
+        );
+    }
 }
@@ -20,6 +20,12 @@ pub struct ITypeArgs {
     pub imm: i64,
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct UnaryArgs {
+    pub rd: XRegister,
+    pub rs1: XRegister,
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct SBTypeArgs {
     pub rs1: XRegister,
@@ -137,6 +143,52 @@ pub enum Instr {
     Remw(RTypeArgs),
     Remuw(RTypeArgs),
 
+    // Zba extension instructions
+    AddUw(RTypeArgs),
+    Sh1add(RTypeArgs),
+    Sh1addUw(RTypeArgs),
+    Sh2add(RTypeArgs),
+    Sh2addUw(RTypeArgs),
+    Sh3add(RTypeArgs),
+    Sh3addUw(RTypeArgs),
+    SlliUw(ITypeArgs),
+
+    // Zbb extension instructions
+    Andn(RTypeArgs),
+    Orn(RTypeArgs),
+    Xnor(RTypeArgs),
+    Clz(UnaryArgs),
+    Clzw(UnaryArgs),
+    Ctz(UnaryArgs),
+    Ctzw(UnaryArgs),
+    Cpop(UnaryArgs),
+    Cpopw(UnaryArgs),
+    Max(RTypeArgs),
+    Maxu(RTypeArgs),
+    Min(RTypeArgs),
+    Minu(RTypeArgs),
+    SextB(UnaryArgs),
+    SextH(UnaryArgs),
+    ZextH(UnaryArgs),
+    Rol(RTypeArgs),
+    Rolw(RTypeArgs),
+    Ror(RTypeArgs),
+    Rori(ITypeArgs),
+    Roriw(ITypeArgs),
+    Rorw(RTypeArgs),
+    OrcB(UnaryArgs),
+    Rev8(UnaryArgs),
+
+    // Zbs extension instructions
+    Bclr(RTypeArgs),
+    Bclri(ITypeArgs),
+    Bext(RTypeArgs),
+    Bexti(ITypeArgs),
+    Binv(RTypeArgs),
+    Binvi(ITypeArgs),
+    Bset(RTypeArgs),
+    Bseti(ITypeArgs),
+
     // Zicsr instructions
     Csrrw(CsrArgs),
     Csrrs(CsrArgs),
@@ -224,6 +276,46 @@ impl Instr {
             | Remu(_)
             | Remw(_)
             | Remuw(_)
+            | AddUw(_)
+            | Sh1add(_)
+            | Sh1addUw(_)
+            | Sh2add(_)
+            | Sh2addUw(_)
+            | Sh3add(_)
+            | Sh3addUw(_)
+            | SlliUw(_)
+            | Andn(_)
+            | Orn(_)
+            | Xnor(_)
+            | Clz(_)
+            | Clzw(_)
+            | Ctz(_)
+            | Ctzw(_)
+            | Cpop(_)
+            | Cpopw(_)
+            | Max(_)
+            | Maxu(_)
+            | Min(_)
+            | Minu(_)
+            | SextB(_)
+            | SextH(_)
+            | ZextH(_)
+            | Rol(_)
+            | Rolw(_)
+            | Ror(_)
+            | Rori(_)
+            | Roriw(_)
+            | Rorw(_)
+            | OrcB(_)
+            | Rev8(_)
+            | Bclr(_)
+            | Bclri(_)
+            | Bext(_)
+            | Bexti(_)
+            | Binv(_)
+            | Binvi(_)
+            | Bset(_)
+            | Bseti(_)
             | Csrrw(_)
             | Csrrs(_)
             | Csrrc(_)
@@ -249,6 +341,12 @@ macro_rules! r_instr {
     };
 }
 
+macro_rules! unary_instr {
+    ($f:expr, $op:expr, $args:expr) => {
+        write!($f, "{} {},{}", $op, $args.rd, $args.rs1)
+    };
+}
+
 macro_rules! i_instr {
     ($f:expr, $op:expr, $args:expr) => {
         write!($f, "{} {},{},{}", $op, $args.rd, $args.rs1, $args.imm)
@@ -444,6 +542,52 @@ impl fmt::Display for Instr {
             Remw(args) => r_instr!(f, "remw", args),
             Remuw(args) => r_instr!(f, "remuw", args),
 
+            // Zba extension instructions
+            AddUw(args) => r_instr!(f, "add.uw", args),
+            Sh1add(args) => r_instr!(f, "sh1add", args),
+            Sh1addUw(args) => r_instr!(f, "sh1add.uw", args),
+            Sh2add(args) => r_instr!(f, "sh2add", args),
+            Sh2addUw(args) => r_instr!(f, "sh2add.uw", args),
+            Sh3add(args) => r_instr!(f, "sh3add", args),
+            Sh3addUw(args) => r_instr!(f, "sh3add.uw", args),
+            SlliUw(args) => i_instr_hex!(f, "slli.uw", args),
+
+            // Zbb extension instructions
+            Andn(args) => r_instr!(f, "andn", args),
+            Orn(args) => r_instr!(f, "orn", args),
+            Xnor(args) => r_instr!(f, "xnor", args),
+            Clz(args) => unary_instr!(f, "clz", args),
+            Clzw(args) => unary_instr!(f, "clzw", args),
+            Ctz(args) => unary_instr!(f, "ctz", args),
+            Ctzw(args) => unary_instr!(f, "ctzw", args),
+            Cpop(args) => unary_instr!(f, "cpop", args),
+            Cpopw(args) => unary_instr!(f, "cpopw", args),
+            Max(args) => r_instr!(f, "max", args),
+            Maxu(args) => r_instr!(f, "maxu", args),
+            Min(args) => r_instr!(f, "min", args),
+            Minu(args) => r_instr!(f, "minu", args),
+            SextB(args) => unary_instr!(f, "sext.b", args),
+            SextH(args) => unary_instr!(f, "sext.h", args),
+            ZextH(args) => unary_instr!(f, "zext.h", args),
+            Rol(args) => r_instr!(f, "rol", args),
+            Rolw(args) => r_instr!(f, "rolw", args),
+            Ror(args) => r_instr!(f, "ror", args),
+            Rori(args) => i_instr_hex!(f, "rori", args),
+            Roriw(args) => i_instr_hex!(f, "roriw", args),
+            Rorw(args) => r_instr!(f, "rorw", args),
+            OrcB(args) => unary_instr!(f, "orc.b", args),
+            Rev8(args) => unary_instr!(f, "rev8", args),
+
+            // Zbs extension instructions
+            Bclr(args) => r_instr!(f, "bclr", args),
+            Bclri(args) => i_instr_hex!(f, "bclri", args),
+            Bext(args) => r_instr!(f, "bext", args),
+            Bexti(args) => i_instr_hex!(f, "bexti", args),
+            Binv(args) => r_instr!(f, "binv", args),
+            Binvi(args) => i_instr_hex!(f, "binvi", args),
+            Bset(args) => r_instr!(f, "bset", args),
+            Bseti(args) => i_instr_hex!(f, "bseti", args),
+
             // Zicsr instructions
             Csrrw(args) => csr_instr!(f, "csrrw", args),
             Csrrs(args) => csr_instr!(f, "csrrs", args),
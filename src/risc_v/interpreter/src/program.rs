@@ -21,6 +21,12 @@ pub struct Program<'a, ML> {
     // representing bytes at `index..index+length` and
     // all the arrays are non-overlapping
     pub segments: BTreeMap<Address, Cow<'a, [u8]>>,
+
+    /// Initial value of the thread pointer, if the program has a TLS block
+    pub thread_pointer: Option<Address>,
+
+    /// Symbols of the program
+    pub symbols: kernel_loader::SymbolTable,
 }
 
 impl<'a, ML> kernel_loader::Memory for Program<'a, ML> {
@@ -102,9 +108,14 @@ impl<'a, ML: MainMemoryLayout> Program<'a, ML> {
             _pd: PhantomData,
             entrypoint: start_if_reloc,
             segments: BTreeMap::new(),
+            thread_pointer: None,
+            symbols: kernel_loader::SymbolTable::default(),
         };
 
-        myself.entrypoint = kernel_loader::load_elf(&mut myself, start_if_reloc, elf)?.entry;
+        let loaded = kernel_loader::load_elf(&mut myself, start_if_reloc, elf)?;
+        myself.entrypoint = loaded.entry;
+        myself.thread_pointer = loaded.thread_pointer;
+        myself.symbols = loaded.symbols;
 
         Ok(myself)
     }
@@ -116,6 +127,8 @@ impl<'a, ML: MainMemoryLayout> Program<'a, ML> {
             _pd: PhantomData,
             entrypoint,
             segments: BTreeMap::from_iter([(entrypoint, Cow::Borrowed(code))]),
+            thread_pointer: None,
+            symbols: kernel_loader::SymbolTable::default(),
         }
     }
 
@@ -148,6 +161,8 @@ mod tests {
             _pd: PhantomData,
             entrypoint: 0,
             segments: BTreeMap::new(),
+            thread_pointer: None,
+            symbols: Default::default(),
         };
         let mut buffer = Cursor::new(vec![0; 2048]);
 
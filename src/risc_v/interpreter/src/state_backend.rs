@@ -1,4 +1,4 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
@@ -55,7 +55,11 @@
 //! [Layouts]: Layout
 //! [Locations]: Location
 
+pub mod hash;
 pub mod memory_backend;
+pub mod merkle;
+pub mod proof;
+pub mod snapshot;
 
 mod layout;
 pub use layout::*;
@@ -330,6 +334,10 @@ pub mod tests {
             unimplemented!()
         }
 
+        fn read_all<E: Elem>(&self, _address: usize, _values: &mut [E]) {
+            unimplemented!()
+        }
+
         fn write<E: Elem>(&mut self, _address: usize, _value: E) {
             unimplemented!()
         }
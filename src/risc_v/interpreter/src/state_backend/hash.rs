@@ -0,0 +1,87 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+use std::fmt;
+use tezos_crypto_rs::blake2b::{self, Blake2bError};
+
+/// Size of a state hash in bytes
+pub const DIGEST_SIZE: usize = 32;
+
+/// Failed to compute a hash
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum HashError {
+    #[error("Failed to compute BLAKE2b digest: {0}")]
+    Blake2b(#[from] Blake2bError),
+
+    #[error("Digest has {0} bytes instead of {DIGEST_SIZE}")]
+    InvalidDigestSize(usize),
+}
+
+/// BLAKE2b hash of some part of the state
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Hash {
+    digest: [u8; DIGEST_SIZE],
+}
+
+impl Hash {
+    /// Hash a slice of bytes.
+    pub fn blake2b_hash_bytes(bytes: &[u8]) -> Result<Self, HashError> {
+        let digest = blake2b::digest_256(bytes)?;
+        Self::try_from(digest.as_slice())
+    }
+}
+
+impl From<[u8; DIGEST_SIZE]> for Hash {
+    fn from(digest: [u8; DIGEST_SIZE]) -> Self {
+        Self { digest }
+    }
+}
+
+impl TryFrom<&[u8]> for Hash {
+    type Error = HashError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let digest = bytes
+            .try_into()
+            .map_err(|_| HashError::InvalidDigestSize(bytes.len()))?;
+        Ok(Self { digest })
+    }
+}
+
+impl AsRef<[u8]> for Hash {
+    fn as_ref(&self) -> &[u8] {
+        &self.digest
+    }
+}
+
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.digest {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Hash({self})")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Hash, HashError, DIGEST_SIZE};
+
+    #[test]
+    fn test_hash_conversions() {
+        let hash = Hash::blake2b_hash_bytes(b"risc-v").unwrap();
+        assert_eq!(Hash::try_from(hash.as_ref()), Ok(hash));
+        assert_eq!(hash.to_string().len(), 2 * DIGEST_SIZE);
+        assert_eq!(
+            Hash::try_from([0u8; 3].as_slice()),
+            Err(HashError::InvalidDigestSize(3))
+        );
+    }
+}
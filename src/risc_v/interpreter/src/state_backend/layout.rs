@@ -2,7 +2,10 @@
 //
 // SPDX-License-Identifier: MIT
 
-use super::alloc::{Choreographer, Location, Placed};
+use super::{
+    alloc::{Choreographer, Location, Placed},
+    merkle::MerkleShape,
+};
 use std::{array, marker::PhantomData};
 
 /// Structural description of a state type
@@ -23,6 +26,9 @@ pub trait Layout {
 
     /// Allocate regions in the given state backend.
     fn allocate<B: super::Manager>(backend: &mut B, placed: Self::Placed) -> Self::Allocated<B>;
+
+    /// Describe the shape of the Merkle tree over the placed state.
+    fn merkle_shape(placed: &Self::Placed) -> MerkleShape;
 }
 
 /// `L::Placed`
@@ -49,6 +55,13 @@ impl<T: super::Elem> Layout for Atom<T> {
     fn allocate<B: super::Manager>(backend: &mut B, placed: Self::Placed) -> Self::Allocated<B> {
         backend.allocate_cell(placed)
     }
+
+    fn merkle_shape(placed: &Self::Placed) -> MerkleShape {
+        MerkleShape::Leaf {
+            offset: placed.offset(),
+            length: placed.size(),
+        }
+    }
 }
 
 /// Layout for a fixed number of values
@@ -69,6 +82,10 @@ impl<T: super::Elem, const LEN: usize> Layout for Array<T, LEN> {
     fn allocate<B: super::Manager>(backend: &mut B, placed: Self::Placed) -> Self::Allocated<B> {
         backend.allocate_region(placed)
     }
+
+    fn merkle_shape(placed: &Self::Placed) -> MerkleShape {
+        MerkleShape::region(placed.offset(), placed.size())
+    }
 }
 
 impl<A, B> Layout for (A, B)
@@ -93,6 +110,10 @@ where
             B::allocate(backend, placed.1),
         )
     }
+
+    fn merkle_shape(placed: &Self::Placed) -> MerkleShape {
+        MerkleShape::Node(vec![A::merkle_shape(&placed.0), B::merkle_shape(&placed.1)])
+    }
 }
 
 impl<A, B, C> Layout for (A, B, C)
@@ -124,6 +145,14 @@ where
             C::allocate(backend, placed.2),
         )
     }
+
+    fn merkle_shape(placed: &Self::Placed) -> MerkleShape {
+        MerkleShape::Node(vec![
+            A::merkle_shape(&placed.0),
+            B::merkle_shape(&placed.1),
+            C::merkle_shape(&placed.2),
+        ])
+    }
 }
 
 impl<A, B, C, D> Layout for (A, B, C, D)
@@ -162,6 +191,15 @@ where
             D::allocate(backend, placed.3),
         )
     }
+
+    fn merkle_shape(placed: &Self::Placed) -> MerkleShape {
+        MerkleShape::Node(vec![
+            A::merkle_shape(&placed.0),
+            B::merkle_shape(&placed.1),
+            C::merkle_shape(&placed.2),
+            D::merkle_shape(&placed.3),
+        ])
+    }
 }
 
 impl<A, B, C, D, E> Layout for (A, B, C, D, E)
@@ -204,6 +242,80 @@ where
             E::allocate(backend, placed.4),
         )
     }
+
+    fn merkle_shape(placed: &Self::Placed) -> MerkleShape {
+        MerkleShape::Node(vec![
+            A::merkle_shape(&placed.0),
+            B::merkle_shape(&placed.1),
+            C::merkle_shape(&placed.2),
+            D::merkle_shape(&placed.3),
+            E::merkle_shape(&placed.4),
+        ])
+    }
+}
+
+impl<A, B, C, D, E, F> Layout for (A, B, C, D, E, F)
+where
+    A: Layout,
+    B: Layout,
+    C: Layout,
+    D: Layout,
+    E: Layout,
+    F: Layout,
+{
+    type Placed = (
+        A::Placed,
+        B::Placed,
+        C::Placed,
+        D::Placed,
+        E::Placed,
+        F::Placed,
+    );
+
+    fn place_with(alloc: &mut Choreographer) -> Self::Placed {
+        (
+            A::place_with(alloc),
+            B::place_with(alloc),
+            C::place_with(alloc),
+            D::place_with(alloc),
+            E::place_with(alloc),
+            F::place_with(alloc),
+        )
+    }
+
+    type Allocated<Back: super::Manager> = (
+        A::Allocated<Back>,
+        B::Allocated<Back>,
+        C::Allocated<Back>,
+        D::Allocated<Back>,
+        E::Allocated<Back>,
+        F::Allocated<Back>,
+    );
+
+    fn allocate<Back: super::Manager>(
+        backend: &mut Back,
+        placed: Self::Placed,
+    ) -> Self::Allocated<Back> {
+        (
+            A::allocate(backend, placed.0),
+            B::allocate(backend, placed.1),
+            C::allocate(backend, placed.2),
+            D::allocate(backend, placed.3),
+            E::allocate(backend, placed.4),
+            F::allocate(backend, placed.5),
+        )
+    }
+
+    fn merkle_shape(placed: &Self::Placed) -> MerkleShape {
+        MerkleShape::Node(vec![
+            A::merkle_shape(&placed.0),
+            B::merkle_shape(&placed.1),
+            C::merkle_shape(&placed.2),
+            D::merkle_shape(&placed.3),
+            E::merkle_shape(&placed.4),
+            F::merkle_shape(&placed.5),
+        ])
+    }
 }
 
 impl<T, const LEN: usize> Layout for [T; LEN]
@@ -221,4 +333,8 @@ where
     fn allocate<B: super::Manager>(backend: &mut B, placed: Self::Placed) -> Self::Allocated<B> {
         placed.map(|placed| T::allocate(backend, placed))
     }
+
+    fn merkle_shape(placed: &Self::Placed) -> MerkleShape {
+        MerkleShape::Node(placed.iter().map(T::merkle_shape).collect())
+    }
 }
@@ -1,8 +1,13 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
-use crate::state_backend::{self as backend, Layout};
+use crate::state_backend::{
+    self as backend,
+    merkle::{DirtyPages, Tracked},
+    proof::{AccessLog, Recorded},
+    Layout,
+};
 use std::{alloc, marker::PhantomData, ptr, slice};
 
 /// In-memory state backend
@@ -41,6 +46,28 @@ where
         unsafe { slice::from_raw_parts(self.backing_storage, self.layout.size()) }
     }
 
+    /// Allocate regions for the given layout placement. Writes to these
+    /// regions are recorded in `dirty`.
+    pub fn allocate_tracked(
+        &mut self,
+        placed: L::Placed,
+        dirty: DirtyPages,
+    ) -> backend::AllocatedOf<L, TrackingManager<'_>> {
+        let mut manager = TrackingManager::new(self.borrow_mut(), dirty);
+        L::allocate(&mut manager, placed)
+    }
+
+    /// Allocate regions for the given layout placement. Reads from and writes
+    /// to these regions are recorded in `log`.
+    pub fn allocate_recorded(
+        &mut self,
+        placed: L::Placed,
+        log: AccessLog,
+    ) -> backend::AllocatedOf<L, RecordingManager<'_>> {
+        let mut manager = RecordingManager::new(self.borrow_mut(), log);
+        L::allocate(&mut manager, placed)
+    }
+
     /// Borrow the backing storage mutably.
     pub fn borrow_mut(&mut self) -> &mut [u8] {
         // SAFETY: [slice::from_raw_parts_mut] is layout safe given we allocated t using
@@ -186,6 +213,82 @@ impl<'backend> backend::Manager for SliceManagerRO<'backend> {
     }
 }
 
+/// Manager for in-memory backing storage which records the pages that have
+/// been written to
+pub struct TrackingManager<'backend> {
+    inner: SliceManager<'backend>,
+    dirty: DirtyPages,
+}
+
+impl<'backend> TrackingManager<'backend> {
+    /// Manage the given slice.
+    pub fn new(backing_storage: &'backend mut [u8], dirty: DirtyPages) -> Self {
+        Self {
+            inner: SliceManager::new(backing_storage),
+            dirty,
+        }
+    }
+}
+
+impl<'backend> backend::Manager for TrackingManager<'backend> {
+    type Region<E: backend::Elem, const LEN: usize> = Tracked<&'backend mut [E; LEN]>;
+
+    fn allocate_region<E: backend::Elem, const LEN: usize>(
+        &mut self,
+        loc: backend::Location<[E; LEN]>,
+    ) -> Self::Region<E, LEN> {
+        let offset = loc.offset();
+        Tracked::new(self.inner.allocate_region(loc), offset, self.dirty.clone())
+    }
+
+    type DynRegion<const LEN: usize> = Tracked<&'backend mut [u8; LEN]>;
+
+    fn allocate_dyn_region<const LEN: usize>(
+        &mut self,
+        loc: backend::Location<[u8; LEN]>,
+    ) -> Self::DynRegion<LEN> {
+        self.allocate_region::<u8, LEN>(loc)
+    }
+}
+
+/// Manager for in-memory backing storage which records the pages that have
+/// been read from or written to
+pub struct RecordingManager<'backend> {
+    inner: SliceManager<'backend>,
+    log: AccessLog,
+}
+
+impl<'backend> RecordingManager<'backend> {
+    /// Manage the given slice.
+    pub fn new(backing_storage: &'backend mut [u8], log: AccessLog) -> Self {
+        Self {
+            inner: SliceManager::new(backing_storage),
+            log,
+        }
+    }
+}
+
+impl<'backend> backend::Manager for RecordingManager<'backend> {
+    type Region<E: backend::Elem, const LEN: usize> = Recorded<&'backend mut [E; LEN]>;
+
+    fn allocate_region<E: backend::Elem, const LEN: usize>(
+        &mut self,
+        loc: backend::Location<[E; LEN]>,
+    ) -> Self::Region<E, LEN> {
+        let offset = loc.offset();
+        Recorded::new(self.inner.allocate_region(loc), offset, self.log.clone())
+    }
+
+    type DynRegion<const LEN: usize> = Recorded<&'backend mut [u8; LEN]>;
+
+    fn allocate_dyn_region<const LEN: usize>(
+        &mut self,
+        loc: backend::Location<[u8; LEN]>,
+    ) -> Self::DynRegion<LEN> {
+        self.allocate_region::<u8, LEN>(loc)
+    }
+}
+
 pub mod test_helpers {
     use super::InMemoryBackend;
     use crate::state_backend::{test_helpers::TestBackendFactory, Layout};
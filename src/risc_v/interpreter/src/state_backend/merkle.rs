@@ -0,0 +1,529 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Merkle trees over the state backend
+//!
+//! Every [Layout] describes the shape of its Merkle tree through
+//! [`Layout::merkle_shape`]. Atoms make up a single leaf, tuples and arrays of
+//! layouts turn into inner nodes. Large regions like the main memory are split
+//! into leaves of [MERKLE_LEAF_SIZE] bytes which are arranged in a balanced
+//! binary tree.
+//!
+//! A [MerkleTree] caches the hashes of all its nodes. Writes through regions
+//! obtained from a tracking manager (see [Tracked]) are recorded in
+//! [DirtyPages]. When updating the tree, only the nodes covering dirty pages are
+//! rehashed.
+//!
+//! Leaf and inner node hashes are domain-separated by a tag byte which
+//! precedes the hashed contents, such that no leaf can be passed off as an
+//! inner node and vice versa.
+
+use super::{
+    hash::{Hash, HashError, DIGEST_SIZE},
+    Backend, DynRegion, Elem, Layout, Region,
+};
+use std::{cell::RefCell, collections::BTreeSet, mem, ops::Range, rc::Rc, sync::OnceLock};
+
+/// Maximum number of bytes in a leaf of the Merkle tree
+pub const MERKLE_LEAF_SIZE: usize = 4096;
+
+/// Tag prepended to the contents of a leaf before hashing
+const LEAF_TAG: u8 = 0;
+
+/// Tag prepended to the hashes of the children of an inner node before hashing
+const NODE_TAG: u8 = 1;
+
+/// Shape of the Merkle tree over a part of the state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MerkleShape {
+    /// Leaf covering `length` bytes starting at `offset` in the backend storage
+    Leaf { offset: usize, length: usize },
+
+    /// Inner node with children ordered by their offsets
+    Node(Vec<MerkleShape>),
+}
+
+impl MerkleShape {
+    /// Shape for a contiguous region of the backend storage. Regions larger
+    /// than [MERKLE_LEAF_SIZE] are split into a balanced binary tree of leaves.
+    pub fn region(offset: usize, length: usize) -> Self {
+        if length <= MERKLE_LEAF_SIZE {
+            return MerkleShape::Leaf { offset, length };
+        }
+
+        let leaves = length.div_ceil(MERKLE_LEAF_SIZE);
+        let left = leaves.div_ceil(2) * MERKLE_LEAF_SIZE;
+        MerkleShape::Node(vec![
+            Self::region(offset, left),
+            Self::region(offset + left, length - left),
+        ])
+    }
+
+    /// Bytes of the backend storage covered by this shape
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            MerkleShape::Leaf { offset, length } => *offset..*offset + *length,
+            MerkleShape::Node(children) => {
+                let start = children.first().map_or(0, |child| child.span().start);
+                let end = children.last().map_or(start, |child| child.span().end);
+                start..end
+            }
+        }
+    }
+
+    /// Number of leaves in the tree
+    pub fn leaves(&self) -> usize {
+        match self {
+            MerkleShape::Leaf { .. } => 1,
+            MerkleShape::Node(children) => children.iter().map(Self::leaves).sum(),
+        }
+    }
+}
+
+/// Set of pages of the backend storage that have been written to
+///
+/// The page size is [MERKLE_LEAF_SIZE]. The set is shared between all regions
+/// of a tracking manager and the owner of the [MerkleTree].
+#[derive(Debug, Clone, Default)]
+pub struct DirtyPages {
+    pages: Rc<RefCell<BTreeSet<usize>>>,
+}
+
+impl DirtyPages {
+    /// Create an empty set of dirty pages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark `length` bytes starting at `offset` as dirty.
+    pub fn mark(&self, offset: usize, length: usize) {
+        if length == 0 {
+            return;
+        }
+
+        let first = offset / MERKLE_LEAF_SIZE;
+        let last = (offset + length - 1) / MERKLE_LEAF_SIZE;
+        self.pages.borrow_mut().extend(first..=last);
+    }
+
+    /// Does any dirty page intersect the given range of bytes?
+    pub fn intersects(&self, range: &Range<usize>) -> bool {
+        if range.is_empty() {
+            return false;
+        }
+
+        let first = range.start / MERKLE_LEAF_SIZE;
+        let last = (range.end - 1) / MERKLE_LEAF_SIZE;
+        self.pages.borrow().range(first..=last).next().is_some()
+    }
+
+    /// Number of dirty pages
+    pub fn len(&self) -> usize {
+        self.pages.borrow().len()
+    }
+
+    /// Are there no dirty pages?
+    pub fn is_empty(&self) -> bool {
+        self.pages.borrow().is_empty()
+    }
+
+    /// Forget about all dirty pages.
+    pub fn clear(&self) {
+        self.pages.borrow_mut().clear()
+    }
+}
+
+/// Region wrapper that records writes in [DirtyPages]
+pub struct Tracked<R> {
+    inner: R,
+    offset: usize,
+    dirty: DirtyPages,
+}
+
+impl<R> Tracked<R> {
+    /// Track writes to `inner` which is located at `offset` in the backend storage.
+    pub fn new(inner: R, offset: usize, dirty: DirtyPages) -> Self {
+        Self {
+            inner,
+            offset,
+            dirty,
+        }
+    }
+
+    #[inline(always)]
+    fn mark<E>(&self, index: usize, count: usize) {
+        let size = mem::size_of::<E>();
+        self.dirty.mark(self.offset + index * size, count * size);
+    }
+}
+
+impl<R: Region> Region for Tracked<R> {
+    type Elem = R::Elem;
+
+    const LEN: usize = R::LEN;
+
+    #[inline(always)]
+    fn read(&self, index: usize) -> Self::Elem {
+        self.inner.read(index)
+    }
+
+    #[inline(always)]
+    fn read_all(&self) -> Vec<Self::Elem> {
+        self.inner.read_all()
+    }
+
+    #[inline(always)]
+    fn read_some(&self, offset: usize, buffer: &mut [Self::Elem]) {
+        self.inner.read_some(offset, buffer)
+    }
+
+    #[inline(always)]
+    fn write(&mut self, index: usize, value: Self::Elem) {
+        self.mark::<Self::Elem>(index, 1);
+        self.inner.write(index, value)
+    }
+
+    #[inline(always)]
+    fn write_all(&mut self, value: &[Self::Elem]) {
+        self.mark::<Self::Elem>(0, value.len());
+        self.inner.write_all(value)
+    }
+
+    #[inline(always)]
+    fn write_some(&mut self, index: usize, buffer: &[Self::Elem]) {
+        self.mark::<Self::Elem>(index, buffer.len());
+        self.inner.write_some(index, buffer)
+    }
+
+    #[inline(always)]
+    fn replace(&mut self, index: usize, value: Self::Elem) -> Self::Elem {
+        self.mark::<Self::Elem>(index, 1);
+        self.inner.replace(index, value)
+    }
+}
+
+impl<R: DynRegion> DynRegion for Tracked<R> {
+    const LEN: usize = R::LEN;
+
+    #[inline(always)]
+    fn read<E: Elem>(&self, address: usize) -> E {
+        self.inner.read(address)
+    }
+
+    #[inline(always)]
+    fn read_all<E: Elem>(&self, address: usize, values: &mut [E]) {
+        self.inner.read_all(address, values)
+    }
+
+    #[inline(always)]
+    fn write<E: Elem>(&mut self, address: usize, value: E) {
+        self.dirty.mark(self.offset + address, mem::size_of::<E>());
+        self.inner.write(address, value)
+    }
+
+    #[inline(always)]
+    fn write_all<E: Elem>(&mut self, address: usize, values: &[E]) {
+        self.dirty
+            .mark(self.offset + address, mem::size_of_val(values));
+        self.inner.write_all(address, values)
+    }
+}
+
+/// Merkle tree with cached hashes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MerkleTree {
+    /// Leaf covering `length` bytes starting at `offset` in the backend storage
+    Leaf {
+        hash: Hash,
+        offset: usize,
+        length: usize,
+    },
+
+    /// Inner node whose children cover the bytes in `span`
+    Node {
+        hash: Hash,
+        span: Range<usize>,
+        children: Vec<MerkleTree>,
+    },
+}
+
+impl MerkleTree {
+    /// Compute the Merkle tree over the entire state of the given backend.
+    pub fn build<B: Backend>(backend: &B) -> Result<Self, HashError> {
+        let placed = B::Layout::placed();
+        let shape = B::Layout::merkle_shape(placed.location());
+        Self::from_shape(&shape, backend)
+    }
+
+    /// Compute the Merkle tree of the given shape.
+    pub fn from_shape<B: Backend>(shape: &MerkleShape, backend: &B) -> Result<Self, HashError> {
+        match shape {
+            MerkleShape::Leaf { offset, length } => Ok(MerkleTree::Leaf {
+                hash: hash_leaf(backend, *offset, *length)?,
+                offset: *offset,
+                length: *length,
+            }),
+
+            MerkleShape::Node(children) => {
+                let children = children
+                    .iter()
+                    .map(|child| Self::from_shape(child, backend))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let hash = hash_children(&children)?;
+                Ok(MerkleTree::Node {
+                    hash,
+                    span: shape.span(),
+                    children,
+                })
+            }
+        }
+    }
+
+    /// Root hash of the tree
+    pub fn root_hash(&self) -> Hash {
+        match self {
+            MerkleTree::Leaf { hash, .. } | MerkleTree::Node { hash, .. } => *hash,
+        }
+    }
+
+    /// Bytes of the backend storage covered by this tree
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            MerkleTree::Leaf { offset, length, .. } => *offset..*offset + *length,
+            MerkleTree::Node { span, .. } => span.clone(),
+        }
+    }
+
+    /// Rehash the parts of the tree that cover dirty pages. Returns the number
+    /// of leaves that have been rehashed.
+    pub fn update<B: Backend>(
+        &mut self,
+        backend: &B,
+        dirty: &DirtyPages,
+    ) -> Result<usize, HashError> {
+        if !dirty.intersects(&self.span()) {
+            return Ok(0);
+        }
+
+        match self {
+            MerkleTree::Leaf {
+                hash,
+                offset,
+                length,
+            } => {
+                *hash = hash_leaf(backend, *offset, *length)?;
+                Ok(1)
+            }
+
+            MerkleTree::Node { hash, children, .. } => {
+                let mut rehashed = 0;
+                for child in children.iter_mut() {
+                    rehashed += child.update(backend, dirty)?;
+                }
+
+                *hash = hash_children(children)?;
+                Ok(rehashed)
+            }
+        }
+    }
+}
+
+/// Hash `length` bytes of the backend storage starting at `offset`.
+pub(super) fn hash_leaf<B: Backend>(
+    backend: &B,
+    offset: usize,
+    length: usize,
+) -> Result<Hash, HashError> {
+    let mut buffer = vec![0u8; 1 + length];
+    buffer[0] = LEAF_TAG;
+    backend.read(offset, &mut buffer[1..]);
+
+    // Large parts of the state, in particular the main memory, are usually
+    // zeroed. The hash of a zeroed leaf is computed only once.
+    static ZERO_LEAF: [u8; MERKLE_LEAF_SIZE] = [0; MERKLE_LEAF_SIZE];
+    static ZERO_LEAF_HASH: OnceLock<Hash> = OnceLock::new();
+
+    if buffer[1..] == ZERO_LEAF {
+        if let Some(hash) = ZERO_LEAF_HASH.get() {
+            return Ok(*hash);
+        }
+
+        let hash = Hash::blake2b_hash_bytes(&buffer)?;
+        return Ok(*ZERO_LEAF_HASH.get_or_init(|| hash));
+    }
+
+    Hash::blake2b_hash_bytes(&buffer)
+}
+
+/// Hash an inner node whose children have the given hashes.
+pub(super) fn hash_node(hashes: &[Hash]) -> Result<Hash, HashError> {
+    let mut buffer = Vec::with_capacity(1 + hashes.len() * DIGEST_SIZE);
+    buffer.push(NODE_TAG);
+    hashes
+        .iter()
+        .for_each(|hash| buffer.extend_from_slice(hash.as_ref()));
+    Hash::blake2b_hash_bytes(&buffer)
+}
+
+/// Hash an inner node with the given children.
+fn hash_children(children: &[MerkleTree]) -> Result<Hash, HashError> {
+    let hashes: Vec<Hash> = children.iter().map(MerkleTree::root_hash).collect();
+    hash_node(&hashes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        hash_leaf, hash_node, DirtyPages, Hash, MerkleShape, MerkleTree, DIGEST_SIZE,
+        MERKLE_LEAF_SIZE,
+    };
+    use crate::{
+        backend_test,
+        state_backend::{
+            memory_backend::InMemoryBackend, tests::randomise_backend, Array, Atom, Backend,
+            Layout, Region,
+        },
+    };
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_region_shape() {
+        assert_eq!(
+            MerkleShape::region(8, 16),
+            MerkleShape::Leaf {
+                offset: 8,
+                length: 16
+            }
+        );
+
+        let shape = MerkleShape::region(0, 5 * MERKLE_LEAF_SIZE + 1);
+        assert_eq!(shape.leaves(), 6);
+
+        // Leaves are never larger than the maximum leaf size and cover the
+        // region without gaps.
+        fn check(shape: &MerkleShape, next: &mut usize) {
+            match shape {
+                MerkleShape::Leaf { offset, length } => {
+                    assert_eq!(*offset, *next);
+                    assert!(*length <= MERKLE_LEAF_SIZE);
+                    *next += length;
+                }
+                MerkleShape::Node(children) => {
+                    assert_eq!(children.len(), 2);
+                    children.iter().for_each(|child| check(child, next))
+                }
+            }
+        }
+
+        let mut next = 0;
+        check(&shape, &mut next);
+        assert_eq!(next, 5 * MERKLE_LEAF_SIZE + 1);
+    }
+
+    #[test]
+    fn test_layout_shape() {
+        type L = (Atom<u8>, Atom<u64>, Array<u8, { 3 * MERKLE_LEAF_SIZE }>);
+        let placed = L::placed();
+
+        assert_eq!(
+            L::merkle_shape(placed.location()),
+            MerkleShape::Node(vec![
+                MerkleShape::Leaf {
+                    offset: 0,
+                    length: 1
+                },
+                MerkleShape::Leaf {
+                    offset: 8,
+                    length: 8
+                },
+                MerkleShape::region(16, 3 * MERKLE_LEAF_SIZE),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_dirty_pages() {
+        let dirty = DirtyPages::new();
+        assert!(dirty.is_empty());
+
+        dirty.mark(MERKLE_LEAF_SIZE - 1, 2);
+        assert_eq!(dirty.len(), 2);
+        assert!(dirty.intersects(&(0..1)));
+        assert!(dirty.intersects(&(2 * MERKLE_LEAF_SIZE - 1..2 * MERKLE_LEAF_SIZE)));
+        assert!(!dirty.intersects(&(2 * MERKLE_LEAF_SIZE..3 * MERKLE_LEAF_SIZE)));
+        assert!(!dirty.intersects(&(0..0)));
+
+        dirty.clear();
+        assert!(dirty.is_empty());
+    }
+
+    #[test]
+    fn test_domain_separation() {
+        type L = Array<u8, { 2 * DIGEST_SIZE }>;
+
+        // A leaf whose contents are the hashes of an inner node's children
+        // does not hash like that inner node.
+        let left = Hash::blake2b_hash_bytes(b"left").unwrap();
+        let right = Hash::blake2b_hash_bytes(b"right").unwrap();
+        let (mut backend, _) = InMemoryBackend::<L>::new();
+        backend.write(0, left.as_ref());
+        backend.write(DIGEST_SIZE, right.as_ref());
+
+        let leaf = hash_leaf(&backend, 0, 2 * DIGEST_SIZE).unwrap();
+        let node = hash_node(&[left, right]).unwrap();
+        assert_ne!(leaf, node);
+        assert_ne!(
+            node,
+            Hash::blake2b_hash_bytes(&[left.as_ref(), right.as_ref()].concat()).unwrap()
+        );
+    }
+
+    backend_test!(test_merkle_tree_deterministic, F, {
+        type L = (Atom<u64>, Array<u32, { 2 * MERKLE_LEAF_SIZE }>);
+
+        let mut backend = F::new::<L>();
+        randomise_backend(&mut backend);
+
+        let tree = MerkleTree::build(&backend).unwrap();
+        assert_eq!(MerkleTree::build(&backend).unwrap(), tree);
+        assert_eq!(tree.span(), 0..8 + 8 * MERKLE_LEAF_SIZE);
+
+        // Changing a single byte changes the root hash.
+        let mut byte = [0u8];
+        backend.read(5000, &mut byte);
+        byte[0] = byte[0].wrapping_add(1);
+        backend.write(5000, &byte);
+        assert_ne!(
+            MerkleTree::build(&backend).unwrap().root_hash(),
+            tree.root_hash()
+        );
+    });
+
+    #[test]
+    fn test_merkle_tree_incremental() {
+        type L = (Atom<u64>, Array<u64, { 4 * MERKLE_LEAF_SIZE }>);
+
+        proptest!(|(writes in proptest::collection::vec((0..4 * MERKLE_LEAF_SIZE, any::<u64>()), 0..16))| {
+            let (mut backend, placed) = InMemoryBackend::<L>::new();
+            randomise_backend(&mut backend);
+
+            let mut tree = MerkleTree::build(&backend).unwrap();
+            let dirty = DirtyPages::new();
+
+            {
+                let (mut atom, mut array) = backend.allocate_tracked(placed, dirty.clone());
+                atom.write(atom.read().wrapping_add(1));
+                for (index, value) in writes.iter() {
+                    array.write(*index, *value);
+                }
+            }
+
+            // Only leaves covering dirty pages have been rehashed. Leaves of
+            // the array are not page-aligned, hence a page may touch two leaves.
+            let rehashed = tree.update(&backend, &dirty).unwrap();
+            prop_assert!(rehashed <= 2 * dirty.len());
+            prop_assert_eq!(tree, MerkleTree::build(&backend).unwrap());
+        });
+    }
+}
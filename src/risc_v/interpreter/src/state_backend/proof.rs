@@ -0,0 +1,701 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Proofs of state transitions
+//!
+//! A [Proof] shows that running some deterministic procedure (usually a single
+//! step of the machine) moves the state from one root hash to another. It is
+//! produced by running the procedure against regions obtained from a
+//! [`RecordingManager`], which records every page of the backend storage that
+//! is read or written, as well as the original contents of the bytes that are
+//! overwritten.
+//!
+//! The proof consists of the Merkle tree over the initial state where every
+//! subtree that hasn't been accessed is pruned down to its hash. The leaves
+//! that remain carry their initial contents. These are recovered from the
+//! final state and the overwritten bytes, which avoids copying the state.
+//!
+//! A verifier reconstructs a partial state from the proof, checks it against
+//! the initial root hash, replays the procedure and makes sure it only accessed
+//! data that is part of the proof. The root hash of the resulting state must
+//! match the final root hash claimed by the proof.
+//!
+//! [`RecordingManager`]: super::memory_backend::RecordingManager
+
+use super::{
+    hash::{Hash, HashError, DIGEST_SIZE},
+    memory_backend::{InMemoryBackend, RecordingManager},
+    merkle::{self, DirtyPages, MerkleShape, MerkleTree},
+    AllocatedOf, Backend, DynRegion, Elem, Layout, Region,
+};
+use std::{cell::RefCell, collections::BTreeMap, mem, rc::Rc, slice};
+
+/// Magic bytes at the start of an encoded proof
+const PROOF_MAGIC: &[u8; 4] = b"RVPF";
+
+/// Version of the proof encoding
+const PROOF_VERSION: u8 = 0;
+
+/// Tag of a pruned subtree in the proof encoding
+const TAG_PRUNED: u8 = 0;
+
+/// Tag of a leaf in the proof encoding
+const TAG_LEAF: u8 = 1;
+
+/// Tag of an inner node in the proof encoding
+const TAG_NODE: u8 = 2;
+
+/// Errors that occur when decoding or verifying proofs
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum ProofError {
+    #[error("Failed to hash state: {0}")]
+    Hash(#[from] HashError),
+
+    #[error("Proof is malformed")]
+    Malformed,
+
+    #[error("Proof does not match the shape of the state")]
+    ShapeMismatch,
+
+    #[error("Initial state hash does not match, expected {expected} but got {actual}")]
+    InitialHashMismatch { expected: Hash, actual: Hash },
+
+    #[error("Final state hash does not match, expected {expected} but got {actual}")]
+    FinalHashMismatch { expected: Hash, actual: Hash },
+
+    #[error("Replaying the proof accessed state that is not part of the proof")]
+    MissingData,
+}
+
+/// Contents of the backend storage at some offset before they were written
+type Overwritten = (usize, Vec<u8>);
+
+/// Pages of the backend storage that have been accessed
+#[derive(Debug, Clone, Default)]
+pub struct AccessLog {
+    /// Pages that have been read or written
+    pub accessed: DirtyPages,
+
+    /// Pages that have been written
+    pub written: DirtyPages,
+
+    /// Previous contents of the bytes that have been written, in the order of
+    /// the writes
+    overwritten: Rc<RefCell<Vec<Overwritten>>>,
+}
+
+impl AccessLog {
+    /// Create an empty access log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline(always)]
+    fn read(&self, offset: usize, length: usize) {
+        self.accessed.mark(offset, length);
+    }
+
+    #[inline(always)]
+    fn write(&self, offset: usize, previous: &[u8]) {
+        self.accessed.mark(offset, previous.len());
+        self.written.mark(offset, previous.len());
+
+        if !previous.is_empty() {
+            self.overwritten
+                .borrow_mut()
+                .push((offset, previous.to_vec()));
+        }
+    }
+
+    /// Contents of the overwritten bytes before the first write to them
+    pub fn initial_contents(&self) -> BTreeMap<usize, u8> {
+        let mut initial = BTreeMap::new();
+        for (offset, previous) in self.overwritten.borrow().iter() {
+            for (i, byte) in previous.iter().enumerate() {
+                initial.entry(offset + i).or_insert(*byte);
+            }
+        }
+        initial
+    }
+}
+
+/// Regions whose stored representation can be viewed as bytes
+pub trait StoredBytes {
+    /// Bytes of the region as found in the backend storage
+    fn stored_bytes(&self) -> &[u8];
+}
+
+impl<E: Elem, const LEN: usize> StoredBytes for &mut [E; LEN] {
+    #[inline(always)]
+    fn stored_bytes(&self) -> &[u8] {
+        // SAFETY: The region lives in the backend storage, which consists of
+        // initialised bytes.
+        unsafe { slice::from_raw_parts(self.as_ptr() as *const u8, mem::size_of::<[E; LEN]>()) }
+    }
+}
+
+/// Region wrapper that records reads and writes in an [AccessLog]
+pub struct Recorded<R> {
+    inner: R,
+    offset: usize,
+    log: AccessLog,
+}
+
+impl<R> Recorded<R> {
+    /// Record accesses to `inner` which is located at `offset` in the backend storage.
+    pub fn new(inner: R, offset: usize, log: AccessLog) -> Self {
+        Self { inner, offset, log }
+    }
+
+    /// Byte range of `count` elements of type `E` starting at `index`
+    #[inline(always)]
+    fn bytes<E>(&self, index: usize, count: usize) -> (usize, usize) {
+        let size = mem::size_of::<E>();
+        (self.offset + index * size, count * size)
+    }
+}
+
+impl<R: StoredBytes> Recorded<R> {
+    /// Record a write of `length` bytes at `offset` in the backend storage
+    /// before it happens.
+    #[inline(always)]
+    fn record_write(&self, offset: usize, length: usize) {
+        let start = offset - self.offset;
+        let previous = &self.inner.stored_bytes()[start..start + length];
+        self.log.write(offset, previous);
+    }
+}
+
+impl<R: Region + StoredBytes> Region for Recorded<R> {
+    type Elem = R::Elem;
+
+    const LEN: usize = R::LEN;
+
+    #[inline(always)]
+    fn read(&self, index: usize) -> Self::Elem {
+        let (offset, length) = self.bytes::<Self::Elem>(index, 1);
+        self.log.read(offset, length);
+        self.inner.read(index)
+    }
+
+    #[inline(always)]
+    fn read_all(&self) -> Vec<Self::Elem> {
+        let (offset, length) = self.bytes::<Self::Elem>(0, R::LEN);
+        self.log.read(offset, length);
+        self.inner.read_all()
+    }
+
+    #[inline(always)]
+    fn read_some(&self, index: usize, buffer: &mut [Self::Elem]) {
+        let (offset, length) = self.bytes::<Self::Elem>(index, buffer.len());
+        self.log.read(offset, length);
+        self.inner.read_some(index, buffer)
+    }
+
+    #[inline(always)]
+    fn write(&mut self, index: usize, value: Self::Elem) {
+        let (offset, length) = self.bytes::<Self::Elem>(index, 1);
+        self.record_write(offset, length);
+        self.inner.write(index, value)
+    }
+
+    #[inline(always)]
+    fn write_all(&mut self, value: &[Self::Elem]) {
+        let (offset, length) = self.bytes::<Self::Elem>(0, value.len());
+        self.record_write(offset, length);
+        self.inner.write_all(value)
+    }
+
+    #[inline(always)]
+    fn write_some(&mut self, index: usize, buffer: &[Self::Elem]) {
+        let (offset, length) = self.bytes::<Self::Elem>(index, buffer.len());
+        self.record_write(offset, length);
+        self.inner.write_some(index, buffer)
+    }
+
+    #[inline(always)]
+    fn replace(&mut self, index: usize, value: Self::Elem) -> Self::Elem {
+        let (offset, length) = self.bytes::<Self::Elem>(index, 1);
+        self.record_write(offset, length);
+        self.inner.replace(index, value)
+    }
+}
+
+impl<R: DynRegion + StoredBytes> DynRegion for Recorded<R> {
+    const LEN: usize = R::LEN;
+
+    #[inline(always)]
+    fn read<E: Elem>(&self, address: usize) -> E {
+        self.log.read(self.offset + address, mem::size_of::<E>());
+        self.inner.read(address)
+    }
+
+    #[inline(always)]
+    fn read_all<E: Elem>(&self, address: usize, values: &mut [E]) {
+        self.log
+            .read(self.offset + address, mem::size_of_val(values));
+        self.inner.read_all(address, values)
+    }
+
+    #[inline(always)]
+    fn write<E: Elem>(&mut self, address: usize, value: E) {
+        self.record_write(self.offset + address, mem::size_of::<E>());
+        self.inner.write(address, value)
+    }
+
+    #[inline(always)]
+    fn write_all<E: Elem>(&mut self, address: usize, values: &[E]) {
+        self.record_write(self.offset + address, mem::size_of_val(values));
+        self.inner.write_all(address, values)
+    }
+}
+
+/// Merkle tree over the initial state, pruned down to the accessed leaves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofTree {
+    /// Subtree that hasn't been accessed
+    Pruned(Hash),
+
+    /// Leaf with its initial contents
+    Leaf(Vec<u8>),
+
+    /// Inner node
+    Node(Vec<ProofTree>),
+}
+
+impl ProofTree {
+    /// Prune the given Merkle tree down to the leaves that cover accessed
+    /// pages. The leaves are read from the backend, with the bytes found in
+    /// `initial` restored to their initial contents.
+    fn from_merkle_tree<B: Backend>(
+        tree: &MerkleTree,
+        backend: &B,
+        accessed: &DirtyPages,
+        initial: &BTreeMap<usize, u8>,
+    ) -> Self {
+        if !accessed.intersects(&tree.span()) {
+            return ProofTree::Pruned(tree.root_hash());
+        }
+
+        match tree {
+            MerkleTree::Leaf { offset, length, .. } => {
+                let mut data = vec![0u8; *length];
+                backend.read(*offset, &mut data);
+                for (address, byte) in initial.range(*offset..*offset + *length) {
+                    data[address - offset] = *byte;
+                }
+                ProofTree::Leaf(data)
+            }
+
+            MerkleTree::Node { children, .. } => ProofTree::Node(
+                children
+                    .iter()
+                    .map(|child| Self::from_merkle_tree(child, backend, accessed, initial))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Write the leaves of the proof into the backend after checking that the
+    /// proof matches the given shape.
+    fn populate<B: Backend>(&self, shape: &MerkleShape, backend: &mut B) -> Result<(), ProofError> {
+        match (self, shape) {
+            (ProofTree::Pruned(_), _) => Ok(()),
+
+            (ProofTree::Leaf(data), MerkleShape::Leaf { offset, length })
+                if data.len() == *length =>
+            {
+                backend.write(*offset, data);
+                Ok(())
+            }
+
+            (ProofTree::Node(children), MerkleShape::Node(shapes))
+                if children.len() == shapes.len() =>
+            {
+                children
+                    .iter()
+                    .zip(shapes)
+                    .try_for_each(|(child, shape)| child.populate(shape, backend))
+            }
+
+            _ => Err(ProofError::ShapeMismatch),
+        }
+    }
+
+    /// Compute the root hash using the leaf contents found in the backend.
+    fn root_hash<B: Backend>(&self, shape: &MerkleShape, backend: &B) -> Result<Hash, ProofError> {
+        match (self, shape) {
+            (ProofTree::Pruned(hash), _) => Ok(*hash),
+
+            (ProofTree::Leaf(_), MerkleShape::Leaf { offset, length }) => {
+                Ok(merkle::hash_leaf(backend, *offset, *length)?)
+            }
+
+            (ProofTree::Node(children), MerkleShape::Node(shapes))
+                if children.len() == shapes.len() =>
+            {
+                let hashes = children
+                    .iter()
+                    .zip(shapes)
+                    .map(|(child, shape)| child.root_hash(shape, backend))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(merkle::hash_node(&hashes)?)
+            }
+
+            _ => Err(ProofError::ShapeMismatch),
+        }
+    }
+
+    /// Check that no pruned subtree covers an accessed page.
+    fn covers(&self, shape: &MerkleShape, accessed: &DirtyPages) -> bool {
+        match (self, shape) {
+            (ProofTree::Pruned(_), shape) => !accessed.intersects(&shape.span()),
+            (ProofTree::Node(children), MerkleShape::Node(shapes)) => children
+                .iter()
+                .zip(shapes)
+                .all(|(child, shape)| child.covers(shape, accessed)),
+            _ => true,
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            ProofTree::Pruned(hash) => {
+                out.push(TAG_PRUNED);
+                out.extend_from_slice(hash.as_ref());
+            }
+
+            ProofTree::Leaf(data) => {
+                out.push(TAG_LEAF);
+                out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                out.extend_from_slice(data);
+            }
+
+            ProofTree::Node(children) => {
+                out.push(TAG_NODE);
+                out.extend_from_slice(&(children.len() as u32).to_le_bytes());
+                children.iter().for_each(|child| child.encode(out));
+            }
+        }
+    }
+
+    /// Decode a proof tree of the given shape. Following the shape bounds
+    /// the depth of the decoded tree by the height of the Merkle tree.
+    fn decode(input: &mut &[u8], shape: &MerkleShape) -> Result<Self, ProofError> {
+        match (take_u8(input)?, shape) {
+            (TAG_PRUNED, _) => Ok(ProofTree::Pruned(take_hash(input)?)),
+
+            (TAG_LEAF, MerkleShape::Leaf { length, .. }) => {
+                if take_u32(input)? as usize != *length {
+                    return Err(ProofError::ShapeMismatch);
+                }
+                Ok(ProofTree::Leaf(take(input, *length)?.to_vec()))
+            }
+
+            (TAG_NODE, MerkleShape::Node(shapes)) => {
+                if take_u32(input)? as usize != shapes.len() {
+                    return Err(ProofError::ShapeMismatch);
+                }
+                let children = shapes
+                    .iter()
+                    .map(|shape| Self::decode(input, shape))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(ProofTree::Node(children))
+            }
+
+            (TAG_LEAF | TAG_NODE, _) => Err(ProofError::ShapeMismatch),
+
+            _ => Err(ProofError::Malformed),
+        }
+    }
+}
+
+/// Proof that running a procedure moves the state from one root hash to another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    initial_state_hash: Hash,
+    final_state_hash: Hash,
+    tree: ProofTree,
+}
+
+impl Proof {
+    /// Run `f` against the state in `backend` and produce a proof for the
+    /// resulting state transition. `f` must be deterministic.
+    ///
+    /// The Merkle tree over the state is built from scratch. Use
+    /// [`Proof::produce_with_tree`] to reuse an existing one.
+    pub fn produce<L, F>(backend: &mut InMemoryBackend<L>, f: F) -> Result<Self, ProofError>
+    where
+        L: Layout,
+        F: FnOnce(AllocatedOf<L, RecordingManager<'_>>),
+    {
+        let mut tree = MerkleTree::build(backend)?;
+        Self::produce_with_tree(backend, &mut tree, f)
+    }
+
+    /// Like [`Proof::produce`], given the up-to-date Merkle `tree` over the
+    /// state in `backend`. Afterwards, `tree` is the Merkle tree over the
+    /// resulting state.
+    pub fn produce_with_tree<L, F>(
+        backend: &mut InMemoryBackend<L>,
+        tree: &mut MerkleTree,
+        f: F,
+    ) -> Result<Self, ProofError>
+    where
+        L: Layout,
+        F: FnOnce(AllocatedOf<L, RecordingManager<'_>>),
+    {
+        let initial_state_hash = tree.root_hash();
+
+        let log = AccessLog::new();
+        f(backend.allocate_recorded(L::placed().into_location(), log.clone()));
+
+        let initial = log.initial_contents();
+        let proof_tree = ProofTree::from_merkle_tree(tree, backend, &log.accessed, &initial);
+        tree.update(backend, &log.written)?;
+
+        Ok(Self {
+            initial_state_hash,
+            final_state_hash: tree.root_hash(),
+            tree: proof_tree,
+        })
+    }
+
+    /// Replay `f` against the partial state contained in the proof and check
+    /// that it arrives at the final state hash.
+    pub fn verify<L, F>(&self, f: F) -> Result<(), ProofError>
+    where
+        L: Layout,
+        F: FnOnce(AllocatedOf<L, RecordingManager<'_>>),
+    {
+        let (mut backend, placed) = InMemoryBackend::<L>::new();
+        let shape = L::merkle_shape(&placed);
+
+        self.tree.populate(&shape, &mut backend)?;
+
+        let initial_state_hash = self.tree.root_hash(&shape, &backend)?;
+        if initial_state_hash != self.initial_state_hash {
+            return Err(ProofError::InitialHashMismatch {
+                expected: self.initial_state_hash,
+                actual: initial_state_hash,
+            });
+        }
+
+        let log = AccessLog::new();
+        f(backend.allocate_recorded(placed, log.clone()));
+
+        if !self.tree.covers(&shape, &log.accessed) {
+            return Err(ProofError::MissingData);
+        }
+
+        let final_state_hash = self.tree.root_hash(&shape, &backend)?;
+        if final_state_hash != self.final_state_hash {
+            return Err(ProofError::FinalHashMismatch {
+                expected: self.final_state_hash,
+                actual: final_state_hash,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Root hash of the state before the transition
+    pub fn initial_state_hash(&self) -> Hash {
+        self.initial_state_hash
+    }
+
+    /// Root hash of the state after the transition
+    pub fn final_state_hash(&self) -> Hash {
+        self.final_state_hash
+    }
+
+    /// Merkle tree contained in the proof
+    pub fn tree(&self) -> &ProofTree {
+        &self.tree
+    }
+
+    /// Serialise the proof.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(PROOF_MAGIC);
+        out.push(PROOF_VERSION);
+        out.extend_from_slice(self.initial_state_hash.as_ref());
+        out.extend_from_slice(self.final_state_hash.as_ref());
+        self.tree.encode(&mut out);
+        out
+    }
+
+    /// Deserialise a proof for a state of layout `L`.
+    pub fn decode<L: Layout>(mut input: &[u8]) -> Result<Self, ProofError> {
+        let input = &mut input;
+
+        if take(input, PROOF_MAGIC.len())? != PROOF_MAGIC || take_u8(input)? != PROOF_VERSION {
+            return Err(ProofError::Malformed);
+        }
+
+        let initial_state_hash = take_hash(input)?;
+        let final_state_hash = take_hash(input)?;
+        let placed = L::placed();
+        let tree = ProofTree::decode(input, &L::merkle_shape(placed.location()))?;
+
+        if !input.is_empty() {
+            return Err(ProofError::Malformed);
+        }
+
+        Ok(Self {
+            initial_state_hash,
+            final_state_hash,
+            tree,
+        })
+    }
+}
+
+/// Take `length` bytes from the input.
+fn take<'a>(input: &mut &'a [u8], length: usize) -> Result<&'a [u8], ProofError> {
+    if input.len() < length {
+        return Err(ProofError::Malformed);
+    }
+
+    let (bytes, rest) = input.split_at(length);
+    *input = rest;
+    Ok(bytes)
+}
+
+fn take_u8(input: &mut &[u8]) -> Result<u8, ProofError> {
+    Ok(take(input, 1)?[0])
+}
+
+fn take_u32(input: &mut &[u8]) -> Result<u32, ProofError> {
+    let bytes = take(input, mem::size_of::<u32>())?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn take_hash(input: &mut &[u8]) -> Result<Hash, ProofError> {
+    Ok(Hash::try_from(take(input, DIGEST_SIZE)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Proof, ProofError, ProofTree, TAG_NODE};
+    use crate::state_backend::{
+        memory_backend::InMemoryBackend,
+        merkle::{MerkleTree, MERKLE_LEAF_SIZE},
+        tests::randomise_backend,
+        AllocatedOf, Array, Atom, Backend, Manager, Region,
+    };
+
+    type L = (Atom<u64>, Array<u64, { 4 * MERKLE_LEAF_SIZE }>);
+
+    /// Procedure which reads the atom to find an index into the array and
+    /// increments the value it finds there
+    fn step<M: Manager>((mut counter, mut array): AllocatedOf<L, M>) {
+        let index = counter.read() as usize % (4 * MERKLE_LEAF_SIZE);
+        array.write(index, array.read(index).wrapping_add(1));
+        counter.write(counter.read().wrapping_add(1));
+    }
+
+    #[test]
+    fn test_proof_roundtrip() {
+        for _ in 0..16 {
+            let (mut backend, _) = InMemoryBackend::<L>::new();
+            randomise_backend(&mut backend);
+
+            let proof = Proof::produce(&mut backend, |space| step(space)).unwrap();
+            assert_ne!(proof.initial_state_hash(), proof.final_state_hash());
+
+            // Only a small part of the state is contained in the proof.
+            let encoded = proof.encode();
+            assert!(encoded.len() < 5 * MERKLE_LEAF_SIZE);
+
+            let decoded = Proof::decode::<L>(&encoded).unwrap();
+            assert_eq!(decoded, proof);
+            assert_eq!(decoded.verify::<L, _>(|space| step(space)), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_proof_chain() {
+        let (mut backend, _) = InMemoryBackend::<L>::new();
+        randomise_backend(&mut backend);
+        let mut tree = MerkleTree::build(&backend).unwrap();
+
+        /// Procedure which overwrites the same locations multiple times
+        fn steps<M: Manager>((mut counter, mut array): AllocatedOf<L, M>) {
+            for _ in 0..3 {
+                array.write(0, array.read(0).wrapping_add(counter.read()));
+                counter.write(counter.read().wrapping_add(1));
+            }
+        }
+
+        // Proofs produced with a cached Merkle tree chain up, and the tree
+        // follows the state.
+        for _ in 0..4 {
+            let initial_state_hash = tree.root_hash();
+            let proof =
+                Proof::produce_with_tree(&mut backend, &mut tree, |space| steps(space)).unwrap();
+            assert_eq!(proof.initial_state_hash(), initial_state_hash);
+            assert_eq!(proof.final_state_hash(), tree.root_hash());
+            assert_eq!(tree, MerkleTree::build(&backend).unwrap());
+            assert_eq!(proof.verify::<L, _>(|space| steps(space)), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_proof_rejected() {
+        let (mut backend, placed) = InMemoryBackend::<L>::new();
+        randomise_backend(&mut backend);
+        backend.allocate(placed).0.write(0);
+        let proof = Proof::produce(&mut backend, |space| step(space)).unwrap();
+
+        // A different procedure arrives at a different state.
+        assert!(matches!(
+            proof.verify::<L, _>(|(mut counter, _)| counter.write(counter.read().wrapping_add(2))),
+            Err(ProofError::FinalHashMismatch { .. })
+        ));
+
+        // Accessing state outside of the proof is not allowed.
+        assert_eq!(
+            proof.verify::<L, _>(|(counter, array)| {
+                counter.read();
+                array.read(2 * MERKLE_LEAF_SIZE);
+            }),
+            Err(ProofError::MissingData)
+        );
+
+        // Tampering with the leaves is detected.
+        let mut tampered = proof.clone();
+        fn tamper(tree: &mut ProofTree) -> bool {
+            match tree {
+                ProofTree::Leaf(data) => {
+                    data[0] ^= 1;
+                    true
+                }
+                ProofTree::Node(children) => children.iter_mut().any(tamper),
+                ProofTree::Pruned(_) => false,
+            }
+        }
+        assert!(tamper(&mut tampered.tree));
+        assert!(matches!(
+            tampered.verify::<L, _>(|space| step(space)),
+            Err(ProofError::InitialHashMismatch { .. })
+        ));
+
+        // Malformed encodings are rejected.
+        let encoded = proof.encode();
+        assert_eq!(
+            Proof::decode::<L>(&encoded[..encoded.len() - 1]),
+            Err(ProofError::Malformed)
+        );
+        assert_eq!(Proof::decode::<L>(b"RVPF\x01"), Err(ProofError::Malformed));
+
+        // Trees which are deeper than the Merkle tree are rejected before
+        // decoding them any further.
+        let mut deep = encoded[..4 + 1 + 2 * 32].to_vec();
+        for _ in 0..100_000 {
+            deep.push(TAG_NODE);
+            deep.extend_from_slice(&1u32.to_le_bytes());
+        }
+        assert_eq!(Proof::decode::<L>(&deep), Err(ProofError::ShapeMismatch));
+    }
+}
@@ -0,0 +1,183 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Snapshots of in-memory backends
+//!
+//! A snapshot starts with a header made of the magic bytes `RVSN`, a version
+//! byte and the size of the state as a little-endian `u64`. The header is
+//! followed by the pages of the state which are not entirely zero, each
+//! prefixed with its index as a little-endian `u32`.
+
+use super::{memory_backend::InMemoryBackend, merkle::MERKLE_LEAF_SIZE, Layout};
+
+/// Magic bytes at the start of every snapshot
+const SNAPSHOT_MAGIC: [u8; 4] = *b"RVSN";
+
+/// Version of the snapshot encoding
+const SNAPSHOT_VERSION: u8 = 0;
+
+/// Size of the snapshot header: magic, version and state size
+const SNAPSHOT_HEADER_SIZE: usize = SNAPSHOT_MAGIC.len() + 1 + 8;
+
+/// Size of the pages the state is split into
+pub const SNAPSHOT_PAGE_SIZE: usize = MERKLE_LEAF_SIZE;
+
+/// Failed to import a snapshot
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("Snapshot does not start with the expected magic bytes")]
+    BadMagic,
+
+    #[error("Unsupported snapshot version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Snapshot holds a state of {actual} bytes instead of {expected}")]
+    SizeMismatch { expected: usize, actual: u64 },
+
+    #[error("Snapshot page {0} is out of bounds")]
+    PageOutOfBounds(u32),
+
+    #[error("Snapshot is truncated")]
+    Truncated,
+}
+
+impl<L: Layout> InMemoryBackend<L> {
+    /// Encode the entire state as a snapshot. Pages which are entirely zero
+    /// are omitted.
+    pub fn export_snapshot(&self) -> Vec<u8> {
+        let state = self.borrow();
+
+        let mut snapshot = Vec::with_capacity(SNAPSHOT_HEADER_SIZE);
+        snapshot.extend_from_slice(&SNAPSHOT_MAGIC);
+        snapshot.push(SNAPSHOT_VERSION);
+        snapshot.extend_from_slice(&(state.len() as u64).to_le_bytes());
+
+        static ZERO_PAGE: [u8; SNAPSHOT_PAGE_SIZE] = [0; SNAPSHOT_PAGE_SIZE];
+
+        for (index, page) in state.chunks(SNAPSHOT_PAGE_SIZE).enumerate() {
+            if page != &ZERO_PAGE[..page.len()] {
+                snapshot.extend_from_slice(&(index as u32).to_le_bytes());
+                snapshot.extend_from_slice(page);
+            }
+        }
+
+        snapshot
+    }
+
+    /// Restore a state from a snapshot produced by
+    /// [`InMemoryBackend::export_snapshot`].
+    pub fn import_snapshot(snapshot: &[u8]) -> Result<Self, SnapshotError> {
+        let (header, mut pages) = split_snapshot(snapshot, SNAPSHOT_HEADER_SIZE)?;
+
+        let (magic, header) = header.split_at(SNAPSHOT_MAGIC.len());
+        if magic != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        if header[0] != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(header[0]));
+        }
+
+        let (mut backend, _) = Self::new();
+        let state = backend.borrow_mut();
+
+        let size = u64::from_le_bytes(header[1..].try_into().unwrap());
+        if size != state.len() as u64 {
+            return Err(SnapshotError::SizeMismatch {
+                expected: state.len(),
+                actual: size,
+            });
+        }
+
+        while !pages.is_empty() {
+            let (index, rest) = split_snapshot(pages, 4)?;
+            let index = u32::from_le_bytes(index.try_into().unwrap());
+
+            let offset = (index as usize).saturating_mul(SNAPSHOT_PAGE_SIZE);
+            if offset >= state.len() {
+                return Err(SnapshotError::PageOutOfBounds(index));
+            }
+
+            let length = SNAPSHOT_PAGE_SIZE.min(state.len() - offset);
+            let (page, rest) = split_snapshot(rest, length)?;
+            state[offset..offset + length].copy_from_slice(page);
+
+            pages = rest;
+        }
+
+        Ok(backend)
+    }
+}
+
+/// Split the snapshot bytes at `mid`, failing if there aren't enough bytes.
+fn split_snapshot(bytes: &[u8], mid: usize) -> Result<(&[u8], &[u8]), SnapshotError> {
+    if bytes.len() < mid {
+        return Err(SnapshotError::Truncated);
+    }
+
+    Ok(bytes.split_at(mid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SnapshotError, SNAPSHOT_PAGE_SIZE};
+    use crate::state_backend::{
+        memory_backend::InMemoryBackend, tests::randomise_backend, Array, Atom, Backend,
+    };
+
+    type L = (Atom<u64>, Array<u8, { 3 * SNAPSHOT_PAGE_SIZE }>);
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let (mut backend, _) = InMemoryBackend::<L>::new();
+        randomise_backend(&mut backend);
+
+        let snapshot = backend.export_snapshot();
+        let restored = InMemoryBackend::<L>::import_snapshot(&snapshot).unwrap();
+        assert_eq!(backend.borrow(), restored.borrow());
+    }
+
+    #[test]
+    fn test_snapshot_sparse() {
+        let (mut backend, _) = InMemoryBackend::<L>::new();
+        backend.write(SNAPSHOT_PAGE_SIZE + 42, &[1, 2, 3]);
+
+        // Only the page holding the non-zero bytes is encoded.
+        let snapshot = backend.export_snapshot();
+        assert_eq!(snapshot.len(), 13 + 4 + SNAPSHOT_PAGE_SIZE);
+
+        let restored = InMemoryBackend::<L>::import_snapshot(&snapshot).unwrap();
+        assert_eq!(backend.borrow(), restored.borrow());
+    }
+
+    #[test]
+    fn test_snapshot_rejected() {
+        type Other = (Atom<u64>, Array<u8, 16>);
+        let (backend, _) = InMemoryBackend::<L>::new();
+        let snapshot = backend.export_snapshot();
+
+        assert_eq!(
+            InMemoryBackend::<Other>::import_snapshot(&snapshot).err(),
+            Some(SnapshotError::SizeMismatch {
+                expected: 24,
+                actual: backend.borrow().len() as u64
+            })
+        );
+
+        let mut bad_page = snapshot.clone();
+        bad_page.extend_from_slice(&100u32.to_le_bytes());
+        assert_eq!(
+            InMemoryBackend::<L>::import_snapshot(&bad_page).err(),
+            Some(SnapshotError::PageOutOfBounds(100))
+        );
+
+        let mut truncated = snapshot;
+        truncated.extend_from_slice(&1u32.to_le_bytes());
+        truncated.extend_from_slice(&[0; 10]);
+        assert_eq!(
+            InMemoryBackend::<L>::import_snapshot(&truncated).err(),
+            Some(SnapshotError::Truncated)
+        );
+    }
+}
@@ -2,7 +2,11 @@
 //
 // SPDX-License-Identifier: MIT
 
-use risc_v_interpreter::{machine_state::mode::Mode, Interpreter, InterpreterResult::*};
+use risc_v_interpreter::{
+    machine_state::{extensions::Extensions, mode::Mode},
+    Interpreter,
+    InterpreterResult::*,
+};
 use std::fs;
 
 const TESTS_DIR: &str = "../../../tezt/tests/riscv-tests/generated";
@@ -11,7 +15,7 @@ const MAX_STEPS: usize = 1_000_000;
 fn interpret_test(contents: &[u8], mode: Mode) {
     let mut backend = Interpreter::create_backend();
     let mut interpreter =
-        Interpreter::new(&mut backend, contents, None, mode).expect("Boot failed");
+        Interpreter::new(&mut backend, contents, None, mode, Extensions::ALL).expect("Boot failed");
     match interpreter.run(MAX_STEPS) {
         Exit { code: 0, .. } => (),
         Exit { code, .. } => panic!("Failed at test case {}", code >> 1),
@@ -1,11 +1,19 @@
 use derive_more::{Error, From};
 use goblin::{
     elf::header::{ET_DYN, ET_EXEC},
-    elf::program_header::{ProgramHeader, PT_LOAD},
+    elf::program_header::{ProgramHeader, PT_LOAD, PT_TLS},
+    elf::reloc::{
+        R_RISCV_64, R_RISCV_JUMP_SLOT, R_RISCV_NONE, R_RISCV_RELATIVE, R_RISCV_TLS_TPREL64,
+    },
+    elf::sym::STB_WEAK,
     elf::Elf,
 };
 use std::io::{Cursor, Seek, SeekFrom, Write};
 
+mod symbols;
+
+pub use symbols::{Symbol, SymbolKind, SymbolTable};
+
 #[derive(Debug, From, Error, derive_more::Display)]
 pub enum Error {
     #[display(fmt = "At address {:#x}: {:?}", addr, "msg.clone()")]
@@ -14,6 +22,18 @@ pub enum Error {
         addr: u64,
     },
     Goblin(goblin::error::Error),
+    #[display(fmt = "Unsupported relocation type {}", r_type)]
+    #[from(ignore)]
+    UnsupportedRelocation {
+        r_type: u32,
+    },
+    #[display(fmt = "Relocation refers to undefined symbol {}", name)]
+    #[from(ignore)]
+    UndefinedSymbol {
+        name: String,
+    },
+    #[display(fmt = "Thread-local relocation without a TLS segment")]
+    MissingTls,
 }
 
 /// [LoadResult] is the outcome of loading an ELF file
@@ -22,6 +42,10 @@ pub struct LoadResult {
     pub entry: u64,
     /// index of the last written byte in memory after loading the ELF
     pub last_written: u64,
+    /// initial value of the thread pointer (`tp`), if the ELF file has a TLS segment
+    pub thread_pointer: Option<u64>,
+    /// symbols of the ELF file, at the addresses they were loaded to
+    pub symbols: SymbolTable,
 }
 
 /// [Memory] is an interface to the linear array of bytes of the RISC-V virtual machine
@@ -61,15 +85,105 @@ pub fn load_elf_nonreloc<'a>(
             mem.set_zero(first_zero, num_zeroes)?;
         }
 
-        last_written = segment.p_paddr + segment.p_memsz;
+        last_written = last_written.max(segment.p_paddr + segment.p_memsz);
     }
 
+    let tls = load_tls(mem, elf, contents, last_written)?;
+    let thread_pointer = tls.map(|(tls_block, _)| tls_block);
+
     Ok(LoadResult {
         entry: elf.entry,
-        last_written,
+        last_written: tls.map_or(last_written, |(_, end)| end),
+        thread_pointer,
+        symbols: SymbolTable::from_elf(elf, 0, thread_pointer),
     })
 }
 
+/// Initialise the thread-local storage block described by the `PT_TLS`
+/// program header, if there is one. The block is placed at the first
+/// suitably aligned address from `free`. Returns the address of the block,
+/// which is the initial value of the thread pointer, and the end of the block.
+fn load_tls<'a>(
+    mem: &mut impl Memory,
+    elf: &Elf<'a>,
+    contents: &'a [u8],
+    free: u64,
+) -> Result<Option<(u64, u64)>, Error> {
+    let Some(segment) = elf
+        .program_headers
+        .iter()
+        .find(|header| header.p_type == PT_TLS)
+    else {
+        return Ok(None);
+    };
+
+    // RISC-V uses TLS variant I without a TCB: the thread pointer points
+    // directly at the TLS block, which starts with the initialisation image.
+    let align = segment.p_align.max(1);
+    let block = (free + align - 1) / align * align;
+
+    mem.write_bytes(block, &contents[segment.file_range()])?;
+    if segment.p_memsz > segment.p_filesz {
+        mem.set_zero(block + segment.p_filesz, segment.p_memsz - segment.p_filesz)?;
+    }
+
+    Ok(Some((block, block + segment.p_memsz)))
+}
+
+/// Value of the dynamic symbol `index` when the ELF file is loaded at `start`.
+/// Absolute symbols keep their value and undefined weak symbols resolve to 0.
+fn symbol_value(elf: &Elf, start: u64, index: usize) -> Result<u64, Error> {
+    let sym = elf.dynsyms.get(index).ok_or(Error::UndefinedSymbol {
+        name: format!("#{index}"),
+    })?;
+
+    if symbols::is_defined(&sym) {
+        Ok(symbols::symbol_address(&sym, start))
+    } else if sym.st_bind() == STB_WEAK {
+        Ok(0)
+    } else {
+        let name = elf.dynstrtab.get_at(sym.st_name).unwrap_or_default();
+        Err(Error::UndefinedSymbol {
+            name: name.to_string(),
+        })
+    }
+}
+
+/// Apply the dynamic relocations of an ELF file loaded at `start`. The
+/// thread pointer is required to resolve thread-local relocations.
+fn apply_relocations(
+    mem: &mut impl Memory,
+    start: u64,
+    elf: &Elf,
+    thread_pointer: Option<u64>,
+) -> Result<(), Error> {
+    for reloc in elf.dynrelas.iter().chain(elf.pltrelocs.iter()) {
+        let addend = reloc.r_addend.unwrap_or_default();
+        let value = match reloc.r_type {
+            R_RISCV_NONE => continue,
+            // B + A
+            R_RISCV_RELATIVE => start.wrapping_add_signed(addend),
+            // S + A
+            R_RISCV_64 => symbol_value(elf, start, reloc.r_sym)?.wrapping_add_signed(addend),
+            // S
+            R_RISCV_JUMP_SLOT => symbol_value(elf, start, reloc.r_sym)?,
+            // Offset of the variable from the thread pointer
+            R_RISCV_TLS_TPREL64 => {
+                if thread_pointer.is_none() {
+                    return Err(Error::MissingTls);
+                }
+                let offset = elf.dynsyms.get(reloc.r_sym).map_or(0, |sym| sym.st_value);
+                offset.wrapping_add_signed(addend)
+            }
+            r_type => return Err(Error::UnsupportedRelocation { r_type }),
+        };
+
+        mem.write_bytes(start + reloc.r_offset, &value.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
 /// Required memory size for loading.
 pub fn mem_size(phs: &[ProgramHeader]) -> usize {
     let phs = phs.iter().filter(|ph| ph.p_type == PT_LOAD);
@@ -107,29 +221,19 @@ pub fn load_elf_reloc<'a>(
             mem.set_zero(first_zero, num_zeroes)?;
         }
 
-        last_written = start + segment.p_vaddr + segment.p_memsz;
-    }
-
-    for r in elf.dynrelas.iter() {
-        let goblin::elf::reloc::Reloc {
-            r_offset,
-            r_addend,
-            r_sym: _,
-            r_type,
-        } = r;
-        match r_type {
-            goblin::elf64::reloc::R_RISCV_RELATIVE => {
-                let relocated = (start as i64 + r_addend.unwrap_or_default()).to_ne_bytes();
-                let buf = &relocated[..];
-                mem.write_bytes(start + r_offset, buf)?;
-            }
-            t => unimplemented!("Unsupported relocation directive {t}"),
-        }
+        last_written = last_written.max(start + segment.p_vaddr + segment.p_memsz);
     }
 
+    let tls = load_tls(mem, elf, contents, last_written)?;
+    let thread_pointer = tls.map(|(tls_block, _)| tls_block);
+
+    apply_relocations(mem, start, elf, thread_pointer)?;
+
     Ok(LoadResult {
         entry: elf.header.e_entry + start,
-        last_written,
+        last_written: tls.map_or(last_written, |(_, end)| end),
+        thread_pointer,
+        symbols: SymbolTable::from_elf(elf, start, thread_pointer),
     })
 }
 
@@ -185,3 +289,335 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{load_elf, Error, SymbolKind};
+    use goblin::elf::{
+        dynamic::{
+            DT_JMPREL, DT_NULL, DT_PLTREL, DT_PLTRELSZ, DT_RELA, DT_RELAENT, DT_RELASZ, DT_STRSZ,
+            DT_STRTAB, DT_SYMENT, DT_SYMTAB,
+        },
+        header::{EM_RISCV, ET_DYN, ET_EXEC},
+        program_header::{PT_DYNAMIC, PT_LOAD, PT_TLS},
+        reloc::{R_RISCV_64, R_RISCV_JUMP_SLOT, R_RISCV_RELATIVE, R_RISCV_TLS_TPREL64},
+        section_header::{SHN_ABS, SHN_UNDEF, SHT_STRTAB, SHT_SYMTAB},
+        sym::{STB_GLOBAL, STB_WEAK, STT_FUNC, STT_NOTYPE, STT_OBJECT, STT_TLS},
+    };
+    use std::io::Cursor;
+
+    /// Size of the zeroed area of a fixture which relocations are applied to
+    const DATA_SIZE: usize = 64;
+
+    /// Section index of defined symbols
+    const SHN_TEXT: u16 = 1;
+
+    struct FixtureSymbol {
+        name: &'static str,
+        bind: u8,
+        typ: u8,
+        shndx: u16,
+        value: u64,
+    }
+
+    fn symbol(name: &'static str, bind: u8, typ: u8, shndx: u32, value: u64) -> FixtureSymbol {
+        FixtureSymbol {
+            name,
+            bind,
+            typ,
+            shndx: shndx as u16,
+            value,
+        }
+    }
+
+    /// Relocation: offset, symbol index, type and addend
+    type Relocation = (u64, usize, u32, i64);
+
+    /// Minimal RISC-V ELF file with a single loadable segment at address 0,
+    /// dynamic relocations and a symbol table serving both as static and
+    /// dynamic one
+    struct Fixture {
+        e_type: u16,
+        symbols: Vec<FixtureSymbol>,
+        relocations: Vec<Relocation>,
+        plt_relocations: Vec<Relocation>,
+        /// Initialisation image, size in memory and alignment of the TLS block
+        tls: Option<(Vec<u8>, u64, u64)>,
+    }
+
+    fn align(bytes: &mut Vec<u8>) -> u64 {
+        bytes.resize((bytes.len() + 15) & !15, 0);
+        bytes.len() as u64
+    }
+
+    fn push(bytes: &mut Vec<u8>, values: &[u64]) {
+        for value in values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    impl Fixture {
+        fn new(e_type: u16) -> Self {
+            Self {
+                e_type,
+                symbols: Vec::new(),
+                relocations: Vec::new(),
+                plt_relocations: Vec::new(),
+                tls: None,
+            }
+        }
+
+        /// Encode the ELF file. Returns its contents and the offset of the
+        /// zeroed data area.
+        fn build(&self) -> (Vec<u8>, u64) {
+            let phnum = if self.tls.is_some() { 3 } else { 2 };
+            let mut bytes = vec![0; 64 + 56 * phnum];
+
+            // The data area comes first so that its offset doesn't depend on
+            // the relocations.
+            let data = align(&mut bytes);
+            bytes.resize(bytes.len() + DATA_SIZE, 0);
+
+            let symtab = align(&mut bytes);
+            let mut strtab = vec![0];
+            bytes.extend_from_slice(&[0; 24]);
+            for sym in &self.symbols {
+                bytes.extend_from_slice(&(strtab.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(&[sym.bind << 4 | sym.typ, 0]);
+                bytes.extend_from_slice(&sym.shndx.to_le_bytes());
+                push(&mut bytes, &[sym.value, 8]);
+                strtab.extend_from_slice(sym.name.as_bytes());
+                strtab.push(0);
+            }
+            let symtab_size = bytes.len() as u64 - symtab;
+            let strtab_offset = align(&mut bytes);
+            bytes.extend_from_slice(&strtab);
+
+            let mut relas = |relocations: &[Relocation]| {
+                let offset = align(&mut bytes);
+                for &(r_offset, sym, r_type, addend) in relocations {
+                    push(
+                        &mut bytes,
+                        &[r_offset, (sym as u64) << 32 | r_type as u64, addend as u64],
+                    );
+                }
+                offset
+            };
+            let rela = relas(&self.relocations);
+            let jmprel = relas(&self.plt_relocations);
+
+            let dynamic = align(&mut bytes);
+            push(
+                &mut bytes,
+                &[
+                    DT_SYMTAB,
+                    symtab,
+                    DT_SYMENT,
+                    24,
+                    DT_STRTAB,
+                    strtab_offset,
+                    DT_STRSZ,
+                    strtab.len() as u64,
+                    DT_RELA,
+                    rela,
+                    DT_RELASZ,
+                    24 * self.relocations.len() as u64,
+                    DT_RELAENT,
+                    24,
+                    DT_JMPREL,
+                    jmprel,
+                    DT_PLTRELSZ,
+                    24 * self.plt_relocations.len() as u64,
+                    DT_PLTREL,
+                    DT_RELA,
+                    DT_NULL,
+                    0,
+                ],
+            );
+            let dynamic_size = bytes.len() as u64 - dynamic;
+
+            let tls = align(&mut bytes);
+            if let Some((image, _, _)) = &self.tls {
+                bytes.extend_from_slice(image);
+            }
+
+            // Section headers: null, symbol table and string table
+            let shoff = align(&mut bytes);
+            bytes.extend_from_slice(&[0; 64]);
+            for (sh_type, offset, size, link, entsize) in [
+                (SHT_SYMTAB, symtab, symtab_size, 2, 24),
+                (SHT_STRTAB, strtab_offset, strtab.len() as u64, 0, 0),
+            ] {
+                push(
+                    &mut bytes,
+                    &[(sh_type as u64) << 32, 0, offset, offset, size],
+                );
+                push(&mut bytes, &[link, 8, entsize]);
+            }
+            let end = bytes.len() as u64;
+
+            let mut header = b"\x7fELF\x02\x01\x01".to_vec();
+            header.resize(16, 0);
+            for half in [self.e_type, EM_RISCV, 1, 0] {
+                header.extend_from_slice(&half.to_le_bytes());
+            }
+            push(&mut header, &[0x40, 64, shoff]);
+            header.extend_from_slice(&0u32.to_le_bytes());
+            for half in [64, 56, phnum as u16, 64, 3, 0] {
+                header.extend_from_slice(&half.to_le_bytes());
+            }
+
+            let mut phdrs = Vec::new();
+            let mut phdr = |p_type: u32, offset: u64, filesz: u64, memsz: u64, align: u64| {
+                push(&mut phdrs, &[p_type as u64 | 6 << 32]);
+                push(&mut phdrs, &[offset, offset, offset, filesz, memsz, align]);
+            };
+            phdr(PT_LOAD, 0, end, end, 0x1000);
+            phdr(PT_DYNAMIC, dynamic, dynamic_size, dynamic_size, 8);
+            if let Some((image, memsz, align)) = &self.tls {
+                phdr(PT_TLS, tls, image.len() as u64, *memsz, *align);
+            }
+
+            bytes[..64].copy_from_slice(&header);
+            bytes[64..64 + phdrs.len()].copy_from_slice(&phdrs);
+            (bytes, data)
+        }
+    }
+
+    fn read_u64(memory: &[u8], addr: u64) -> u64 {
+        let addr = addr as usize;
+        u64::from_le_bytes(memory[addr..addr + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_relocations() {
+        const START: u64 = 0x10000;
+
+        let mut fixture = Fixture::new(ET_DYN);
+        fixture.symbols = vec![
+            symbol("func", STB_GLOBAL, STT_FUNC, SHN_TEXT as u32, 0x100),
+            symbol("abs", STB_GLOBAL, STT_NOTYPE, SHN_ABS, 0x1234),
+            symbol("weak", STB_WEAK, STT_NOTYPE, SHN_UNDEF, 0),
+            symbol("tvar", STB_GLOBAL, STT_TLS, SHN_TEXT as u32, 8),
+        ];
+        fixture.tls = Some((vec![1, 2, 3, 4], 16, 16));
+
+        let (_, data) = fixture.build();
+        fixture.relocations = vec![
+            (data, 0, R_RISCV_RELATIVE, 0x10),
+            (data + 8, 1, R_RISCV_64, 4),
+            (data + 16, 2, R_RISCV_64, 1),
+            (data + 24, 3, R_RISCV_64, 0),
+            (data + 32, 4, R_RISCV_TLS_TPREL64, 2),
+        ];
+        fixture.plt_relocations = vec![(data + 40, 1, R_RISCV_JUMP_SLOT, 0)];
+        let (contents, relocated_data) = fixture.build();
+        assert_eq!(relocated_data, data);
+
+        let mut memory = Cursor::new(Vec::new());
+        let result = load_elf(&mut memory, START, &contents).unwrap();
+        let memory = memory.into_inner();
+
+        assert_eq!(read_u64(&memory, START + data), START + 0x10);
+        assert_eq!(read_u64(&memory, START + data + 8), START + 0x104);
+        // Absolute symbols are not relocated.
+        assert_eq!(read_u64(&memory, START + data + 16), 0x1235);
+        // Undefined weak symbols resolve to 0.
+        assert_eq!(read_u64(&memory, START + data + 24), 0);
+        assert_eq!(read_u64(&memory, START + data + 32), 10);
+        assert_eq!(read_u64(&memory, START + data + 40), START + 0x100);
+        assert_eq!(result.entry, START + 0x40);
+
+        // The TLS block follows the loaded segment and is aligned.
+        let tp = result.thread_pointer.unwrap();
+        assert_eq!(tp, (START + contents.len() as u64 + 15) & !15);
+        assert_eq!(result.last_written, tp + 16);
+        assert_eq!(
+            memory[tp as usize..],
+            [1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+
+        let symbols = &result.symbols;
+        assert_eq!(symbols.at(START + 0x100).unwrap().name, "func");
+        assert_eq!(symbols.by_name("abs").unwrap().address, 0x1234);
+        let tvar = symbols.by_name("tvar").unwrap();
+        assert_eq!((tvar.address, tvar.kind), (tp + 8, SymbolKind::ThreadLocal));
+        assert!(symbols.by_name("weak").is_none());
+        assert_eq!(symbols.len(), 3);
+    }
+
+    #[test]
+    fn test_relocation_errors() {
+        let mut fixture = Fixture::new(ET_DYN);
+        fixture.symbols = vec![
+            symbol("missing", STB_GLOBAL, STT_NOTYPE, SHN_UNDEF, 0),
+            symbol("tvar", STB_GLOBAL, STT_TLS, SHN_TEXT as u32, 0),
+        ];
+        let (_, data) = fixture.build();
+        let load = |fixture: &Fixture| {
+            let (contents, _) = fixture.build();
+            load_elf(&mut Cursor::new(Vec::new()), 0x1000, &contents).map(|_| ())
+        };
+
+        fixture.relocations = vec![(data, 1, R_RISCV_64, 0)];
+        assert!(matches!(
+            load(&fixture),
+            Err(Error::UndefinedSymbol { name }) if name == "missing"
+        ));
+
+        fixture.relocations = vec![(data, 2, R_RISCV_TLS_TPREL64, 0)];
+        assert!(matches!(load(&fixture), Err(Error::MissingTls)));
+
+        // R_RISCV_COPY
+        fixture.relocations = vec![(data, 2, 4, 0)];
+        assert!(matches!(
+            load(&fixture),
+            Err(Error::UnsupportedRelocation { r_type: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_symbol_table() {
+        let mut fixture = Fixture::new(ET_EXEC);
+        fixture.symbols = vec![
+            symbol("label", STB_GLOBAL, STT_NOTYPE, SHN_TEXT as u32, 0x100),
+            symbol("first", STB_GLOBAL, STT_FUNC, SHN_TEXT as u32, 0x100),
+            symbol("second", STB_WEAK, STT_OBJECT, SHN_TEXT as u32, 0x100),
+            symbol("abs", STB_GLOBAL, STT_NOTYPE, SHN_ABS, 0x40),
+            symbol("tvar", STB_GLOBAL, STT_TLS, SHN_TEXT as u32, 4),
+        ];
+        fixture.tls = Some((vec![7; 8], 8, 64));
+        let (contents, _) = fixture.build();
+
+        let mut memory = Cursor::new(Vec::new());
+        let result = load_elf(&mut memory, 0x1000, &contents).unwrap();
+        let symbols = &result.symbols;
+
+        // Non-relocatable files are loaded where they are linked.
+        assert_eq!(result.entry, 0x40);
+
+        // Every name of an address is kept, functions and objects first.
+        let names: Vec<_> = symbols
+            .aliases(0x100)
+            .iter()
+            .map(|symbol| symbol.name.as_str())
+            .collect();
+        assert_eq!(names, ["first", "second", "label"]);
+        assert_eq!(symbols.at(0x100).unwrap().name, "first");
+        assert_eq!(
+            symbols
+                .containing(0x104)
+                .map(|(sym, offset)| (sym.name.as_str(), offset)),
+            Some(("first", 4))
+        );
+        assert!(symbols.containing(0x108).is_none());
+        assert_eq!(symbols.by_name("label").unwrap().kind, SymbolKind::Other);
+        assert_eq!(symbols.len(), 5);
+
+        // The TLS block of a non-relocatable file follows its segments.
+        let tp = result.thread_pointer.unwrap();
+        assert_eq!(tp % 64, 0);
+        assert_eq!(symbols.by_name("tvar").unwrap().address, tp + 4);
+        assert_eq!(memory.into_inner()[tp as usize..], [7; 8]);
+    }
+}
@@ -0,0 +1,141 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+use goblin::elf::{
+    section_header::{SHN_ABS, SHN_UNDEF},
+    sym::{STT_FUNC, STT_OBJECT, STT_TLS},
+    Elf, Sym,
+};
+use std::collections::BTreeMap;
+
+/// Kind of entity a [Symbol] refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Object,
+    /// Thread-local variable, located in the TLS block
+    ThreadLocal,
+    Other,
+}
+
+/// Symbol of a loaded ELF file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// Name of the symbol, as found in the ELF file (i.e. mangled)
+    pub name: String,
+    /// Address of the symbol in the memory of the virtual machine
+    pub address: u64,
+    pub size: u64,
+    pub kind: SymbolKind,
+}
+
+/// Symbols of a loaded ELF file, indexed by their address. All names of an
+/// address are kept, functions and objects before other symbols.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: BTreeMap<u64, Vec<Symbol>>,
+}
+
+impl SymbolTable {
+    /// Collect the defined symbols of an ELF file loaded with the given
+    /// `offset`. The static symbol table is used if present, the dynamic one
+    /// otherwise. Thread-local symbols are placed relative to `tls_block`, or
+    /// omitted if there is none.
+    pub(crate) fn from_elf(elf: &Elf, offset: u64, tls_block: Option<u64>) -> Self {
+        let (syms, strtab) = if elf.syms.is_empty() {
+            (&elf.dynsyms, &elf.dynstrtab)
+        } else {
+            (&elf.syms, &elf.strtab)
+        };
+
+        let mut symbols = BTreeMap::<u64, Vec<Symbol>>::new();
+        for sym in syms.iter().filter(is_defined) {
+            let Some(name) = strtab.get_at(sym.st_name).filter(|name| !name.is_empty()) else {
+                continue;
+            };
+
+            let value = symbol_address(&sym, offset);
+            let (kind, address) = match sym.st_type() {
+                STT_FUNC => (SymbolKind::Function, value),
+                STT_OBJECT => (SymbolKind::Object, value),
+                STT_TLS => match tls_block {
+                    Some(tls_block) => (SymbolKind::ThreadLocal, tls_block + sym.st_value),
+                    None => continue,
+                },
+                _ => (SymbolKind::Other, value),
+            };
+
+            let symbol = Symbol {
+                name: name.to_string(),
+                address,
+                size: sym.st_size,
+                kind,
+            };
+
+            symbols.entry(address).or_default().push(symbol);
+        }
+
+        // Functions and objects take precedence over other symbols (e.g.
+        // section or label symbols) at the same address.
+        for aliases in symbols.values_mut() {
+            aliases.sort_by_key(|symbol| {
+                !matches!(symbol.kind, SymbolKind::Function | SymbolKind::Object)
+            });
+        }
+
+        Self { symbols }
+    }
+
+    /// Symbol whose extent contains `address`, along with the offset of
+    /// `address` from the start of the symbol
+    pub fn containing(&self, address: u64) -> Option<(&Symbol, u64)> {
+        let (start, aliases) = self.symbols.range(..=address).next_back()?;
+        let symbol = aliases.first()?;
+        let offset = address - start;
+        (offset < symbol.size.max(1)).then_some((symbol, offset))
+    }
+
+    /// Symbol starting exactly at `address`
+    pub fn at(&self, address: u64) -> Option<&Symbol> {
+        self.aliases(address).first()
+    }
+
+    /// All symbols starting exactly at `address`, preferred one first
+    pub fn aliases(&self, address: u64) -> &[Symbol] {
+        self.symbols.get(&address).map_or(&[], Vec::as_slice)
+    }
+
+    /// Find a symbol by its name.
+    pub fn by_name(&self, name: &str) -> Option<&Symbol> {
+        self.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// All symbols, in ascending order of address
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.values().flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+/// Whether the symbol is defined in the ELF file, rather than imported
+pub(crate) fn is_defined(sym: &Sym) -> bool {
+    sym.st_shndx != SHN_UNDEF as usize
+}
+
+/// Address of a defined symbol when the ELF file is loaded with the given
+/// `offset`. Absolute symbols are not affected by relocation.
+pub(crate) fn symbol_address(sym: &Sym, offset: u64) -> u64 {
+    if sym.st_shndx == SHN_ABS as usize {
+        sym.st_value
+    } else {
+        offset + sym.st_value
+    }
+}
@@ -4,6 +4,56 @@ module Types = Api_types
 module Functions (S : FOREIGN) = struct
   open S
 
-  let add =
-    foreign "octez_risc_v_add" (uintptr_t @-> uintptr_t @-> returning uintptr_t)
+  type pvm
+
+  let pvm : pvm structure typ = structure "OctezRiscVPvm"
+
+  let pvm_new = foreign "octez_risc_v_pvm_new" (void @-> returning (ptr pvm))
+
+  let pvm_free = foreign "octez_risc_v_pvm_free" (ptr pvm @-> returning void)
+
+  let install_boot_sector =
+    foreign
+      "octez_risc_v_install_boot_sector"
+      (ptr pvm @-> ptr uint8_t @-> uintptr_t @-> returning bool)
+
+  let state_hash =
+    foreign
+      "octez_risc_v_state_hash"
+      (ptr pvm @-> ptr uint8_t @-> returning bool)
+
+  let compute_step_many =
+    foreign
+      "octez_risc_v_compute_step_many"
+      (ptr pvm @-> uintptr_t @-> returning uintptr_t)
+
+  let get_status =
+    foreign "octez_risc_v_get_status" (ptr pvm @-> returning Types.status)
+
+  let set_input =
+    foreign
+      "octez_risc_v_set_input"
+      (ptr pvm @-> uint64_t @-> uint64_t @-> ptr uint8_t @-> uintptr_t
+     @-> returning bool)
+
+  let outbox_len =
+    foreign "octez_risc_v_outbox_len" (ptr pvm @-> returning uintptr_t)
+
+  let outbox_message =
+    foreign
+      "octez_risc_v_outbox_message"
+      (ptr pvm @-> uintptr_t @-> ptr uintptr_t @-> returning (ptr uint8_t))
+
+  let export_snapshot =
+    foreign
+      "octez_risc_v_export_snapshot"
+      (ptr pvm @-> ptr uintptr_t @-> returning (ptr uint8_t))
+
+  let import_snapshot =
+    foreign
+      "octez_risc_v_import_snapshot"
+      (ptr uint8_t @-> uintptr_t @-> returning (ptr pvm))
+
+  let bytes_free =
+    foreign "octez_risc_v_bytes_free" (ptr uint8_t @-> uintptr_t @-> returning void)
 end
@@ -1 +1,19 @@
-module Types (F : Ctypes.TYPE) = struct end
+module Types (F : Ctypes.TYPE) = struct
+  open F
+
+  let state_hash_size = constant "OCTEZ_RISC_V_STATE_HASH_SIZE" int
+
+  type status = Eval | Input | Failed
+
+  let status_eval = constant "OctezRiscVStatus_Eval" int64_t
+
+  let status_input = constant "OctezRiscVStatus_Input" int64_t
+
+  let status_failed = constant "OctezRiscVStatus_Failed" int64_t
+
+  let status =
+    enum
+      "OctezRiscVStatus"
+      [(Eval, status_eval); (Input, status_input); (Failed, status_failed)]
+      ~unexpected:(fun _ -> failwith "Unexpected OctezRiscVStatus value")
+end
@@ -5,7 +5,12 @@ use std::env;
 fn generate_c_headers() {
     let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
 
+    let mut config = cbindgen::Config::default();
+    // Avoid clashes between enum variants of different enums in C.
+    config.enumeration.prefix_with_name = true;
+
     cbindgen::Builder::new()
+        .with_config(config)
         .with_crate(crate_dir)
         .with_language(cbindgen::Language::C)
         .generate()
@@ -1,8 +1,89 @@
 open Api
 open Ctypes
 
-let add (x : int) (y : int) =
-  let x_ptr = Uintptr.of_int x in
-  let y_ptr = Uintptr.of_int y in
-  let res = Functions.add x_ptr y_ptr in
-  Uintptr.to_int res
+type state = Functions.pvm structure ptr
+
+type status = Types.status = Eval | Input | Failed
+
+let state_hash_size = Types.state_hash_size
+
+(* The function stubs are generated with [(concurrency unlocked)], see the
+   [ctypes] stanza in the dune file. They release the runtime lock while the
+   Rust code runs, hence the GC may run concurrently. Arguments which are
+   finalised by the GC, like [state] or buffers allocated through [Ctypes],
+   must be kept alive until the call returns. *)
+let keep_alive value result =
+  ignore (Sys.opaque_identity value) ;
+  result
+
+(* Copy [bytes] to a C buffer which stays alive while [f] runs. *)
+let with_c_bytes bytes f =
+  let buffer = CArray.of_string bytes in
+  keep_alive
+    buffer
+    (f
+       (coerce (ptr char) (ptr uint8_t) (CArray.start buffer))
+       (Uintptr.of_int (String.length bytes)))
+
+(* Copy a buffer returned by the API and release it. *)
+let take_c_bytes bytes len =
+  let len = Uintptr.to_int len in
+  let result = string_from_ptr (coerce (ptr uint8_t) (ptr char) bytes) ~length:len in
+  Functions.bytes_free bytes (Uintptr.of_int len) ;
+  result
+
+let manage state =
+  Gc.finalise Functions.pvm_free state ;
+  state
+
+let make_state () : state = manage (Functions.pvm_new ())
+
+let install_boot_sector state kernel =
+  keep_alive state (with_c_bytes kernel (Functions.install_boot_sector state))
+
+let state_hash state =
+  let buffer = CArray.make char state_hash_size in
+  let ok =
+    keep_alive
+      state
+      (Functions.state_hash
+         state
+         (coerce (ptr char) (ptr uint8_t) (CArray.start buffer)))
+  in
+  if ok then Some (string_from_ptr (CArray.start buffer) ~length:state_hash_size)
+  else None
+
+let compute_step_many state steps =
+  Uintptr.to_int
+    (keep_alive state (Functions.compute_step_many state (Uintptr.of_int steps)))
+
+let get_status state = keep_alive state (Functions.get_status state)
+
+let set_input state ~level ~counter payload =
+  keep_alive
+    state
+    (with_c_bytes
+       payload
+       (Functions.set_input
+          state
+          (Unsigned.UInt64.of_int64 level)
+          (Unsigned.UInt64.of_int64 counter)))
+
+let outbox_len state =
+  Uintptr.to_int (keep_alive state (Functions.outbox_len state))
+
+let outbox_message state index =
+  let len = allocate uintptr_t Uintptr.zero in
+  let message =
+    keep_alive state (Functions.outbox_message state (Uintptr.of_int index) len)
+  in
+  if is_null message then None else Some (take_c_bytes message !@len)
+
+let export_snapshot state =
+  let len = allocate uintptr_t Uintptr.zero in
+  let snapshot = keep_alive state (Functions.export_snapshot state len) in
+  if is_null snapshot then None else Some (take_c_bytes snapshot !@len)
+
+let import_snapshot snapshot =
+  let state = with_c_bytes snapshot Functions.import_snapshot in
+  if is_null state then None else Some (manage state)
@@ -1,8 +1,380 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! C API used by the rollup node
+//!
+//! A PVM state is created with [`octez_risc_v_pvm_new`] or
+//! [`octez_risc_v_import_snapshot`] and must be released with
+//! [`octez_risc_v_pvm_free`]. Byte buffers returned by the API must be released
+//! with [`octez_risc_v_bytes_free`].
+//!
+//! Panics must not unwind into the caller. A panic while operating on a PVM
+//! state marks it as failed, see [`OctezRiscVStatus::Failed`]. All further
+//! operations on a failed PVM state report errors.
+
+pub mod node_pvm;
+pub mod proof;
 pub mod state;
 
-use risc_v_interpreter::add;
+use node_pvm::NodePvm;
+use risc_v_interpreter::{
+    exec_env::sbi::Sbi, machine_state::bus::main_memory::M1G, state_backend::hash::DIGEST_SIZE,
+};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+/// Size of a state hash in bytes
+pub const OCTEZ_RISC_V_STATE_HASH_SIZE: usize = 32;
+
+#[allow(clippy::assertions_on_constants)]
+const _: () = assert!(OCTEZ_RISC_V_STATE_HASH_SIZE == DIGEST_SIZE);
+
+/// PVM state handed out to the rollup node
+pub struct OctezRiscVPvm {
+    inner: NodePvm<Sbi, M1G>,
+    failed: bool,
+}
+
+impl OctezRiscVPvm {
+    fn new(inner: NodePvm<Sbi, M1G>) -> Self {
+        Self {
+            inner,
+            failed: false,
+        }
+    }
+
+    /// Run `f` on the PVM. If the PVM has failed before or `f` panics, the
+    /// PVM is marked as failed and `error` is returned instead.
+    fn guard<R>(&mut self, error: R, f: impl FnOnce(&mut NodePvm<Sbi, M1G>) -> R) -> R {
+        if self.failed {
+            return error;
+        }
+
+        match panic::catch_unwind(AssertUnwindSafe(|| f(&mut self.inner))) {
+            Ok(result) => result,
+            Err(_) => {
+                self.failed = true;
+                error
+            }
+        }
+    }
+
+    /// Run `f` on a read-only view of the PVM, like [`OctezRiscVPvm::guard`].
+    /// A panic does not mark the PVM as failed, as `f` can't have modified it.
+    fn guard_ro<R>(&self, error: R, f: impl FnOnce(&NodePvm<Sbi, M1G>) -> R) -> R {
+        if self.failed {
+            return error;
+        }
 
+        panic::catch_unwind(AssertUnwindSafe(|| f(&self.inner))).unwrap_or(error)
+    }
+}
+
+/// Run `f`, returning `error` if it panics.
+fn guard<R>(error: R, f: impl FnOnce() -> R) -> R {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(error)
+}
+
+/// Status of the PVM
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OctezRiscVStatus {
+    /// Evaluating normally
+    Eval,
+
+    /// Input has been requested by the PVM
+    Input,
+
+    /// The PVM has failed due to an internal error and can't be used anymore
+    Failed,
+}
+
+impl From<state::Status> for OctezRiscVStatus {
+    fn from(status: state::Status) -> Self {
+        match status {
+            state::Status::Eval => Self::Eval,
+            state::Status::Input => Self::Input,
+        }
+    }
+}
+
+/// Obtain a slice from a pointer and length passed through the C API.
+///
+/// # Safety
+///
+/// `data` must either be null with `len == 0` or point to `len` readable
+/// bytes.
+unsafe fn bytes_from_raw<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+/// Hand a byte vector over to the caller.
+fn bytes_into_raw(bytes: Vec<u8>, len: &mut usize) -> *mut u8 {
+    let bytes = bytes.into_boxed_slice();
+    *len = bytes.len();
+    Box::into_raw(bytes) as *mut u8
+}
+
+/// Create a fresh PVM state. Returns null if the state could not be created.
+#[no_mangle]
+pub extern "C" fn octez_risc_v_pvm_new() -> *mut OctezRiscVPvm {
+    guard(ptr::null_mut(), || {
+        Box::into_raw(Box::new(OctezRiscVPvm::new(NodePvm::new())))
+    })
+}
+
+/// Release a PVM state.
+///
+/// # Safety
+///
+/// `pvm` must have been obtained from this API and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_pvm_free(pvm: *mut OctezRiscVPvm) {
+    if !pvm.is_null() {
+        guard((), || drop(Box::from_raw(pvm)));
+    }
+}
+
+/// Install a kernel given as an ELF executable. Returns `false` if the kernel
+/// could not be loaded or the PVM has failed.
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state and `kernel` must point to `kernel_len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_install_boot_sector(
+    pvm: *mut OctezRiscVPvm,
+    kernel: *const u8,
+    kernel_len: usize,
+) -> bool {
+    let kernel = bytes_from_raw(kernel, kernel_len);
+    (*pvm).guard(false, |pvm| pvm.install_boot_sector(kernel).is_ok())
+}
+
+/// Compute the state hash and write it to `hash`, which must have room for
+/// [`OCTEZ_RISC_V_STATE_HASH_SIZE`] bytes. Returns `false` if hashing failed
+/// or the PVM has failed.
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state and `hash` must point to
+/// [`OCTEZ_RISC_V_STATE_HASH_SIZE`] writable bytes.
 #[no_mangle]
-pub extern "C" fn octez_risc_v_add(left: usize, right: usize) -> usize {
-    add(left, right)
+pub unsafe extern "C" fn octez_risc_v_state_hash(pvm: *mut OctezRiscVPvm, hash: *mut u8) -> bool {
+    (*pvm).guard(false, |pvm| match pvm.state_hash() {
+        Ok(state_hash) => {
+            let state_hash = state_hash.as_ref();
+            ptr::copy_nonoverlapping(state_hash.as_ptr(), hash, state_hash.len());
+            true
+        }
+        Err(_) => false,
+    })
+}
+
+/// Perform at most `max_steps` steps. Returns the number of steps performed,
+/// which is 0 if the PVM has failed.
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_compute_step_many(
+    pvm: *mut OctezRiscVPvm,
+    max_steps: usize,
+) -> usize {
+    (*pvm).guard(0, |pvm| pvm.compute_step_many(max_steps))
+}
+
+/// Get the current status of the PVM.
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_get_status(pvm: *const OctezRiscVPvm) -> OctezRiscVStatus {
+    (*pvm).guard_ro(OctezRiscVStatus::Failed, |pvm| pvm.status().into())
+}
+
+/// Feed an inbox message to the PVM. Returns `false` if the PVM is not waiting
+/// for input or has failed.
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state and `payload` must point to `payload_len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_set_input(
+    pvm: *mut OctezRiscVPvm,
+    level: u64,
+    counter: u64,
+    payload: *const u8,
+    payload_len: usize,
+) -> bool {
+    let payload = bytes_from_raw(payload, payload_len);
+    (*pvm).guard(false, |pvm| pvm.set_input(level, counter, payload))
+}
+
+/// Number of messages in the outbox.
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_outbox_len(pvm: *const OctezRiscVPvm) -> usize {
+    (*pvm).guard_ro(0, |pvm| pvm.outbox_len())
+}
+
+/// Retrieve the outbox message at position `index`. Returns null if there is
+/// no such message or the PVM has failed. Otherwise, the length of the message is written to `len`
+/// and the message must be released using [`octez_risc_v_bytes_free`].
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state and `len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_outbox_message(
+    pvm: *const OctezRiscVPvm,
+    index: usize,
+    len: *mut usize,
+) -> *mut u8 {
+    match (*pvm).guard_ro(None, |pvm| pvm.outbox_message(index)) {
+        Some(message) => bytes_into_raw(message, &mut *len),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Export a snapshot of the PVM state. Returns null if the PVM has failed.
+/// Otherwise, the length of the snapshot is written to `len` and the snapshot
+/// must be released using [`octez_risc_v_bytes_free`].
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state and `len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_export_snapshot(
+    pvm: *const OctezRiscVPvm,
+    len: *mut usize,
+) -> *mut u8 {
+    match (*pvm).guard_ro(None, |pvm| Some(pvm.export_snapshot())) {
+        Some(snapshot) => bytes_into_raw(snapshot, &mut *len),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Create a PVM state from a snapshot. Returns null if the snapshot is
+/// invalid.
+///
+/// # Safety
+///
+/// `snapshot` must point to `snapshot_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_import_snapshot(
+    snapshot: *const u8,
+    snapshot_len: usize,
+) -> *mut OctezRiscVPvm {
+    let snapshot = bytes_from_raw(snapshot, snapshot_len);
+    guard(ptr::null_mut(), || {
+        match NodePvm::import_snapshot(snapshot) {
+            Ok(inner) => Box::into_raw(Box::new(OctezRiscVPvm::new(inner))),
+            Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
+/// Release a byte buffer returned by this API.
+///
+/// # Safety
+///
+/// `bytes` and `len` must have been returned together by this API and `bytes`
+/// must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_bytes_free(bytes: *mut u8, len: usize) {
+    if !bytes.is_null() {
+        guard((), || {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(bytes, len)))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_c_api_roundtrip() {
+        unsafe {
+            let pvm = octez_risc_v_pvm_new();
+            assert_eq!(octez_risc_v_get_status(pvm), OctezRiscVStatus::Eval);
+            assert!(!octez_risc_v_install_boot_sector(
+                pvm,
+                b"no elf".as_ptr(),
+                6
+            ));
+            assert!(!octez_risc_v_set_input(pvm, 0, 0, ptr::null(), 0));
+            assert_eq!(octez_risc_v_outbox_len(pvm), 0);
+
+            let mut len = 0;
+            assert!(octez_risc_v_outbox_message(pvm, 0, &mut len).is_null());
+
+            assert_eq!(octez_risc_v_compute_step_many(pvm, 100), 100);
+
+            let mut hash = [0u8; OCTEZ_RISC_V_STATE_HASH_SIZE];
+            assert!(octez_risc_v_state_hash(pvm, hash.as_mut_ptr()));
+
+            let snapshot = octez_risc_v_export_snapshot(pvm, &mut len);
+            let restored = octez_risc_v_import_snapshot(snapshot, len);
+            assert!(!restored.is_null());
+            octez_risc_v_bytes_free(snapshot, len);
+
+            let mut restored_hash = [0u8; OCTEZ_RISC_V_STATE_HASH_SIZE];
+            assert!(octez_risc_v_state_hash(
+                restored,
+                restored_hash.as_mut_ptr()
+            ));
+            assert_eq!(hash, restored_hash);
+
+            assert_eq!(octez_risc_v_compute_step_many(pvm, 10), 10);
+            assert_eq!(octez_risc_v_compute_step_many(restored, 10), 10);
+            assert!(octez_risc_v_state_hash(pvm, hash.as_mut_ptr()));
+            assert!(octez_risc_v_state_hash(
+                restored,
+                restored_hash.as_mut_ptr()
+            ));
+            assert_eq!(hash, restored_hash);
+
+            octez_risc_v_pvm_free(pvm);
+            octez_risc_v_pvm_free(restored);
+
+            assert!(octez_risc_v_import_snapshot(b"RVSN".as_ptr(), 4).is_null());
+        }
+    }
+
+    #[test]
+    fn test_c_api_failed() {
+        unsafe {
+            let pvm = octez_risc_v_pvm_new();
+
+            // A panic does not unwind through the API, but marks the PVM as
+            // failed.
+            assert_eq!((*pvm).guard(1, |_| panic!("internal error")), 1);
+            assert_eq!(octez_risc_v_get_status(pvm), OctezRiscVStatus::Failed);
+            assert_eq!(octez_risc_v_compute_step_many(pvm, 10), 0);
+
+            let mut hash = [0u8; OCTEZ_RISC_V_STATE_HASH_SIZE];
+            assert!(!octez_risc_v_state_hash(pvm, hash.as_mut_ptr()));
+
+            let mut len = 0;
+            assert!(octez_risc_v_export_snapshot(pvm, &mut len).is_null());
+
+            octez_risc_v_pvm_free(pvm);
+        }
+    }
 }
@@ -0,0 +1,216 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! PVM state as managed by the rollup node
+//!
+//! [NodePvm] owns the backing storage of a PVM together with a cached
+//! [MerkleTree]. All writes are tracked so that computing the state hash only
+//! rehashes the parts of the state that changed since the last time.
+
+use crate::state::{Pvm, PvmLayout, Status, INITIAL_VERSION};
+use risc_v_interpreter::{
+    exec_env::ExecutionEnvironment,
+    machine_state::bus::main_memory::MainMemoryLayout,
+    state_backend::{
+        hash::{Hash, HashError},
+        memory_backend::{InMemoryBackend, SliceManagerRO, TrackingManager},
+        merkle::{DirtyPages, MerkleTree},
+        snapshot, Backend, Layout,
+    },
+    InterpreterError,
+};
+
+/// Failed to import a snapshot
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum SnapshotError {
+    #[error(transparent)]
+    Encoding(#[from] snapshot::SnapshotError),
+
+    #[error("Snapshot holds a PVM of unsupported version {0}")]
+    UnsupportedPvmVersion(u64),
+}
+
+/// PVM state owned by the rollup node
+pub struct NodePvm<EE: ExecutionEnvironment, ML: MainMemoryLayout> {
+    backend: InMemoryBackend<PvmLayout<EE, ML>>,
+    dirty: DirtyPages,
+    tree: Option<MerkleTree>,
+}
+
+impl<EE: ExecutionEnvironment, ML: MainMemoryLayout> NodePvm<EE, ML> {
+    /// Create a fresh PVM state.
+    pub fn new() -> Self {
+        let (backend, _) = InMemoryBackend::new();
+        let mut pvm = Self::from_backend(backend);
+        pvm.with_pvm(|pvm| pvm.reset());
+        pvm
+    }
+
+    fn from_backend(backend: InMemoryBackend<PvmLayout<EE, ML>>) -> Self {
+        Self {
+            backend,
+            dirty: DirtyPages::new(),
+            tree: None,
+        }
+    }
+
+    /// Run `f` on the PVM. Writes to the state are tracked.
+    fn with_pvm<R>(&mut self, f: impl FnOnce(&mut Pvm<EE, ML, TrackingManager>) -> R) -> R {
+        let placed = PvmLayout::<EE, ML>::placed().into_location();
+        let space = self.backend.allocate_tracked(placed, self.dirty.clone());
+        f(&mut Pvm::bind(space))
+    }
+
+    /// Run `f` on a read-only view of the PVM.
+    fn with_pvm_ro<R>(&self, f: impl FnOnce(&Pvm<EE, ML, SliceManagerRO>) -> R) -> R {
+        let placed = PvmLayout::<EE, ML>::placed().into_location();
+        f(&Pvm::bind(self.backend.allocate_ro(placed)))
+    }
+
+    /// Install a kernel given as an ELF executable.
+    pub fn install_boot_sector(&mut self, kernel: &[u8]) -> Result<(), InterpreterError> {
+        self.with_pvm(|pvm| pvm.install_program(kernel, None))
+    }
+
+    /// Compute the hash of the entire state.
+    pub fn state_hash(&mut self) -> Result<Hash, HashError> {
+        match &mut self.tree {
+            Some(tree) => {
+                tree.update(&self.backend, &self.dirty)?;
+            }
+            None => {
+                self.tree = Some(MerkleTree::build(&self.backend)?);
+            }
+        }
+
+        self.dirty.clear();
+        Ok(self
+            .tree
+            .as_ref()
+            .map_or_else(Hash::default, MerkleTree::root_hash))
+    }
+
+    /// Perform at most `max_steps` steps. Returns the number of steps
+    /// performed.
+    pub fn compute_step_many(&mut self, max_steps: usize) -> usize {
+        self.with_pvm(|pvm| pvm.step_many(max_steps))
+    }
+
+    /// Get the current machine status.
+    pub fn status(&self) -> Status {
+        self.with_pvm_ro(|pvm| pvm.status())
+    }
+
+    /// Provide input. Returns `false` if the PVM is not waiting for input.
+    pub fn set_input(&mut self, level: u64, counter: u64, payload: &[u8]) -> bool {
+        self.with_pvm(|pvm| pvm.provide_input(level, counter, payload))
+    }
+
+    /// Number of messages in the outbox.
+    pub fn outbox_len(&self) -> usize {
+        self.with_pvm_ro(|pvm| pvm.outbox_len())
+    }
+
+    /// Retrieve the message at position `index` of the outbox.
+    pub fn outbox_message(&self, index: usize) -> Option<Vec<u8>> {
+        self.with_pvm_ro(|pvm| pvm.outbox_message(index))
+    }
+
+    /// Encode the entire state as a snapshot. Pages which are entirely zero
+    /// are omitted.
+    pub fn export_snapshot(&self) -> Vec<u8> {
+        self.backend.export_snapshot()
+    }
+
+    /// Restore a state from a snapshot produced by [`NodePvm::export_snapshot`].
+    pub fn import_snapshot(snapshot: &[u8]) -> Result<Self, SnapshotError> {
+        let backend = InMemoryBackend::<PvmLayout<EE, ML>>::import_snapshot(snapshot)?;
+
+        let placed = PvmLayout::<EE, ML>::placed().into_location();
+        let version = backend.allocate_ro(placed).0.read();
+        if version != INITIAL_VERSION {
+            return Err(SnapshotError::UnsupportedPvmVersion(version));
+        }
+
+        Ok(Self::from_backend(backend))
+    }
+}
+
+impl<EE: ExecutionEnvironment, ML: MainMemoryLayout> Default for NodePvm<EE, ML> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NodePvm, SnapshotError};
+    use crate::state::Status;
+    use risc_v_interpreter::{
+        exec_env::posix::Posix, machine_state::bus::main_memory::M1K,
+        state_backend::snapshot::SnapshotError::*,
+    };
+
+    type TestPvm = NodePvm<Posix, M1K>;
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let mut pvm = TestPvm::new();
+        assert_eq!(pvm.status(), Status::Eval);
+        assert_eq!(pvm.outbox_len(), 0);
+        assert!(!pvm.set_input(0, 0, b"message"));
+
+        // The zeroed main memory decodes as illegal instructions which trap
+        // into the (zeroed) trap handler. As no instruction retires, time
+        // does not advance either.
+        assert_eq!(pvm.compute_step_many(10), 10);
+        let hash = pvm.state_hash().unwrap();
+
+        let snapshot = pvm.export_snapshot();
+        let mut restored = TestPvm::import_snapshot(&snapshot).unwrap();
+        assert_eq!(restored.state_hash().unwrap(), hash);
+
+        assert_eq!(pvm.compute_step_many(5), 5);
+        assert_eq!(restored.compute_step_many(5), 5);
+        assert_eq!(pvm.state_hash().unwrap(), restored.state_hash().unwrap());
+    }
+
+    #[test]
+    fn test_snapshot_rejected() {
+        let snapshot = TestPvm::new().export_snapshot();
+
+        assert_eq!(
+            TestPvm::import_snapshot(&snapshot[..3]).err(),
+            Some(SnapshotError::Encoding(Truncated))
+        );
+
+        let mut bad_magic = snapshot.clone();
+        bad_magic[0] ^= 0xFF;
+        assert_eq!(
+            TestPvm::import_snapshot(&bad_magic).err(),
+            Some(SnapshotError::Encoding(BadMagic))
+        );
+
+        let mut bad_version = snapshot.clone();
+        bad_version[4] = 42;
+        assert_eq!(
+            TestPvm::import_snapshot(&bad_version).err(),
+            Some(SnapshotError::Encoding(UnsupportedVersion(42)))
+        );
+
+        let mut bad_page = snapshot.clone();
+        bad_page.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            TestPvm::import_snapshot(&bad_page).err(),
+            Some(SnapshotError::Encoding(PageOutOfBounds(u32::MAX)))
+        );
+
+        let mut truncated = snapshot;
+        truncated.extend_from_slice(&0u32.to_le_bytes());
+        assert_eq!(
+            TestPvm::import_snapshot(&truncated).err(),
+            Some(SnapshotError::Encoding(Truncated))
+        );
+    }
+}
@@ -0,0 +1,166 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Proofs for single steps of the PVM
+
+use crate::state::{Pvm, PvmLayout};
+use risc_v_interpreter::{
+    exec_env::ExecutionEnvironment,
+    machine_state::bus::main_memory::MainMemoryLayout,
+    state_backend::{
+        memory_backend::InMemoryBackend,
+        proof::{Proof, ProofError},
+    },
+};
+
+/// Perform one step of the PVM whose state is held in `backend` and produce a
+/// proof for it.
+pub fn prove_step<EE, ML>(
+    backend: &mut InMemoryBackend<PvmLayout<EE, ML>>,
+) -> Result<Proof, ProofError>
+where
+    EE: ExecutionEnvironment,
+    ML: MainMemoryLayout,
+{
+    Proof::produce(backend, |space| {
+        Pvm::<EE, ML, _>::bind(space).step();
+    })
+}
+
+/// Verify that the proof shows a valid step of the PVM.
+pub fn verify_step<EE, ML>(proof: &Proof) -> Result<(), ProofError>
+where
+    EE: ExecutionEnvironment,
+    ML: MainMemoryLayout,
+{
+    proof.verify::<PvmLayout<EE, ML>, _>(|space| {
+        Pvm::<EE, ML, _>::bind(space).step();
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prove_step, verify_step};
+    use crate::state::{Pvm, PvmLayout, INITIAL_VERSION};
+    use rand::Rng;
+    use risc_v_interpreter::{
+        exec_env::posix::Posix,
+        machine_state::{
+            bus::{
+                main_memory::{MainMemoryLayout, M1K},
+                start_of_main_memory, Addressable,
+            },
+            mode::Mode,
+            registers::x3,
+        },
+        state_backend::{memory_backend::InMemoryBackend, proof::Proof, Backend, Layout},
+    };
+
+    type TestLayout = PvmLayout<Posix, M1K>;
+
+    /// Random contents for a PVM backend which can be bound and stepped
+    fn random_state() -> Vec<u8> {
+        let mut rng = rand::thread_rng();
+        let mut data = vec![0u8; TestLayout::placed().size()];
+        rng.fill(data.as_mut_slice());
+
+        let (mut backend, placed) = InMemoryBackend::<TestLayout>::new();
+        backend.write(0, &data);
+
+        let mut space = backend.allocate(placed);
+        space.0.write(INITIAL_VERSION);
+        let mut pvm = Pvm::<Posix, M1K, _>::bind(space);
+
+        // Not all values are valid privilege modes, and random addresses are
+        // unlikely to point into the main memory.
+        let mode = [Mode::User, Mode::Supervisor, Mode::Machine][rng.gen_range(0..3)];
+        pvm.machine_state.hart.mode.write(mode);
+        let offset = rng.gen_range(0..M1K::BYTES as u64 / 4) * 4;
+        pvm.machine_state
+            .hart
+            .pc
+            .write(start_of_main_memory::<M1K>() + offset);
+
+        backend.read(0, &mut data);
+        data
+    }
+
+    fn backend_with(data: &[u8]) -> InMemoryBackend<TestLayout> {
+        let (mut backend, _) = InMemoryBackend::<TestLayout>::new();
+        backend.write(0, data);
+        backend
+    }
+
+    #[test]
+    fn test_prove_verify_random_states() {
+        for _ in 0..16 {
+            let mut backend = backend_with(&random_state());
+
+            let proof = prove_step::<Posix, M1K>(&mut backend).unwrap();
+            let decoded = Proof::decode::<TestLayout>(&proof.encode()).unwrap();
+            assert_eq!(decoded, proof);
+            assert_eq!(verify_step::<Posix, M1K>(&decoded), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_prove_deterministic() {
+        for _ in 0..4 {
+            let state = random_state();
+            let mut backend1 = backend_with(&state);
+            let mut backend2 = backend_with(&state);
+
+            // The same state steps to the same state, with the same proof.
+            let proof1 = prove_step::<Posix, M1K>(&mut backend1).unwrap();
+            let proof2 = prove_step::<Posix, M1K>(&mut backend2).unwrap();
+            assert_eq!(proof1.initial_state_hash(), proof2.initial_state_hash());
+            assert_eq!(proof1.final_state_hash(), proof2.final_state_hash());
+            assert_eq!(proof1.encode(), proof2.encode());
+
+            let size = TestLayout::placed().size();
+            let (mut data1, mut data2) = (vec![0u8; size], vec![0u8; size]);
+            backend1.read(0, &mut data1);
+            backend2.read(0, &mut data2);
+            assert_eq!(data1, data2);
+        }
+    }
+
+    #[test]
+    fn test_prove_verify_steps() {
+        let (mut backend, placed) = InMemoryBackend::<TestLayout>::new();
+
+        {
+            let mut pvm = Pvm::<Posix, M1K, _>::bind(backend.allocate(placed));
+            pvm.reset();
+
+            // addi x1, x1, 1; sd x1, 0(x3); jal x0, -8
+            let start = start_of_main_memory::<M1K>();
+            let program: [u32; 3] = [0x00108093, 0x0011B023, 0xFF9FF06F];
+            pvm.machine_state.bus.write_all(start, &program).unwrap();
+            pvm.machine_state.hart.pc.write(start);
+            pvm.machine_state.hart.xregisters.write(x3, start + 512);
+        }
+
+        let mut previous = None;
+        for _ in 0..6 {
+            let proof = prove_step::<Posix, M1K>(&mut backend).unwrap();
+            let proof = Proof::decode::<TestLayout>(&proof.encode()).unwrap();
+            assert_eq!(verify_step::<Posix, M1K>(&proof), Ok(()));
+
+            // Proofs chain up.
+            if let Some(previous) = previous {
+                assert_eq!(proof.initial_state_hash(), previous);
+            }
+            previous = Some(proof.final_state_hash());
+        }
+
+        // The backend holds the state after all steps.
+        let pvm =
+            Pvm::<Posix, M1K, _>::bind(backend.allocate(TestLayout::placed().into_location()));
+        assert_eq!(
+            pvm.machine_state.hart.pc.read(),
+            start_of_main_memory::<M1K>()
+        );
+    }
+}
@@ -7,9 +7,14 @@
 
 use risc_v_interpreter::{
     exec_env::{self, ExecutionEnvironment, ExecutionEnvironmentState},
-    machine_state::{self, bus::main_memory, StepManyResult},
+    machine_state::{
+        self, bus::devices::uart::TransmitHandler, bus::main_memory, bus::Addressable,
+        extensions::Extensions, mode::Mode, registers::XRegister, trace::Commit, StepManyResult,
+    },
+    program::Program,
     state_backend,
     traps::EnvironException,
+    InterpreterError,
 };
 
 /// PVM state layout
@@ -20,7 +25,7 @@ pub type PvmLayout<EE, ML> = (
 );
 
 /// Value for the initial version
-const INITIAL_VERSION: u64 = 0;
+pub(crate) const INITIAL_VERSION: u64 = 0;
 
 /// Proof-generating virtual machine
 pub struct Pvm<
@@ -57,17 +62,59 @@ impl<EE: ExecutionEnvironment, ML: main_memory::MainMemoryLayout, M: state_backe
         self.syscall_state.reset();
     }
 
+    /// Select the optional ISA extensions available to the kernel. All of
+    /// them are enabled after a reset. The device tree written when installing
+    /// a kernel reflects the selection.
+    pub fn set_extensions(&mut self, extensions: Extensions) {
+        self.machine_state.hart.extensions.write(extensions);
+    }
+
+    /// Install a kernel given as an ELF executable and prepare the machine to
+    /// boot it in machine mode.
+    pub fn install_program(
+        &mut self,
+        program: &[u8],
+        initrd: Option<&[u8]>,
+    ) -> Result<(), InterpreterError> {
+        let program = Program::<ML>::from_elf(program)?;
+        self.machine_state
+            .setup_boot(&program, initrd, Mode::Machine)?;
+        Ok(())
+    }
+
+    /// Install the handler which receives the bytes written to the console.
+    pub fn set_console_output(&mut self, handler: TransmitHandler) {
+        self.machine_state
+            .bus
+            .devices
+            .uart
+            .set_transmit_handler(handler);
+    }
+
     /// Provide input. Returns `false` if the machine state is not in
     /// `Status::Input` status.
-    pub fn provide_input(&mut self, _level: u64, _counter: u64, _payload: &[u8]) -> bool {
-        // TODO: https://gitlab.com/tezos/tezos/-/issues/6945
-        // Implement input provider
-        todo!("Input function is not implemented")
+    pub fn provide_input(&mut self, level: u64, counter: u64, payload: &[u8]) -> bool {
+        self.syscall_state
+            .provide_input(&mut self.machine_state, level, counter, payload)
     }
 
     /// Get the current machine status.
     pub fn status(&self) -> Status {
-        Status::Eval
+        if self.syscall_state.awaits_input() {
+            Status::Input
+        } else {
+            Status::Eval
+        }
+    }
+
+    /// Number of messages in the outbox.
+    pub fn outbox_len(&self) -> usize {
+        self.syscall_state.outbox_len()
+    }
+
+    /// Retrieve the message at position `index` of the outbox.
+    pub fn outbox_message(&self, index: usize) -> Option<Vec<u8>> {
+        self.syscall_state.outbox_message(index)
     }
 
     /// Defines how to handle exceptions in the PVM execution environment.
@@ -93,6 +140,10 @@ impl<EE: ExecutionEnvironment, ML: main_memory::MainMemoryLayout, M: state_backe
 
     /// Perform one step. Returns `false` if the PVM is not in [`Status::Eval`] status.
     pub fn step(&mut self) -> bool {
+        if self.status() != Status::Eval {
+            return false;
+        }
+
         if let Err(exc) = self.machine_state.step() {
             self.handle_exception(exc);
         }
@@ -100,6 +151,41 @@ impl<EE: ExecutionEnvironment, ML: main_memory::MainMemoryLayout, M: state_backe
         true
     }
 
+    /// Perform one step like [`Pvm::step`], describing the instruction which
+    /// retired, if any. Instructions which are handled by the execution
+    /// environment aren't described.
+    pub fn step_traced(&mut self) -> Option<Commit> {
+        if self.status() != Status::Eval {
+            return None;
+        }
+
+        match self.machine_state.step_traced() {
+            Ok(commit) => commit,
+            Err(exc) => {
+                self.handle_exception(exc);
+                None
+            }
+        }
+    }
+
+    /// Read the program counter.
+    pub fn read_pc(&self) -> u64 {
+        self.machine_state.hart.pc.read()
+    }
+
+    /// Read an integer register.
+    pub fn read_xregister(&self, reg: XRegister) -> u64 {
+        self.machine_state.hart.xregisters.read(reg)
+    }
+
+    /// Read `length` bytes of memory starting at `address`. Returns `None` if
+    /// the range isn't addressable.
+    pub fn read_memory(&self, address: u64, length: usize) -> Option<Vec<u8>> {
+        let mut buffer = vec![0; length];
+        self.machine_state.bus.read_all(address, &mut buffer).ok()?;
+        Some(buffer)
+    }
+
     /// Perform at most `max_steps` steps. Returns the actual number of steps
     /// performed (retired instructions)
     ///
@@ -115,6 +201,10 @@ impl<EE: ExecutionEnvironment, ML: main_memory::MainMemoryLayout, M: state_backe
     /// (a possible case: the privilege mode access violation is treated in EE,
     /// but a page fault is not)
     pub fn step_many(&mut self, max_steps: usize) -> usize {
+        if self.status() != Status::Eval {
+            return 0;
+        }
+
         self.step_many_accum(max_steps, 0)
     }
 
@@ -147,6 +237,7 @@ impl<EE: ExecutionEnvironment, ML: main_memory::MainMemoryLayout, M: state_backe
 }
 
 /// Machine status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Status {
     /// Evaluating normally
     Eval,
@@ -154,3 +245,58 @@ pub enum Status {
     /// Input has been requested by the PVM
     Input,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Pvm, PvmLayout};
+    use risc_v_interpreter::{
+        exec_env::posix::Posix,
+        machine_state::{
+            bus::{main_memory::M1K, start_of_main_memory, Addressable},
+            registers::{x1, x3},
+        },
+        state_backend::{
+            memory_backend::InMemoryBackend,
+            merkle::{DirtyPages, MerkleTree},
+            Layout,
+        },
+    };
+
+    type TestLayout = PvmLayout<Posix, M1K>;
+
+    #[test]
+    fn test_incremental_state_hash() {
+        let (mut backend, placed) = InMemoryBackend::<TestLayout>::new();
+        let dirty = DirtyPages::new();
+        let mut pvm = Pvm::<Posix, M1K, _>::bind(backend.allocate_tracked(placed, dirty.clone()));
+        pvm.reset();
+
+        // addi x1, x1, 1; sd x1, 0(x3); jal x0, -8
+        let start = start_of_main_memory::<M1K>();
+        let program: [u32; 3] = [0x00108093, 0x0011B023, 0xFF9FF06F];
+        pvm.machine_state.bus.write_all(start, &program).unwrap();
+        pvm.machine_state.hart.pc.write(start);
+        pvm.machine_state.hart.xregisters.write(x3, start + 512);
+        drop(pvm);
+
+        let mut tree = MerkleTree::build(&backend).unwrap();
+        dirty.clear();
+
+        for _ in 0..10 {
+            let mut pvm = Pvm::<Posix, M1K, _>::bind(
+                backend.allocate_tracked(TestLayout::placed().into_location(), dirty.clone()),
+            );
+            pvm.step();
+            let x1_value = pvm.machine_state.hart.xregisters.read(x1);
+            drop(pvm);
+
+            let previous = tree.root_hash();
+            tree.update(&backend, &dirty).unwrap();
+            dirty.clear();
+
+            assert_ne!(tree.root_hash(), previous);
+            assert_eq!(tree, MerkleTree::build(&backend).unwrap());
+            assert!(x1_value > 0);
+        }
+    }
+}
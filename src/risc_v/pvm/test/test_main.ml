@@ -1,9 +1,26 @@
 open Octez_risc_v_pvm
 
-let add_test () =
-  let res = Main.add 5 6 in
-  Alcotest.(check int "add" res 11)
+let roundtrip_test () =
+  let state = Main.make_state () in
+  Alcotest.(check bool "status" true (Main.get_status state = Main.Eval)) ;
+  Alcotest.(
+    check bool "install" false (Main.install_boot_sector state "not an ELF")) ;
+  Alcotest.(check int "outbox" 0 (Main.outbox_len state)) ;
+  Alcotest.(check int "steps" 100 (Main.compute_step_many state 100)) ;
+  let hash = Main.state_hash state in
+  match Option.bind (Main.export_snapshot state) Main.import_snapshot with
+  | None -> Alcotest.fail "Failed to export and import snapshot"
+  | Some restored ->
+      Alcotest.(check (option string) "hash" hash (Main.state_hash restored)) ;
+      ignore (Main.compute_step_many state 10) ;
+      ignore (Main.compute_step_many restored 10) ;
+      Alcotest.(
+        check
+          (option string)
+          "hash after steps"
+          (Main.state_hash state)
+          (Main.state_hash restored))
 
-let tests = [("Main", [("add", `Quick, add_test)])]
+let tests = [("Main", [("roundtrip", `Quick, roundtrip_test)])]
 
 let () = Alcotest.run ~__FILE__ "RISC-V interpreter" tests
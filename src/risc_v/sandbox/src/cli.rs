@@ -4,6 +4,8 @@
 // SPDX-License-Identifier: MIT
 
 use clap::{Parser, Subcommand, ValueEnum};
+use risc_v_interpreter::machine_state::extensions::{Extension, Extensions};
+use tezos_smart_rollup_debug::log::LogFilter;
 
 #[derive(Debug, Clone, Subcommand)]
 pub enum Mode {
@@ -13,6 +15,17 @@ pub enum Mode {
     Debug(Options),
     /// Run a program using rvemu
     Rvemu(Options),
+    /// Produce a proof for a single step of a program
+    Prove(ProveOptions),
+    /// Verify a proof for a single step
+    Verify(VerifyOptions),
+    /// Serve a program to GDB using the remote serial protocol
+    Gdbserver(GdbServerOptions),
+    /// Compare two snapshots taken with `run --snapshot-at`
+    Diff(DiffOptions),
+    /// Run a program using the RISC-V interpreter and rvemu in lockstep,
+    /// stopping at the first divergence
+    DiffTrace(Options),
 }
 
 #[derive(Clone, ValueEnum, Debug)]
@@ -47,6 +60,12 @@ pub struct Options {
     #[arg(long)]
     pub posix: bool,
 
+    /// Let `run` serve the Supervisor Binary Interface to the program instead
+    /// of POSIX-style system calls. Durable storage and reveals are not
+    /// served yet, use `rvemu` for kernels relying on them
+    #[arg(long, conflicts_with = "posix")]
+    pub sbi: bool,
+
     /// Rollup address
     #[arg(short, long, default_value = "sr1UNDWPUYVeomgG15wn5jSw689EJ4RNnVQa")]
     pub address: String,
@@ -55,11 +74,117 @@ pub struct Options {
     #[arg(short = 'l', long, default_value_t = 0)]
     pub origination_level: u64,
 
+    /// Directory containing preimages that can be revealed by the kernel
+    #[arg(long)]
+    pub preimages_dir: Option<String>,
+
+    #[arg(short = 'm', long, value_enum, default_value_t = ExitMode::User)]
+    pub posix_exit_mode: ExitMode,
+
+    /// Optional ISA extensions to disable, e.g. `zbb,zbs`
+    #[arg(long, value_delimiter = ',')]
+    pub disable_extensions: Vec<Extension>,
+
+    /// Stop after the given number of steps and write a snapshot of the state
+    #[arg(long, requires = "sbi", requires = "snapshot_output")]
+    pub snapshot_at: Option<u64>,
+
+    /// Path where the snapshot shall be written to
+    #[arg(long, requires = "snapshot_at")]
+    pub snapshot_output: Option<String>,
+
+    /// Resume the run from a snapshot instead of booting the program
+    #[arg(long, requires = "sbi")]
+    pub resume_from: Option<String>,
+
+    /// Write a trace of the retired instructions in the format of Spike's
+    /// `--log-commits`
+    #[arg(long, requires = "sbi")]
+    pub trace: Option<String>,
+
+    /// Precede each traced instruction with its disassembly
+    #[arg(long, requires = "trace")]
+    pub trace_disassembly: bool,
+
+    /// Filter of the structured log records printed, e.g. `warn` or
+    /// `info,my_kernel::inbox=trace`
+    #[arg(long, default_value = "trace", value_parser = parse_log_filter)]
+    pub log_filter: LogFilter,
+}
+
+/// Options for producing a proof
+#[derive(Debug, Clone, Parser)]
+pub struct ProveOptions {
+    /// Path to the input ELF executable
+    #[arg(short, long)]
+    pub input: String,
+
+    /// Path to the initrd
+    #[arg(long)]
+    pub initrd: Option<String>,
+
+    /// Number of steps to run before the step that is proven
+    #[arg(short, long, default_value_t = 0)]
+    pub steps: usize,
+
+    /// Path where the proof shall be written to
+    #[arg(short, long)]
+    pub output: String,
+}
+
+/// Options for verifying a proof
+#[derive(Debug, Clone, Parser)]
+pub struct VerifyOptions {
+    /// Path to the proof
+    #[arg(short, long)]
+    pub proof: String,
+}
+
+/// Options for serving a program to GDB
+#[derive(Debug, Clone, Parser)]
+pub struct GdbServerOptions {
+    /// Path to the input ELF executable
+    #[arg(short, long)]
+    pub input: String,
+
+    /// Path to the initrd
+    #[arg(long)]
+    pub initrd: Option<String>,
+
+    /// Port to listen on for the GDB connection
+    #[arg(short, long, default_value_t = 1234)]
+    pub port: u16,
+
     #[arg(short = 'm', long, value_enum, default_value_t = ExitMode::User)]
     pub posix_exit_mode: ExitMode,
+
+    /// Optional ISA extensions to disable, e.g. `zbb,zbs`
+    #[arg(long, value_delimiter = ',')]
+    pub disable_extensions: Vec<Extension>,
+}
+
+/// Options for comparing snapshots
+#[derive(Debug, Clone, Parser)]
+pub struct DiffOptions {
+    /// Path to the first snapshot
+    pub before: String,
+
+    /// Path to the second snapshot
+    pub after: String,
+}
+
+fn parse_log_filter(filter: &str) -> Result<LogFilter, String> {
+    filter.parse().map_err(|err| format!("{err}"))
 }
 
 /// Parse the command-line arguments.
 pub fn parse() -> Cli {
     Cli::parse()
 }
+
+/// Optional ISA extensions left enabled after disabling `disabled`
+pub fn enabled_extensions(disabled: &[Extension]) -> Extensions {
+    disabled
+        .iter()
+        .fold(Extensions::ALL, |exts, ext| exts.without(*ext))
+}
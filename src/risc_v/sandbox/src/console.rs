@@ -0,0 +1,124 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Console output of the kernel, decoding the structured log records written
+//! to its debug log.
+
+use std::io::Write;
+use tezos_smart_rollup_debug::log::{self, DecodeError, LogFilter};
+
+/// Maximum size of a buffered record. Longer records, or a stray record
+/// separator followed by a huge length, are printed as plain text rather
+/// than buffered forever.
+const MAX_RECORD_SIZE: usize = 4096;
+
+/// Forward bytes written to the UART console to an output, pretty-printing
+/// the log records kept by the filter.
+pub struct Console<W: Write> {
+    filter: LogFilter,
+    /// Bytes of the record being written, if any.
+    record: Vec<u8>,
+    out: W,
+}
+
+impl<W: Write> Console<W> {
+    pub fn new(filter: LogFilter, out: W) -> Self {
+        Self {
+            filter,
+            record: Vec::new(),
+            out,
+        }
+    }
+
+    pub fn output(&mut self, byte: u8) {
+        if self.record.is_empty() && !log::is_record(&[byte]) {
+            return self.write(&[byte]);
+        }
+
+        self.record.push(byte);
+        match log::decode(&self.record) {
+            Err(DecodeError::Incomplete) if self.record.len() < MAX_RECORD_SIZE => {}
+            Ok((record, _)) => {
+                if self.filter.matches(&record) {
+                    self.write(format!("{record}\n").as_bytes());
+                }
+                self.record.clear();
+            }
+            // Not a record after all, or too long to buffer: print it as is.
+            Err(DecodeError::Invalid | DecodeError::Incomplete) => {
+                let record = std::mem::take(&mut self.record);
+                self.write(&record);
+            }
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // Console output is best-effort, failing to print must not stop the machine.
+        let _ = self.out.write_all(bytes).and_then(|_| self.out.flush());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Console, MAX_RECORD_SIZE};
+    use tezos_smart_rollup_debug::log::{encode, Level, Value};
+
+    fn console_output(filter: &str, bytes: &[u8]) -> String {
+        let mut console = Console::new(filter.parse().unwrap(), Vec::new());
+        bytes.iter().for_each(|byte| console.output(*byte));
+        String::from_utf8(console.out).unwrap()
+    }
+
+    #[test]
+    fn test_records_are_decoded() {
+        let info = encode(
+            Level::Info,
+            "kernel",
+            "Deposit",
+            &[("amount", Value::Nat(5))],
+        );
+        let debug = encode(Level::Debug, "kernel", "Details", &[]);
+        let bytes = ["plain\n", &info, &debug, "text"].concat();
+
+        assert_eq!(
+            "plain\nINFO  kernel: Deposit amount=5\nDEBUG kernel: Details\ntext",
+            console_output("trace", bytes.as_bytes())
+        );
+        // Filtered records are dropped, but not plain text
+        assert_eq!(
+            "plain\nINFO  kernel: Deposit amount=5\ntext",
+            console_output("info", bytes.as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_invalid_records_are_printed() {
+        // A record separator not starting a valid record is printed as is,
+        // and the following bytes are decoded again.
+        let info = encode(Level::Info, "kernel", "Deposit", &[]);
+        let bytes = ["\x1e9abc ", &info].concat();
+
+        assert_eq!(
+            "\x1e9abc INFO  kernel: Deposit\n",
+            console_output("trace", bytes.as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_oversized_records_are_printed() {
+        // A record separator and level followed by a huge target length would
+        // keep the record incomplete: it is printed once over the size limit.
+        let line = "plain text\n".repeat(MAX_RECORD_SIZE / 8);
+        let bytes = ["\x1e2@@@@@1", &line].concat();
+        assert_eq!(bytes, console_output("trace", bytes.as_bytes()));
+
+        // Records are decoded again afterwards
+        let info = encode(Level::Info, "kernel", "Deposit", &[]);
+        let bytes = ["\x1e2@@@@@1", &line, &info].concat();
+        assert_eq!(
+            ["\x1e2@@@@@1", &line, "INFO  kernel: Deposit\n"].concat(),
+            console_output("trace", bytes.as_bytes())
+        );
+    }
+}
@@ -1,4 +1,5 @@
 // SPDX-FileCopyrightText: 2024 Nomadic Labs <contact@nomadic-labs.com>
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
@@ -11,12 +12,23 @@ use ratatui::{
     widgets::{block::*, *},
 };
 use risc_v_interpreter::{
-    machine_state::{mode::Mode, registers},
+    machine_state::{
+        bus::{main_memory::M1G, start_of_main_memory, Addressable},
+        csregisters::CSRegister,
+        extensions::Extensions,
+        mode::Mode,
+        registers::{self, parse_xregister, XRegister},
+        MachineState,
+    },
+    parser::{self, instruction::Instr},
+    state_backend::memory_backend::SliceManager,
     Interpreter, InterpreterResult,
 };
 use std::collections::{BTreeMap, HashSet};
+use symbols::Symbols;
 
 mod errors;
+mod symbols;
 mod tui;
 
 const GREEN: Color = tailwind::GREEN.c400;
@@ -24,10 +36,41 @@ const YELLOW: Color = tailwind::YELLOW.c400;
 const RED: Color = tailwind::RED.c500;
 const BLUE: Color = tailwind::BLUE.c400;
 const ORANGE: Color = tailwind::ORANGE.c500;
+const GRAY: Color = tailwind::GRAY.c500;
 const SELECTED_STYLE_FG: Color = BLUE;
 const NEXT_STYLE_FG: Color = GREEN;
 const MAX_STEPS: usize = 1_000_000;
 
+/// Number of bytes shown per line of the memory pane
+const MEMORY_LINE_WIDTH: u64 = 8;
+
+/// Number of lines of the memory pane
+const MEMORY_LINES: u16 = 8;
+
+/// CSRs shown in the CSR view
+const CSRS: [CSRegister; 20] = [
+    CSRegister::mstatus,
+    CSRegister::misa,
+    CSRegister::medeleg,
+    CSRegister::mideleg,
+    CSRegister::mie,
+    CSRegister::mip,
+    CSRegister::mtvec,
+    CSRegister::mscratch,
+    CSRegister::mepc,
+    CSRegister::mcause,
+    CSRegister::mtval,
+    CSRegister::sstatus,
+    CSRegister::sie,
+    CSRegister::sip,
+    CSRegister::stvec,
+    CSRegister::sscratch,
+    CSRegister::sepc,
+    CSRegister::scause,
+    CSRegister::stval,
+    CSRegister::satp,
+];
+
 #[derive(Debug)]
 struct Instruction<'a> {
     address: u64,
@@ -41,11 +84,46 @@ struct ProgramView<'a> {
     breakpoints: HashSet<u64>,
 }
 
+/// Registers shown in the registers pane
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RegisterView {
+    General,
+    Csr,
+}
+
+/// Command whose argument is being typed in by the user
+#[derive(Clone, Copy)]
+enum PromptKind {
+    GotoMemory,
+    EditRegister,
+    EditMemory,
+}
+
+impl PromptKind {
+    fn label(self) -> &'static str {
+        match self {
+            PromptKind::GotoMemory => " Go to address or function: ",
+            PromptKind::EditRegister => " Set register (e.g. a0=0x10): ",
+            PromptKind::EditMemory => " Write memory (e.g. 0x80000000=deadbeef): ",
+        }
+    }
+}
+
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+}
+
 pub struct DebuggerApp<'a> {
     title: &'a str,
     interpreter: &'a mut Interpreter<'a>,
     program: ProgramView<'a>,
+    symbols: Symbols,
     status: InterpreterResult,
+    register_view: RegisterView,
+    memory_address: u64,
+    prompt: Option<Prompt>,
+    message: Option<String>,
 }
 
 macro_rules! register_line {
@@ -59,13 +137,24 @@ macro_rules! register_line {
 }
 
 impl<'a> DebuggerApp<'a> {
-    pub fn launch(fname: &str, contents: &[u8]) -> Result<()> {
+    pub fn launch(fname: &str, contents: &[u8], extensions: Extensions) -> Result<()> {
         let mut backend = Interpreter::create_backend();
-        let (mut interpreter, prog) =
-            Interpreter::new_with_parsed_program(&mut backend, contents, None, Mode::User)?;
+        let (mut interpreter, prog, symbol_table) = Interpreter::new_with_parsed_program(
+            &mut backend,
+            contents,
+            None,
+            Mode::User,
+            extensions,
+        )?;
+        let symbols = Symbols::load(contents, start_of_main_memory::<M1G>(), &symbol_table);
         errors::install_hooks()?;
         let terminal = tui::init()?;
-        DebuggerApp::new(&mut interpreter, fname, &prog).run_debugger(terminal)?;
+        let mut app = DebuggerApp::new(&mut interpreter, fname, &prog);
+        match symbols {
+            Ok(symbols) => app.symbols = symbols,
+            Err(err) => app.message = Some(format!("No symbols: {err}")),
+        }
+        app.run_debugger(terminal)?;
         tui::restore()?;
         Ok(())
     }
@@ -75,6 +164,7 @@ impl<'a> DebuggerApp<'a> {
         title: &'a str,
         program: &'a BTreeMap<u64, String>,
     ) -> Self {
+        let memory_address = interpreter.read_pc();
         Self {
             title,
             interpreter,
@@ -87,7 +177,12 @@ impl<'a> DebuggerApp<'a> {
                     })
                     .collect::<Vec<Instruction>>(),
             ),
+            symbols: Symbols::default(),
             status: InterpreterResult::Running(0),
+            register_view: RegisterView::General,
+            memory_address,
+            prompt: None,
+            message: None,
         }
     }
 
@@ -95,19 +190,42 @@ impl<'a> DebuggerApp<'a> {
         loop {
             self.draw(&mut terminal)?;
             if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    use KeyCode::*;
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+
+                use KeyCode::*;
+                if let Some(prompt) = self.prompt.as_mut() {
                     match key.code {
-                        Char('q') | Esc => return Ok(()),
-                        Char('s') => self.step(1),
-                        Char('b') => self.program.set_breakpoint(),
-                        Char('r') => self.step_until_breakpoint(),
-                        Char('j') | Down => self.program.next(),
-                        Char('k') | Up => self.program.previous(),
-                        Char('g') | Home => self.program.go_top(),
-                        Char('G') | End => self.program.go_bottom(),
+                        Esc => self.prompt = None,
+                        Enter => self.submit_prompt(),
+                        Backspace => {
+                            prompt.input.pop();
+                        }
+                        Char(c) => prompt.input.push(c),
                         _ => {}
                     }
+                    continue;
+                }
+
+                self.message = None;
+                match key.code {
+                    Char('q') | Esc => return Ok(()),
+                    Char('s') => self.step(1),
+                    Char('b') => self.program.set_breakpoint(),
+                    Char('r') => self.step_until_breakpoint(),
+                    Char('f') => self.step_until_return(),
+                    Char('j') | Down => self.program.next(),
+                    Char('k') | Up => self.program.previous(),
+                    Char('g') | Home => self.program.go_top(),
+                    Char('G') | End => self.program.go_bottom(),
+                    Char('c') => self.toggle_register_view(),
+                    Char('m') => self.open_prompt(PromptKind::GotoMemory),
+                    Char('e') => self.open_prompt(PromptKind::EditRegister),
+                    Char('w') => self.open_prompt(PromptKind::EditMemory),
+                    PageDown => self.scroll_memory(MEMORY_LINES as i64),
+                    PageUp => self.scroll_memory(-(MEMORY_LINES as i64)),
+                    _ => {}
                 }
             }
         }
@@ -118,20 +236,30 @@ impl<'a> DebuggerApp<'a> {
         Ok(())
     }
 
-    fn update_after_step(&mut self, result: InterpreterResult) {
+    /// Select the instruction the PC points to.
+    fn sync_pc(&mut self) {
         let pc = self.interpreter.read_pc();
-        self.program.next_instr = self
+        match self
             .program
             .instructions
             .iter()
             .position(|instr| instr.address == pc)
-            .unwrap_or_else(|| {
-                panic!(
+        {
+            Some(next_instr) => {
+                self.program.next_instr = next_instr;
+                self.program.state.select(Some(next_instr));
+            }
+            None => {
+                self.message = Some(format!(
                     "pc {:x} does not correspond to any instruction's address",
                     pc
-                )
-            });
-        self.program.state.select(Some(self.program.next_instr));
+                ))
+            }
+        }
+    }
+
+    fn update_after_step(&mut self, result: InterpreterResult) {
+        self.sync_pc();
         self.status = result;
     }
 
@@ -148,21 +276,112 @@ impl<'a> DebuggerApp<'a> {
         self.update_after_step(result);
     }
 
+    /// Run until the current function returns to its caller, stopping early
+    /// at breakpoints. See [`CallTracker`] for how returns are recognised.
+    fn step_until_return(&mut self) {
+        let breakpoints = &self.program.breakpoints;
+        let mut calls = CallTracker::default();
+        let mut returned = false;
+        let mut first = true;
+
+        let result = self.interpreter.step_many(MAX_STEPS, |m| {
+            if returned || (!first && breakpoints.contains(&m.hart.pc.read())) {
+                return false;
+            }
+            first = false;
+
+            if let Some(instr) = next_instruction(m) {
+                returned = calls.returns(&instr);
+            }
+
+            true
+        });
+        self.update_after_step(result);
+    }
+
+    fn toggle_register_view(&mut self) {
+        self.register_view = match self.register_view {
+            RegisterView::General => RegisterView::Csr,
+            RegisterView::Csr => RegisterView::General,
+        };
+    }
+
+    fn scroll_memory(&mut self, lines: i64) {
+        let offset = lines.wrapping_mul(MEMORY_LINE_WIDTH as i64);
+        self.memory_address = self.memory_address.wrapping_add_signed(offset);
+    }
+
+    fn open_prompt(&mut self, kind: PromptKind) {
+        self.prompt = Some(Prompt {
+            kind,
+            input: String::new(),
+        });
+    }
+
+    fn submit_prompt(&mut self) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+
+        let result = match prompt.kind {
+            PromptKind::GotoMemory => self.goto_memory(prompt.input.trim()),
+            PromptKind::EditRegister => self.edit_register(prompt.input.trim()),
+            PromptKind::EditMemory => self.edit_memory(prompt.input.trim()),
+        };
+
+        if let Err(message) = result {
+            self.message = Some(message);
+        }
+    }
+
+    fn goto_memory(&mut self, input: &str) -> Result<(), String> {
+        self.memory_address = parse_location(input, &self.symbols)?;
+        Ok(())
+    }
+
+    fn edit_register(&mut self, input: &str) -> Result<(), String> {
+        match parse_register_edit(input)? {
+            RegisterEdit::Pc(value) => {
+                self.interpreter.write_pc(value);
+                self.sync_pc();
+            }
+            RegisterEdit::X(reg, value) => self.interpreter.write_register(reg, value),
+        }
+
+        Ok(())
+    }
+
+    fn edit_memory(&mut self, input: &str) -> Result<(), String> {
+        let (addr, bytes) = parse_memory_edit(input)?;
+        self.interpreter
+            .write_memory(addr, &bytes)
+            .map_err(|_| format!("Address {addr:x} is out of bounds"))
+    }
+
     fn render_program_pane(&mut self, area: Rect, buf: &mut Buffer) {
         let title = Title::from(format!(" {} ", self.title).bold());
         let block = Block::default()
             .title(title.alignment(Alignment::Left))
             .borders(Borders::ALL)
             .border_set(border::THICK);
+
+        let mut previous_line = None;
         let instructions: Vec<ListItem> = self
             .program
             .instructions
             .iter()
             .enumerate()
             .map(|(i, instr)| {
+                // Only mention source lines when they change.
+                let source = self.symbols.line_starting_at(instr.address);
+                let source = source.filter(|source| previous_line != Some(*source));
+                previous_line = source.or(previous_line);
+
                 instr.to_list_item(
                     i == self.program.next_instr,
                     self.program.breakpoints.contains(&instr.address),
+                    self.symbols.function_starting_at(instr.address),
+                    source,
                 )
             })
             .collect();
@@ -177,6 +396,13 @@ impl<'a> DebuggerApp<'a> {
     }
 
     fn render_registers_pane(&mut self, area: Rect, buf: &mut Buffer) {
+        match self.register_view {
+            RegisterView::General => self.render_general_registers(area, buf),
+            RegisterView::Csr => self.render_csrs(area, buf),
+        }
+    }
+
+    fn render_general_registers(&mut self, area: Rect, buf: &mut Buffer) {
         let title = Title::from(" Registers ".bold());
         let block = Block::default()
             .title(title.alignment(Alignment::Left))
@@ -224,17 +450,86 @@ impl<'a> DebuggerApp<'a> {
             .render(area, buf)
     }
 
+    fn render_csrs(&mut self, area: Rect, buf: &mut Buffer) {
+        let title = Title::from(" CSRs ".bold());
+        let block = Block::default()
+            .title(title.alignment(Alignment::Left))
+            .borders(Borders::ALL)
+            .border_set(border::THICK);
+
+        let mut lines = vec![Line::from(vec![
+            "   mode: ".into(),
+            format!("{:?}", self.interpreter.read_mode()).fg(YELLOW),
+        ])];
+        lines.extend(CSRS.iter().map(|&csr| {
+            Line::from(vec![
+                format!("   {csr}: ").into(),
+                format!("0x{:x}", self.interpreter.read_csr(csr)).fg(ORANGE),
+            ])
+        }));
+
+        Paragraph::new(Text::from(lines))
+            .left_aligned()
+            .block(block)
+            .render(area, buf)
+    }
+
+    fn render_memory_pane(&mut self, area: Rect, buf: &mut Buffer) {
+        let title = Title::from(" Memory ".bold());
+        let block = Block::default()
+            .title(title.alignment(Alignment::Left))
+            .borders(Borders::ALL)
+            .border_set(border::THICK);
+
+        let lines: Vec<Line> = (0..MEMORY_LINES as u64)
+            .map(|line| {
+                let address = self.memory_address.wrapping_add(line * MEMORY_LINE_WIDTH);
+                let mut bytes = [0u8; MEMORY_LINE_WIDTH as usize];
+                let readable = self.interpreter.read_memory(address, &mut bytes).is_ok();
+
+                let (hex, ascii) = if readable {
+                    let hex = bytes.map(|byte| format!("{byte:02x}")).join(" ");
+                    let ascii = bytes
+                        .iter()
+                        .map(|&byte| {
+                            if byte.is_ascii_graphic() {
+                                byte as char
+                            } else {
+                                '.'
+                            }
+                        })
+                        .collect::<String>();
+                    (hex, ascii)
+                } else {
+                    (
+                        ["??"; MEMORY_LINE_WIDTH as usize].join(" "),
+                        " ".repeat(MEMORY_LINE_WIDTH as usize),
+                    )
+                };
+
+                Line::from(vec![
+                    format!("   {address:08x}  ").fg(ORANGE),
+                    format!("{hex}  ").fg(YELLOW),
+                    ascii.fg(GRAY),
+                ])
+            })
+            .collect();
+
+        Paragraph::new(Text::from(lines))
+            .left_aligned()
+            .block(block)
+            .render(area, buf)
+    }
+
     fn render_status_pane(&mut self, area: Rect, buf: &mut Buffer) {
         let title = Title::from(" Status ".bold());
         let block = Block::default()
             .title(title.alignment(Alignment::Left))
             .borders(Borders::ALL)
             .border_set(border::THICK);
-        let pc_line = Line::from(vec![
-            "   PC: ".into(),
-            format!("{:x}", self.interpreter.read_pc()).fg(ORANGE),
-        ]);
-        let status_text = match &self.status {
+        let pc = self.interpreter.read_pc();
+        let pc_line = Line::from(vec!["   PC: ".into(), format!("{:x}", pc).fg(ORANGE)]);
+        let mut status_text = match &self.status {
             InterpreterResult::Running(steps) => vec![
                 Line::from(vec!["   Running".bold().fg(GREEN)]),
                 Line::from(vec![format!("   Steps executed: {}", steps).into()]),
@@ -254,6 +549,25 @@ impl<'a> DebuggerApp<'a> {
                 pc_line,
             ],
         };
+
+        if let Some((function, offset)) = self.symbols.function_at(pc) {
+            status_text.push(Line::from(vec![
+                "   Function: ".into(),
+                format!("{function}+0x{offset:x}").fg(BLUE),
+            ]));
+        }
+
+        if let Some((file, line)) = self.symbols.line_at(pc) {
+            status_text.push(Line::from(vec![
+                "   Source: ".into(),
+                format!("{file}:{line}").fg(BLUE),
+            ]));
+        }
+
+        if let Some(message) = &self.message {
+            status_text.push(Line::from(vec![format!("   {message}").fg(RED)]));
+        }
+
         Paragraph::new(Text::from(status_text))
             .left_aligned()
             .block(block)
@@ -261,6 +575,16 @@ impl<'a> DebuggerApp<'a> {
     }
 
     fn render_bottom_bar(&mut self, area: Rect, buf: &mut Buffer) {
+        if let Some(prompt) = &self.prompt {
+            Line::from(vec![
+                prompt.kind.label().fg(BLUE).bold(),
+                prompt.input.as_str().into(),
+                "█".into(),
+            ])
+            .render(area, buf);
+            return;
+        }
+
         Line::from(vec![
             " Step ".into(),
             "<s>  ".fg(BLUE).bold(),
@@ -270,6 +594,16 @@ impl<'a> DebuggerApp<'a> {
             "<b>  ".fg(BLUE).bold(),
             " Run ".into(),
             "<r>  ".fg(BLUE).bold(),
+            " Finish function ".into(),
+            "<f>  ".fg(BLUE).bold(),
+            " CSRs ".into(),
+            "<c>  ".fg(BLUE).bold(),
+            " Memory ".into(),
+            "<m/PgUp/PgDn>  ".fg(BLUE).bold(),
+            " Edit register ".into(),
+            "<e>  ".fg(BLUE).bold(),
+            " Edit memory ".into(),
+            "<w>  ".fg(BLUE).bold(),
             " Quit ".into(),
             "<q> ".fg(BLUE).bold(),
         ])
@@ -292,18 +626,29 @@ impl Widget for &mut DebuggerApp<'_> {
 
         let rhs_layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints(vec![Constraint::Fill(1), Constraint::Length(5)]);
-        let [registers_area, status_area] = rhs_layout.areas(rhs_area);
+            .constraints(vec![
+                Constraint::Fill(1),
+                Constraint::Length(MEMORY_LINES + 2),
+                Constraint::Length(8),
+            ]);
+        let [registers_area, memory_area, status_area] = rhs_layout.areas(rhs_area);
 
         self.render_program_pane(program_area, buf);
         self.render_registers_pane(registers_area, buf);
+        self.render_memory_pane(memory_area, buf);
         self.render_status_pane(status_area, buf);
         self.render_bottom_bar(outer_layout[1], buf);
     }
 }
 
 impl Instruction<'_> {
-    fn to_list_item(&self, next: bool, breakpoint: bool) -> ListItem {
+    fn to_list_item(
+        &self,
+        next: bool,
+        breakpoint: bool,
+        function: Option<&str>,
+        source: Option<(&str, u64)>,
+    ) -> ListItem {
         let color = if next {
             Style::default()
                 .add_modifier(Modifier::BOLD)
@@ -312,6 +657,15 @@ impl Instruction<'_> {
         } else {
             Style::default()
         };
+
+        let mut lines = Vec::new();
+        if let Some(function) = function {
+            lines.push(Line::from(format!(" <{function}>:").fg(BLUE).bold()));
+        }
+        if let Some((file, line)) = source {
+            lines.push(Line::from(format!("     {file}:{line}").fg(GRAY)));
+        }
+
         let mut line = Vec::new();
         line.push(
             format!(
@@ -326,7 +680,9 @@ impl Instruction<'_> {
             line.push(" ".into());
         }
         line.pop();
-        ListItem::new(Line::from(line).style(color))
+        lines.push(Line::from(line).style(color));
+
+        ListItem::new(Text::from(lines))
     }
 }
 
@@ -386,3 +742,188 @@ impl ProgramView<'_> {
         self.state.select(Some(self.instructions.len() - 1));
     }
 }
+
+/// Follows calls and returns to find when the function in which tracking
+/// started returns.
+///
+/// Calls and returns are recognised using the return-address stack hints of
+/// the RISC-V ISA manual: `ra` and `t0` are link registers, jumps writing a
+/// link register are calls and `jalr`s reading a link register other than the
+/// one they write are returns. Tail calls are plain jumps, so the return of a
+/// tail-called function is the return of the function which made the tail
+/// call. Jumps not following these conventions, e.g. `longjmp`, are not
+/// tracked.
+#[derive(Debug, Default)]
+struct CallTracker {
+    depth: usize,
+}
+
+impl CallTracker {
+    /// Account for `instr` being executed. Returns whether it returns from the
+    /// function in which tracking started.
+    fn returns(&mut self, instr: &Instr) -> bool {
+        let (rd, rs1) = match instr {
+            Instr::Jal(args) => (args.rd, None),
+            Instr::Jalr(args) => (args.rd, Some(args.rs1)),
+            _ => return false,
+        };
+        let is_link = |reg: XRegister| reg == registers::ra || reg == registers::t0;
+
+        // A jump between two different link registers returns and calls
+        // again, like a coroutine switch.
+        if rs1.is_some_and(|rs1| is_link(rs1) && rs1 != rd) {
+            match self.depth.checked_sub(1) {
+                Some(depth) => self.depth = depth,
+                None => return true,
+            }
+        }
+
+        if is_link(rd) {
+            self.depth += 1;
+        }
+
+        false
+    }
+}
+
+/// Decode the instruction the hart is about to execute.
+fn next_instruction(machine: &MachineState<M1G, SliceManager>) -> Option<Instr> {
+    let pc = machine.hart.pc.read();
+    let lower: u16 = machine.bus.read(pc).ok()?;
+    parser::parse(lower, || machine.bus.read(pc.wrapping_add(2))).ok()
+}
+
+/// Parse a value given either in hexadecimal with a `0x` prefix or in decimal.
+fn parse_value(input: &str) -> Option<u64> {
+    match input.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => input.parse().ok(),
+    }
+}
+
+/// Parse a register given by its ABI name (e.g. `a0`) or number (e.g. `x10`).
+fn parse_register(name: &str) -> Option<XRegister> {
+    (0..32).map(parse_xregister).find(|reg| {
+        reg.to_string() == name
+            || format!("{reg:?}") == name
+            || (name == "fp" && *reg == registers::s0)
+    })
+}
+
+/// Parse an address, or the name of a function.
+fn parse_location(input: &str, symbols: &Symbols) -> Result<u64, String> {
+    parse_value(input)
+        .or_else(|| symbols.function_address(input))
+        .ok_or_else(|| format!("Unknown address or function {input}"))
+}
+
+/// Register assignment typed in the prompt
+#[derive(Debug, PartialEq, Eq)]
+enum RegisterEdit {
+    Pc(u64),
+    X(XRegister, u64),
+}
+
+/// Parse a register assignment of the form `<register>=<value>`.
+fn parse_register_edit(input: &str) -> Result<RegisterEdit, String> {
+    let (name, value) = input.split_once('=').ok_or("Expected <register>=<value>")?;
+    let (name, value) = (name.trim(), value.trim());
+    let value = parse_value(value).ok_or_else(|| format!("Invalid value {value}"))?;
+
+    if name == "pc" {
+        return Ok(RegisterEdit::Pc(value));
+    }
+
+    let reg = parse_register(name).ok_or_else(|| format!("Unknown register {name}"))?;
+    Ok(RegisterEdit::X(reg, value))
+}
+
+/// Parse a memory write of the form `<address>=<hex bytes>`.
+fn parse_memory_edit(input: &str) -> Result<(u64, Vec<u8>), String> {
+    let (addr, bytes) = input
+        .split_once('=')
+        .ok_or("Expected <address>=<hex bytes>")?;
+    let addr = parse_value(addr.trim()).ok_or_else(|| format!("Invalid address {addr}"))?;
+    let bytes = hex::decode(bytes.trim()).map_err(|err| format!("Invalid bytes: {err}"))?;
+    Ok((addr, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_location, parse_memory_edit, parse_register_edit, parse_value, symbols::Symbols,
+        CallTracker, RegisterEdit,
+    };
+    use risc_v_interpreter::{
+        machine_state::registers::{a0, ra, s0, t0, t1, x0, XRegister},
+        parser::instruction::{ITypeArgs, Instr, UJTypeArgs},
+    };
+
+    fn jal(rd: XRegister) -> Instr {
+        Instr::Jal(UJTypeArgs { rd, imm: 16 })
+    }
+
+    fn jalr(rd: XRegister, rs1: XRegister) -> Instr {
+        Instr::Jalr(ITypeArgs { rd, rs1, imm: 0 })
+    }
+
+    #[test]
+    fn test_call_tracker() {
+        // Nested calls through `ra` return before the current function.
+        let mut calls = CallTracker::default();
+        assert!(!calls.returns(&jal(ra)));
+        assert!(!calls.returns(&jalr(ra, a0)));
+        assert!(!calls.returns(&jalr(x0, ra)));
+        assert!(!calls.returns(&jalr(x0, ra)));
+        assert!(calls.returns(&jalr(x0, ra)));
+
+        // So do calls linked through `t0`, e.g. to save and restore registers.
+        let mut calls = CallTracker::default();
+        assert!(!calls.returns(&jal(t0)));
+        assert!(!calls.returns(&jalr(x0, t0)));
+        assert!(calls.returns(&jalr(x0, ra)));
+
+        // Tail calls and other jumps are not calls, the tail-called function
+        // returns for the current one.
+        let mut calls = CallTracker::default();
+        assert!(!calls.returns(&jal(x0)));
+        assert!(!calls.returns(&jalr(x0, t1)));
+        assert!(!calls.returns(&Instr::Unknown { instr: 0 }));
+        assert!(calls.returns(&jalr(x0, ra)));
+
+        // Swapping link registers returns and calls again.
+        let mut calls = CallTracker::default();
+        assert!(!calls.returns(&jal(ra)));
+        assert!(!calls.returns(&jalr(t0, ra)));
+        assert!(!calls.returns(&jalr(ra, ra)));
+        assert!(!calls.returns(&jalr(x0, ra)));
+        assert!(!calls.returns(&jalr(x0, t0)));
+        assert!(calls.returns(&jalr(ra, t0)));
+    }
+
+    #[test]
+    fn test_parse_prompts() {
+        assert_eq!(parse_value("0x10"), Some(16));
+        assert_eq!(parse_value("10"), Some(10));
+        assert_eq!(parse_value("0xg"), None);
+
+        let symbols = Symbols::default();
+        assert_eq!(parse_location("0x80000000", &symbols), Ok(0x8000_0000));
+        assert!(parse_location("main", &symbols).is_err());
+
+        assert_eq!(parse_register_edit("pc=0x10"), Ok(RegisterEdit::Pc(16)));
+        assert_eq!(parse_register_edit(" a0 = 7"), Ok(RegisterEdit::X(a0, 7)));
+        assert_eq!(parse_register_edit("x10=7"), Ok(RegisterEdit::X(a0, 7)));
+        assert_eq!(parse_register_edit("fp=1"), Ok(RegisterEdit::X(s0, 1)));
+        assert!(parse_register_edit("a0").is_err());
+        assert!(parse_register_edit("y0=1").is_err());
+        assert!(parse_register_edit("a0=zz").is_err());
+
+        assert_eq!(
+            parse_memory_edit("0x80000000 = deadbeef"),
+            Ok((0x8000_0000, vec![0xde, 0xad, 0xbe, 0xef]))
+        );
+        assert!(parse_memory_edit("0x80000000=xyz").is_err());
+        assert!(parse_memory_edit("main=00").is_err());
+    }
+}
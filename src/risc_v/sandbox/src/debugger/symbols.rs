@@ -0,0 +1,218 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Symbolisation of program addresses using the symbol table returned by the
+//! kernel loader and the DWARF line tables
+
+use gimli::{EndianSlice, LittleEndian};
+use goblin::elf::{header::ET_DYN, Elf};
+use kernel_loader::{SymbolKind, SymbolTable};
+use std::{borrow::Cow, collections::BTreeMap, error::Error, path::Path};
+
+/// A function from the symbol table
+struct Function {
+    name: String,
+    size: u64,
+}
+
+/// A row of the DWARF line table
+struct SourceLine {
+    file: String,
+    line: u64,
+}
+
+/// Function names and source lines of a program
+#[derive(Default)]
+pub struct Symbols {
+    functions: BTreeMap<u64, Function>,
+    lines: BTreeMap<u64, SourceLine>,
+}
+
+impl Symbols {
+    /// Extract symbols from an ELF executable, given the symbol table obtained
+    /// when loading it. Relocatable executables are assumed to be loaded at
+    /// `start`, like the kernel loader does.
+    pub fn load(
+        contents: &[u8],
+        start: u64,
+        symbols: &SymbolTable,
+    ) -> Result<Self, Box<dyn Error>> {
+        let elf = Elf::parse(contents)?;
+        let offset = if elf.header.e_type == ET_DYN {
+            start
+        } else {
+            0
+        };
+
+        let functions = symbols
+            .iter()
+            .filter(|sym| sym.kind == SymbolKind::Function && sym.address != offset)
+            .map(|sym| {
+                let function = Function {
+                    name: format!("{:#}", rustc_demangle::demangle(&sym.name)),
+                    size: sym.size,
+                };
+                (sym.address, function)
+            })
+            .collect();
+
+        Ok(Self {
+            functions,
+            lines: line_table(&elf, contents, offset)?,
+        })
+    }
+
+    /// Find the function containing `addr`. Returns its name and the offset of
+    /// `addr` from the start of the function.
+    pub fn function_at(&self, addr: u64) -> Option<(&str, u64)> {
+        let (start, function) = self.functions.range(..=addr).next_back()?;
+        let offset = addr - start;
+        (offset < function.size.max(1)).then_some((function.name.as_str(), offset))
+    }
+
+    /// Name of the function starting exactly at `addr`
+    pub fn function_starting_at(&self, addr: u64) -> Option<&str> {
+        self.functions
+            .get(&addr)
+            .map(|function| function.name.as_str())
+    }
+
+    /// Find the address of the function called `name`.
+    pub fn function_address(&self, name: &str) -> Option<u64> {
+        self.functions
+            .iter()
+            .find(|(_, function)| function.name == name)
+            .map(|(addr, _)| *addr)
+    }
+
+    /// Source location of the instruction at `addr`
+    pub fn line_at(&self, addr: u64) -> Option<(&str, u64)> {
+        let (_, line) = self.lines.range(..=addr).next_back()?;
+        Some((line.file.as_str(), line.line))
+    }
+
+    /// Source location, if a line table row starts exactly at `addr`
+    pub fn line_starting_at(&self, addr: u64) -> Option<(&str, u64)> {
+        let line = self.lines.get(&addr)?;
+        Some((line.file.as_str(), line.line))
+    }
+}
+
+/// Collect the rows of all DWARF line programs.
+fn line_table(
+    elf: &Elf,
+    contents: &[u8],
+    offset: u64,
+) -> Result<BTreeMap<u64, SourceLine>, gimli::Error> {
+    let load_section = |id: gimli::SectionId| -> Result<Cow<[u8]>, gimli::Error> {
+        let section = elf
+            .section_headers
+            .iter()
+            .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(id.name()));
+        let data = section
+            .and_then(|header| header.file_range())
+            .and_then(|range| contents.get(range))
+            .unwrap_or_default();
+        Ok(Cow::Borrowed(data))
+    };
+
+    let dwarf = gimli::Dwarf::load(load_section)?;
+    let dwarf = dwarf.borrow(|section| EndianSlice::new(section, LittleEndian));
+
+    let mut lines = BTreeMap::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let Some(program) = unit.line_program.clone() else {
+            continue;
+        };
+
+        let mut rows = program.rows();
+        while let Some((header, row)) = rows.next_row()? {
+            let Some(line) = row.line().filter(|_| !row.end_sequence()) else {
+                continue;
+            };
+
+            let file = match row.file(header) {
+                Some(file) => {
+                    let path = dwarf.attr_string(&unit, file.path_name())?;
+                    let path = path.to_string_lossy();
+                    // Only the file name is shown, full paths take up too much space.
+                    Path::new(path.as_ref())
+                        .file_name()
+                        .map_or_else(|| path.to_string(), |name| name.to_string_lossy().into())
+                }
+                None => "??".to_string(),
+            };
+
+            lines.insert(
+                row.address() + offset,
+                SourceLine {
+                    file,
+                    line: line.get(),
+                },
+            );
+        }
+    }
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Function, SourceLine, Symbols};
+    use std::collections::BTreeMap;
+
+    fn symbols() -> Symbols {
+        let function = |name: &str, size| Function {
+            name: name.to_string(),
+            size,
+        };
+        let line = |file: &str, line| SourceLine {
+            file: file.to_string(),
+            line,
+        };
+
+        Symbols {
+            functions: BTreeMap::from([
+                (0x1000, function("main", 0x20)),
+                (0x1020, function("helper", 0x10)),
+                // Symbols without a size only cover their first instruction.
+                (0x2000, function("_start", 0)),
+            ]),
+            lines: BTreeMap::from([(0x1000, line("main.rs", 3)), (0x1008, line("main.rs", 4))]),
+        }
+    }
+
+    #[test]
+    fn test_function_lookup() {
+        let symbols = symbols();
+
+        assert_eq!(symbols.function_at(0x1000), Some(("main", 0)));
+        assert_eq!(symbols.function_at(0x101c), Some(("main", 0x1c)));
+        assert_eq!(symbols.function_at(0x1024), Some(("helper", 4)));
+        assert_eq!(symbols.function_at(0x1030), None);
+        assert_eq!(symbols.function_at(0x2000), Some(("_start", 0)));
+        assert_eq!(symbols.function_at(0x2002), None);
+        assert_eq!(symbols.function_at(0xfff), None);
+
+        assert_eq!(symbols.function_starting_at(0x1020), Some("helper"));
+        assert_eq!(symbols.function_starting_at(0x1024), None);
+
+        assert_eq!(symbols.function_address("helper"), Some(0x1020));
+        assert_eq!(symbols.function_address("missing"), None);
+    }
+
+    #[test]
+    fn test_line_lookup() {
+        let symbols = symbols();
+
+        assert_eq!(symbols.line_at(0x1004), Some(("main.rs", 3)));
+        assert_eq!(symbols.line_at(0x1010), Some(("main.rs", 4)));
+        assert_eq!(symbols.line_at(0xfff), None);
+
+        assert_eq!(symbols.line_starting_at(0x1008), Some(("main.rs", 4)));
+        assert_eq!(symbols.line_starting_at(0x1004), None);
+    }
+}
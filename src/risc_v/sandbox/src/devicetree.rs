@@ -6,6 +6,7 @@
 //!   - https://elinux.org/Device_Tree_Usage
 //!   - https://github.com/devicetree-org/devicetree-specification/releases/tag/v0.4
 
+use risc_v_interpreter::machine_state::extensions::Extensions;
 use rvemu::{bus::DRAM_BASE, dram::DRAM_SIZE};
 use std::error::Error;
 
@@ -13,8 +14,12 @@ use std::error::Error;
 pub use risc_v_interpreter::devicetree::InitialRamDisk;
 
 /// Generate a Flattened Device Tree for the current hardware configuration.
+/// rvemu implements none of the optional extensions.
 pub fn generate(initrd: Option<InitialRamDisk>) -> Result<Vec<u8>, Box<dyn Error>> {
     Ok(risc_v_interpreter::devicetree::generate_custom(
-        DRAM_BASE, DRAM_SIZE, initrd,
+        DRAM_BASE,
+        DRAM_SIZE,
+        initrd,
+        Extensions::NONE,
     )?)
 }
@@ -0,0 +1,695 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! GDB remote serial protocol server
+//!
+//! Exposes an [`Interpreter`] to GDB so that kernels can be debugged with
+//! `riscv64-unknown-elf-gdb`, including source-level debugging using the DWARF
+//! information of the ELF file given to GDB.
+//!
+//! Breakpoints are kept by the server rather than patched into memory.
+//! Watchpoints are implemented by decoding every instruction before it is
+//! executed and checking whether the load or store it performs overlaps with
+//! a watched range.
+
+mod packet;
+
+use packet::{Connection, Received};
+use risc_v_interpreter::{
+    machine_state::{
+        bus::{main_memory::MainMemoryLayout, Addressable},
+        registers::{parse_xregister, XRegister},
+        MachineState,
+    },
+    parser::{self, instruction::Instr},
+    state_backend::Manager,
+    Interpreter, InterpreterResult,
+};
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    fmt::Write,
+    io,
+    net::{Ipv4Addr, TcpListener},
+};
+
+/// Number of general purpose registers followed by the PC
+const NUM_REGISTERS: usize = 33;
+
+/// Index of the PC in the register file described to GDB
+const PC_REGNUM: usize = 32;
+
+/// Largest packet we are willing to receive
+const PACKET_SIZE: usize = 0x4000;
+
+/// Number of steps to run before checking for an interrupt from the client
+const CONTINUE_CHUNK: usize = 100_000;
+
+/// GDB signal numbers
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSYS: u8 = 12;
+
+/// Register names in the order of GDB's RISC-V register numbering
+const REGISTER_NAMES: [&str; NUM_REGISTERS] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "fp", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6", "pc",
+];
+
+/// Kind of memory access a watchpoint triggers on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+impl WatchKind {
+    fn matches(self, is_store: bool) -> bool {
+        match self {
+            WatchKind::Write => is_store,
+            WatchKind::Read => !is_store,
+            WatchKind::Access => true,
+        }
+    }
+
+    fn stop_name(self) -> &'static str {
+        match self {
+            WatchKind::Write => "watch",
+            WatchKind::Read => "rwatch",
+            WatchKind::Access => "awatch",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Watchpoint {
+    kind: WatchKind,
+    addr: u64,
+    len: u64,
+}
+
+/// Breakpoints and watchpoints set by the client
+#[derive(Debug, Default)]
+struct StopPoints {
+    breakpoints: BTreeSet<u64>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl StopPoints {
+    /// Insert or remove the stop point described by the arguments of a `Z` or
+    /// `z` packet. Returns the reply.
+    fn update(&mut self, insert: bool, args: &str) -> &'static str {
+        let mut parts = args.splitn(3, ',');
+        let (Some(kind), Some(addr), Some(len)) = (parts.next(), parts.next(), parts.next()) else {
+            return ERROR;
+        };
+
+        let (Ok(addr), Ok(len)) = (
+            u64::from_str_radix(addr, 16),
+            // Breakpoint kinds may be followed by conditions which we ignore.
+            u64::from_str_radix(len.split(';').next().unwrap_or_default(), 16),
+        ) else {
+            return ERROR;
+        };
+
+        let kind = match kind {
+            // Software and hardware breakpoints are the same to us.
+            "0" | "1" => {
+                if insert {
+                    self.breakpoints.insert(addr);
+                } else {
+                    self.breakpoints.remove(&addr);
+                }
+                return OK;
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return UNSUPPORTED,
+        };
+
+        let watchpoint = Watchpoint { kind, addr, len };
+        if insert {
+            self.watchpoints.push(watchpoint);
+        } else {
+            self.watchpoints.retain(|other| *other != watchpoint);
+        }
+
+        OK
+    }
+}
+
+/// Why the target stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StopReason {
+    Signal(u8),
+    Watch(WatchKind, u64),
+    Exited(usize),
+}
+
+impl StopReason {
+    fn reply(self) -> String {
+        match self {
+            StopReason::Signal(signal) => format!("S{signal:02x}"),
+            StopReason::Watch(kind, addr) => {
+                format!("T{SIGTRAP:02x}{}:{addr:x};", kind.stop_name())
+            }
+            StopReason::Exited(code) => format!("W{:02x}", code as u8),
+        }
+    }
+}
+
+/// Listen on `port` for a GDB client and serve it until it detaches.
+pub fn serve(interpreter: &mut Interpreter, port: u16) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    eprintln!("Waiting for GDB to connect on port {port}");
+
+    let (stream, peer) = listener.accept()?;
+    eprintln!("GDB connected from {peer}");
+
+    let mut server = GdbServer {
+        interpreter,
+        connection: Connection::new(stream)?,
+        stop_points: StopPoints::default(),
+        stop: StopReason::Signal(SIGTRAP),
+    };
+    server.run()?;
+
+    Ok(())
+}
+
+struct GdbServer<'a, 'b> {
+    interpreter: &'b mut Interpreter<'a>,
+    connection: Connection,
+    stop_points: StopPoints,
+    stop: StopReason,
+}
+
+/// What to do after handling a packet
+enum Action {
+    Reply(Vec<u8>),
+    StartNoAckMode,
+    Detach,
+    Kill,
+}
+
+impl From<&str> for Action {
+    fn from(reply: &str) -> Self {
+        Action::Reply(reply.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for Action {
+    fn from(reply: String) -> Self {
+        Action::Reply(reply.into_bytes())
+    }
+}
+
+const OK: &str = "OK";
+const ERROR: &str = "E01";
+const UNSUPPORTED: &str = "";
+
+impl GdbServer<'_, '_> {
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            let packet = match self.connection.receive()? {
+                Some(Received::Packet(packet)) => packet,
+                // The target is not running, so there's nothing to interrupt.
+                Some(Received::Interrupt) => continue,
+                None => return Ok(()),
+            };
+
+            match self.handle(&packet)? {
+                Action::Reply(reply) => self.connection.send(&reply)?,
+                Action::StartNoAckMode => {
+                    self.connection.send(OK.as_bytes())?;
+                    self.connection.disable_acks();
+                }
+                Action::Detach => {
+                    self.connection.send(OK.as_bytes())?;
+                    return Ok(());
+                }
+                Action::Kill => return Ok(()),
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &[u8]) -> io::Result<Action> {
+        let Some((&command, args)) = packet.split_first() else {
+            return Ok(UNSUPPORTED.into());
+        };
+        let args = String::from_utf8_lossy(args);
+
+        let action = match command {
+            b'?' => self.stop.reply().into(),
+            b'g' => self.read_registers().into(),
+            b'G' => self.write_registers(&args).into(),
+            b'p' => self.read_register(&args).into(),
+            b'P' => self.write_register(&args).into(),
+            b'm' => self.read_memory(&args).into(),
+            b'M' => self.write_memory(&args).into(),
+            b'c' => self.resume(false, Some(&args))?.into(),
+            b's' => self.resume(true, Some(&args))?.into(),
+            // The signal to deliver is ignored, it may be followed by an address.
+            b'C' => self
+                .resume(false, args.split_once(';').map(|(_, addr)| addr))?
+                .into(),
+            b'S' => self
+                .resume(true, args.split_once(';').map(|(_, addr)| addr))?
+                .into(),
+            b'Z' => self.stop_points.update(true, &args).into(),
+            b'z' => self.stop_points.update(false, &args).into(),
+            b'H' | b'T' => OK.into(),
+            b'q' => self.query(&args).into(),
+            b'Q' if args == "StartNoAckMode" => Action::StartNoAckMode,
+            b'v' => self.handle_v(&args)?.into(),
+            b'D' => Action::Detach,
+            b'k' => Action::Kill,
+            _ => UNSUPPORTED.into(),
+        };
+
+        Ok(action)
+    }
+
+    fn query(&mut self, args: &str) -> String {
+        if args.starts_with("Supported") {
+            format!(
+                "PacketSize={PACKET_SIZE:x};qXfer:features:read+;QStartNoAckMode+;vContSupported+"
+            )
+        } else if let Some(annex) = args.strip_prefix("Xfer:features:read:target.xml:") {
+            read_chunk(&target_xml(), annex)
+        } else if args == "Attached" {
+            "1".to_string()
+        } else if args == "C" {
+            "QC1".to_string()
+        } else if args == "fThreadInfo" {
+            "m1".to_string()
+        } else if args == "sThreadInfo" {
+            "l".to_string()
+        } else {
+            UNSUPPORTED.to_string()
+        }
+    }
+
+    fn handle_v(&mut self, args: &str) -> io::Result<String> {
+        if args == "Cont?" {
+            return Ok("vCont;c;C;s;S".to_string());
+        }
+
+        if let Some(actions) = args.strip_prefix("Cont;") {
+            // There is a single thread, so the first action applies to it.
+            let action = actions.split(';').next().unwrap_or_default();
+            return match action.bytes().next() {
+                Some(b'c' | b'C') => self.resume(false, None),
+                Some(b's' | b'S') => self.resume(true, None),
+                _ => Ok(ERROR.to_string()),
+            };
+        }
+
+        Ok(UNSUPPORTED.to_string())
+    }
+
+    fn register(&self, regnum: usize) -> u64 {
+        if regnum == PC_REGNUM {
+            self.interpreter.read_pc()
+        } else {
+            self.interpreter.read_register(xregister(regnum))
+        }
+    }
+
+    fn set_register(&mut self, regnum: usize, value: u64) {
+        if regnum == PC_REGNUM {
+            self.interpreter.write_pc(value)
+        } else {
+            self.interpreter.write_register(xregister(regnum), value)
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        (0..NUM_REGISTERS)
+            .map(|regnum| hex::encode(self.register(regnum).to_le_bytes()))
+            .collect()
+    }
+
+    fn write_registers(&mut self, args: &str) -> &'static str {
+        let Ok(bytes) = hex::decode(args) else {
+            return ERROR;
+        };
+
+        if bytes.len() < NUM_REGISTERS * 8 {
+            return ERROR;
+        }
+
+        for (regnum, value) in bytes.chunks_exact(8).take(NUM_REGISTERS).enumerate() {
+            self.set_register(regnum, u64::from_le_bytes(value.try_into().unwrap()));
+        }
+
+        OK
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        match usize::from_str_radix(args, 16) {
+            Ok(regnum) if regnum < NUM_REGISTERS => {
+                hex::encode(self.register(regnum).to_le_bytes())
+            }
+            _ => ERROR.to_string(),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> &'static str {
+        let Some((regnum, value)) = args.split_once('=') else {
+            return ERROR;
+        };
+
+        let regnum = match usize::from_str_radix(regnum, 16) {
+            Ok(regnum) if regnum < NUM_REGISTERS => regnum,
+            _ => return ERROR,
+        };
+
+        match hex::decode(value)
+            .ok()
+            .and_then(|value| <[u8; 8]>::try_from(value).ok())
+        {
+            Some(value) => {
+                self.set_register(regnum, u64::from_le_bytes(value));
+                OK
+            }
+            None => ERROR,
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let Some((addr, len)) = parse_addr_len(args) else {
+            return ERROR.to_string();
+        };
+
+        let mut buffer = vec![0; (len as usize).min(PACKET_SIZE / 2)];
+        match self.interpreter.read_memory(addr, &mut buffer) {
+            Ok(()) => hex::encode(buffer),
+            Err(_) => ERROR.to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> &'static str {
+        let Some((location, data)) = args.split_once(':') else {
+            return ERROR;
+        };
+
+        let Some((addr, len)) = parse_addr_len(location) else {
+            return ERROR;
+        };
+
+        match hex::decode(data) {
+            Ok(data) if data.len() as u64 == len => {
+                match self.interpreter.write_memory(addr, &data) {
+                    Ok(()) => OK,
+                    Err(_) => ERROR,
+                }
+            }
+            _ => ERROR,
+        }
+    }
+
+    /// Continue or single-step the target, optionally resuming at `addr`.
+    /// Returns the stop reply.
+    fn resume(&mut self, step: bool, addr: Option<&str>) -> io::Result<String> {
+        if let Some(addr) = addr.filter(|addr| !addr.is_empty()) {
+            match u64::from_str_radix(addr, 16) {
+                Ok(addr) => self.interpreter.write_pc(addr),
+                Err(_) => return Ok(ERROR.to_string()),
+            }
+        }
+
+        self.stop = self.execute(step)?;
+        Ok(self.stop.reply())
+    }
+
+    fn execute(&mut self, step: bool) -> io::Result<StopReason> {
+        if let StopReason::Exited(code) = self.stop {
+            return Ok(StopReason::Exited(code));
+        }
+
+        let max_steps = if step { 1 } else { CONTINUE_CHUNK };
+        let mut first = true;
+
+        loop {
+            let breakpoints = &self.stop_points.breakpoints;
+            let watchpoints = &self.stop_points.watchpoints;
+            let mut hit = None;
+            let mut pending = None;
+
+            let result = self.interpreter.step_many(max_steps, |machine| {
+                if let Some(watch) = pending.take() {
+                    hit = Some(watch);
+                    return false;
+                }
+
+                let pc = machine.hart.pc.read();
+                if !first && breakpoints.contains(&pc) {
+                    hit = Some(StopReason::Signal(SIGTRAP));
+                    return false;
+                }
+
+                first = false;
+                pending = watchpoint_hit(machine, watchpoints);
+                true
+            });
+
+            match result {
+                InterpreterResult::Exit { code, .. } => return Ok(StopReason::Exited(code)),
+                InterpreterResult::Exception(exception, _) => {
+                    eprintln!("Unhandled exception: {exception:?}");
+                    return Ok(StopReason::Signal(SIGSYS));
+                }
+                InterpreterResult::Running(_) => {}
+            }
+
+            if let Some(stop) = hit.or(pending) {
+                return Ok(stop);
+            }
+
+            if step {
+                return Ok(StopReason::Signal(SIGTRAP));
+            }
+
+            if self.connection.poll_interrupt()? {
+                return Ok(StopReason::Signal(SIGINT));
+            }
+        }
+    }
+}
+
+/// Check whether the instruction about to be executed accesses memory
+/// covered by one of the watchpoints.
+fn watchpoint_hit<ML: MainMemoryLayout, M: Manager>(
+    machine: &MachineState<ML, M>,
+    watchpoints: &[Watchpoint],
+) -> Option<StopReason> {
+    if watchpoints.is_empty() {
+        return None;
+    }
+
+    let pc = machine.hart.pc.read();
+    let lower: u16 = machine.bus.read(pc).ok()?;
+    let instr = parser::parse(lower, || machine.bus.read(pc.wrapping_add(2))).ok()?;
+
+    let (base, offset, width, is_store) = match instr {
+        Instr::Lb(args) | Instr::Lbu(args) => (args.rs1, args.imm, 1, false),
+        Instr::Lh(args) | Instr::Lhu(args) => (args.rs1, args.imm, 2, false),
+        Instr::Lw(args) | Instr::Lwu(args) => (args.rs1, args.imm, 4, false),
+        Instr::Ld(args) => (args.rs1, args.imm, 8, false),
+        Instr::Sb(args) => (args.rs1, args.imm, 1, true),
+        Instr::Sh(args) => (args.rs1, args.imm, 2, true),
+        Instr::Sw(args) => (args.rs1, args.imm, 4, true),
+        Instr::Sd(args) => (args.rs1, args.imm, 8, true),
+        _ => return None,
+    };
+
+    let addr = machine
+        .hart
+        .xregisters
+        .read(base)
+        .wrapping_add(offset as u64);
+
+    watchpoints
+        .iter()
+        .find(|watch| {
+            watch.kind.matches(is_store)
+                && addr < watch.addr.saturating_add(watch.len)
+                && watch.addr < addr.saturating_add(width)
+        })
+        .map(|watch| StopReason::Watch(watch.kind, addr.max(watch.addr)))
+}
+
+fn xregister(regnum: usize) -> XRegister {
+    parse_xregister(regnum as u32)
+}
+
+/// Parse the `addr,length` arguments of memory packets.
+fn parse_addr_len(args: &str) -> Option<(u64, u64)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((
+        u64::from_str_radix(addr, 16).ok()?,
+        u64::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+/// Answer a `qXfer` read request of the form `offset,length`.
+fn read_chunk(document: &str, annex: &str) -> String {
+    let Some((offset, len)) = parse_addr_len(annex) else {
+        return ERROR.to_string();
+    };
+
+    let offset = (offset as usize).min(document.len());
+    let end = offset.saturating_add(len as usize).min(document.len());
+    let marker = if end == document.len() { 'l' } else { 'm' };
+
+    format!("{marker}{}", &document[offset..end])
+}
+
+/// Target description announcing the RV64 general purpose registers
+fn target_xml() -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd"><target version="1.0"><architecture>riscv:rv64</architecture><feature name="org.gnu.gdb.riscv.cpu">"#,
+    );
+
+    for (regnum, name) in REGISTER_NAMES.iter().enumerate() {
+        let kind = match *name {
+            "pc" | "ra" => "code_ptr",
+            "sp" | "gp" | "tp" | "fp" => "data_ptr",
+            _ => "int",
+        };
+        let _ = write!(
+            xml,
+            r#"<reg name="{name}" bitsize="64" type="{kind}" regnum="{regnum}"/>"#
+        );
+    }
+
+    xml.push_str("</feature></target>");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        read_chunk, watchpoint_hit, StopPoints, StopReason, WatchKind, Watchpoint, ERROR, OK,
+        UNSUPPORTED,
+    };
+    use risc_v_interpreter::{
+        machine_state::{
+            bus::{main_memory::M1K, start_of_main_memory, Addressable},
+            registers::x3,
+            MachineState, MachineStateLayout,
+        },
+        state_backend::{memory_backend::InMemoryBackend, Backend},
+    };
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_update_stop_points() {
+        let mut points = StopPoints::default();
+
+        // Conditions following the kind of a breakpoint are ignored.
+        assert_eq!(points.update(true, "0,1000,4"), OK);
+        assert_eq!(points.update(true, "1,2000,2;X1,0"), OK);
+        assert_eq!(points.breakpoints, BTreeSet::from([0x1000, 0x2000]));
+
+        assert_eq!(points.update(true, "2,3000,8"), OK);
+        assert_eq!(points.update(true, "4,3000,8"), OK);
+        assert_eq!(
+            points.watchpoints,
+            [
+                Watchpoint {
+                    kind: WatchKind::Write,
+                    addr: 0x3000,
+                    len: 8
+                },
+                Watchpoint {
+                    kind: WatchKind::Access,
+                    addr: 0x3000,
+                    len: 8
+                }
+            ]
+        );
+
+        // Only the stop point of the given kind is removed.
+        assert_eq!(points.update(false, "0,1000,4"), OK);
+        assert_eq!(points.update(false, "2,3000,8"), OK);
+        assert_eq!(points.breakpoints, BTreeSet::from([0x2000]));
+        assert_eq!(points.watchpoints.len(), 1);
+        assert_eq!(points.watchpoints[0].kind, WatchKind::Access);
+
+        assert_eq!(points.update(true, "0,1000"), ERROR);
+        assert_eq!(points.update(true, "0,xyz,4"), ERROR);
+        assert_eq!(points.update(true, "5,1000,4"), UNSUPPORTED);
+        assert_eq!(points.breakpoints, BTreeSet::from([0x2000]));
+    }
+
+    #[test]
+    fn test_read_chunk() {
+        let document = "abcdef";
+        assert_eq!(read_chunk(document, "0,4"), "mabcd");
+        assert_eq!(read_chunk(document, "4,4"), "lef");
+        assert_eq!(read_chunk(document, "0,ffffffffffffffff"), "labcdef");
+
+        // Reading past the end of the document yields an empty last chunk.
+        assert_eq!(read_chunk(document, "10,4"), "l");
+        assert_eq!(read_chunk(document, "4"), ERROR);
+    }
+
+    #[test]
+    fn test_watchpoint_hit() {
+        // ld x1, 0(x3); sd x1, 4(x3)
+        const LD: u32 = 0x0001B083;
+        const SD: u32 = 0x0011B223;
+
+        let (mut backend, placed) = InMemoryBackend::<MachineStateLayout<M1K>>::new();
+        let mut machine = MachineState::<M1K, _>::bind(backend.allocate(placed));
+        let start = start_of_main_memory::<M1K>();
+        let data = start + 0x100;
+        machine.bus.write_all(start, &[LD, SD]).unwrap();
+        machine.hart.xregisters.write(x3, data);
+
+        let watch = |kind, addr, len| Watchpoint { kind, addr, len };
+
+        // The load accesses 8 bytes at `data`.
+        machine.hart.pc.write(start);
+        assert_eq!(watchpoint_hit(&machine, &[]), None);
+        assert_eq!(
+            watchpoint_hit(&machine, &[watch(WatchKind::Read, data + 4, 1)]),
+            Some(StopReason::Watch(WatchKind::Read, data + 4))
+        );
+        assert_eq!(
+            watchpoint_hit(&machine, &[watch(WatchKind::Write, data, 8)]),
+            None
+        );
+        assert_eq!(
+            watchpoint_hit(&machine, &[watch(WatchKind::Access, data + 8, 8)]),
+            None
+        );
+
+        // The store accesses 8 bytes at `data + 4`.
+        machine.hart.pc.write(start + 4);
+        assert_eq!(
+            watchpoint_hit(
+                &machine,
+                &[
+                    watch(WatchKind::Read, data, 8),
+                    watch(WatchKind::Write, data, 8)
+                ]
+            ),
+            Some(StopReason::Watch(WatchKind::Write, data + 4))
+        );
+        assert_eq!(
+            watchpoint_hit(&machine, &[watch(WatchKind::Access, data + 11, 4)]),
+            Some(StopReason::Watch(WatchKind::Access, data + 11))
+        );
+        assert_eq!(
+            watchpoint_hit(&machine, &[watch(WatchKind::Access, data + 12, 4)]),
+            None
+        );
+    }
+}
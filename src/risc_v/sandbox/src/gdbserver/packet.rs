@@ -0,0 +1,351 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Packet framing of the GDB remote serial protocol
+
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+/// Byte sent by the client to interrupt a running target
+const INTERRUPT: u8 = 0x03;
+
+/// Offset between the repeat count of a run-length encoded byte and the
+/// character encoding it
+const RUN_LENGTH_OFFSET: u8 = 29;
+
+/// Something received from the client
+pub enum Received {
+    /// A packet with a valid checksum, unescaped and run-length decoded
+    Packet(Vec<u8>),
+
+    /// The client wants the target to stop.
+    Interrupt,
+}
+
+/// Connection to a GDB client
+pub struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    acks: bool,
+    /// Bytes received before an interrupt, which are read before the rest
+    pending: VecDeque<u8>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        Ok(Self {
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+            acks: true,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Stop sending and expecting acknowledgements.
+    pub fn disable_acks(&mut self) {
+        self.acks = false;
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.pending.pop_front() {
+            return Ok(Some(byte));
+        }
+
+        let mut byte = [0];
+        match self.reader.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    fn expect_byte(&mut self) -> io::Result<u8> {
+        self.read_byte()?
+            .ok_or_else(|| io::Error::from(ErrorKind::UnexpectedEof))
+    }
+
+    /// Wait for the next packet or interrupt. Returns `None` once the client
+    /// has closed the connection.
+    pub fn receive(&mut self) -> io::Result<Option<Received>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(INTERRUPT) => return Ok(Some(Received::Interrupt)),
+                Some(b'$') => {}
+                // Acknowledgements and noise between packets
+                Some(_) => continue,
+            }
+
+            let mut payload = Vec::new();
+            let mut checksum = 0u8;
+            let mut malformed = false;
+            loop {
+                match self.expect_byte()? {
+                    b'#' => break,
+                    b'}' => {
+                        let escaped = self.expect_byte()?;
+                        checksum = checksum.wrapping_add(b'}').wrapping_add(escaped);
+                        payload.push(escaped ^ 0x20);
+                    }
+                    b'*' => {
+                        let count = self.expect_byte()?;
+                        checksum = checksum.wrapping_add(b'*').wrapping_add(count);
+                        match (
+                            payload.last().copied(),
+                            count.checked_sub(RUN_LENGTH_OFFSET),
+                        ) {
+                            (Some(byte), Some(repeat)) => {
+                                payload.extend(std::iter::repeat(byte).take(repeat as usize))
+                            }
+                            // A run needs a preceding byte and a printable count.
+                            _ => malformed = true,
+                        }
+                    }
+                    byte => {
+                        checksum = checksum.wrapping_add(byte);
+                        payload.push(byte);
+                    }
+                }
+            }
+
+            let expected = [self.expect_byte()?, self.expect_byte()?];
+            let valid = !malformed
+                && std::str::from_utf8(&expected)
+                    .ok()
+                    .and_then(|expected| u8::from_str_radix(expected, 16).ok())
+                    == Some(checksum);
+
+            if self.acks {
+                self.writer.write_all(if valid { b"+" } else { b"-" })?;
+            }
+
+            if valid || (!self.acks && !malformed) {
+                return Ok(Some(Received::Packet(payload)));
+            }
+        }
+    }
+
+    /// Send a packet, retransmitting it until the client acknowledges it.
+    pub fn send(&mut self, payload: &[u8]) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(payload.len() + 4);
+        packet.push(b'$');
+
+        let mut checksum = 0u8;
+        for &byte in payload {
+            if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+                packet.extend_from_slice(&[b'}', byte ^ 0x20]);
+                checksum = checksum.wrapping_add(b'}').wrapping_add(byte ^ 0x20);
+            } else {
+                packet.push(byte);
+                checksum = checksum.wrapping_add(byte);
+            }
+        }
+        packet.extend_from_slice(format!("#{checksum:02x}").as_bytes());
+
+        loop {
+            self.writer.write_all(&packet)?;
+
+            if !self.acks {
+                return Ok(());
+            }
+
+            loop {
+                match self.expect_byte()? {
+                    b'+' => return Ok(()),
+                    b'-' => break,
+                    _ => continue,
+                }
+            }
+        }
+    }
+
+    /// Check whether the client has asked to interrupt the target without
+    /// blocking.
+    pub fn poll_interrupt(&mut self) -> io::Result<bool> {
+        self.reader.get_ref().set_nonblocking(true)?;
+        let pending = match self.reader.fill_buf() {
+            Ok(buffer) => buffer.iter().position(|&byte| byte == INTERRUPT),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => None,
+            Err(err) => {
+                self.reader.get_ref().set_nonblocking(false)?;
+                return Err(err);
+            }
+        };
+        self.reader.get_ref().set_nonblocking(false)?;
+
+        match pending {
+            Some(position) => {
+                // Only the interrupt is taken out of the stream, the bytes
+                // received before it are kept for the next packet.
+                self.pending
+                    .extend(self.reader.buffer()[..position].iter().copied());
+                self.reader.consume(position + 1);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Connection, Received};
+    use std::{
+        io::{ErrorKind, Read, Write},
+        net::{TcpListener, TcpStream},
+    };
+
+    /// Connect a [`Connection`] to a client socket over loopback.
+    fn connect() -> (Connection, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (Connection::new(server).unwrap(), client)
+    }
+
+    /// Frame `payload` with its checksum, without escaping.
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let checksum = payload
+            .iter()
+            .fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        let mut packet = vec![b'$'];
+        packet.extend_from_slice(payload);
+        packet.extend_from_slice(format!("#{checksum:02x}").as_bytes());
+        packet
+    }
+
+    fn receive_packet(connection: &mut Connection) -> Vec<u8> {
+        match connection.receive().unwrap() {
+            Some(Received::Packet(payload)) => payload,
+            Some(Received::Interrupt) => panic!("Unexpected interrupt"),
+            None => panic!("Unexpected end of stream"),
+        }
+    }
+
+    fn read_acks(client: &mut TcpStream, count: usize) -> Vec<u8> {
+        let mut acks = vec![0; count];
+        client.read_exact(&mut acks).unwrap();
+        acks
+    }
+
+    #[test]
+    fn test_receive_framing() {
+        let (mut connection, mut client) = connect();
+
+        // Noise and acknowledgements between packets are skipped.
+        client.write_all(b"+-x").unwrap();
+        client.write_all(&frame(b"qSupported")).unwrap();
+        client.write_all(&frame(b"")).unwrap();
+        client.write_all(&[0x03]).unwrap();
+
+        assert_eq!(receive_packet(&mut connection), b"qSupported");
+        assert_eq!(receive_packet(&mut connection), b"");
+        assert!(matches!(
+            connection.receive().unwrap(),
+            Some(Received::Interrupt)
+        ));
+        assert_eq!(read_acks(&mut client, 2), b"++");
+
+        drop(client);
+        assert!(connection.receive().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_receive_escapes_and_runs() {
+        let (mut connection, mut client) = connect();
+
+        // `}` escapes the next byte, `*` repeats the previous byte.
+        client.write_all(&frame(b"X}\x03}]")).unwrap();
+        client.write_all(&frame(b"0* ")).unwrap();
+        client.write_all(&frame(b"a}\x03*\"")).unwrap();
+
+        assert_eq!(receive_packet(&mut connection), b"X#}");
+        assert_eq!(receive_packet(&mut connection), b"0000");
+        assert_eq!(receive_packet(&mut connection), b"a######");
+        assert_eq!(read_acks(&mut client, 3), b"+++");
+    }
+
+    #[test]
+    fn test_receive_rejects_bad_packets() {
+        let (mut connection, mut client) = connect();
+
+        // Wrong checksum, non-hex checksum and a run without a preceding
+        // byte are all rejected and must be retransmitted.
+        client.write_all(b"$m0,4#00").unwrap();
+        client.write_all(b"$m0,4#zz").unwrap();
+        client.write_all(&frame(b"* ")).unwrap();
+        client.write_all(&frame(b"m0,4")).unwrap();
+
+        assert_eq!(receive_packet(&mut connection), b"m0,4");
+        assert_eq!(read_acks(&mut client, 4), b"---+");
+
+        // Without acknowledgements, checksums are not enforced.
+        connection.disable_acks();
+        client.write_all(b"$g#00").unwrap();
+        assert_eq!(receive_packet(&mut connection), b"g");
+
+        // A stream ending mid-packet is an error.
+        client.write_all(b"$m0").unwrap();
+        drop(client);
+        assert_eq!(
+            connection.receive().err().map(|err| err.kind()),
+            Some(ErrorKind::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn test_send() {
+        let (mut connection, mut client) = connect();
+
+        // Retransmitted until the client acknowledges it, ignoring noise.
+        client.write_all(b"-x+").unwrap();
+        connection.send(b"a$b#c}d*").unwrap();
+
+        let packet = b"$a}\x04b}\x03c}]d}\n#";
+        let checksum = packet[1..packet.len() - 1]
+            .iter()
+            .fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        let mut expected = packet.to_vec();
+        expected.extend_from_slice(format!("{checksum:02x}").as_bytes());
+
+        let mut sent = vec![0; 2 * expected.len()];
+        client.read_exact(&mut sent).unwrap();
+        assert_eq!(sent, [expected.clone(), expected.clone()].concat());
+
+        // Sent packets decode to the original payload.
+        connection.disable_acks();
+        connection.send(b"OK").unwrap();
+        let mut sent = vec![0; 6];
+        client.read_exact(&mut sent).unwrap();
+        assert_eq!(sent, frame(b"OK"));
+
+        let (mut receiver, mut sender) = connect();
+        sender.write_all(&expected).unwrap();
+        assert_eq!(receive_packet(&mut receiver), b"a$b#c}d*");
+    }
+
+    #[test]
+    fn test_poll_interrupt() {
+        let (mut connection, mut client) = connect();
+        assert!(!connection.poll_interrupt().unwrap());
+
+        client.write_all(&[b'+', 0x03]).unwrap();
+        client.flush().unwrap();
+        while !connection.poll_interrupt().unwrap() {}
+
+        client.write_all(&frame(b"c")).unwrap();
+        assert_eq!(receive_packet(&mut connection), b"c");
+
+        // Packets received before the interrupt are not discarded.
+        client
+            .write_all(&[frame(b"g"), vec![0x03], frame(b"s")].concat())
+            .unwrap();
+        client.flush().unwrap();
+        while !connection.poll_interrupt().unwrap() {}
+        assert_eq!(receive_packet(&mut connection), b"g");
+        assert_eq!(receive_packet(&mut connection), b"s");
+    }
+}
@@ -1,38 +1,88 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 // SPDX-FileCopyrightText: 2024 Nomadic Labs <contact@nomadic-labs.com>
 //
 // SPDX-License-Identifier: MIT
 
-use cli::Options;
+use cli::{DiffOptions, GdbServerOptions, Options, ProveOptions, VerifyOptions};
+use console::Console;
+use octez_risc_v_pvm::{
+    proof::{prove_step, verify_step},
+    state::{Pvm, PvmLayout, Status},
+};
 use risc_v_interpreter::{
-    machine_state::mode::Mode, traps::EnvironException, Interpreter, InterpreterResult::*,
+    exec_env::{posix::Posix, sbi::Sbi},
+    machine_state::{
+        bus::{in_main_memory, main_memory::M1G},
+        mode::Mode,
+        registers::parse_xregister,
+        trace::{Commit, MemoryAccess},
+        MachineState,
+    },
+    state_backend::{memory_backend::InMemoryBackend, proof::Proof, Backend, Layout, Manager},
+    traps::EnvironException,
+    Interpreter,
+    InterpreterResult::*,
 };
 use rvemu::emulator::Emulator;
+use snapshot::{Progress, Snapshot};
 use std::error::Error;
+use std::io::{BufWriter, Write};
 use std::path::Path;
 use tezos_crypto_rs::hash::ContractKt1Hash;
+use tezos_smart_rollup_debug::log::LogFilter;
 use tezos_smart_rollup_encoding::{
     michelson::MichelsonUnit, public_key_hash::PublicKeyHash, smart_rollup::SmartRollupAddress,
 };
+use tezos_smart_rollup_mock::MockHost;
 
 mod cli;
+mod console;
 mod debugger;
 mod devicetree;
+mod gdbserver;
 mod inbox;
 mod rvemu_boot;
 mod rvemu_syscall;
+mod snapshot;
 
 /// Convert a RISC-V exception into an error.
 pub fn exception_to_error(exc: EnvironException) -> Box<dyn Error> {
     format!("{:?}", exc).into()
 }
 
+/// Layout of the PVM state for SBI kernels
+type SbiLayout = PvmLayout<Sbi, M1G>;
+
+/// Maximum number of steps for POSIX-style programs
+const MAX_STEPS: usize = 1000000;
+
 fn run(opts: Options) -> Result<(), Box<dyn Error>> {
+    if opts.sbi {
+        run_sbi(opts)
+    } else {
+        run_posix(opts)
+    }
+}
+
+/// Forward bytes written to the UART console to stdout, decoding log records
+/// kept by `filter`.
+fn console_output(filter: LogFilter) -> Box<dyn FnMut(u8)> {
+    let mut console = Console::new(filter, std::io::stdout());
+    Box::new(move |byte| console.output(byte))
+}
+
+fn run_posix(opts: Options) -> Result<(), Box<dyn Error>> {
     let contents = std::fs::read(&opts.input)?;
     let mut backend = Interpreter::create_backend();
-    let mut interpreter = Interpreter::new(&mut backend, &contents, None, posix_exit_mode(opts))?;
+    let mut interpreter = Interpreter::new(
+        &mut backend,
+        &contents,
+        None,
+        posix_exit_mode(&opts.posix_exit_mode),
+        cli::enabled_extensions(&opts.disable_extensions),
+    )?;
 
-    const MAX_STEPS: usize = 1000000;
+    interpreter.set_console_output(console_output(opts.log_filter.clone()));
 
     match interpreter.run(MAX_STEPS) {
         Exit { code: 0, .. } => Ok(()),
@@ -42,6 +92,138 @@ fn run(opts: Options) -> Result<(), Box<dyn Error>> {
     }
 }
 
+/// Install the program in a fresh PVM state set up for SBI kernels.
+fn boot_sbi(
+    opts: &Options,
+    address: &SmartRollupAddress,
+) -> Result<InMemoryBackend<SbiLayout>, Box<dyn Error>> {
+    let contents = std::fs::read(&opts.input)?;
+    let initrd = opts.initrd.as_ref().map(std::fs::read).transpose()?;
+
+    let (mut backend, placed) = InMemoryBackend::<SbiLayout>::new();
+    let mut pvm = Pvm::<Sbi, M1G, _>::bind(backend.allocate(placed));
+    pvm.reset();
+    pvm.set_extensions(cli::enabled_extensions(&opts.disable_extensions));
+    pvm.install_program(&contents, initrd.as_deref())?;
+    pvm.syscall_state.set_metadata(
+        address.hash().as_ref().as_slice().try_into()?,
+        opts.origination_level,
+    );
+
+    Ok(backend)
+}
+
+/// Feed the next inbox message to a PVM waiting for input. Returns `false`
+/// once the inbox has been drained, unless asked to keep going.
+fn feed_input<M: Manager>(
+    pvm: &mut Pvm<Sbi, M1G, M>,
+    inbox: &mut inbox::Inbox,
+    keep_going: bool,
+) -> bool {
+    if inbox.none_count() >= 2 && !keep_going {
+        return false;
+    }
+
+    match inbox.next() {
+        Some((level, id, data)) => pvm.provide_input(level as u64, id as u64, &data),
+        None => pvm.provide_input(0, 0, &[]),
+    };
+
+    true
+}
+
+fn run_sbi(opts: Options) -> Result<(), Box<dyn Error>> {
+    let address = SmartRollupAddress::from_b58check(opts.address.as_str())?;
+    let mut inbox = test_inbox(&address);
+
+    let (mut backend, mut progress) = match &opts.resume_from {
+        Some(path) => {
+            let snapshot = Snapshot::decode(&std::fs::read(path)?)?;
+            let backend = InMemoryBackend::<SbiLayout>::import_snapshot(&snapshot.state)?;
+
+            // The inbox isn't part of the state, replay the reads made before
+            // the snapshot was taken.
+            for _ in 0..snapshot.progress.inbox_position {
+                inbox.next();
+            }
+
+            (backend, snapshot.progress)
+        }
+
+        None => (boot_sbi(&opts, &address)?, Progress::default()),
+    };
+
+    let mut trace = opts
+        .trace
+        .as_ref()
+        .map(|path| std::fs::File::create(path).map(BufWriter::new))
+        .transpose()?;
+
+    // Resuming from a snapshot taken after the requested step can't reach it.
+    if let Some(snapshot_at) = opts.snapshot_at {
+        progress.steps_until(snapshot_at)?;
+    }
+
+    let mut pvm = Pvm::<Sbi, M1G, _>::bind(backend.allocate(SbiLayout::placed().into_location()));
+    pvm.set_console_output(console_output(opts.log_filter.clone()));
+
+    while !pvm.syscall_state.is_shut_down()
+        && opts.snapshot_at.map_or(true, |at| progress.steps < at)
+    {
+        match pvm.status() {
+            Status::Eval => {
+                let max_steps = match opts.snapshot_at {
+                    Some(snapshot_at) => snapshot_at.saturating_sub(progress.steps),
+                    None => u64::MAX,
+                };
+                let max_steps = max_steps.min(MAX_STEPS as u64) as usize;
+
+                match &mut trace {
+                    Some(trace) => {
+                        if let Some(commit) = pvm.step_traced() {
+                            if opts.trace_disassembly {
+                                writeln!(trace, "{}", commit.disassembly())?;
+                            }
+                            writeln!(trace, "{commit}")?;
+                        }
+                        progress.steps += 1;
+                    }
+
+                    None => progress.steps += pvm.step_many(max_steps) as u64,
+                }
+            }
+
+            Status::Input => {
+                if !feed_input(&mut pvm, &mut inbox, opts.keep_going) {
+                    break;
+                }
+                progress.inbox_position += 1;
+            }
+        }
+    }
+
+    drop(pvm);
+
+    if let Some(mut trace) = trace {
+        trace.flush()?;
+    }
+
+    if let (Some(snapshot_at), Some(output)) = (opts.snapshot_at, &opts.snapshot_output) {
+        if progress.steps != snapshot_at {
+            return Err(format!("Stopped after {} of {snapshot_at} steps", progress.steps).into());
+        }
+
+        let snapshot = Snapshot {
+            progress,
+            state: backend.export_snapshot(),
+        };
+        std::fs::write(output, snapshot.encode())?;
+        eprintln!("Snapshot after {snapshot_at} steps written to {output}");
+    }
+
+    Ok(())
+}
+
 fn debug(opts: Options) -> Result<(), Box<dyn Error>> {
     let path = Path::new(&opts.input);
     let fname = path
@@ -50,24 +232,267 @@ fn debug(opts: Options) -> Result<(), Box<dyn Error>> {
         .to_str()
         .ok_or("File name cannot be converted to string")?;
     let contents = std::fs::read(path)?;
-    Ok(debugger::DebuggerApp::launch(fname, &contents)?)
+    Ok(debugger::DebuggerApp::launch(
+        fname,
+        &contents,
+        cli::enabled_extensions(&opts.disable_extensions),
+    )?)
 }
 
-fn rvemu(opts: Options) -> Result<(), Box<dyn Error>> {
+/// Handler for the environment calls made to rvemu
+type RvemuSyscallHandler = fn(
+    &mut Emulator,
+    &rvemu_syscall::RollupMetadata,
+    &mut inbox::Inbox,
+    &MockHost,
+) -> Result<(), Box<dyn Error>>;
+
+/// Load the program into rvemu and prepare the host it interacts with.
+fn rvemu_setup(
+    opts: &Options,
+) -> Result<(Emulator, rvemu_syscall::RollupMetadata, MockHost), Box<dyn Error>> {
     let mut emu = Emulator::new();
 
     // Load the ELF binary into the emulator.
     let contents = std::fs::read(&opts.input)?;
 
-    rvemu_boot::setup_boot(&mut emu, &contents, opts.initrd)?;
+    rvemu_boot::setup_boot(&mut emu, &contents, opts.initrd.clone())?;
 
     // Rollup metadata
     let meta = rvemu_syscall::RollupMetadata {
         origination_level: opts.origination_level,
-        address: SmartRollupAddress::from_b58check(opts.address.as_str()).unwrap(),
+        address: SmartRollupAddress::from_b58check(opts.address.as_str())?,
+    };
+
+    // Durable storage, outbox and preimages
+    let mut host = MockHost::with_address(&meta.address);
+    if let Some(preimages_dir) = &opts.preimages_dir {
+        for entry in std::fs::read_dir(preimages_dir)? {
+            host.set_preimage(std::fs::read(entry?.path())?);
+        }
+    }
+
+    Ok((emu, meta, host))
+}
+
+/// Run a single instruction in rvemu, handling environment calls.
+fn rvemu_step(
+    emu: &mut Emulator,
+    handle_syscall: RvemuSyscallHandler,
+    meta: &rvemu_syscall::RollupMetadata,
+    inbox: &mut inbox::Inbox,
+    host: &MockHost,
+) -> Result<(), Box<dyn Error>> {
+    let prev_pc = emu.cpu.pc;
+
+    emu.cpu.devices_increment();
+
+    if let Some(interrupt) = emu.cpu.check_pending_interrupt() {
+        interrupt.take_trap(&mut emu.cpu);
+
+        // We don't do anything with the devices at the moment. So we'll
+        // just panic if they magically come alive.
+        panic!("Interrupt {:?}", interrupt);
+    }
+
+    emu.cpu
+        .execute()
+        .map(|_| ())
+        .or_else(|exception| -> Result<(), Box<dyn Error>> {
+            match exception {
+                rvemu::exception::Exception::EnvironmentCallFromSMode
+                | rvemu::exception::Exception::EnvironmentCallFromUMode => {
+                    handle_syscall(emu, meta, inbox, host).map_err(|err| -> Box<dyn Error> {
+                        format!("Failed to handle environment call at {prev_pc:x}: {}", err)
+                            .as_str()
+                            .into()
+                    })?;
+
+                    // We need to update the program counter ourselves now.
+                    // This is a recent change in behaviour in RVEmu.
+                    emu.cpu.pc += 4;
+
+                    Ok(())
+                }
+
+                _ => {
+                    let trap = exception.take_trap(&mut emu.cpu);
+
+                    // Don't bother handling other exceptions. For now they're
+                    // all fatal.
+                    panic!("Exception {:?} at {:#x}: {:?}", exception, prev_pc, trap)
+                }
+            }
+        })
+}
+
+fn rvemu(opts: Options) -> Result<(), Box<dyn Error>> {
+    let (mut emu, meta, host) = rvemu_setup(&opts)?;
+    let mut inbox = test_inbox(&meta.address);
+
+    let handle_syscall: RvemuSyscallHandler = if opts.posix {
+        |emu, _, _, _| rvemu_syscall::handle_posix(emu)
+    } else {
+        rvemu_syscall::handle_sbi
+    };
+
+    let mut prev_pc = emu.cpu.pc;
+
+    while inbox.none_count() < 2 || opts.keep_going {
+        rvemu_step(&mut emu, handle_syscall, &meta, &mut inbox, &host)?;
+
+        // If the program loops in place we assume it is stuck.
+        if prev_pc == emu.cpu.pc {
+            panic!("Stuck at {:#x}", prev_pc);
+        }
+
+        prev_pc = emu.cpu.pc;
+    }
+
+    for output in host.outbox_at(host.level()) {
+        eprintln!("Outbox message: {}", hex::encode(output));
+    }
+
+    Ok(())
+}
+
+/// Differences between the registers of the interpreter and rvemu
+fn rvemu_mismatches<M: Manager>(pvm: &Pvm<Sbi, M1G, M>, emu: &Emulator) -> Vec<String> {
+    let mut mismatches = Vec::new();
+
+    let (ours, theirs) = (pvm.read_pc(), emu.cpu.pc);
+    if ours != theirs {
+        mismatches.push(format!(
+            "pc: {ours:#x} (interpreter) != {theirs:#x} (rvemu)"
+        ));
+    }
+
+    for index in 1..32 {
+        let reg = parse_xregister(index);
+        let (ours, theirs) = (pvm.read_xregister(reg), emu.cpu.xregs.read(index as u64));
+        if ours != theirs {
+            mismatches.push(format!(
+                "{reg} ({reg:?}): {ours:#x} (interpreter) != {theirs:#x} (rvemu)"
+            ));
+        }
+    }
+
+    mismatches
+}
+
+/// Differences between the memory accessed by the instruction of `commit` in
+/// the interpreter and rvemu. Stores must also have written the traced value.
+/// Accesses outside of main memory are skipped, because reading devices in
+/// rvemu has side effects.
+fn memory_mismatches<M: Manager>(
+    pvm: &Pvm<Sbi, M1G, M>,
+    emu: &mut Emulator,
+    commit: &Commit,
+) -> Vec<String> {
+    let (address, width, stored) = match commit.memory_access {
+        Some(MemoryAccess::Load { address, width }) => (address, width as usize, None),
+        Some(MemoryAccess::Store {
+            address,
+            width,
+            value,
+        }) => (address, width as usize, Some(value)),
+        None => return Vec::new(),
     };
 
-    // Prepare inbox
+    let end = address.wrapping_add(width as u64 - 1);
+    if !in_main_memory::<M1G>(address) || !in_main_memory::<M1G>(end) {
+        return Vec::new();
+    }
+
+    let show = |bytes: &Option<Vec<u8>>| bytes.as_ref().map_or("-".to_string(), hex::encode);
+    let ours = pvm.read_memory(address, width);
+    let theirs = rvemu_syscall::read_memory(emu, address, width as u64).ok();
+
+    let mut mismatches = Vec::new();
+    if ours != theirs {
+        mismatches.push(format!(
+            "mem {address:#x}: {} (interpreter) != {} (rvemu)",
+            show(&ours),
+            show(&theirs)
+        ));
+    }
+
+    if let Some(value) = stored {
+        let expected = Some(value.to_le_bytes()[..width].to_vec());
+        if ours != expected {
+            mismatches.push(format!(
+                "mem {address:#x}: {} (interpreter) != {} (traced store)",
+                show(&ours),
+                show(&expected)
+            ));
+        }
+    }
+
+    mismatches
+}
+
+fn diff_trace(opts: Options) -> Result<(), Box<dyn Error>> {
+    if opts.posix {
+        return Err("Only SBI programs can be traced in lockstep".into());
+    }
+
+    let address = SmartRollupAddress::from_b58check(opts.address.as_str())?;
+    let mut inbox = test_inbox(&address);
+    let mut backend = boot_sbi(&opts, &address)?;
+    let mut pvm = Pvm::<Sbi, M1G, _>::bind(backend.allocate(SbiLayout::placed().into_location()));
+
+    // Console output is left to rvemu.
+    pvm.set_console_output(Box::new(|_| {}));
+
+    let (mut emu, meta, host) = rvemu_setup(&opts)?;
+    let mut rvemu_inbox = test_inbox(&meta.address);
+
+    let mut steps = 0u64;
+    let mut commit: Option<Commit> = None;
+
+    loop {
+        let mut mismatches = rvemu_mismatches(&pvm, &emu);
+        if let Some(commit) = &commit {
+            mismatches.extend(memory_mismatches(&pvm, &mut emu, commit));
+        }
+
+        if !mismatches.is_empty() {
+            eprintln!("Divergence after {steps} steps");
+            if let Some(commit) = &commit {
+                eprintln!("{}", commit.disassembly());
+                eprintln!("{commit}");
+            }
+            for mismatch in mismatches {
+                eprintln!("  {mismatch}");
+            }
+            return Err("The interpreter and rvemu diverged".into());
+        }
+
+        if pvm.syscall_state.is_shut_down() {
+            break;
+        }
+
+        commit = pvm.step_traced();
+        if pvm.status() == Status::Input && !feed_input(&mut pvm, &mut inbox, opts.keep_going) {
+            break;
+        }
+
+        rvemu_step(
+            &mut emu,
+            rvemu_syscall::handle_sbi,
+            &meta,
+            &mut rvemu_inbox,
+            &host,
+        )?;
+        steps += 1;
+    }
+
+    eprintln!("No divergence in {steps} steps");
+    Ok(())
+}
+
+/// Inbox fed to kernels run in the sandbox
+fn test_inbox(address: &SmartRollupAddress) -> inbox::Inbox {
     let mut inbox = inbox::InboxBuilder::new();
     inbox
         .insert_external(vec![1, 2, 3, 4])
@@ -80,82 +505,99 @@ fn rvemu(opts: Options) -> Result<(), Box<dyn Error>> {
         .insert_transfer(
             ContractKt1Hash::from_base58_check("KT1EfTusMLoeCAAGd9MZJn5yKzFr6kJU5U91").unwrap(),
             PublicKeyHash::from_b58check("tz1dJ21ejKD17t7HKcKkTPuwQphgcSiehTYi").unwrap(),
-            meta.address.clone(),
+            address.clone(),
             MichelsonUnit,
         );
-    let mut inbox = inbox.build();
-
-    let handle_syscall = if opts.posix {
-        fn dummy(
-            emu: &mut Emulator,
-            _: &rvemu_syscall::RollupMetadata,
-            _: &mut inbox::Inbox,
-        ) -> Result<(), Box<dyn Error>> {
-            rvemu_syscall::handle_posix(emu)
-        }
-        dummy
-    } else {
-        rvemu_syscall::handle_sbi
-    };
+    inbox.build()
+}
 
-    let mut prev_pc = emu.cpu.pc;
+fn prove(opts: ProveOptions) -> Result<(), Box<dyn Error>> {
+    let contents = std::fs::read(&opts.input)?;
+    let initrd = opts.initrd.map(std::fs::read).transpose()?;
 
-    while inbox.none_count() < 2 || opts.keep_going {
-        emu.cpu.devices_increment();
+    let (mut backend, placed) = InMemoryBackend::<PvmLayout<Posix, M1G>>::new();
 
-        if let Some(interrupt) = emu.cpu.check_pending_interrupt() {
-            interrupt.take_trap(&mut emu.cpu);
+    {
+        let mut pvm = Pvm::<Posix, M1G, _>::bind(backend.allocate(placed));
+        pvm.reset();
+        pvm.install_program(&contents, initrd.as_deref())?;
 
-            // We don't do anything with the devices at the moment. So we'll
-            // just panic if they magically come alive.
-            panic!("Interrupt {:?}", interrupt);
+        let steps = pvm.step_many(opts.steps);
+        if steps < opts.steps {
+            return Err(format!("Stopped after {steps} of {} steps", opts.steps).into());
         }
+    }
 
-        emu.cpu
-            .execute()
-            .map(|_| ())
-            .or_else(|exception| -> Result<(), Box<dyn Error>> {
-                match exception {
-                    rvemu::exception::Exception::EnvironmentCallFromSMode
-                    | rvemu::exception::Exception::EnvironmentCallFromUMode => {
-                        handle_syscall(&mut emu, &meta, &mut inbox).map_err(
-                            |err| -> Box<dyn Error> {
-                                format!("Failed to handle environment call at {prev_pc:x}: {}", err)
-                                    .as_str()
-                                    .into()
-                            },
-                        )?;
-
-                        // We need to update the program counter ourselves now.
-                        // This is a recent change in behaviour in RVEmu.
-                        emu.cpu.pc += 4;
-
-                        Ok(())
-                    }
+    let proof = prove_step::<Posix, M1G>(&mut backend)?;
+    std::fs::write(&opts.output, proof.encode())?;
 
-                    _ => {
-                        let trap = exception.take_trap(&mut emu.cpu);
+    println!("Initial state hash: {}", proof.initial_state_hash());
+    println!("Final state hash:   {}", proof.final_state_hash());
 
-                        // Don't bother handling other exceptions. For now they're
-                        // all fatal.
-                        panic!("Exception {:?} at {:#x}: {:?}", exception, prev_pc, trap)
-                    }
-                }
-            })?;
+    Ok(())
+}
 
-        // If the program loops in place we assume it is stuck.
-        if prev_pc == emu.cpu.pc {
-            panic!("Stuck at {:#x}", prev_pc);
-        }
+fn verify(opts: VerifyOptions) -> Result<(), Box<dyn Error>> {
+    let proof = Proof::decode::<PvmLayout<Posix, M1G>>(&std::fs::read(opts.proof)?)?;
+    verify_step::<Posix, M1G>(&proof)?;
 
-        prev_pc = emu.cpu.pc;
+    println!(
+        "Proof is valid: {} -> {}",
+        proof.initial_state_hash(),
+        proof.final_state_hash()
+    );
+
+    Ok(())
+}
+
+fn gdbserver(opts: GdbServerOptions) -> Result<(), Box<dyn Error>> {
+    let contents = std::fs::read(&opts.input)?;
+    let initrd = opts.initrd.map(std::fs::read).transpose()?;
+    let mut backend = Interpreter::create_backend();
+    let mut interpreter = Interpreter::new(
+        &mut backend,
+        &contents,
+        initrd.as_deref(),
+        posix_exit_mode(&opts.posix_exit_mode),
+        cli::enabled_extensions(&opts.disable_extensions),
+    )?;
+
+    interpreter.set_console_output(console_output(LogFilter::default()));
+
+    gdbserver::serve(&mut interpreter, opts.port)
+}
+
+fn diff(opts: DiffOptions) -> Result<(), Box<dyn Error>> {
+    let load = |path: &str| -> Result<_, Box<dyn Error>> {
+        let snapshot = Snapshot::decode(&std::fs::read(path)?)?;
+        let backend = InMemoryBackend::<SbiLayout>::import_snapshot(&snapshot.state)?;
+        Ok((snapshot.progress, backend))
+    };
+
+    let (before_progress, before) = load(&opts.before)?;
+    let (after_progress, after) = load(&opts.after)?;
+
+    if before_progress != after_progress {
+        println!("progress: {before_progress:?} -> {after_progress:?}");
+    }
+
+    let before =
+        MachineState::<M1G, _>::bind(before.allocate_ro(SbiLayout::placed().into_location()).1);
+    let after =
+        MachineState::<M1G, _>::bind(after.allocate_ro(SbiLayout::placed().into_location()).1);
+
+    let diff = before.diff(&after);
+    if diff.is_empty() {
+        println!("Machine states are identical");
+    } else {
+        print!("{diff}");
     }
 
     Ok(())
 }
 
-fn posix_exit_mode(opts: Options) -> Mode {
-    match opts.posix_exit_mode {
+fn posix_exit_mode(mode: &cli::ExitMode) -> Mode {
+    match mode {
         cli::ExitMode::User => Mode::User,
         cli::ExitMode::Supervisor => Mode::Supervisor,
         cli::ExitMode::Machine => Mode::Machine,
@@ -168,5 +610,10 @@ fn main() -> Result<(), Box<dyn Error>> {
         cli::Mode::Rvemu(opts) => rvemu(opts),
         cli::Mode::Run(opts) => run(opts),
         cli::Mode::Debug(opts) => debug(opts),
+        cli::Mode::Prove(opts) => prove(opts),
+        cli::Mode::Verify(opts) => verify(opts),
+        cli::Mode::Gdbserver(opts) => gdbserver(opts),
+        cli::Mode::Diff(opts) => diff(opts),
+        cli::Mode::DiffTrace(opts) => diff_trace(opts),
     }
 }
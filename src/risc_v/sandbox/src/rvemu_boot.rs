@@ -22,6 +22,7 @@ pub const A4: u64 = 14;
 pub const A5: u64 = 15;
 pub const A6: u64 = 16;
 pub const A7: u64 = 17;
+pub const TP: u64 = 4;
 
 /// Configure the emulator so it is ready to boot.
 pub fn setup_boot(
@@ -32,6 +33,8 @@ pub fn setup_boot(
     let LoadResult {
         entry,
         last_written,
+        thread_pointer,
+        ..
     } = kernel_loader::load_elf(&mut emu.cpu.bus, rvemu::bus::DRAM_BASE, contents)?;
 
     let initrd_addr = last_written;
@@ -68,5 +71,10 @@ pub fn setup_boot(
     // Linux and HermitOS expect the pointer to the device tree in register a1 (x11).
     emu.cpu.xregs.write(A1, dtb_addr);
 
+    // Point the thread pointer at the initial TLS block, if any.
+    if let Some(thread_pointer) = thread_pointer {
+        emu.cpu.xregs.write(TP, thread_pointer);
+    }
+
     Ok(())
 }
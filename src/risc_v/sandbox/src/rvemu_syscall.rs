@@ -1,4 +1,4 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
@@ -6,7 +6,7 @@
 //!   - https://www.scs.stanford.edu/~zyedidia/docs/riscv/riscv-sbi.pdf
 
 use crate::inbox::Inbox;
-use crate::rvemu_boot::{A0, A1, A2, A3, A6, A7};
+use crate::rvemu_boot::{A0, A1, A2, A3, A4, A6, A7};
 use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
 use kernel_loader::Memory;
 use rvemu::cpu::{AccessType, BYTE};
@@ -20,9 +20,17 @@ use std::{
 use tezos_smart_rollup_constants::riscv::{
     SBI_CONSOLE_PUTCHAR, SBI_FIRMWARE_TEZOS, SBI_SHUTDOWN, SBI_TEZOS_BLAKE2B_HASH256,
     SBI_TEZOS_ED25519_SIGN, SBI_TEZOS_ED25519_VERIFY, SBI_TEZOS_INBOX_NEXT, SBI_TEZOS_META_ADDRESS,
-    SBI_TEZOS_META_ORIGINATION_LEVEL,
+    SBI_TEZOS_META_ORIGINATION_LEVEL, SBI_TEZOS_REVEAL, SBI_TEZOS_REVEAL_PREIMAGE,
+    SBI_TEZOS_STORE_COPY, SBI_TEZOS_STORE_DELETE, SBI_TEZOS_STORE_DELETE_VALUE,
+    SBI_TEZOS_STORE_HAS, SBI_TEZOS_STORE_LIST_SIZE, SBI_TEZOS_STORE_MOVE, SBI_TEZOS_STORE_READ,
+    SBI_TEZOS_STORE_VALUE_SIZE, SBI_TEZOS_STORE_WRITE, SBI_TEZOS_WRITE_OUTPUT,
 };
-use tezos_smart_rollup_encoding::smart_rollup::SmartRollupAddress;
+use tezos_smart_rollup_core::{
+    smart_rollup_core::SmartRollupCore, GENERIC_INVALID_ACCESS, MAX_FILE_CHUNK_SIZE,
+    PREIMAGE_HASH_SIZE,
+};
+use tezos_smart_rollup_encoding::{dac::pages::MAX_PAGE_SIZE, smart_rollup::SmartRollupAddress};
+use tezos_smart_rollup_mock::MockHost;
 
 type SBIResult = Result<(), Box<dyn Error>>;
 
@@ -40,7 +48,7 @@ fn read_physical_address(emu: &mut Emulator, register: u64) -> Result<u64, Box<d
 }
 
 /// Read a series of bytes from memory.
-fn read_memory(emu: &mut Emulator, address: u64, len: u64) -> Result<Vec<u8>, Box<dyn Error>> {
+pub fn read_memory(emu: &mut Emulator, address: u64, len: u64) -> Result<Vec<u8>, Box<dyn Error>> {
     let mut buffer = vec![0u8; len as usize];
 
     for i in 0..len {
@@ -179,8 +187,197 @@ fn sbi_tezos_blake2b_hash256(emu: &mut Emulator) -> SBIResult {
     Ok(())
 }
 
+/// Read a byte buffer whose address and length are held in the given registers.
+fn read_buffer(emu: &mut Emulator, addr_reg: u64, len_reg: u64) -> Result<Vec<u8>, Box<dyn Error>> {
+    let addr = read_physical_address(emu, addr_reg)?;
+    let len = emu.cpu.xregs.read(len_reg);
+    read_memory(emu, addr, len)
+}
+
+/// Return the result of a host function to the caller via `a0`.
+fn write_result(emu: &mut Emulator, result: impl Into<i64>) {
+    emu.cpu.xregs.write(A0, result.into() as u64);
+}
+
+/// Write a message to the outbox.
+fn sbi_tezos_write_output(emu: &mut Emulator, host: &MockHost) -> SBIResult {
+    let output = read_buffer(emu, A0, A1)?;
+    let result = unsafe { host.write_output(output.as_ptr(), output.len()) };
+    write_result(emu, result);
+    Ok(())
+}
+
+/// Call a durable storage function which only takes a path.
+fn sbi_tezos_store_path<R: Into<i64>>(
+    emu: &mut Emulator,
+    store_fn: unsafe fn(&MockHost, *const u8, usize) -> R,
+    host: &MockHost,
+) -> SBIResult {
+    let path = read_buffer(emu, A0, A1)?;
+    let result = unsafe { store_fn(host, path.as_ptr(), path.len()) };
+    write_result(emu, result);
+    Ok(())
+}
+
+/// Call a durable storage function which takes a source and destination path.
+fn sbi_tezos_store_from_to(
+    emu: &mut Emulator,
+    store_fn: unsafe fn(&MockHost, *const u8, usize, *const u8, usize) -> i32,
+    host: &MockHost,
+) -> SBIResult {
+    let from_path = read_buffer(emu, A0, A1)?;
+    let to_path = read_buffer(emu, A2, A3)?;
+    let result = unsafe {
+        store_fn(
+            host,
+            from_path.as_ptr(),
+            from_path.len(),
+            to_path.as_ptr(),
+            to_path.len(),
+        )
+    };
+    write_result(emu, result);
+    Ok(())
+}
+
+/// Read part of a value from the durable storage.
+fn sbi_tezos_store_read(emu: &mut Emulator, host: &MockHost) -> SBIResult {
+    let path = read_buffer(emu, A0, A1)?;
+    let offset = emu.cpu.xregs.read(A2) as usize;
+    let dest_addr = read_physical_address(emu, A3)?;
+    let max_bytes = (emu.cpu.xregs.read(A4) as usize).min(MAX_FILE_CHUNK_SIZE);
+
+    let mut buffer = vec![0u8; max_bytes];
+    let result = unsafe {
+        host.store_read(
+            path.as_ptr(),
+            path.len(),
+            offset,
+            buffer.as_mut_ptr(),
+            max_bytes,
+        )
+    };
+
+    if result > 0 {
+        emu.cpu
+            .bus
+            .write_bytes(dest_addr, &buffer[..result as usize])?;
+    }
+
+    write_result(emu, result);
+    Ok(())
+}
+
+/// Write part of a value to the durable storage.
+fn sbi_tezos_store_write(emu: &mut Emulator, host: &MockHost) -> SBIResult {
+    let path = read_buffer(emu, A0, A1)?;
+    let offset = emu.cpu.xregs.read(A2) as usize;
+    let value = read_buffer(emu, A3, A4)?;
+
+    let result = unsafe {
+        host.store_write(
+            path.as_ptr(),
+            path.len(),
+            offset,
+            value.as_ptr(),
+            value.len(),
+        )
+    };
+
+    write_result(emu, result);
+    Ok(())
+}
+
+/// Write data that has been revealed by the host to the caller's buffer.
+fn write_revealed(emu: &mut Emulator, dest_addr: u64, data: &[u8]) -> SBIResult {
+    emu.cpu.bus.write_bytes(dest_addr, data)?;
+    write_result(emu, data.len() as i64);
+    Ok(())
+}
+
+/// Largest preimage which can be revealed
+const MAX_PREIMAGE_SIZE: usize = MAX_PAGE_SIZE;
+
+/// Reveal the preimage of a hash. Returns the preimage, or the error code
+/// reported to the kernel, as for [`reveal`].
+fn reveal_preimage(host: &MockHost, hash: &[u8], max_bytes: usize) -> Result<Vec<u8>, i32> {
+    // Malformed hashes are the kernel's mistake, they must not stop the sandbox.
+    if hash.len() != PREIMAGE_HASH_SIZE {
+        return Err(GENERIC_INVALID_ACCESS);
+    }
+
+    let max_bytes = max_bytes.min(MAX_PREIMAGE_SIZE);
+    let mut buffer = vec![0u8; max_bytes];
+    let length =
+        unsafe { host.reveal_preimage(hash.as_ptr(), hash.len(), buffer.as_mut_ptr(), max_bytes) };
+    buffer.truncate(length as usize);
+
+    Ok(buffer)
+}
+
+/// Answer a reveal request encoded as in the Tezos protocol. Returns the
+/// revealed data, or the error code reported by the host.
+///
+/// Besides preimages, the largest data that can be revealed is a DAL page.
+fn reveal(host: &MockHost, payload: &[u8], max_bytes: usize) -> Result<Vec<u8>, i32> {
+    let max_size = MAX_PREIMAGE_SIZE.max(host.dal_parameters().page_size as usize);
+    let max_bytes = max_bytes.min(max_size);
+
+    let mut buffer = vec![0u8; max_bytes];
+    let result = unsafe {
+        host.reveal(
+            payload.as_ptr(),
+            payload.len(),
+            buffer.as_mut_ptr(),
+            max_bytes,
+        )
+    };
+    if result < 0 {
+        return Err(result);
+    }
+
+    buffer.truncate(result as usize);
+    Ok(buffer)
+}
+
+/// Reveal the preimage of a hash.
+fn sbi_tezos_reveal_preimage(emu: &mut Emulator, host: &MockHost) -> SBIResult {
+    let hash = read_buffer(emu, A0, A1)?;
+    let dest_addr = read_physical_address(emu, A2)?;
+    let max_bytes = emu.cpu.xregs.read(A3) as usize;
+
+    match reveal_preimage(host, &hash, max_bytes) {
+        Ok(preimage) => write_revealed(emu, dest_addr, &preimage),
+        Err(code) => {
+            write_result(emu, code);
+            Ok(())
+        }
+    }
+}
+
+/// Reveal data requested using the protocol's encoding of reveal requests:
+/// raw data, metadata, DAL pages or DAL parameters.
+fn sbi_tezos_reveal(emu: &mut Emulator, host: &MockHost) -> SBIResult {
+    let payload = read_buffer(emu, A0, A1)?;
+    let dest_addr = read_physical_address(emu, A2)?;
+    let max_bytes = emu.cpu.xregs.read(A3) as usize;
+
+    match reveal(host, &payload, max_bytes) {
+        Ok(data) => write_revealed(emu, dest_addr, &data),
+        Err(code) => {
+            write_result(emu, code);
+            Ok(())
+        }
+    }
+}
+
 /// Handle a system call originating from the user program.
-pub fn handle_sbi(emu: &mut Emulator, meta: &RollupMetadata, inbox: &mut Inbox) -> SBIResult {
+pub fn handle_sbi(
+    emu: &mut Emulator,
+    meta: &RollupMetadata,
+    inbox: &mut Inbox,
+    host: &MockHost,
+) -> SBIResult {
     // TODO: https://gitlab.com/tezos/tezos/-/issues/6767
     // Feed errors back to caller instead of raising them in the sandbox.
     // This means this function most likely should return unit.
@@ -199,6 +396,24 @@ pub fn handle_sbi(emu: &mut Emulator, meta: &RollupMetadata, inbox: &mut Inbox)
                 SBI_TEZOS_ED25519_SIGN => sbi_tezos_ed25519_sign(emu),
                 SBI_TEZOS_ED25519_VERIFY => sbi_tezos_ed25519_verify(emu),
                 SBI_TEZOS_BLAKE2B_HASH256 => sbi_tezos_blake2b_hash256(emu),
+                SBI_TEZOS_WRITE_OUTPUT => sbi_tezos_write_output(emu, host),
+                SBI_TEZOS_STORE_HAS => sbi_tezos_store_path(emu, MockHost::store_has, host),
+                SBI_TEZOS_STORE_READ => sbi_tezos_store_read(emu, host),
+                SBI_TEZOS_STORE_WRITE => sbi_tezos_store_write(emu, host),
+                SBI_TEZOS_STORE_DELETE => sbi_tezos_store_path(emu, MockHost::store_delete, host),
+                SBI_TEZOS_STORE_DELETE_VALUE => {
+                    sbi_tezos_store_path(emu, MockHost::store_delete_value, host)
+                }
+                SBI_TEZOS_STORE_LIST_SIZE => {
+                    sbi_tezos_store_path(emu, MockHost::store_list_size, host)
+                }
+                SBI_TEZOS_STORE_MOVE => sbi_tezos_store_from_to(emu, MockHost::store_move, host),
+                SBI_TEZOS_STORE_COPY => sbi_tezos_store_from_to(emu, MockHost::store_copy, host),
+                SBI_TEZOS_STORE_VALUE_SIZE => {
+                    sbi_tezos_store_path(emu, MockHost::store_value_size, host)
+                }
+                SBI_TEZOS_REVEAL_PREIMAGE => sbi_tezos_reveal_preimage(emu, host),
+                SBI_TEZOS_REVEAL => sbi_tezos_reveal(emu, host),
                 _ => Err(format!(
                     "Unimplemented Tezos SBI extension ({sbi_extension}) function {sbi_function}"
                 )
@@ -226,3 +441,110 @@ pub fn handle_posix(emu: &mut Emulator) -> SBIResult {
         _ => Err(format!("Unimplemented system call {syscall_number}").into()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{reveal, reveal_preimage, MAX_PREIMAGE_SIZE};
+    use tezos_smart_rollup_core::{smart_rollup_core::SmartRollupCore, GENERIC_INVALID_ACCESS};
+    use tezos_smart_rollup_mock::MockHost;
+
+    /// Encode a request for a DAL page.
+    fn dal_page_request(published_level: u32, slot_index: u8, page_index: i16) -> Vec<u8> {
+        let mut request = vec![2];
+        request.extend_from_slice(&(published_level as i32).to_be_bytes());
+        request.push(slot_index);
+        request.extend_from_slice(&page_index.to_be_bytes());
+        request
+    }
+
+    #[test]
+    fn test_reveal_raw_data() {
+        let mut host = MockHost::default();
+        let preimage: Vec<u8> = (0..=255).cycle().take(MAX_PREIMAGE_SIZE).collect();
+        let hash = host.set_preimage(preimage.clone());
+
+        let mut request = vec![0];
+        request.extend_from_slice(&hash);
+        assert_eq!(reveal(&host, &request, 16), Ok(preimage[..16].to_vec()));
+
+        // Oversized buffers are clamped to the largest preimage.
+        assert_eq!(reveal(&host, &request, usize::MAX), Ok(preimage.clone()));
+        assert_eq!(reveal_preimage(&host, &hash, usize::MAX), Ok(preimage));
+
+        // Requests with a malformed hash are rejected with the same error code.
+        assert_eq!(
+            reveal(&host, &request[..10], 16),
+            Err(GENERIC_INVALID_ACCESS)
+        );
+        assert_eq!(
+            reveal_preimage(&host, &hash[..10], 16),
+            Err(GENERIC_INVALID_ACCESS)
+        );
+        assert_eq!(reveal_preimage(&host, &[], 16), Err(GENERIC_INVALID_ACCESS));
+    }
+
+    #[test]
+    fn test_reveal_metadata() {
+        let host = MockHost::default();
+
+        let mut metadata = [0u8; 24];
+        let length = unsafe { host.reveal_metadata(metadata.as_mut_ptr(), metadata.len()) };
+        assert_eq!(length as usize, metadata.len());
+
+        assert_eq!(reveal(&host, &[1], usize::MAX), Ok(metadata.to_vec()));
+        assert_eq!(reveal(&host, &[1], 4), Ok(metadata[..4].to_vec()));
+    }
+
+    #[test]
+    fn test_reveal_dal_page() {
+        let mut host = MockHost::default();
+        let parameters = host.dal_parameters();
+
+        let published_level = host.level();
+        let slot: Vec<u8> = (0..=255)
+            .cycle()
+            .take(parameters.slot_size as usize)
+            .collect();
+        host.publish_dal_slot(published_level, 1, slot.clone());
+
+        // The slot is not attested yet.
+        let request = dal_page_request(published_level, 1, 1);
+        assert_eq!(reveal(&host, &request, usize::MAX), Ok(vec![]));
+
+        for _ in 0..parameters.attestation_lag {
+            host.run_level(|_| {});
+        }
+
+        // Oversized buffers are clamped to the page size.
+        let page_size = parameters.page_size as usize;
+        assert_eq!(
+            reveal(&host, &request, usize::MAX),
+            Ok(slot[page_size..2 * page_size].to_vec())
+        );
+        assert_eq!(
+            reveal(&host, &request, 8),
+            Ok(slot[page_size..page_size + 8].to_vec())
+        );
+
+        // Pages outside of the slot are rejected.
+        let request = dal_page_request(published_level, 1, i16::MAX);
+        assert!(reveal(&host, &request, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_reveal_dal_parameters() {
+        let host = MockHost::default();
+        let parameters = host.dal_parameters();
+
+        let revealed = reveal(&host, &[3], usize::MAX).unwrap();
+        assert_eq!(revealed.len(), 32);
+        assert_eq!(revealed[24..], (parameters.page_size as i64).to_be_bytes());
+    }
+
+    #[test]
+    fn test_reveal_unknown() {
+        let host = MockHost::default();
+        assert!(reveal(&host, &[], usize::MAX).is_err());
+        assert!(reveal(&host, &[42], usize::MAX).is_err());
+    }
+}
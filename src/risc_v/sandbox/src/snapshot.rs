@@ -0,0 +1,154 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Sandbox snapshots
+//!
+//! A sandbox snapshot wraps a snapshot of the PVM state with the progress of
+//! the run, which isn't part of the PVM state: the number of steps taken and
+//! the number of inbox messages consumed. The encoding is the magic bytes
+//! `RVSB`, a version byte, both counters as little-endian `u64`s and finally
+//! the PVM state snapshot.
+
+use std::error::Error;
+
+/// Magic bytes at the start of every sandbox snapshot
+const MAGIC: [u8; 4] = *b"RVSB";
+
+/// Version of the sandbox snapshot encoding
+const VERSION: u8 = 0;
+
+/// Size of the sandbox snapshot header
+const HEADER_SIZE: usize = MAGIC.len() + 1 + 8 + 8;
+
+/// Progress of a sandbox run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Number of steps taken so far
+    pub steps: u64,
+
+    /// Number of times the inbox has been read from
+    pub inbox_position: u64,
+}
+
+impl Progress {
+    /// Number of steps left until step `target`. Fails if the run is already
+    /// past it, e.g. when resuming from a later snapshot.
+    pub fn steps_until(&self, target: u64) -> Result<u64, String> {
+        target.checked_sub(self.steps).ok_or_else(|| {
+            format!(
+                "Cannot stop at step {target}, the run is already at step {}",
+                self.steps
+            )
+        })
+    }
+}
+
+/// Snapshot of a sandbox run
+pub struct Snapshot {
+    pub progress: Progress,
+
+    /// Encoded PVM state
+    pub state: Vec<u8>,
+}
+
+impl Snapshot {
+    /// Encode the snapshot.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.state.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.progress.steps.to_le_bytes());
+        bytes.extend_from_slice(&self.progress.inbox_position.to_le_bytes());
+        bytes.extend_from_slice(&self.state);
+        bytes
+    }
+
+    /// Decode a snapshot produced by [`Snapshot::encode`].
+    pub fn decode(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if bytes.len() < HEADER_SIZE || bytes[..MAGIC.len()] != MAGIC {
+            return Err("Not a sandbox snapshot".into());
+        }
+
+        let version = bytes[MAGIC.len()];
+        if version != VERSION {
+            return Err(format!("Unsupported sandbox snapshot version {version}").into());
+        }
+
+        let read_u64 =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        let progress = Progress {
+            steps: read_u64(MAGIC.len() + 1),
+            inbox_position: read_u64(MAGIC.len() + 9),
+        };
+
+        Ok(Self {
+            progress,
+            state: bytes[HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Progress, Snapshot, HEADER_SIZE, MAGIC};
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let snapshot = Snapshot {
+            progress: Progress {
+                steps: 1234,
+                inbox_position: 5,
+            },
+            state: vec![1, 2, 3],
+        };
+
+        let encoded = snapshot.encode();
+        assert_eq!(encoded.len(), HEADER_SIZE + 3);
+        assert_eq!(encoded[..MAGIC.len()], MAGIC);
+
+        let decoded = Snapshot::decode(&encoded).unwrap();
+        assert_eq!(decoded.progress, snapshot.progress);
+        assert_eq!(decoded.state, snapshot.state);
+
+        // An empty state is valid as far as the sandbox snapshot goes.
+        let empty = Snapshot {
+            progress: Progress::default(),
+            state: vec![],
+        };
+        let decoded = Snapshot::decode(&empty.encode()).unwrap();
+        assert_eq!(decoded.progress, Progress::default());
+        assert!(decoded.state.is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_rejected() {
+        let encoded = Snapshot {
+            progress: Progress::default(),
+            state: vec![0; 8],
+        }
+        .encode();
+
+        assert!(Snapshot::decode(&encoded[..HEADER_SIZE - 1]).is_err());
+        assert!(Snapshot::decode(b"").is_err());
+
+        let mut bad_magic = encoded.clone();
+        bad_magic[0] = b'X';
+        assert!(Snapshot::decode(&bad_magic).is_err());
+
+        let mut bad_version = encoded;
+        bad_version[MAGIC.len()] += 1;
+        assert!(Snapshot::decode(&bad_version).is_err());
+    }
+
+    #[test]
+    fn test_steps_until() {
+        let progress = Progress {
+            steps: 10,
+            inbox_position: 0,
+        };
+        assert_eq!(progress.steps_until(15), Ok(5));
+        assert_eq!(progress.steps_until(10), Ok(0));
+        assert!(progress.steps_until(9).is_err());
+    }
+}